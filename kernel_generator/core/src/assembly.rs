@@ -1,3 +1,13 @@
+use crate::gas::{self, Width};
+use crate::liveness::{self, Register, VerifyError};
+use crate::name::{self, Name};
+use crate::peephole::{self, PeepholeReport};
+use crate::register::{Gpr, Mask, Xmm, Ymm, Zmm, N_PHYSICAL_REGISTERS, N_REGISTERS};
+use crate::schedule;
+use crate::spill::{self, SpillReport};
+use crate::GenerateError;
+
+use std::collections::HashMap;
 use std::fmt::Write;
 
 #[derive(Clone, Copy)]
@@ -10,9 +20,9 @@ pub enum PrefetchType {
 
 pub struct Assembly {
     arr: Vec<Instruction>,
-    var_asms: Vec<(&'static str, &'static str)>,
-    zmm_used: [bool; 32],
-    k_used: [bool; 4],
+    var_asms: Vec<(Name, Name)>,
+    // general purpose registers the kernel declares, see `argument_int`
+    operands: Vec<gas::Operand>,
 }
 
 const fn is_comment(asm: &str) -> bool {
@@ -20,141 +30,141 @@ const fn is_comment(asm: &str) -> bool {
     asm.len() > 2 && arr[0] == b'/' && arr[1] == b'/'
 }
 
-enum Instruction {
-    Comment(&'static str),
+pub(crate) enum Instruction {
+    Comment(Name),
     Nop,
-    Label(&'static str),
-    JumpNotZero(&'static str), // jnz loop0
-
-    MaskOn(u8),
-    MaskSet(u8, &'static str), // kmovw reg_src, k_dst
-    MaskNot(u8, u8),           // knotw k_src, k_dst
-
-    AddImmediate(&'static str, i16),
-    SubImmediate(&'static str, i16), // sub $0x1, %[J]
-    SetImmediate(&'static str, i16),
-    ShiftRight(&'static str, u8), // sar $0x3,%edx or sar %edx
-    MovReg(&'static str, &'static str),
-
-    MovF64x8(u8, u8),
-    MovF64x2(u8, u8),
-    MovI32x8(u8, u8),
-
-    LoadF64x8(u8, &'static str, i16),
-    LoadF64x1(u8, &'static str, i16),
-    LoadI32x8(u8, &'static str, i16),
-    StoreF64x8(&'static str, i16, u8),
-    StoreF64x1(&'static str, i16, u8),
-    GatherF64x8(u8, &'static str, u8, u8),
-    Prefetch(PrefetchType, &'static str, i16),
-
-    AddF64x8(u8, u8, u8),
-    AddF64x4(u8, u8, u8),
-    AddF64x2(u8, u8, u8),
-    AddF64x1(u8, u8, u8),
-    LoadAddF64x8(u8, u8, &'static str, i16),
-    LoadAddF64x1(u8, u8, &'static str, i16),
-    MulF64x8(u8, u8, u8),
-    MulF64x1(u8, u8, u8),
-    LoadMulF64x8(u8, u8, &'static str, i16),
-    LoadMulF64x1(u8, u8, &'static str, i16),
-    MulAddF64x8(u8, u8, u8),
-    MulAddF64x1(u8, u8, u8),
-    LoadMulAddF64x8(u8, u8, &'static str, i16),
-    LoadMulAddF64x1(u8, u8, &'static str, i16),
-    NMulSubF64x8(u8, u8, u8),
-    LoadNMulSubF64x8(u8, u8, &'static str, i16),
-    DivF64x8(u8, u8, u8),
-    DivF64x1(u8, u8, u8),
-    LoadDivF64x8(u8, u8, &'static str, i16),
-    LoadDivF64x1(u8, u8, &'static str, i16),
-
-    LUMix4F64x8(u8, u8, u8), // vinsertf64x4 $0x0, ymm_src2, zmm_src1, zmm_dst = lower src1 & upper src2
-    ULMix4F64x8(u8, u8, u8), // valignq $0x4, zmm_src2, zmm_src1, zmm_dst = upper src1 & lower src2
-    Mix2F64x8Mask(u8, u8, u8), // vpermpd $0x4e, zmm_src, zmm_dst%{%%k%}
-    LUMix1F64x8(u8, u8, u8), // vshufpd $0xaa, zmm_src2, zmm_src1, zmm_dst \t\n"
-    ULMix1F64x8(u8, u8, u8), // vshufpd $0x55, zmm_src2, zmm_src1, zmm_dst \t\n"
-    ExtractU4F64x8(u8, u8),  // vextractf128 $0x1, zmm_src, ymm_dst
-    ExtractU2F64x4(u8, u8),  // vextractf128 $0x1, ymm_src, xmm_dst
-    Fold1AddF64x2(u8, u8),   // vhaddpd xmm_src, xmm_src, xmm_dst
+    Label(Name),
+    JumpNotZero(Name), // jnz loop0
+
+    MaskOn(Mask),
+    MaskSet(Mask, Gpr),  // kmovw reg_src, k_dst
+    MaskNot(Mask, Mask), // knotw k_src, k_dst
+
+    AddImmediate(Gpr, i16),
+    SubImmediate(Gpr, i16), // sub $0x1, %[J]
+    SetImmediate(Gpr, i16),
+    ShiftRight(Gpr, u8), // sar $0x3,%edx or sar %edx
+    MovReg(Gpr, Gpr),
+
+    MovF64x8(Zmm, Zmm),
+    MovF64x2(Xmm, Xmm),
+    MovI32x8(Ymm, Ymm),
+
+    LoadF64x8(Zmm, Gpr, i16),
+    LoadF64x1(Xmm, Gpr, i16),
+    LoadI32x8(Ymm, Gpr, i16),
+    StoreF64x8(Gpr, i16, Zmm),
+    StoreF64x1(Gpr, i16, Xmm),
+    GatherF64x8(Zmm, Gpr, Ymm, Mask),
+    Prefetch(PrefetchType, Gpr, i16),
+
+    AddF64x8(Zmm, Zmm, Zmm),
+    AddF64x4(Ymm, Ymm, Ymm),
+    AddF64x2(Xmm, Xmm, Xmm),
+    AddF64x1(Xmm, Xmm, Xmm),
+    LoadAddF64x8(Zmm, Zmm, Gpr, i16),
+    LoadAddF64x1(Xmm, Xmm, Gpr, i16),
+    MulF64x8(Zmm, Zmm, Zmm),
+    MulF64x1(Xmm, Xmm, Xmm),
+    LoadMulF64x8(Zmm, Zmm, Gpr, i16),
+    LoadMulF64x1(Xmm, Xmm, Gpr, i16),
+    MulAddF64x8(Zmm, Zmm, Zmm),
+    MulAddF64x1(Xmm, Xmm, Xmm),
+    LoadMulAddF64x8(Zmm, Zmm, Gpr, i16),
+    LoadMulAddF64x1(Xmm, Xmm, Gpr, i16),
+    NMulSubF64x8(Zmm, Zmm, Zmm),
+    LoadNMulSubF64x8(Zmm, Zmm, Gpr, i16),
+    DivF64x8(Zmm, Zmm, Zmm),
+    DivF64x1(Xmm, Xmm, Xmm),
+    LoadDivF64x8(Zmm, Zmm, Gpr, i16),
+    LoadDivF64x1(Xmm, Xmm, Gpr, i16),
+
+    LUMix4F64x8(Zmm, Ymm, Zmm), // vinsertf64x4 $0x0, ymm_src2, zmm_src1, zmm_dst = lower src1 & upper src2
+    ULMix4F64x8(Zmm, Zmm, Zmm), // valignq $0x4, zmm_src2, zmm_src1, zmm_dst = upper src1 & lower src2
+    Mix2F64x8Mask(Zmm, Zmm, Mask), // vpermpd $0x4e, zmm_src, zmm_dst%{%%k%}
+    LUMix1F64x8(Zmm, Zmm, Zmm), // vshufpd $0xaa, zmm_src2, zmm_src1, zmm_dst \t\n"
+    ULMix1F64x8(Zmm, Zmm, Zmm), // vshufpd $0x55, zmm_src2, zmm_src1, zmm_dst \t\n"
+    ExtractU4F64x8(Ymm, Zmm),   // vextractf128 $0x1, zmm_src, ymm_dst
+    ExtractU2F64x4(Xmm, Ymm),   // vextractf128 $0x1, ymm_src, xmm_dst
+    Fold1AddF64x2(Xmm, Xmm),    // vhaddpd xmm_src, xmm_src, xmm_dst
 }
 
 impl std::fmt::Display for Instruction {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match *self {
-            Instruction::Comment(comment) => match comment {
+            Instruction::Comment(ref comment) => match &**comment {
                 "" => write!(f, ""),
                 _ => write!(f, "// {}", comment),
             },
             Instruction::Nop => write!(f, "nop"),
-            Instruction::Label(name) => write!(f, "{}:", name),
-            Instruction::JumpNotZero(label) => write!(f, "jnz {}", label),
+            Instruction::Label(ref name) => write!(f, "{}:", name),
+            Instruction::JumpNotZero(ref label) => write!(f, "jnz {}", label),
 
-            Instruction::MaskOn(k) => write!(f, "kxnorw %%k0, %%k0, %%k{}", k),
-            Instruction::MaskSet(k, reg_name) => write!(f, "kmovw %[{}], %%k{}", reg_name, k),
-            Instruction::MaskNot(k_dst, k_src) => write!(f, "knotw %%k{}, %%k{}", k_src, k_dst),
+            Instruction::MaskOn(k) => write!(f, "kxnorw %%k0, %%k0, %%{}", k),
+            Instruction::MaskSet(k, ref reg_name) => write!(f, "kmovw %[{}], %%{}", reg_name, k),
+            Instruction::MaskNot(k_dst, k_src) => write!(f, "knotw %%{}, %%{}", k_src, k_dst),
 
-            Instruction::AddImmediate(reg_name, imm) => match imm > 0 {
+            Instruction::AddImmediate(ref reg_name, imm) => match imm > 0 {
                 true => write!(f, "add $0x{:x}, %[{}]", imm, reg_name),
                 false => write!(f, "add $-0x{:x}, %[{}]", -imm, reg_name),
             },
-            Instruction::SubImmediate(reg_name, imm) => match imm > 0 {
+            Instruction::SubImmediate(ref reg_name, imm) => match imm > 0 {
                 true => write!(f, "sub $0x{:x}, %[{}]", imm, reg_name),
                 false => write!(f, "sub $-0x{:x}, %[{}]", -imm, reg_name),
             },
-            Instruction::SetImmediate(reg_name, imm) => match imm > 0 {
+            Instruction::SetImmediate(ref reg_name, imm) => match imm > 0 {
                 true => write!(f, "movl $0x{:x}, %[{}]", imm, reg_name),
                 false => write!(f, "movl $-0x{:x}, %[{}]", -imm, reg_name),
             },
-            Instruction::ShiftRight(reg_name, imm) => match imm {
+            Instruction::ShiftRight(ref reg_name, imm) => match imm {
                 1 => write!(f, "sar %[{}]", reg_name),
                 _ => write!(f, "sar $0x{:x}, %[{}]", imm, reg_name),
             },
-            Instruction::MovReg(reg_dist, reg_src) => {
+            Instruction::MovReg(ref reg_dist, ref reg_src) => {
                 write!(f, "mov %[{}], %[{}]", reg_src, reg_dist)
             }
 
             Instruction::MovF64x8(zmm_dst, zmm_src) => {
-                write!(f, "vmovupd %%zmm{}, %%zmm{}", zmm_src, zmm_dst)
+                write!(f, "vmovupd %%{}, %%{}", zmm_src, zmm_dst)
             }
             Instruction::MovF64x2(xmm_dst, xmm_src) => {
-                write!(f, "vmovupd %%xmm{}, %%xmm{}", xmm_src, xmm_dst)
+                write!(f, "vmovupd %%{}, %%{}", xmm_src, xmm_dst)
             }
             Instruction::MovI32x8(ymm_dst, ymm_src) => {
-                write!(f, "vmovupd %%ymm{}, %%ymm{}", ymm_src, ymm_dst)
+                write!(f, "vmovupd %%{}, %%{}", ymm_src, ymm_dst)
             }
 
-            Instruction::LoadF64x8(zmm, reg_base, imm_offset) => match imm_offset {
-                0 => write!(f, "vmovupd (%[{}]), %%zmm{}", reg_base, zmm),
-                imm if imm > 0 => write!(f, "vmovupd 0x{:x}(%[{}]), %%zmm{}", imm, reg_base, zmm),
-                imm => write!(f, "vmovupd -0x{:x}(%[{}]), %%zmm{}", -imm, reg_base, zmm),
+            Instruction::LoadF64x8(zmm, ref reg_base, imm_offset) => match imm_offset {
+                0 => write!(f, "vmovupd (%[{}]), %%{}", reg_base, zmm),
+                imm if imm > 0 => write!(f, "vmovupd 0x{:x}(%[{}]), %%{}", imm, reg_base, zmm),
+                imm => write!(f, "vmovupd -0x{:x}(%[{}]), %%{}", -imm, reg_base, zmm),
             },
-            Instruction::LoadF64x1(xmm, reg_base, imm_offset) => match imm_offset {
-                0 => write!(f, "vmovsd (%[{}]), %%xmm{}", reg_base, xmm),
-                imm if imm > 0 => write!(f, "vmovsd 0x{:x}(%[{}]), %%xmm{}", imm, reg_base, xmm),
-                imm => write!(f, "vmovsd -0x{:x}(%[{}]), %%xmm{}", -imm, reg_base, xmm),
+            Instruction::LoadF64x1(xmm, ref reg_base, imm_offset) => match imm_offset {
+                0 => write!(f, "vmovsd (%[{}]), %%{}", reg_base, xmm),
+                imm if imm > 0 => write!(f, "vmovsd 0x{:x}(%[{}]), %%{}", imm, reg_base, xmm),
+                imm => write!(f, "vmovsd -0x{:x}(%[{}]), %%{}", -imm, reg_base, xmm),
             },
-            Instruction::LoadI32x8(ymm, reg_base, imm_offset) => match imm_offset {
-                0 => write!(f, "vmovdqa (%[{}]), %%ymm{}", reg_base, ymm),
-                imm if imm > 0 => write!(f, "vmovdqa 0x{:x}(%[{}]), %%ymm{}", imm, reg_base, ymm),
-                imm => write!(f, "vmovdqa -0x{:x}(%[{}]), %%ymm{}", -imm, reg_base, ymm),
+            Instruction::LoadI32x8(ymm, ref reg_base, imm_offset) => match imm_offset {
+                0 => write!(f, "vmovdqa (%[{}]), %%{}", reg_base, ymm),
+                imm if imm > 0 => write!(f, "vmovdqa 0x{:x}(%[{}]), %%{}", imm, reg_base, ymm),
+                imm => write!(f, "vmovdqa -0x{:x}(%[{}]), %%{}", -imm, reg_base, ymm),
             },
-            Instruction::StoreF64x8(reg_base, imm_offset, zmm) => match imm_offset {
-                0 => write!(f, "vmovupd %%zmm{}, (%[{}])", zmm, reg_base),
-                imm if imm > 0 => write!(f, "vmovupd %%zmm{}, 0x{:x}(%[{}])", zmm, imm, reg_base),
-                imm => write!(f, "vmovupd %%zmm{}, -0x{:x}(%[{}])", zmm, -imm, reg_base),
+            Instruction::StoreF64x8(ref reg_base, imm_offset, zmm) => match imm_offset {
+                0 => write!(f, "vmovupd %%{}, (%[{}])", zmm, reg_base),
+                imm if imm > 0 => write!(f, "vmovupd %%{}, 0x{:x}(%[{}])", zmm, imm, reg_base),
+                imm => write!(f, "vmovupd %%{}, -0x{:x}(%[{}])", zmm, -imm, reg_base),
             },
-            Instruction::StoreF64x1(reg_base, imm_offset, xmm) => match imm_offset {
-                0 => write!(f, "vmovsd %%xmm{}, (%[{}])", xmm, reg_base),
-                imm if imm > 0 => write!(f, "vmovsd %%xmm{}, 0x{:x}(%[{}])", xmm, imm, reg_base),
-                imm => write!(f, "vmovsd %%xmm{}, -0x{:x}(%[{}])", xmm, -imm, reg_base),
+            Instruction::StoreF64x1(ref reg_base, imm_offset, xmm) => match imm_offset {
+                0 => write!(f, "vmovsd %%{}, (%[{}])", xmm, reg_base),
+                imm if imm > 0 => write!(f, "vmovsd %%{}, 0x{:x}(%[{}])", xmm, imm, reg_base),
+                imm => write!(f, "vmovsd %%{}, -0x{:x}(%[{}])", xmm, -imm, reg_base),
             },
-            Instruction::GatherF64x8(zmm, reg_base, ymm_idx, k) => write!(
+            Instruction::GatherF64x8(zmm, ref reg_base, ymm_idx, k) => write!(
                 f,
-                "vgatherdpd (%[{}],%%ymm{},8), %%zmm{}%{{%%k{}%}}",
+                "vgatherdpd (%[{}],%%{},8), %%{}%{{%%{}%}}",
                 reg_base, ymm_idx, zmm, k
             ),
-            Instruction::Prefetch(prefetch_type, reg_base, imm_offset) => {
+            Instruction::Prefetch(prefetch_type, ref reg_base, imm_offset) => {
                 let inst = match prefetch_type {
                     PrefetchType::NTA => "prefetchnta",
                     PrefetchType::T0 => "prefetcht0",
@@ -168,119 +178,95 @@ impl std::fmt::Display for Instruction {
             }
 
             Instruction::AddF64x8(zmm_dst, zmm_src0, zmm_src1) => {
-                write!(
-                    f,
-                    "vaddpd %%zmm{}, %%zmm{}, %%zmm{}",
-                    zmm_src1, zmm_src0, zmm_dst
-                )
+                write!(f, "vaddpd %%{}, %%{}, %%{}", zmm_src1, zmm_src0, zmm_dst)
             }
             Instruction::AddF64x4(ymm_dst, ymm_src0, ymm_src1) => {
-                write!(
-                    f,
-                    "vaddpd %%ymm{}, %%ymm{}, %%ymm{}",
-                    ymm_src1, ymm_src0, ymm_dst
-                )
+                write!(f, "vaddpd %%{}, %%{}, %%{}", ymm_src1, ymm_src0, ymm_dst)
             }
             Instruction::AddF64x2(xmm_dst, xmm_src0, xmm_src1) => {
-                write!(
-                    f,
-                    "vaddpd %%xmm{}, %%xmm{}, %%xmm{}",
-                    xmm_src1, xmm_src0, xmm_dst
-                )
+                write!(f, "vaddpd %%{}, %%{}, %%{}", xmm_src1, xmm_src0, xmm_dst)
             }
             Instruction::AddF64x1(xmm_dst, xmm_src0, xmm_src1) => {
-                write!(
-                    f,
-                    "vaddsd %%xmm{}, %%xmm{}, %%xmm{}",
-                    xmm_src1, xmm_src0, xmm_dst
-                )
+                write!(f, "vaddsd %%{}, %%{}, %%{}", xmm_src1, xmm_src0, xmm_dst)
             }
-            Instruction::LoadAddF64x8(zmm_dst, zmm_src0, reg_base1, imm_offset1) => {
+            Instruction::LoadAddF64x8(zmm_dst, zmm_src0, ref reg_base1, imm_offset1) => {
                 match imm_offset1 {
                     0 => write!(
                         f,
-                        "vaddpd (%[{}]), %%zmm{}, %%zmm{}",
+                        "vaddpd (%[{}]), %%{}, %%{}",
                         reg_base1, zmm_src0, zmm_dst
                     ),
                     imm if imm > 0 => write!(
                         f,
-                        "vaddpd 0x{:x}(%[{}]), %%zmm{}, %%zmm{}",
+                        "vaddpd 0x{:x}(%[{}]), %%{}, %%{}",
                         imm, reg_base1, zmm_src0, zmm_dst
                     ),
                     imm => write!(
                         f,
-                        "vaddpd -0x{:x}(%[{}]), %%zmm{}, %%zmm{}",
+                        "vaddpd -0x{:x}(%[{}]), %%{}, %%{}",
                         -imm, reg_base1, zmm_src0, zmm_dst
                     ),
                 }
             }
-            Instruction::LoadAddF64x1(xmm_dst, xmm_src0, reg_base1, imm_offset1) => {
+            Instruction::LoadAddF64x1(xmm_dst, xmm_src0, ref reg_base1, imm_offset1) => {
                 match imm_offset1 {
                     0 => write!(
                         f,
-                        "vaddsd (%[{}]), %%xmm{}, %%xmm{}",
+                        "vaddsd (%[{}]), %%{}, %%{}",
                         reg_base1, xmm_src0, xmm_dst
                     ),
                     imm if imm > 0 => write!(
                         f,
-                        "vaddsd 0x{:x}(%[{}]), %%xmm{}, %%xmm{}",
+                        "vaddsd 0x{:x}(%[{}]), %%{}, %%{}",
                         imm, reg_base1, xmm_src0, xmm_dst
                     ),
                     imm => write!(
                         f,
-                        "vaddsd -0x{:x}(%[{}]), %%xmm{}, %%xmm{}",
+                        "vaddsd -0x{:x}(%[{}]), %%{}, %%{}",
                         -imm, reg_base1, xmm_src0, xmm_dst
                     ),
                 }
             }
             Instruction::MulF64x8(zmm_dst, zmm_src0, zmm_src1) => {
-                write!(
-                    f,
-                    "vmulpd %%zmm{}, %%zmm{}, %%zmm{}",
-                    zmm_src1, zmm_src0, zmm_dst
-                )
+                write!(f, "vmulpd %%{}, %%{}, %%{}", zmm_src1, zmm_src0, zmm_dst)
             }
             Instruction::MulF64x1(xmm_dst, xmm_src0, xmm_src1) => {
-                write!(
-                    f,
-                    "vmulsd %%xmm{}, %%xmm{}, %%xmm{}",
-                    xmm_src1, xmm_src0, xmm_dst
-                )
+                write!(f, "vmulsd %%{}, %%{}, %%{}", xmm_src1, xmm_src0, xmm_dst)
             }
-            Instruction::LoadMulF64x8(zmm_dst, zmm_src0, reg_base1, imm_offset1) => {
+            Instruction::LoadMulF64x8(zmm_dst, zmm_src0, ref reg_base1, imm_offset1) => {
                 match imm_offset1 {
                     0 => write!(
                         f,
-                        "vmulpd (%[{}]), %%zmm{}, %%zmm{}",
+                        "vmulpd (%[{}]), %%{}, %%{}",
                         reg_base1, zmm_src0, zmm_dst
                     ),
                     imm if imm > 0 => write!(
                         f,
-                        "vmulpd 0x{:x}(%[{}]), %%zmm{}, %%zmm{}",
+                        "vmulpd 0x{:x}(%[{}]), %%{}, %%{}",
                         imm, reg_base1, zmm_src0, zmm_dst
                     ),
                     imm => write!(
                         f,
-                        "vmulpd -0x{:x}(%[{}]), %%zmm{}, %%zmm{}",
+                        "vmulpd -0x{:x}(%[{}]), %%{}, %%{}",
                         -imm, reg_base1, zmm_src0, zmm_dst
                     ),
                 }
             }
-            Instruction::LoadMulF64x1(xmm_dst, xmm_src0, reg_base1, imm_offset1) => {
+            Instruction::LoadMulF64x1(xmm_dst, xmm_src0, ref reg_base1, imm_offset1) => {
                 match imm_offset1 {
                     0 => write!(
                         f,
-                        "vmulsd (%[{}]), %%xmm{}, %%xmm{}",
+                        "vmulsd (%[{}]), %%{}, %%{}",
                         reg_base1, xmm_src0, xmm_dst
                     ),
                     imm if imm > 0 => write!(
                         f,
-                        "vmulsd 0x{:x}(%[{}]), %%xmm{}, %%xmm{}",
+                        "vmulsd 0x{:x}(%[{}]), %%{}, %%{}",
                         imm, reg_base1, xmm_src0, xmm_dst
                     ),
                     imm => write!(
                         f,
-                        "vmulsd -0x{:x}(%[{}]), %%xmm{}, %%xmm{}",
+                        "vmulsd -0x{:x}(%[{}]), %%{}, %%{}",
                         -imm, reg_base1, xmm_src0, xmm_dst
                     ),
                 }
@@ -288,51 +274,51 @@ impl std::fmt::Display for Instruction {
             Instruction::MulAddF64x8(zmm_dst, zmm_src0, zmm_src1) => {
                 write!(
                     f,
-                    "vfmadd231pd %%zmm{}, %%zmm{}, %%zmm{}",
+                    "vfmadd231pd %%{}, %%{}, %%{}",
                     zmm_src1, zmm_src0, zmm_dst
                 )
             }
             Instruction::MulAddF64x1(xmm_dst, xmm_src0, xmm_src1) => {
                 write!(
                     f,
-                    "vfmadd231sd %%xmm{}, %%xmm{}, %%xmm{}",
+                    "vfmadd231sd %%{}, %%{}, %%{}",
                     xmm_src1, xmm_src0, xmm_dst
                 )
             }
-            Instruction::LoadMulAddF64x8(zmm_dst, zmm_src0, reg_base1, imm_offset1) => {
+            Instruction::LoadMulAddF64x8(zmm_dst, zmm_src0, ref reg_base1, imm_offset1) => {
                 match imm_offset1 {
                     0 => write!(
                         f,
-                        "vfmadd231pd (%[{}]), %%zmm{}, %%zmm{}",
+                        "vfmadd231pd (%[{}]), %%{}, %%{}",
                         reg_base1, zmm_src0, zmm_dst
                     ),
                     imm if imm > 0 => write!(
                         f,
-                        "vfmadd231pd 0x{:x}(%[{}]), %%zmm{}, %%zmm{}",
+                        "vfmadd231pd 0x{:x}(%[{}]), %%{}, %%{}",
                         imm, reg_base1, zmm_src0, zmm_dst
                     ),
                     imm => write!(
                         f,
-                        "vfmadd231pd -0x{:x}(%[{}]), %%zmm{}, %%zmm{}",
+                        "vfmadd231pd -0x{:x}(%[{}]), %%{}, %%{}",
                         -imm, reg_base1, zmm_src0, zmm_dst
                     ),
                 }
             }
-            Instruction::LoadMulAddF64x1(xmm_dst, xmm_src0, reg_base1, imm_offset1) => {
+            Instruction::LoadMulAddF64x1(xmm_dst, xmm_src0, ref reg_base1, imm_offset1) => {
                 match imm_offset1 {
                     0 => write!(
                         f,
-                        "vfmadd231sd (%[{}]), %%xmm{}, %%xmm{}",
+                        "vfmadd231sd (%[{}]), %%{}, %%{}",
                         reg_base1, xmm_src0, xmm_dst
                     ),
                     imm if imm > 0 => write!(
                         f,
-                        "vfmadd231sd 0x{:x}(%[{}]), %%xmm{}, %%xmm{}",
+                        "vfmadd231sd 0x{:x}(%[{}]), %%{}, %%{}",
                         imm, reg_base1, xmm_src0, xmm_dst
                     ),
                     imm => write!(
                         f,
-                        "vfmadd231sd -0x{:x}(%[{}]), %%xmm{}, %%xmm{}",
+                        "vfmadd231sd -0x{:x}(%[{}]), %%{}, %%{}",
                         -imm, reg_base1, xmm_src0, xmm_dst
                     ),
                 }
@@ -340,77 +326,69 @@ impl std::fmt::Display for Instruction {
             Instruction::NMulSubF64x8(zmm_dst, zmm_src0, zmm_src1) => {
                 write!(
                     f,
-                    "vfnmsub231pd %%zmm{}, %%zmm{}, %%zmm{}",
+                    "vfnmsub231pd %%{}, %%{}, %%{}",
                     zmm_src1, zmm_src0, zmm_dst
                 )
             }
-            Instruction::LoadNMulSubF64x8(zmm_dst, zmm_src0, reg_base1, imm_offset1) => {
+            Instruction::LoadNMulSubF64x8(zmm_dst, zmm_src0, ref reg_base1, imm_offset1) => {
                 match imm_offset1 {
                     0 => write!(
                         f,
-                        "vfnmsub231pd (%[{}]), %%zmm{}, %%zmm{}",
+                        "vfnmsub231pd (%[{}]), %%{}, %%{}",
                         reg_base1, zmm_src0, zmm_dst
                     ),
                     imm if imm > 0 => write!(
                         f,
-                        "vfnmsub231pd 0x{:x}(%[{}]), %%zmm{}, %%zmm{}",
+                        "vfnmsub231pd 0x{:x}(%[{}]), %%{}, %%{}",
                         imm, reg_base1, zmm_src0, zmm_dst
                     ),
                     imm => write!(
                         f,
-                        "vfnmsub231pd -0x{:x}(%[{}]), %%zmm{}, %%zmm{}",
+                        "vfnmsub231pd -0x{:x}(%[{}]), %%{}, %%{}",
                         -imm, reg_base1, zmm_src0, zmm_dst
                     ),
                 }
             }
             Instruction::DivF64x8(zmm_dst, zmm_src0, zmm_src1) => {
-                write!(
-                    f,
-                    "vdivpd %%zmm{}, %%zmm{}, %%zmm{}",
-                    zmm_src1, zmm_src0, zmm_dst
-                )
+                write!(f, "vdivpd %%{}, %%{}, %%{}", zmm_src1, zmm_src0, zmm_dst)
             }
             Instruction::DivF64x1(xmm_dst, xmm_src0, xmm_src1) => {
-                write!(
-                    f,
-                    "vdivsd %%xmm{}, %%xmm{}, %%xmm{}",
-                    xmm_src1, xmm_src0, xmm_dst
-                )
+                write!(f, "vdivsd %%{}, %%{}, %%{}", xmm_src1, xmm_src0, xmm_dst)
             }
-            Instruction::LoadDivF64x8(zmm_dst, zmm_src0, reg_base1, imm_offset1) => {
+            Instruction::LoadDivF64x8(zmm_dst, zmm_src0, ref reg_base1, imm_offset1) => {
                 match imm_offset1 {
                     0 => write!(
                         f,
-                        "vdivpd (%[{}]), %%zmm{}, %%zmm{}",
+                        "vdivpd (%[{}]), %%{}, %%{}",
                         reg_base1, zmm_src0, zmm_dst
                     ),
                     imm if imm > 0 => write!(
                         f,
-                        "vdivpd 0x{:x}(%[{}]), %%zmm{}, %%zmm{}",
+                        "vdivpd 0x{:x}(%[{}]), %%{}, %%{}",
                         imm, reg_base1, zmm_src0, zmm_dst
                     ),
                     imm => write!(
                         f,
-                        "vdivpd -0x{:x}(%[{}]), %%zmm{}, %%zmm{}",
+                        "vdivpd -0x{:x}(%[{}]), %%{}, %%{}",
                         -imm, reg_base1, zmm_src0, zmm_dst
                     ),
                 }
             }
-            Instruction::LoadDivF64x1(xmm_dst, xmm_src0, reg_base1, imm_offset1) => {
+            Instruction::LoadDivF64x1(xmm_dst, xmm_src0, ref reg_base1, imm_offset1) => {
                 match imm_offset1 {
                     0 => write!(
                         f,
-                        "vdivsd (%[{}]), %%xmm{}, %%xmm{}",
+                        "vdivsd (%[{}]), %%{}, %%{}",
                         reg_base1, xmm_src0, xmm_dst
                     ),
                     imm if imm > 0 => write!(
                         f,
-                        "vdivsd 0x{:x}(%[{}]), %%xmm{}, %%xmm{}",
+                        "vdivsd 0x{:x}(%[{}]), %%{}, %%{}",
                         imm, reg_base1, xmm_src0, xmm_dst
                     ),
                     imm => write!(
                         f,
-                        "vdivsd -0x{:x}(%[{}]), %%xmm{}, %%xmm{}",
+                        "vdivsd -0x{:x}(%[{}]), %%{}, %%{}",
                         -imm, reg_base1, xmm_src0, xmm_dst
                     ),
                 }
@@ -418,85 +396,590 @@ impl std::fmt::Display for Instruction {
 
             Instruction::LUMix4F64x8(zmm_dst, ymm_src0, zmm_src1) => write!(
                 f,
-                "vinsertf64x4 $0x0, %%ymm{}, %%zmm{}, %%zmm{}",
+                "vinsertf64x4 $0x0, %%{}, %%{}, %%{}",
                 ymm_src0, zmm_src1, zmm_dst
             ),
             Instruction::ULMix4F64x8(zmm_dst, zmm_src0, zmm_src1) => write!(
                 f,
-                "valignq $0x4, %%zmm{}, %%zmm{}, %%zmm{}",
+                "valignq $0x4, %%{}, %%{}, %%{}",
                 zmm_src0, zmm_src1, zmm_dst
             ),
             Instruction::Mix2F64x8Mask(zmm_dst, zmm_src, k) => write!(
                 f,
-                "vpermpd $0x4e, %%zmm{}, %%zmm{}%{{%%k{}%}}",
+                "vpermpd $0x4e, %%{}, %%{}%{{%%{}%}}",
                 zmm_src, zmm_dst, k
             ),
             Instruction::LUMix1F64x8(zmm_dst, zmm_src0, zmm_src1) => write!(
                 f,
-                "vshufpd $0xaa, %%zmm{}, %%zmm{}, %%zmm{}",
+                "vshufpd $0xaa, %%{}, %%{}, %%{}",
                 zmm_src1, zmm_src0, zmm_dst
             ),
             Instruction::ULMix1F64x8(zmm_dst, zmm_src0, zmm_src1) => write!(
                 f,
-                "vshufpd $0x55, %%zmm{}, %%zmm{}, %%zmm{}",
+                "vshufpd $0x55, %%{}, %%{}, %%{}",
                 zmm_src1, zmm_src0, zmm_dst
             ),
             Instruction::ExtractU4F64x8(ymm_dst, zmm_src) => {
-                write!(f, "vextractf64x4 $0x1, %%zmm{}, %%ymm{}", zmm_src, ymm_dst)
+                write!(f, "vextractf64x4 $0x1, %%{}, %%{}", zmm_src, ymm_dst)
             }
             Instruction::ExtractU2F64x4(xmm_dst, ymm_src) => {
-                write!(f, "vextractf128 $0x1, %%ymm{}, %%xmm{}", ymm_src, xmm_dst)
+                write!(f, "vextractf128 $0x1, %%{}, %%{}", ymm_src, xmm_dst)
             }
             Instruction::Fold1AddF64x2(xmm_dst, xmm_src) => {
-                write!(
-                    f,
-                    "vhaddpd %%xmm{}, %%xmm{}, %%xmm{}",
-                    xmm_src, xmm_src, xmm_dst
-                )
+                write!(f, "vhaddpd %%{}, %%{}, %%{}", xmm_src, xmm_src, xmm_dst)
             }
         }
     }
 }
 
+// a location an instruction reads or writes. zmm, ymm and xmm views share
+// one `Vector`, and memory is not tracked.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub(crate) enum Operand {
+    Vector(u8),
+    Mask(u8),
+    Gpr(Name),
+    Flags,
+}
+
+impl From<Zmm> for Operand {
+    fn from(zmm: Zmm) -> Self {
+        Operand::Vector(zmm.index())
+    }
+}
+
+impl From<Ymm> for Operand {
+    fn from(ymm: Ymm) -> Self {
+        Operand::Vector(ymm.index())
+    }
+}
+
+impl From<Xmm> for Operand {
+    fn from(xmm: Xmm) -> Self {
+        Operand::Vector(xmm.index())
+    }
+}
+
+impl From<Mask> for Operand {
+    fn from(k: Mask) -> Self {
+        Operand::Mask(k.index())
+    }
+}
+
+impl From<&Gpr> for Operand {
+    fn from(reg: &Gpr) -> Self {
+        Operand::Gpr(reg.0.clone())
+    }
+}
+
+impl Instruction {
+    // gathers and masked vpermpd merge into their destination, and fma
+    // accumulates into it, so those read the destination as well.
+    pub(crate) fn uses(&self) -> Vec<Operand> {
+        match *self {
+            Instruction::Comment(_) | Instruction::Nop | Instruction::Label(_) => Vec::new(),
+            Instruction::JumpNotZero(_) => Vec::from([Operand::Flags]),
+
+            Instruction::MaskOn(_) => Vec::new(),
+            Instruction::MaskSet(_, ref reg) => Vec::from([reg.into()]),
+            Instruction::MaskNot(_, k_src) => Vec::from([k_src.into()]),
+
+            Instruction::AddImmediate(ref reg, _)
+            | Instruction::SubImmediate(ref reg, _)
+            | Instruction::ShiftRight(ref reg, _) => Vec::from([reg.into()]),
+            Instruction::SetImmediate(_, _) => Vec::new(),
+            Instruction::MovReg(_, ref reg_src) => Vec::from([reg_src.into()]),
+
+            Instruction::MovF64x8(_, zmm_src) => Vec::from([zmm_src.into()]),
+            Instruction::MovF64x2(_, xmm_src) => Vec::from([xmm_src.into()]),
+            Instruction::MovI32x8(_, ymm_src) => Vec::from([ymm_src.into()]),
+
+            Instruction::LoadF64x8(_, ref reg, _)
+            | Instruction::LoadF64x1(_, ref reg, _)
+            | Instruction::LoadI32x8(_, ref reg, _)
+            | Instruction::Prefetch(_, ref reg, _) => Vec::from([reg.into()]),
+            Instruction::StoreF64x8(ref reg, _, zmm) => Vec::from([reg.into(), zmm.into()]),
+            Instruction::StoreF64x1(ref reg, _, xmm) => Vec::from([reg.into(), xmm.into()]),
+            Instruction::GatherF64x8(zmm, ref reg, ymm_idx, k) => {
+                Vec::from([zmm.into(), reg.into(), ymm_idx.into(), k.into()])
+            }
+
+            Instruction::AddF64x8(_, zmm_src0, zmm_src1)
+            | Instruction::MulF64x8(_, zmm_src0, zmm_src1)
+            | Instruction::DivF64x8(_, zmm_src0, zmm_src1)
+            | Instruction::ULMix4F64x8(_, zmm_src0, zmm_src1)
+            | Instruction::LUMix1F64x8(_, zmm_src0, zmm_src1)
+            | Instruction::ULMix1F64x8(_, zmm_src0, zmm_src1) => {
+                Vec::from([zmm_src0.into(), zmm_src1.into()])
+            }
+            Instruction::AddF64x4(_, ymm_src0, ymm_src1) => {
+                Vec::from([ymm_src0.into(), ymm_src1.into()])
+            }
+            Instruction::AddF64x2(_, xmm_src0, xmm_src1)
+            | Instruction::AddF64x1(_, xmm_src0, xmm_src1)
+            | Instruction::MulF64x1(_, xmm_src0, xmm_src1)
+            | Instruction::DivF64x1(_, xmm_src0, xmm_src1) => {
+                Vec::from([xmm_src0.into(), xmm_src1.into()])
+            }
+            Instruction::LoadAddF64x8(_, zmm_src0, ref reg, _)
+            | Instruction::LoadMulF64x8(_, zmm_src0, ref reg, _)
+            | Instruction::LoadDivF64x8(_, zmm_src0, ref reg, _) => {
+                Vec::from([zmm_src0.into(), reg.into()])
+            }
+            Instruction::LoadAddF64x1(_, xmm_src0, ref reg, _)
+            | Instruction::LoadMulF64x1(_, xmm_src0, ref reg, _)
+            | Instruction::LoadDivF64x1(_, xmm_src0, ref reg, _) => {
+                Vec::from([xmm_src0.into(), reg.into()])
+            }
+            Instruction::MulAddF64x8(zmm_dst, zmm_src0, zmm_src1)
+            | Instruction::NMulSubF64x8(zmm_dst, zmm_src0, zmm_src1) => {
+                Vec::from([zmm_dst.into(), zmm_src0.into(), zmm_src1.into()])
+            }
+            Instruction::MulAddF64x1(xmm_dst, xmm_src0, xmm_src1) => {
+                Vec::from([xmm_dst.into(), xmm_src0.into(), xmm_src1.into()])
+            }
+            Instruction::LoadMulAddF64x8(zmm_dst, zmm_src0, ref reg, _)
+            | Instruction::LoadNMulSubF64x8(zmm_dst, zmm_src0, ref reg, _) => {
+                Vec::from([zmm_dst.into(), zmm_src0.into(), reg.into()])
+            }
+            Instruction::LoadMulAddF64x1(xmm_dst, xmm_src0, ref reg, _) => {
+                Vec::from([xmm_dst.into(), xmm_src0.into(), reg.into()])
+            }
+
+            Instruction::LUMix4F64x8(_, ymm_src0, zmm_src1) => {
+                Vec::from([ymm_src0.into(), zmm_src1.into()])
+            }
+            Instruction::Mix2F64x8Mask(zmm_dst, zmm_src, k) => {
+                Vec::from([zmm_dst.into(), zmm_src.into(), k.into()])
+            }
+            Instruction::ExtractU4F64x8(_, zmm_src) => Vec::from([zmm_src.into()]),
+            Instruction::ExtractU2F64x4(_, ymm_src) => Vec::from([ymm_src.into()]),
+            Instruction::Fold1AddF64x2(_, xmm_src) => Vec::from([xmm_src.into()]),
+        }
+    }
+
+    // a gather clears its mask as elements arrive, so it writes the mask too.
+    pub(crate) fn defs(&self) -> Vec<Operand> {
+        match *self {
+            Instruction::Comment(_)
+            | Instruction::Nop
+            | Instruction::Label(_)
+            | Instruction::JumpNotZero(_) => Vec::new(),
+
+            Instruction::MaskOn(k) | Instruction::MaskSet(k, _) | Instruction::MaskNot(k, _) => {
+                Vec::from([k.into()])
+            }
+
+            Instruction::AddImmediate(ref reg, _)
+            | Instruction::SubImmediate(ref reg, _)
+            | Instruction::ShiftRight(ref reg, _) => Vec::from([reg.into(), Operand::Flags]),
+            Instruction::SetImmediate(ref reg, _) | Instruction::MovReg(ref reg, _) => {
+                Vec::from([reg.into()])
+            }
+
+            Instruction::StoreF64x8(_, _, _)
+            | Instruction::StoreF64x1(_, _, _)
+            | Instruction::Prefetch(_, _, _) => Vec::new(),
+            Instruction::GatherF64x8(zmm, _, _, k) => Vec::from([zmm.into(), k.into()]),
+
+            Instruction::MovF64x8(zmm_dst, _)
+            | Instruction::LoadF64x8(zmm_dst, _, _)
+            | Instruction::AddF64x8(zmm_dst, _, _)
+            | Instruction::LoadAddF64x8(zmm_dst, _, _, _)
+            | Instruction::MulF64x8(zmm_dst, _, _)
+            | Instruction::LoadMulF64x8(zmm_dst, _, _, _)
+            | Instruction::MulAddF64x8(zmm_dst, _, _)
+            | Instruction::LoadMulAddF64x8(zmm_dst, _, _, _)
+            | Instruction::NMulSubF64x8(zmm_dst, _, _)
+            | Instruction::LoadNMulSubF64x8(zmm_dst, _, _, _)
+            | Instruction::DivF64x8(zmm_dst, _, _)
+            | Instruction::LoadDivF64x8(zmm_dst, _, _, _)
+            | Instruction::LUMix4F64x8(zmm_dst, _, _)
+            | Instruction::ULMix4F64x8(zmm_dst, _, _)
+            | Instruction::Mix2F64x8Mask(zmm_dst, _, _)
+            | Instruction::LUMix1F64x8(zmm_dst, _, _)
+            | Instruction::ULMix1F64x8(zmm_dst, _, _) => Vec::from([zmm_dst.into()]),
+            Instruction::MovI32x8(ymm_dst, _)
+            | Instruction::LoadI32x8(ymm_dst, _, _)
+            | Instruction::AddF64x4(ymm_dst, _, _)
+            | Instruction::ExtractU4F64x8(ymm_dst, _) => Vec::from([ymm_dst.into()]),
+            Instruction::MovF64x2(xmm_dst, _)
+            | Instruction::LoadF64x1(xmm_dst, _, _)
+            | Instruction::AddF64x2(xmm_dst, _, _)
+            | Instruction::AddF64x1(xmm_dst, _, _)
+            | Instruction::LoadAddF64x1(xmm_dst, _, _, _)
+            | Instruction::MulF64x1(xmm_dst, _, _)
+            | Instruction::LoadMulF64x1(xmm_dst, _, _, _)
+            | Instruction::MulAddF64x1(xmm_dst, _, _)
+            | Instruction::LoadMulAddF64x1(xmm_dst, _, _, _)
+            | Instruction::DivF64x1(xmm_dst, _, _)
+            | Instruction::LoadDivF64x1(xmm_dst, _, _, _)
+            | Instruction::ExtractU2F64x4(xmm_dst, _)
+            | Instruction::Fold1AddF64x2(xmm_dst, _) => Vec::from([xmm_dst.into()]),
+        }
+    }
+
+    // the same instruction with every vector register number passed through `f`
+    pub(crate) fn map_vectors(&self, f: impl Fn(u8) -> u8) -> Instruction {
+        let z = |zmm: Zmm| Zmm(f(zmm.0));
+        let y = |ymm: Ymm| Ymm(f(ymm.0));
+        let x = |xmm: Xmm| Xmm(f(xmm.0));
+
+        match *self {
+            Instruction::Comment(ref comment) => Instruction::Comment(comment.clone()),
+            Instruction::Nop => Instruction::Nop,
+            Instruction::Label(ref name) => Instruction::Label(name.clone()),
+            Instruction::JumpNotZero(ref label) => Instruction::JumpNotZero(label.clone()),
+
+            Instruction::MaskOn(k) => Instruction::MaskOn(k),
+            Instruction::MaskSet(k, ref reg) => Instruction::MaskSet(k, reg.clone()),
+            Instruction::MaskNot(k_dst, k_src) => Instruction::MaskNot(k_dst, k_src),
+
+            Instruction::AddImmediate(ref reg, imm) => Instruction::AddImmediate(reg.clone(), imm),
+            Instruction::SubImmediate(ref reg, imm) => Instruction::SubImmediate(reg.clone(), imm),
+            Instruction::SetImmediate(ref reg, imm) => Instruction::SetImmediate(reg.clone(), imm),
+            Instruction::ShiftRight(ref reg, imm) => Instruction::ShiftRight(reg.clone(), imm),
+            Instruction::MovReg(ref reg_dst, ref reg_src) => {
+                Instruction::MovReg(reg_dst.clone(), reg_src.clone())
+            }
+
+            Instruction::MovF64x8(dst, src) => Instruction::MovF64x8(z(dst), z(src)),
+            Instruction::MovF64x2(dst, src) => Instruction::MovF64x2(x(dst), x(src)),
+            Instruction::MovI32x8(dst, src) => Instruction::MovI32x8(y(dst), y(src)),
+
+            Instruction::LoadF64x8(dst, ref reg, imm) => {
+                Instruction::LoadF64x8(z(dst), reg.clone(), imm)
+            }
+            Instruction::LoadF64x1(dst, ref reg, imm) => {
+                Instruction::LoadF64x1(x(dst), reg.clone(), imm)
+            }
+            Instruction::LoadI32x8(dst, ref reg, imm) => {
+                Instruction::LoadI32x8(y(dst), reg.clone(), imm)
+            }
+            Instruction::StoreF64x8(ref reg, imm, src) => {
+                Instruction::StoreF64x8(reg.clone(), imm, z(src))
+            }
+            Instruction::StoreF64x1(ref reg, imm, src) => {
+                Instruction::StoreF64x1(reg.clone(), imm, x(src))
+            }
+            Instruction::GatherF64x8(dst, ref reg, idx, k) => {
+                Instruction::GatherF64x8(z(dst), reg.clone(), y(idx), k)
+            }
+            Instruction::Prefetch(prefetch_type, ref reg, imm) => {
+                Instruction::Prefetch(prefetch_type, reg.clone(), imm)
+            }
+
+            Instruction::AddF64x8(dst, src0, src1) => {
+                Instruction::AddF64x8(z(dst), z(src0), z(src1))
+            }
+            Instruction::AddF64x4(dst, src0, src1) => {
+                Instruction::AddF64x4(y(dst), y(src0), y(src1))
+            }
+            Instruction::AddF64x2(dst, src0, src1) => {
+                Instruction::AddF64x2(x(dst), x(src0), x(src1))
+            }
+            Instruction::AddF64x1(dst, src0, src1) => {
+                Instruction::AddF64x1(x(dst), x(src0), x(src1))
+            }
+            Instruction::LoadAddF64x8(dst, src0, ref reg, imm) => {
+                Instruction::LoadAddF64x8(z(dst), z(src0), reg.clone(), imm)
+            }
+            Instruction::LoadAddF64x1(dst, src0, ref reg, imm) => {
+                Instruction::LoadAddF64x1(x(dst), x(src0), reg.clone(), imm)
+            }
+            Instruction::MulF64x8(dst, src0, src1) => {
+                Instruction::MulF64x8(z(dst), z(src0), z(src1))
+            }
+            Instruction::MulF64x1(dst, src0, src1) => {
+                Instruction::MulF64x1(x(dst), x(src0), x(src1))
+            }
+            Instruction::LoadMulF64x8(dst, src0, ref reg, imm) => {
+                Instruction::LoadMulF64x8(z(dst), z(src0), reg.clone(), imm)
+            }
+            Instruction::LoadMulF64x1(dst, src0, ref reg, imm) => {
+                Instruction::LoadMulF64x1(x(dst), x(src0), reg.clone(), imm)
+            }
+            Instruction::MulAddF64x8(dst, src0, src1) => {
+                Instruction::MulAddF64x8(z(dst), z(src0), z(src1))
+            }
+            Instruction::MulAddF64x1(dst, src0, src1) => {
+                Instruction::MulAddF64x1(x(dst), x(src0), x(src1))
+            }
+            Instruction::LoadMulAddF64x8(dst, src0, ref reg, imm) => {
+                Instruction::LoadMulAddF64x8(z(dst), z(src0), reg.clone(), imm)
+            }
+            Instruction::LoadMulAddF64x1(dst, src0, ref reg, imm) => {
+                Instruction::LoadMulAddF64x1(x(dst), x(src0), reg.clone(), imm)
+            }
+            Instruction::NMulSubF64x8(dst, src0, src1) => {
+                Instruction::NMulSubF64x8(z(dst), z(src0), z(src1))
+            }
+            Instruction::LoadNMulSubF64x8(dst, src0, ref reg, imm) => {
+                Instruction::LoadNMulSubF64x8(z(dst), z(src0), reg.clone(), imm)
+            }
+            Instruction::DivF64x8(dst, src0, src1) => {
+                Instruction::DivF64x8(z(dst), z(src0), z(src1))
+            }
+            Instruction::DivF64x1(dst, src0, src1) => {
+                Instruction::DivF64x1(x(dst), x(src0), x(src1))
+            }
+            Instruction::LoadDivF64x8(dst, src0, ref reg, imm) => {
+                Instruction::LoadDivF64x8(z(dst), z(src0), reg.clone(), imm)
+            }
+            Instruction::LoadDivF64x1(dst, src0, ref reg, imm) => {
+                Instruction::LoadDivF64x1(x(dst), x(src0), reg.clone(), imm)
+            }
+
+            Instruction::LUMix4F64x8(dst, src0, src1) => {
+                Instruction::LUMix4F64x8(z(dst), y(src0), z(src1))
+            }
+            Instruction::ULMix4F64x8(dst, src0, src1) => {
+                Instruction::ULMix4F64x8(z(dst), z(src0), z(src1))
+            }
+            Instruction::Mix2F64x8Mask(dst, src, k) => {
+                Instruction::Mix2F64x8Mask(z(dst), z(src), k)
+            }
+            Instruction::LUMix1F64x8(dst, src0, src1) => {
+                Instruction::LUMix1F64x8(z(dst), z(src0), z(src1))
+            }
+            Instruction::ULMix1F64x8(dst, src0, src1) => {
+                Instruction::ULMix1F64x8(z(dst), z(src0), z(src1))
+            }
+            Instruction::ExtractU4F64x8(dst, src) => Instruction::ExtractU4F64x8(y(dst), z(src)),
+            Instruction::ExtractU2F64x4(dst, src) => Instruction::ExtractU2F64x4(x(dst), y(src)),
+            Instruction::Fold1AddF64x2(dst, src) => Instruction::Fold1AddF64x2(x(dst), x(src)),
+        }
+    }
+}
+
 impl Assembly {
     pub fn new() -> Self {
         Assembly {
             arr: Vec::new(),
             var_asms: Vec::new(),
-            zmm_used: [false; 32],
-            k_used: [false; 4],
+            operands: Vec::new(),
+        }
+    }
+
+    pub(crate) fn instructions(&self) -> &[Instruction] {
+        &self.arr
+    }
+
+    pub(crate) fn operands(&self) -> &[gas::Operand] {
+        &self.operands
+    }
+
+    // a generator declares the general purpose registers of its kernel: the
+    // arguments in C parameter order, then the counters and copies it sets
+    // up itself. `gas::Function` and `Emulator` take them from here, so
+    // their callers only bind the arguments.
+    pub fn argument_int(self, name: &str) -> Self {
+        self.operand(gas::Operand::argument(name, Width::Int))
+    }
+
+    pub fn argument_pointer(self, name: &str) -> Self {
+        self.operand(gas::Operand::argument(name, Width::Pointer))
+    }
+
+    pub fn local_int(self, name: &str) -> Self {
+        self.operand(gas::Operand::local(name, Width::Int))
+    }
+
+    pub fn local_pointer(self, name: &str) -> Self {
+        self.operand(gas::Operand::local(name, Width::Pointer))
+    }
+
+    // local initialized with the value of the operand `src`
+    pub fn local_copy(self, name: &str, src: &str) -> Self {
+        let operand = gas::Operand::copy(&self.operands, name, src);
+        self.operand(operand)
+    }
+
+    // the first declaration of a name wins, so that fragments may declare
+    // the operands they use again
+    fn operand(mut self, operand: gas::Operand) -> Self {
+        if !self.operands.iter().any(|o| o.name == operand.name) {
+            self.operands.push(operand);
+        }
+        self
+    }
+
+    // every vector register and mask the instructions write. spilled
+    // registers live in memory and are left out.
+    pub fn clobbers(&self) -> Vec<Register> {
+        let mut zmm_written = [false; N_REGISTERS];
+        let mut k_written = [false; 7];
+        for operand in self.arr.iter().flat_map(|inst| inst.defs()) {
+            match operand {
+                Operand::Vector(i) => zmm_written[i as usize] = true,
+                Operand::Mask(i) => k_written[(i - 1) as usize] = true,
+                Operand::Gpr(_) | Operand::Flags => {}
+            }
+        }
+
+        let iter_zmm = (0..32)
+            .filter(|&i| zmm_written[i])
+            .map(|i| Register::Zmm(Zmm::new(i as u8)));
+        let iter_k = (0..7)
+            .filter(|&i| k_written[i])
+            .map(|i| Register::Mask(Mask::new(i as u8 + 1)));
+        iter_zmm.chain(iter_k).collect()
+    }
+
+    // checks a whole kernel for registers read before they are written on
+    // some path and writes nobody reads. the clobber list of `print` is
+    // `clobbers`, so it cannot leave a written register out.
+    pub fn verify(&self) -> Result<(), Vec<VerifyError>> {
+        let errors = liveness::verify(&self.arr);
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
         }
     }
 
-    pub fn var_asm(mut self, var: &'static str, asm: &'static str) -> Self {
-        self.var_asms.push((var, asm));
+    // runs the peephole optimizations of `peephole` on a whole kernel
+    pub fn peephole(mut self) -> (Self, PeepholeReport) {
+        let (arr, report) = peephole::optimize(self.arr);
+        self.arr = arr;
+        (self, report)
+    }
+
+    // spilled vector registers of the kernel, each counted once
+    pub fn n_spilled(&self) -> usize {
+        let mut is_spilled = [false; N_REGISTERS];
+        let operands = self
+            .arr
+            .iter()
+            .flat_map(|inst| inst.uses().into_iter().chain(inst.defs()));
+        for operand in operands {
+            match operand {
+                Operand::Vector(i) if i >= N_PHYSICAL_REGISTERS => is_spilled[i as usize] = true,
+                _ => {}
+            }
+        }
+        is_spilled.iter().filter(|&&x| x).count()
+    }
+
+    // rewrites the accesses to spilled registers of a whole kernel into
+    // reloads and stores of 64-byte slots from `base_name` on, which must not
+    // move. fails with `MovingSpillArea` when it does, and with
+    // `RegisterOverflow` when no physical register is free around such an
+    // access.
+    pub fn spill(mut self, base_name: &str) -> Result<(Self, SpillReport), GenerateError> {
+        let (arr, report) = spill::spill(self.arr, Gpr::new(base_name))?;
+        self.arr = arr;
+        Ok((self, report))
+    }
+
+    // puts `prefix` in front of every label and jump target, so that kernels
+    // with the same labels can go into one translation unit
+    pub fn prefix_labels(mut self, prefix: &str) -> Self {
+        for inst in self.arr.iter_mut() {
+            match inst {
+                Instruction::Label(label) | Instruction::JumpNotZero(label) => {
+                    *label = name::prefixed(prefix, label)
+                }
+                _ => {}
+            }
+        }
         self
     }
 
-    pub fn print(
+    // reorders independent instructions inside straight-line code for KNL;
+    // labels, jumps, comments and nops stay in place
+    pub fn schedule(mut self) -> Self {
+        self.arr = schedule::schedule(self.arr);
+        self
+    }
+
+    // the local label each label and jump is printed as, by position: labels
+    // are numbered in the order they are defined, and a jump goes to the
+    // definition before (`1b`) or after it (`1f`). an assembler takes a local
+    // label more than once, so that kernels with the same labels can go into
+    // one translation unit, or one kernel can be inlined more than once.
+    // jumps to labels the kernel does not define keep the name.
+    pub(crate) fn local_labels(&self) -> Vec<Option<String>> {
+        let mut labels: HashMap<&str, (usize, usize)> = HashMap::new();
+        for (i, inst) in self.arr.iter().enumerate() {
+            if let Instruction::Label(name) = inst {
+                let n = labels.len() + 1;
+                labels.entry(&**name).or_insert((n, i));
+            }
+        }
+
+        self.arr
+            .iter()
+            .enumerate()
+            .map(|(i, inst)| match inst {
+                Instruction::Label(name) => Some(format!("{}:", labels[&**name].0)),
+                Instruction::JumpNotZero(label) => match labels.get(&**label) {
+                    Some(&(n, at)) if at < i => Some(format!("jnz {}b", n)),
+                    Some(&(n, _)) => Some(format!("jnz {}f", n)),
+                    None => None,
+                },
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn var_asm(mut self, var: &str, asm: &str) -> Self {
+        self.var_asms.push((Name::from(var), Name::from(asm)));
+        self
+    }
+
+    pub fn print(&self, tab: usize, variable_names: &[&str], asm_names: &[&str]) -> String {
+        let operands: Vec<(String, &str)> = variable_names
+            .iter()
+            .zip(asm_names.iter())
+            .map(|(var, asm)| (var.to_string(), *asm))
+            .collect();
+        self.print_bound(tab, &operands, &[], &[], &[])
+    }
+
+    // `print` with the named operands in `registers` written as the general
+    // purpose registers they are bound to, such as `%%r10d`, instead of
+    // taken from C variables. `prologue` goes in front of the instructions,
+    // and `gprs` are clobbered as well.
+    pub(crate) fn print_bound(
         &self,
         tab: usize,
-        variable_names: &[&'static str],
-        asm_names: &[&'static str],
+        operands: &[(String, &str)],
+        registers: &[(&str, String)],
+        prologue: &[String],
+        gprs: &[&str],
     ) -> String {
         let tab = "    ".repeat(tab);
         let mut output = String::new();
 
         write!(output, "{}asm volatile(\n", tab).unwrap();
+        for asm in prologue.iter() {
+            write!(output, "{}\" {:48} \\t\\n\"\n", tab, asm).unwrap();
+        }
 
-        for inst in self.arr.iter() {
-            let asm = inst.to_string();
-            match asm.as_str() {
-                "" => write!(output, "\n").unwrap(),
-                asm if is_comment(asm) => write!(output, "{}{:48}\n", tab, asm).unwrap(),
-                _ => write!(output, "{}\" {:48} \\t\\n\"\n", tab, asm).unwrap(),
+        let local_labels = self.local_labels();
+        for (inst, local) in self.arr.iter().zip(local_labels) {
+            let (asm, name) = match (inst, local) {
+                (Instruction::Label(name) | Instruction::JumpNotZero(name), Some(local)) => {
+                    (local, Some(name))
+                }
+                _ => (inst.to_string(), None),
+            };
+            let asm = registers.iter().fold(asm, |asm, (name, reg)| {
+                asm.replace(&format!("%[{}]", name), reg)
+            });
+            match (asm.as_str(), name) {
+                ("", _) => write!(output, "\n").unwrap(),
+                (asm, _) if is_comment(asm) => write!(output, "{}{:48}\n", tab, asm).unwrap(),
+                (asm, Some(name)) => {
+                    write!(output, "{}\" {:48} \\t\\n\" // {}\n", tab, asm, name).unwrap()
+                }
+                (asm, None) => write!(output, "{}\" {:48} \\t\\n\"\n", tab, asm).unwrap(),
             }
         }
 
-        let output_operands = variable_names
+        let output_operands = operands
             .iter()
-            .zip(asm_names.iter())
-            .map(|(var, asm)| format!("[{}]\"+r\"({})", *asm, *var))
+            .map(|(var, asm)| format!("[{}]\"+r\"({})", *asm, var))
             .reduce(|mut acc, x| {
                 acc.reserve(x.len() + 2);
                 acc.push_str(", ");
@@ -508,14 +991,12 @@ impl Assembly {
 
         write!(output, "{}:\n", tab).unwrap();
 
-        let iter_zmm = (0..32)
-            .filter(|i| self.zmm_used[*i])
-            .map(|i| format!("\"zmm{}\"", i));
-        let iter_k = (0..4)
-            .filter(|i| self.k_used[*i])
-            .map(|i| format!("\"k{}\"", i + 1));
-        let clobbers = iter_zmm
-            .chain(iter_k)
+        let iter_gpr = gprs.iter().map(|reg| format!("\"{}\"", reg));
+        let clobbers = self
+            .clobbers()
+            .iter()
+            .map(|reg| format!("\"{}\"", reg))
+            .chain(iter_gpr)
             .reduce(|mut acc, x| {
                 acc.reserve(x.len() + 2);
                 acc.push_str(", ");
@@ -533,24 +1014,20 @@ impl Assembly {
         let mut other = other;
         self.arr.append(&mut other.arr);
         self.var_asms.append(&mut other.var_asms);
-
-        for i in 0..32 {
-            self.zmm_used[i] = self.zmm_used[i] | other.zmm_used[i];
-        }
-        for i in 0..4 {
-            self.k_used[i] = self.k_used[i] | other.k_used[i];
+        for operand in other.operands {
+            self = self.operand(operand);
         }
 
         self
     }
 
     pub fn empty_line(mut self) -> Self {
-        self.arr.push(Instruction::Comment(""));
+        self.arr.push(Instruction::Comment(Name::from("")));
         self
     }
 
-    pub fn comment(mut self, comment: &'static str) -> Self {
-        self.arr.push(Instruction::Comment(comment));
+    pub fn comment(mut self, comment: &str) -> Self {
+        self.arr.push(Instruction::Comment(Name::from(comment)));
         self
     }
 
@@ -561,351 +1038,347 @@ impl Assembly {
         self
     }
 
-    pub fn label(mut self, name: &'static str) -> Self {
-        self.arr.push(Instruction::Label(name));
+    pub fn label(mut self, name: &str) -> Self {
+        self.arr.push(Instruction::Label(Name::from(name)));
         self
     }
 
-    pub fn jump_nz(mut self, name: &'static str) -> Self {
-        self.arr.push(Instruction::JumpNotZero(name));
+    pub fn jump_nz(mut self, name: &str) -> Self {
+        self.arr.push(Instruction::JumpNotZero(Name::from(name)));
         self
     }
 
-    pub fn mask_on(mut self, k: u8) -> Self {
+    pub fn mask_on(mut self, k: Mask) -> Self {
         self.arr.push(Instruction::MaskOn(k));
-        self.k_used[(k - 1) as usize] = true;
         self
     }
 
-    pub fn add_immediate(mut self, reg_name: &'static str, imm: i16) -> Self {
-        self.arr.push(Instruction::AddImmediate(reg_name, imm));
+    pub fn add_immediate(mut self, reg_name: &str, imm: i16) -> Self {
+        self.arr
+            .push(Instruction::AddImmediate(Gpr::new(reg_name), imm));
         self
     }
 
-    pub fn sub_immediate(mut self, reg_name: &'static str, imm: i16) -> Self {
-        self.arr.push(Instruction::SubImmediate(reg_name, imm));
+    pub fn sub_immediate(mut self, reg_name: &str, imm: i16) -> Self {
+        self.arr
+            .push(Instruction::SubImmediate(Gpr::new(reg_name), imm));
         self
     }
 
-    pub fn set_immediate(mut self, reg_name: &'static str, imm: i16) -> Self {
-        self.arr.push(Instruction::SetImmediate(reg_name, imm));
+    pub fn set_immediate(mut self, reg_name: &str, imm: i16) -> Self {
+        self.arr
+            .push(Instruction::SetImmediate(Gpr::new(reg_name), imm));
         self
     }
 
-    pub fn shift_right(mut self, reg_name: &'static str, imm: u8) -> Self {
-        self.arr.push(Instruction::ShiftRight(reg_name, imm));
+    pub fn shift_right(mut self, reg_name: &str, imm: u8) -> Self {
+        self.arr
+            .push(Instruction::ShiftRight(Gpr::new(reg_name), imm));
         self
     }
 
-    pub fn move_reg(mut self, reg_dst: &'static str, reg_src: &'static str) -> Assembly {
-        self.arr.push(Instruction::MovReg(reg_dst, reg_src));
+    pub fn move_reg(mut self, reg_dst: &str, reg_src: &str) -> Assembly {
+        self.arr
+            .push(Instruction::MovReg(Gpr::new(reg_dst), Gpr::new(reg_src)));
         self
     }
 
-    pub fn move_f64x8(mut self, zmm_dst: u8, zmm_src: u8) -> Assembly {
+    pub fn move_f64x8(mut self, zmm_dst: Zmm, zmm_src: Zmm) -> Assembly {
         self.arr.push(Instruction::MovF64x8(zmm_dst, zmm_src));
-        self.zmm_used[zmm_dst as usize] = true;
         self
     }
 
-    pub fn move_f64x2(mut self, xmm_dst: u8, xmm_src: u8) -> Assembly {
+    pub fn move_f64x2(mut self, xmm_dst: Xmm, xmm_src: Xmm) -> Assembly {
         self.arr.push(Instruction::MovF64x2(xmm_dst, xmm_src));
-        self.zmm_used[xmm_dst as usize] = true;
         self
     }
 
-    pub fn move_i32x8(mut self, ymm_dst: u8, ymm_src: u8) -> Assembly {
+    pub fn move_i32x8(mut self, ymm_dst: Ymm, ymm_src: Ymm) -> Assembly {
         self.arr.push(Instruction::MovI32x8(ymm_dst, ymm_src));
-        self.zmm_used[ymm_dst as usize] = true;
         self
     }
 
-    pub fn load_f64x8(mut self, zmm: u8, reg_name: &'static str, base: i16) -> Self {
-        self.arr.push(Instruction::LoadF64x8(zmm, reg_name, base));
-        self.zmm_used[zmm as usize] = true;
+    pub fn load_f64x8(mut self, zmm: Zmm, reg_name: &str, base: i16) -> Self {
+        self.arr
+            .push(Instruction::LoadF64x8(zmm, Gpr::new(reg_name), base));
         self
     }
 
-    pub fn load_f64x1(mut self, xmm: u8, reg_name: &'static str, base: i16) -> Self {
-        self.arr.push(Instruction::LoadF64x1(xmm, reg_name, base));
-        self.zmm_used[xmm as usize] = true;
+    pub fn load_f64x1(mut self, xmm: Xmm, reg_name: &str, base: i16) -> Self {
+        self.arr
+            .push(Instruction::LoadF64x1(xmm, Gpr::new(reg_name), base));
         self
     }
 
-    pub fn load_i32x8(mut self, ymm: u8, reg_name: &'static str, base: i16) -> Self {
-        assert!(ymm < 16, "VEX instruction can only use ymm less than 16");
-        self.arr.push(Instruction::LoadI32x8(ymm, reg_name, base));
-        self.zmm_used[ymm as usize] = true;
+    pub fn load_i32x8(mut self, ymm: Ymm, reg_name: &str, base: i16) -> Self {
+        assert!(
+            ymm.index() < 16,
+            "VEX instruction can only use ymm less than 16"
+        );
+        self.arr
+            .push(Instruction::LoadI32x8(ymm, Gpr::new(reg_name), base));
         self
     }
 
-    pub fn store_f64x8(mut self, reg_name: &'static str, base: i16, zmm: u8) -> Self {
-        self.arr.push(Instruction::StoreF64x8(reg_name, base, zmm));
+    pub fn store_f64x8(mut self, reg_name: &str, base: i16, zmm: Zmm) -> Self {
+        self.arr
+            .push(Instruction::StoreF64x8(Gpr::new(reg_name), base, zmm));
         self
     }
 
-    pub fn store_f64x1(mut self, reg_name: &'static str, base: i16, xmm: u8) -> Self {
-        self.arr.push(Instruction::StoreF64x1(reg_name, base, xmm));
+    pub fn store_f64x1(mut self, reg_name: &str, base: i16, xmm: Xmm) -> Self {
+        self.arr
+            .push(Instruction::StoreF64x1(Gpr::new(reg_name), base, xmm));
         self
     }
 
-    pub fn gather_f64x8(mut self, zmm: u8, reg_name: &'static str, ymm_idx: u8, k: u8) -> Self {
+    pub fn gather_f64x8(mut self, zmm: Zmm, reg_name: &str, ymm_idx: Ymm, k: Mask) -> Self {
         assert!(
-            zmm != ymm_idx,
+            zmm != ymm_idx.zmm(),
             "Operands `dst` and `src_idx` of VGATHERDPD must be different."
         );
-        self.arr
-            .push(Instruction::GatherF64x8(zmm, reg_name, ymm_idx, k));
-        self.zmm_used[zmm as usize] = true;
+        self.arr.push(Instruction::GatherF64x8(
+            zmm,
+            Gpr::new(reg_name),
+            ymm_idx,
+            k,
+        ));
         self
     }
 
-    pub fn prefetch(
-        mut self,
-        prefetch_type: PrefetchType,
-        reg_name: &'static str,
-        base: i16,
-    ) -> Self {
-        self.arr
-            .push(Instruction::Prefetch(prefetch_type, reg_name, base));
+    pub fn prefetch(mut self, prefetch_type: PrefetchType, reg_name: &str, base: i16) -> Self {
+        self.arr.push(Instruction::Prefetch(
+            prefetch_type,
+            Gpr::new(reg_name),
+            base,
+        ));
         self
     }
 
-    pub fn add_f64x8(mut self, zmm_dst: u8, zmm_src0: u8, zmm_src1: u8) -> Assembly {
+    pub fn add_f64x8(mut self, zmm_dst: Zmm, zmm_src0: Zmm, zmm_src1: Zmm) -> Assembly {
         self.arr
             .push(Instruction::AddF64x8(zmm_dst, zmm_src0, zmm_src1));
-        self.zmm_used[zmm_dst as usize] = true;
         self
     }
 
-    pub fn add_f64x1(mut self, xmm_dst: u8, xmm_src0: u8, xmm_src1: u8) -> Assembly {
+    pub fn add_f64x1(mut self, xmm_dst: Xmm, xmm_src0: Xmm, xmm_src1: Xmm) -> Assembly {
         self.arr
             .push(Instruction::AddF64x1(xmm_dst, xmm_src0, xmm_src1));
-        self.zmm_used[xmm_dst as usize] = true;
         self
     }
 
     pub fn loadadd_f64x8(
         mut self,
-        zmm_dst: u8,
-        zmm_src0: u8,
-        reg_name_src1: &'static str,
+        zmm_dst: Zmm,
+        zmm_src0: Zmm,
+        reg_name_src1: &str,
         base_src1: i16,
     ) -> Assembly {
         self.arr.push(Instruction::LoadAddF64x8(
             zmm_dst,
             zmm_src0,
-            reg_name_src1,
+            Gpr::new(reg_name_src1),
             base_src1,
         ));
-        self.zmm_used[zmm_dst as usize] = true;
         self
     }
 
     pub fn loadadd_f64x1(
         mut self,
-        xmm_dst: u8,
-        xmm_src0: u8,
-        reg_name_src1: &'static str,
+        xmm_dst: Xmm,
+        xmm_src0: Xmm,
+        reg_name_src1: &str,
         base_src1: i16,
     ) -> Assembly {
         self.arr.push(Instruction::LoadAddF64x1(
             xmm_dst,
             xmm_src0,
-            reg_name_src1,
+            Gpr::new(reg_name_src1),
             base_src1,
         ));
-        self.zmm_used[xmm_dst as usize] = true;
         self
     }
 
-    pub fn mul_f64x8(mut self, zmm_dst: u8, zmm_src0: u8, zmm_src1: u8) -> Assembly {
+    pub fn mul_f64x8(mut self, zmm_dst: Zmm, zmm_src0: Zmm, zmm_src1: Zmm) -> Assembly {
         self.arr
             .push(Instruction::MulF64x8(zmm_dst, zmm_src0, zmm_src1));
-        self.zmm_used[zmm_dst as usize] = true;
         self
     }
 
-    pub fn mul_f64x1(mut self, xmm_dst: u8, xmm_src0: u8, xmm_src1: u8) -> Assembly {
+    pub fn mul_f64x1(mut self, xmm_dst: Xmm, xmm_src0: Xmm, xmm_src1: Xmm) -> Assembly {
         self.arr
             .push(Instruction::MulF64x1(xmm_dst, xmm_src0, xmm_src1));
-        self.zmm_used[xmm_dst as usize] = true;
         self
     }
 
     pub fn loadmul_f64x8(
         mut self,
-        zmm_dst: u8,
-        zmm_src0: u8,
-        reg_name_src1: &'static str,
+        zmm_dst: Zmm,
+        zmm_src0: Zmm,
+        reg_name_src1: &str,
         base_src1: i16,
     ) -> Assembly {
         self.arr.push(Instruction::LoadMulF64x8(
             zmm_dst,
             zmm_src0,
-            reg_name_src1,
+            Gpr::new(reg_name_src1),
             base_src1,
         ));
-        self.zmm_used[zmm_dst as usize] = true;
         self
     }
 
     pub fn loadmul_f64x1(
         mut self,
-        xmm_dst: u8,
-        xmm_src0: u8,
-        reg_name_src1: &'static str,
+        xmm_dst: Xmm,
+        xmm_src0: Xmm,
+        reg_name_src1: &str,
         base_src1: i16,
     ) -> Assembly {
         self.arr.push(Instruction::LoadMulF64x1(
             xmm_dst,
             xmm_src0,
-            reg_name_src1,
+            Gpr::new(reg_name_src1),
             base_src1,
         ));
-        self.zmm_used[xmm_dst as usize] = true;
         self
     }
 
-    pub fn muladd_f64x8(mut self, zmm_dst: u8, zmm_src0: u8, zmm_src1: u8) -> Assembly {
+    pub fn muladd_f64x8(mut self, zmm_dst: Zmm, zmm_src0: Zmm, zmm_src1: Zmm) -> Assembly {
         self.arr
             .push(Instruction::MulAddF64x8(zmm_dst, zmm_src0, zmm_src1));
-        self.zmm_used[zmm_dst as usize] = true;
         self
     }
 
-    pub fn muladd_f64x1(mut self, xmm_dst: u8, xmm_src0: u8, xmm_src1: u8) -> Assembly {
+    pub fn muladd_f64x1(mut self, xmm_dst: Xmm, xmm_src0: Xmm, xmm_src1: Xmm) -> Assembly {
         self.arr
             .push(Instruction::MulAddF64x1(xmm_dst, xmm_src0, xmm_src1));
-        self.zmm_used[xmm_dst as usize] = true;
         self
     }
 
     pub fn loadmuladd_f64x8(
         mut self,
-        zmm_dst: u8,
-        zmm_src0: u8,
-        reg_src1: &'static str,
+        zmm_dst: Zmm,
+        zmm_src0: Zmm,
+        reg_src1: &str,
         base_src1: i16,
     ) -> Assembly {
         self.arr.push(Instruction::LoadMulAddF64x8(
-            zmm_dst, zmm_src0, reg_src1, base_src1,
+            zmm_dst,
+            zmm_src0,
+            Gpr::new(reg_src1),
+            base_src1,
         ));
-        self.zmm_used[zmm_dst as usize] = true;
         self
     }
 
     pub fn loadmuladd_f64x1(
         mut self,
-        xmm_dst: u8,
-        xmm_src0: u8,
-        reg_src1: &'static str,
+        xmm_dst: Xmm,
+        xmm_src0: Xmm,
+        reg_src1: &str,
         base_src1: i16,
     ) -> Assembly {
         self.arr.push(Instruction::LoadMulAddF64x1(
-            xmm_dst, xmm_src0, reg_src1, base_src1,
+            xmm_dst,
+            xmm_src0,
+            Gpr::new(reg_src1),
+            base_src1,
         ));
-        self.zmm_used[xmm_dst as usize] = true;
         self
     }
 
-    pub fn nmulsub_f64x8(mut self, zmm_dst: u8, zmm_src0: u8, zmm_src1: u8) -> Assembly {
+    pub fn nmulsub_f64x8(mut self, zmm_dst: Zmm, zmm_src0: Zmm, zmm_src1: Zmm) -> Assembly {
         self.arr
             .push(Instruction::NMulSubF64x8(zmm_dst, zmm_src0, zmm_src1));
-        self.zmm_used[zmm_dst as usize] = true;
         self
     }
 
     pub fn loadnmulsub_f64x8(
         mut self,
-        zmm_dst: u8,
-        zmm_src0: u8,
-        reg_src1: &'static str,
+        zmm_dst: Zmm,
+        zmm_src0: Zmm,
+        reg_src1: &str,
         base_src1: i16,
     ) -> Assembly {
         self.arr.push(Instruction::LoadNMulSubF64x8(
-            zmm_dst, zmm_src0, reg_src1, base_src1,
+            zmm_dst,
+            zmm_src0,
+            Gpr::new(reg_src1),
+            base_src1,
         ));
-        self.zmm_used[zmm_dst as usize] = true;
         self
     }
 
-    pub fn div_f64x8(mut self, zmm_dst: u8, zmm_src0: u8, zmm_src1: u8) -> Assembly {
+    pub fn div_f64x8(mut self, zmm_dst: Zmm, zmm_src0: Zmm, zmm_src1: Zmm) -> Assembly {
         self.arr
             .push(Instruction::DivF64x8(zmm_dst, zmm_src0, zmm_src1));
-        self.zmm_used[zmm_dst as usize] = true;
         self
     }
 
-    pub fn div_f64x1(mut self, xmm_dst: u8, xmm_src0: u8, xmm_src1: u8) -> Assembly {
+    pub fn div_f64x1(mut self, xmm_dst: Xmm, xmm_src0: Xmm, xmm_src1: Xmm) -> Assembly {
         self.arr
             .push(Instruction::DivF64x1(xmm_dst, xmm_src0, xmm_src1));
-        self.zmm_used[xmm_dst as usize] = true;
         self
     }
 
     pub fn loaddiv_f64x8(
         mut self,
-        zmm_dst: u8,
-        zmm_src0: u8,
-        reg_name_src1: &'static str,
+        zmm_dst: Zmm,
+        zmm_src0: Zmm,
+        reg_name_src1: &str,
         base_src1: i16,
     ) -> Assembly {
         self.arr.push(Instruction::LoadDivF64x8(
             zmm_dst,
             zmm_src0,
-            reg_name_src1,
+            Gpr::new(reg_name_src1),
             base_src1,
         ));
-        self.zmm_used[zmm_dst as usize] = true;
         self
     }
 
     pub fn loaddiv_f64x1(
         mut self,
-        xmm_dst: u8,
-        xmm_src0: u8,
-        reg_name_src1: &'static str,
+        xmm_dst: Xmm,
+        xmm_src0: Xmm,
+        reg_name_src1: &str,
         base_src1: i16,
     ) -> Assembly {
         self.arr.push(Instruction::LoadDivF64x1(
             xmm_dst,
             xmm_src0,
-            reg_name_src1,
+            Gpr::new(reg_name_src1),
             base_src1,
         ));
-        self.zmm_used[xmm_dst as usize] = true;
         self
     }
 
-    pub fn mix4add_f64x8(mut self, zmm_dst: u8, zmm_src0: u8, zmm_src1: u8) -> Assembly {
+    pub fn mix4add_f64x8(mut self, zmm_dst: Zmm, zmm_src0: Zmm, zmm_src1: Zmm) -> Assembly {
         self.arr
-            .push(Instruction::LUMix4F64x8(zmm_dst, zmm_src0, zmm_src1));
+            .push(Instruction::LUMix4F64x8(zmm_dst, zmm_src0.ymm(), zmm_src1));
         self.arr
             .push(Instruction::ULMix4F64x8(zmm_src1, zmm_src0, zmm_src1));
         self.arr
             .push(Instruction::AddF64x8(zmm_dst, zmm_dst, zmm_src1));
-        self.zmm_used[zmm_dst as usize] = true;
-        self.zmm_used[zmm_src1 as usize] = true;
         self
     }
 
-    pub fn init_mix2mask(mut self, reg_name: &'static str, mask0: u8, mask1: u8) -> Assembly {
-        self.arr.push(Instruction::SetImmediate(reg_name, 0x33));
-        self.arr.push(Instruction::MaskSet(mask0, reg_name));
+    pub fn init_mix2mask(mut self, reg_name: &str, mask0: Mask, mask1: Mask) -> Assembly {
+        self.arr
+            .push(Instruction::SetImmediate(Gpr::new(reg_name), 0x33));
+        self.arr
+            .push(Instruction::MaskSet(mask0, Gpr::new(reg_name)));
         self.arr.push(Instruction::MaskNot(mask1, mask0));
-        self.k_used[(mask0 - 1) as usize] = true;
-        self.k_used[(mask1 - 1) as usize] = true;
         self
     }
 
     pub fn mix2add_f64x8(
         mut self,
-        zmm_dst: u8,
-        zmm_src0: u8,
-        zmm_src1: u8,
-        mask0: u8,
-        mask1: u8,
+        zmm_dst: Zmm,
+        zmm_src0: Zmm,
+        zmm_src1: Zmm,
+        mask0: Mask,
+        mask1: Mask,
     ) -> Assembly {
         self.arr.push(Instruction::MovF64x8(zmm_dst, zmm_src1));
         self.arr
@@ -914,47 +1387,39 @@ impl Assembly {
             .push(Instruction::Mix2F64x8Mask(zmm_src0, zmm_dst, mask1));
         self.arr
             .push(Instruction::AddF64x8(zmm_dst, zmm_src0, zmm_src1));
-        self.zmm_used[zmm_dst as usize] = true;
-        self.zmm_used[zmm_src0 as usize] = true;
-        self.zmm_used[zmm_src1 as usize] = true;
         self
     }
 
-    pub fn mix1add_f64x8(mut self, zmm_dst: u8, zmm_src0: u8, zmm_src1: u8) -> Assembly {
+    pub fn mix1add_f64x8(mut self, zmm_dst: Zmm, zmm_src0: Zmm, zmm_src1: Zmm) -> Assembly {
         self.arr
             .push(Instruction::LUMix1F64x8(zmm_dst, zmm_src0, zmm_src1));
         self.arr
             .push(Instruction::ULMix1F64x8(zmm_src0, zmm_src0, zmm_src1));
         self.arr
             .push(Instruction::AddF64x8(zmm_dst, zmm_dst, zmm_src0));
-        self.zmm_used[zmm_dst as usize] = true;
-        self.zmm_used[zmm_src0 as usize] = true;
         self
     }
 
-    pub fn fold4add_f64x8(mut self, ymm_dst: u8, zmm_src: u8) -> Assembly {
-        assert!(ymm_dst != zmm_src);
+    pub fn fold4add_f64x8(mut self, ymm_dst: Ymm, zmm_src: Zmm) -> Assembly {
+        assert!(ymm_dst.zmm() != zmm_src);
 
         self.arr.push(Instruction::ExtractU4F64x8(ymm_dst, zmm_src));
         self.arr
-            .push(Instruction::AddF64x4(ymm_dst, ymm_dst, zmm_src));
-        self.zmm_used[ymm_dst as usize] = true;
+            .push(Instruction::AddF64x4(ymm_dst, ymm_dst, zmm_src.ymm()));
         self
     }
 
-    pub fn fold2add_f64x4(mut self, xmm_dst: u8, ymm_src: u8) -> Assembly {
-        assert!(xmm_dst != ymm_src);
+    pub fn fold2add_f64x4(mut self, xmm_dst: Xmm, ymm_src: Ymm) -> Assembly {
+        assert!(xmm_dst.ymm() != ymm_src);
 
         self.arr.push(Instruction::ExtractU2F64x4(xmm_dst, ymm_src));
         self.arr
-            .push(Instruction::AddF64x2(xmm_dst, xmm_dst, ymm_src));
-        self.zmm_used[xmm_dst as usize] = true;
+            .push(Instruction::AddF64x2(xmm_dst, xmm_dst, ymm_src.xmm()));
         self
     }
 
-    pub fn fold1add_f64x2(mut self, xmm_dst: u8, xmm_src: u8) -> Assembly {
+    pub fn fold1add_f64x2(mut self, xmm_dst: Xmm, xmm_src: Xmm) -> Assembly {
         self.arr.push(Instruction::Fold1AddF64x2(xmm_dst, xmm_src));
-        self.zmm_used[xmm_dst as usize] = true;
         self
     }
 }
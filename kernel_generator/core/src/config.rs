@@ -0,0 +1,331 @@
+// Flat configurations in JSON and TOML.
+//
+// A configuration is a list of named values, each a boolean, an integer or a
+// string. `fields` and `set` give and take them by name, checked against what
+// each parameter allows, and `Fields` carries them through serde: a
+// configuration derives `Serialize` and `Deserialize` with
+// `#[serde(into = "Fields", try_from = "Fields")]`, and is written as a JSON
+// object or a TOML table in the order of its fields.
+//
+// A file is read on top of a configuration it starts from, so it may leave
+// out what it does not change. It may also keep the values of several kernels
+// in a table each, as `[spmv]` in TOML or `"spmv": { ... }` in JSON, next to
+// values at the top level that all of them take; a file with tables must
+// have the one of the kernel it is read for. Arrays, floats and null are not
+// part of the format.
+
+use serde::de::{self, Deserializer, MapAccess, Unexpected, Visitor};
+use serde::ser::{SerializeMap, Serializer};
+use serde::{Deserialize, Serialize};
+
+use std::fmt;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value {
+    Bool(bool),
+    Int(i64),
+    Str(String),
+}
+
+impl Value {
+    pub fn to_bool(&self, key: &str) -> Result<bool, ConfigError> {
+        match *self {
+            Value::Bool(value) => Ok(value),
+            _ => Err(self.invalid(key, "true or false".to_string())),
+        }
+    }
+
+    pub fn to_int(&self, key: &str, min: i64, max: i64) -> Result<i64, ConfigError> {
+        match *self {
+            Value::Int(value) if min <= value && value <= max => Ok(value),
+            _ => Err(self.invalid(key, format!("an integer from {} to {}", min, max))),
+        }
+    }
+
+    // the position of the string in `choices`
+    pub fn to_choice(&self, key: &str, choices: &[&str]) -> Result<usize, ConfigError> {
+        let position = match self {
+            Value::Str(value) => choices.iter().position(|choice| choice == value),
+            _ => None,
+        };
+        position.ok_or_else(|| self.invalid(key, format!("one of {}", choices.join(", "))))
+    }
+
+    pub fn invalid(&self, key: &str, expected: String) -> ConfigError {
+        ConfigError::InvalidValue {
+            key: key.to_string(),
+            value: self.to_string(),
+            expected,
+        }
+    }
+}
+
+// the value as JSON and TOML write it
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match serde_json::to_string(self) {
+            Ok(text) => write!(f, "{}", text),
+            Err(_) => Err(fmt::Error),
+        }
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Bool(value) => serializer.serialize_bool(*value),
+            Value::Int(value) => serializer.serialize_i64(*value),
+            Value::Str(value) => serializer.serialize_str(value),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a boolean, an integer or a string")
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<Value, E> {
+        Ok(Value::Bool(value))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Value, E> {
+        Ok(Value::Int(value))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Value, E> {
+        match i64::try_from(value) {
+            Ok(value) => Ok(Value::Int(value)),
+            Err(_) => Err(E::invalid_value(Unexpected::Unsigned(value), &self)),
+        }
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Value, E> {
+        Ok(Value::Str(value.to_string()))
+    }
+}
+
+// the named values of a configuration, in the order they are written
+#[derive(Clone, PartialEq, Debug)]
+pub struct Fields(Vec<(String, Value)>);
+
+impl Fields {
+    pub fn of<C: Config>(config: &C) -> Self {
+        Fields(
+            config
+                .fields()
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    // `config` with each value set in turn
+    pub fn apply<C: Config>(self, config: C) -> Result<C, ConfigError> {
+        let mut config = config;
+        for (key, value) in self.0.iter() {
+            config = config.set(key, value)?;
+        }
+
+        Ok(config)
+    }
+}
+
+impl Serialize for Fields {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (key, value) in self.0.iter() {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Fields {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let file = deserializer.deserialize_map(FileVisitor)?;
+        match file.tables.first() {
+            Some((name, _)) => Err(de::Error::custom(ConfigError::UnknownKey(name.clone()))),
+            None => Ok(file.fields),
+        }
+    }
+}
+
+// the values at the top level of a file and its tables, in order
+struct File {
+    fields: Fields,
+    tables: Vec<(String, Fields)>,
+}
+
+impl File {
+    // the values at the top level, then those of the table of `C`
+    fn config<C: Config>(self, config: C) -> Result<C, ConfigError> {
+        let config = self.fields.apply(config)?;
+        let has_tables = !self.tables.is_empty();
+        match self.tables.into_iter().find(|(name, _)| name == C::TABLE) {
+            Some((_, fields)) => fields.apply(config),
+            None if has_tables => Err(ConfigError::MissingTable(C::TABLE)),
+            None => Ok(config),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for File {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(FileVisitor)
+    }
+}
+
+struct FileVisitor;
+
+impl<'de> Visitor<'de> for FileVisitor {
+    type Value = File;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a table of parameters")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<File, A::Error> {
+        let mut file = File {
+            fields: Fields(Vec::new()),
+            tables: Vec::new(),
+        };
+        let mut keys = Vec::new();
+        while let Some(key) = map.next_key::<String>()? {
+            if keys.contains(&key) {
+                return Err(de::Error::custom(ConfigError::DuplicateKey(key)));
+            }
+            keys.push(key.clone());
+            match map.next_value::<Entry>()? {
+                Entry::Value(value) => file.fields.0.push((key, value)),
+                Entry::Table(table) => match table.tables.is_empty() {
+                    true => file.tables.push((key, table.fields)),
+                    false => return Err(de::Error::custom("tables are not nested")),
+                },
+            }
+        }
+
+        Ok(file)
+    }
+}
+
+// a value of a file, or a table of them
+enum Entry {
+    Value(Value),
+    Table(File),
+}
+
+impl<'de> Deserialize<'de> for Entry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(EntryVisitor)
+    }
+}
+
+struct EntryVisitor;
+
+impl<'de> Visitor<'de> for EntryVisitor {
+    type Value = Entry;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a boolean, an integer, a string or a table")
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<Entry, E> {
+        ValueVisitor.visit_bool(value).map(Entry::Value)
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Entry, E> {
+        ValueVisitor.visit_i64(value).map(Entry::Value)
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Entry, E> {
+        ValueVisitor.visit_u64(value).map(Entry::Value)
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Entry, E> {
+        ValueVisitor.visit_str(value).map(Entry::Value)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Entry, A::Error> {
+        FileVisitor.visit_map(map).map(Entry::Table)
+    }
+}
+
+pub trait Config: Serialize + Sized {
+    // the table of the configuration in a file that has one for each kernel
+    const TABLE: &'static str;
+
+    // the named values of the configuration, in the order they are written
+    fn fields(&self) -> Vec<(&'static str, Value)>;
+
+    // the configuration with the value of `key` replaced
+    fn set(self, key: &str, value: &Value) -> Result<Self, ConfigError>;
+
+    fn to_json(&self) -> String {
+        let text = serde_json::to_string_pretty(self);
+        text.expect("configurations are plain values") + "\n"
+    }
+
+    fn to_toml(&self) -> String {
+        toml::to_string(self).expect("configurations are plain values")
+    }
+
+    fn read_json(self, text: &str) -> Result<Self, ConfigError> {
+        let file: File = serde_json::from_str(text).map_err(ConfigError::Json)?;
+        file.config(self)
+    }
+
+    fn read_toml(self, text: &str) -> Result<Self, ConfigError> {
+        let file: File = toml::from_str(text).map_err(ConfigError::Toml)?;
+        file.config(self)
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    // syntax errors, and the values of a file serde fails on
+    Json(serde_json::Error),
+    Toml(toml::de::Error),
+    DuplicateKey(String),
+    UnknownKey(String),
+    InvalidValue {
+        key: String,
+        value: String,
+        expected: String,
+    },
+    // a file with tables for other kernels but not the one it is read for
+    MissingTable(&'static str),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Json(err) => write!(f, "{}", err),
+            Self::Toml(err) => write!(f, "{}", err.to_string().trim_end()),
+            Self::DuplicateKey(key) => write!(f, "`{}` is given more than once", key),
+            Self::UnknownKey(key) => write!(f, "unknown parameter `{}`", key),
+            Self::InvalidValue {
+                key,
+                value,
+                expected,
+            } => write!(
+                f,
+                "invalid value {} for `{}`, expected {}",
+                value, key, expected
+            ),
+            Self::MissingTable(table) => write!(f, "no `{}` table", table),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
@@ -0,0 +1,166 @@
+// Relocatable ELF64 object for x86-64 holding one function.
+//
+// Sections: null, .text, .note.GNU-stack, .symtab, .strtab, .shstrtab.
+// The code has no outside references, so no relocation section is needed.
+
+const EHDR_SIZE: usize = 64;
+const SHDR_SIZE: usize = 64;
+const SYM_SIZE: usize = 24;
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHF_ALLOC: u64 = 0x2;
+const SHF_EXECINSTR: u64 = 0x4;
+
+const TEXT: u16 = 1;
+const STRTAB: u32 = 4;
+const SHSTRTAB: u16 = 5;
+
+pub(crate) fn relocatable(symbol: &str, text: &[u8]) -> Vec<u8> {
+    let shstrtab = b"\0.text\0.note.GNU-stack\0.symtab\0.strtab\0.shstrtab\0";
+    let name = |section: &str| {
+        let pos = shstrtab
+            .windows(section.len() + 1)
+            .position(|w| &w[..section.len()] == section.as_bytes() && w[section.len()] == 0)
+            .unwrap();
+        pos as u32
+    };
+
+    let mut strtab = vec![0];
+    strtab.extend_from_slice(symbol.as_bytes());
+    strtab.push(0);
+
+    // null, .text section symbol, then the only global: the function
+    let mut symtab = vec![0; SYM_SIZE];
+    symtab.extend(sym(0, 0x03, TEXT, 0, 0));
+    symtab.extend(sym(1, 0x12, TEXT, 0, text.len() as u64));
+
+    let text_offset = EHDR_SIZE;
+    let symtab_offset = align(text_offset + text.len(), 8);
+    let strtab_offset = symtab_offset + symtab.len();
+    let shstrtab_offset = strtab_offset + strtab.len();
+    let shdr_offset = align(shstrtab_offset + shstrtab.len(), 8);
+
+    let mut elf = Vec::new();
+
+    // header
+    elf.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+    elf.extend_from_slice(&[0; 8]);
+    elf.extend_from_slice(&1u16.to_le_bytes()); // ET_REL
+    elf.extend_from_slice(&0x3eu16.to_le_bytes()); // EM_X86_64
+    elf.extend_from_slice(&1u32.to_le_bytes());
+    elf.extend_from_slice(&0u64.to_le_bytes()); // entry
+    elf.extend_from_slice(&0u64.to_le_bytes()); // phoff
+    elf.extend_from_slice(&(shdr_offset as u64).to_le_bytes());
+    elf.extend_from_slice(&0u32.to_le_bytes()); // flags
+    elf.extend_from_slice(&(EHDR_SIZE as u16).to_le_bytes());
+    elf.extend_from_slice(&0u16.to_le_bytes()); // phentsize
+    elf.extend_from_slice(&0u16.to_le_bytes()); // phnum
+    elf.extend_from_slice(&(SHDR_SIZE as u16).to_le_bytes());
+    elf.extend_from_slice(&6u16.to_le_bytes());
+    elf.extend_from_slice(&SHSTRTAB.to_le_bytes());
+
+    elf.extend_from_slice(text);
+    elf.resize(symtab_offset, 0);
+    elf.extend_from_slice(&symtab);
+    elf.extend_from_slice(&strtab);
+    elf.extend_from_slice(shstrtab);
+    elf.resize(shdr_offset, 0);
+
+    // section headers
+    elf.extend_from_slice(&[0; SHDR_SIZE]);
+    elf.extend(shdr(
+        name(".text"),
+        SHT_PROGBITS,
+        SHF_ALLOC | SHF_EXECINSTR,
+        text_offset,
+        text.len(),
+        (0, 0),
+        16,
+        0,
+    ));
+    elf.extend(shdr(
+        name(".note.GNU-stack"),
+        SHT_PROGBITS,
+        0,
+        symtab_offset,
+        0,
+        (0, 0),
+        1,
+        0,
+    ));
+    // sh_info is the index of the first global symbol
+    elf.extend(shdr(
+        name(".symtab"),
+        SHT_SYMTAB,
+        0,
+        symtab_offset,
+        symtab.len(),
+        (STRTAB, 2),
+        8,
+        SYM_SIZE,
+    ));
+    elf.extend(shdr(
+        name(".strtab"),
+        SHT_STRTAB,
+        0,
+        strtab_offset,
+        strtab.len(),
+        (0, 0),
+        1,
+        0,
+    ));
+    elf.extend(shdr(
+        name(".shstrtab"),
+        SHT_STRTAB,
+        0,
+        shstrtab_offset,
+        shstrtab.len(),
+        (0, 0),
+        1,
+        0,
+    ));
+
+    elf
+}
+
+fn align(offset: usize, align: usize) -> usize {
+    offset.div_ceil(align) * align
+}
+
+fn sym(name: u32, info: u8, shndx: u16, value: u64, size: u64) -> Vec<u8> {
+    let mut sym = Vec::with_capacity(SYM_SIZE);
+    sym.extend_from_slice(&name.to_le_bytes());
+    sym.push(info);
+    sym.push(0);
+    sym.extend_from_slice(&shndx.to_le_bytes());
+    sym.extend_from_slice(&value.to_le_bytes());
+    sym.extend_from_slice(&size.to_le_bytes());
+    sym
+}
+
+#[allow(clippy::too_many_arguments)]
+fn shdr(
+    name: u32,
+    kind: u32,
+    flags: u64,
+    offset: usize,
+    size: usize,
+    (link, info): (u32, u32),
+    addralign: u64,
+    entsize: usize,
+) -> Vec<u8> {
+    let mut shdr = Vec::with_capacity(SHDR_SIZE);
+    shdr.extend_from_slice(&name.to_le_bytes());
+    shdr.extend_from_slice(&kind.to_le_bytes());
+    shdr.extend_from_slice(&flags.to_le_bytes());
+    shdr.extend_from_slice(&0u64.to_le_bytes()); // addr
+    shdr.extend_from_slice(&(offset as u64).to_le_bytes());
+    shdr.extend_from_slice(&(size as u64).to_le_bytes());
+    shdr.extend_from_slice(&link.to_le_bytes());
+    shdr.extend_from_slice(&info.to_le_bytes());
+    shdr.extend_from_slice(&addralign.to_le_bytes());
+    shdr.extend_from_slice(&(entsize as u64).to_le_bytes());
+    shdr
+}
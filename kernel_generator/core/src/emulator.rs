@@ -0,0 +1,674 @@
+use crate::assembly::Instruction;
+use crate::gas::{self, Source};
+use crate::name::Name;
+use crate::register::{Gpr, Mask, Xmm, Ymm, Zmm};
+use crate::Assembly;
+
+use std::collections::HashMap;
+use std::fmt;
+
+const MEMORY_BASE: u64 = 0x1000_0000;
+const MEMORY_ALIGN: u64 = 64;
+const MEMORY_GUARD: u64 = 0x1000;
+const DEFAULT_STEP_LIMIT: u64 = 100_000_000;
+
+pub struct Memory {
+    regions: Vec<Region>,
+    next_addr: u64,
+}
+
+struct Region {
+    addr: u64,
+    bytes: Vec<u8>,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Memory {
+            regions: Vec::new(),
+            next_addr: MEMORY_BASE,
+        }
+    }
+
+    // every region is 64-byte aligned and followed by an unmapped guard,
+    // so reading past the end of an array faults instead of silently
+    // reading its neighbour.
+    pub fn alloc(&mut self, size: usize) -> u64 {
+        let addr = self.next_addr;
+        self.regions.push(Region {
+            addr,
+            bytes: vec![0; size],
+        });
+
+        let end = addr + size as u64 + MEMORY_GUARD;
+        self.next_addr = end.div_ceil(MEMORY_ALIGN) * MEMORY_ALIGN;
+
+        addr
+    }
+
+    pub fn alloc_f64(&mut self, data: &[f64]) -> u64 {
+        let size = size_of_val(data);
+        let addr = self.alloc(size);
+        let bytes = self.bytes_mut(addr, size).unwrap();
+        for (chunk, x) in bytes.chunks_exact_mut(size_of::<f64>()).zip(data.iter()) {
+            chunk.copy_from_slice(&x.to_le_bytes());
+        }
+        addr
+    }
+
+    pub fn alloc_i32(&mut self, data: &[i32]) -> u64 {
+        let size = size_of_val(data);
+        let addr = self.alloc(size);
+        let bytes = self.bytes_mut(addr, size).unwrap();
+        for (chunk, x) in bytes.chunks_exact_mut(size_of::<i32>()).zip(data.iter()) {
+            chunk.copy_from_slice(&x.to_le_bytes());
+        }
+        addr
+    }
+
+    pub fn read_f64(&self, addr: u64) -> Result<f64, EmulateError> {
+        let bytes = self.bytes(addr, size_of::<f64>())?;
+        Ok(f64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    pub fn read_f64s(&self, addr: u64, len: usize) -> Result<Vec<f64>, EmulateError> {
+        (0..len)
+            .map(|i| self.read_f64(addr + (i * size_of::<f64>()) as u64))
+            .collect()
+    }
+
+    pub fn read_i32(&self, addr: u64) -> Result<i32, EmulateError> {
+        let bytes = self.bytes(addr, size_of::<i32>())?;
+        Ok(i32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    pub fn write_f64(&mut self, addr: u64, x: f64) -> Result<(), EmulateError> {
+        let bytes = self.bytes_mut(addr, size_of::<f64>())?;
+        bytes.copy_from_slice(&x.to_le_bytes());
+        Ok(())
+    }
+
+    fn region_offset(&self, addr: u64, len: usize) -> Result<(usize, usize), EmulateError> {
+        for (i, region) in self.regions.iter().enumerate() {
+            let end = region.addr + region.bytes.len() as u64;
+            if region.addr <= addr && addr + len as u64 <= end {
+                return Ok((i, (addr - region.addr) as usize));
+            }
+        }
+        Err(EmulateError::SegmentationFault(addr))
+    }
+
+    fn bytes(&self, addr: u64, len: usize) -> Result<&[u8], EmulateError> {
+        let (i, offset) = self.region_offset(addr, len)?;
+        Ok(&self.regions[i].bytes[offset..offset + len])
+    }
+
+    fn bytes_mut(&mut self, addr: u64, len: usize) -> Result<&mut [u8], EmulateError> {
+        let (i, offset) = self.region_offset(addr, len)?;
+        Ok(&mut self.regions[i].bytes[offset..offset + len])
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Width {
+    Dword,
+    Qword,
+}
+
+impl Width {
+    fn mask(&self) -> u64 {
+        match self {
+            Width::Dword => u32::MAX as u64,
+            Width::Qword => u64::MAX,
+        }
+    }
+
+    fn sign_extend(&self, value: u64) -> i64 {
+        match self {
+            Width::Dword => value as u32 as i32 as i64,
+            Width::Qword => value as i64,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Variable {
+    value: u64,
+    width: Width,
+}
+
+// Named operands (`%[NAME]`) behave like the C variables they are bound to:
+// `int`s are 32-bit registers and pointers are 64-bit registers.
+pub struct Emulator {
+    memory: Memory,
+    gprs: HashMap<Name, Variable>,
+    zmm: [[u64; 8]; 32],
+    k: [u16; 8],
+    zero_flag: bool,
+    step_limit: u64,
+    n_steps: u64,
+}
+
+impl Emulator {
+    pub fn new(memory: Memory) -> Self {
+        Emulator {
+            memory,
+            gprs: HashMap::new(),
+            zmm: [[0; 8]; 32],
+            k: [0; 8],
+            zero_flag: false,
+            step_limit: DEFAULT_STEP_LIMIT,
+            n_steps: 0,
+        }
+    }
+
+    pub fn int(mut self, name: &str, value: i32) -> Self {
+        let gpr = Variable {
+            value: value as u32 as u64,
+            width: Width::Dword,
+        };
+        self.gprs.insert(Name::from(name), gpr);
+        self
+    }
+
+    pub fn pointer(mut self, name: &str, addr: u64) -> Self {
+        let gpr = Variable {
+            value: addr,
+            width: Width::Qword,
+        };
+        self.gprs.insert(Name::from(name), gpr);
+        self
+    }
+
+    pub fn step_limit(mut self, step_limit: u64) -> Self {
+        self.step_limit = step_limit;
+        self
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    pub fn read_int(&self, name: &str) -> Option<i32> {
+        self.gprs.get(name).map(|gpr| gpr.value as u32 as i32)
+    }
+
+    pub fn read_pointer(&self, name: &str) -> Option<u64> {
+        self.gprs.get(name).map(|gpr| gpr.value)
+    }
+
+    pub fn zmm_f64x8(&self, zmm: Zmm) -> [f64; 8] {
+        self.f64x8(zmm.index())
+    }
+
+    pub fn mask(&self, k: Mask) -> u16 {
+        self.k[k.index() as usize]
+    }
+
+    pub fn n_steps(&self) -> u64 {
+        self.n_steps
+    }
+
+    pub fn run(&mut self, asm: &Assembly) -> Result<(), EmulateError> {
+        self.declare(asm);
+        let insts = asm.instructions();
+
+        let mut labels = HashMap::new();
+        for (i, inst) in insts.iter().enumerate() {
+            if let Instruction::Label(name) = inst {
+                if labels.insert(&**name, i).is_some() {
+                    return Err(EmulateError::DuplicateLabel(name.clone()));
+                }
+            }
+        }
+
+        let mut pc = 0;
+        while pc < insts.len() {
+            self.n_steps += 1;
+            if self.n_steps > self.step_limit {
+                return Err(EmulateError::StepLimitExceeded(self.step_limit));
+            }
+
+            match &insts[pc] {
+                Instruction::JumpNotZero(label) => match self.zero_flag {
+                    true => pc += 1,
+                    false => {
+                        pc = *labels
+                            .get(&**label)
+                            .ok_or_else(|| EmulateError::UndefinedLabel(label.clone()))?
+                    }
+                },
+                inst => {
+                    self.execute(inst)?;
+                    pc += 1;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // binds the locals `asm` declares and the caller left unbound: counters
+    // and pointers start at zero, copies with the value of their source.
+    // arguments must be bound by the caller.
+    fn declare(&mut self, asm: &Assembly) -> () {
+        for operand in asm.operands() {
+            if self.gprs.contains_key(&operand.name) {
+                continue;
+            }
+            let width = match operand.width {
+                gas::Width::Int => Width::Dword,
+                gas::Width::Pointer => Width::Qword,
+            };
+            let gpr = match operand.source {
+                Source::Argument => continue,
+                Source::Uninitialized => Variable { value: 0, width },
+                Source::Copy(ref src) => match self.gprs.get(src) {
+                    Some(gpr) => Variable {
+                        value: gpr.value,
+                        width,
+                    },
+                    None => continue,
+                },
+            };
+            self.gprs.insert(operand.name.clone(), gpr);
+        }
+    }
+
+    fn execute(&mut self, inst: &Instruction) -> Result<(), EmulateError> {
+        match *inst {
+            Instruction::Comment(_) | Instruction::Nop | Instruction::Label(_) => {}
+            Instruction::JumpNotZero(_) => unreachable!(),
+            Instruction::Prefetch(_, Gpr(ref reg_base), _) => {
+                // prefetches never fault, but the base must still be bound.
+                self.gpr(reg_base)?;
+            }
+
+            Instruction::MaskOn(Mask(k)) => self.k[k as usize] = 0xffff,
+            Instruction::MaskSet(Mask(k), Gpr(ref reg_name)) => {
+                self.k[k as usize] = self.gpr(reg_name)?.value as u16
+            }
+            Instruction::MaskNot(Mask(k_dst), Mask(k_src)) => {
+                self.k[k_dst as usize] = !self.k[k_src as usize]
+            }
+
+            Instruction::AddImmediate(Gpr(ref reg_name), imm) => {
+                let gpr = self.gpr(reg_name)?;
+                let value = gpr.value.wrapping_add(imm as i64 as u64);
+                self.set_gpr(reg_name, value, true);
+            }
+            Instruction::SubImmediate(Gpr(ref reg_name), imm) => {
+                let gpr = self.gpr(reg_name)?;
+                let value = gpr.value.wrapping_sub(imm as i64 as u64);
+                self.set_gpr(reg_name, value, true);
+            }
+            Instruction::SetImmediate(Gpr(ref reg_name), imm) => {
+                self.gpr(reg_name)?;
+                self.set_gpr(reg_name, imm as i32 as u32 as u64, false);
+            }
+            Instruction::ShiftRight(Gpr(ref reg_name), imm) => {
+                let gpr = self.gpr(reg_name)?;
+                let value = gpr.width.sign_extend(gpr.value) >> imm;
+                self.set_gpr(reg_name, value as u64, true);
+            }
+            Instruction::MovReg(Gpr(ref reg_dst), Gpr(ref reg_src)) => {
+                self.gpr(reg_dst)?;
+                let value = self.gpr(reg_src)?.value;
+                self.set_gpr(reg_dst, value, false);
+            }
+
+            Instruction::MovF64x8(Zmm(zmm_dst), Zmm(zmm_src)) => {
+                self.zmm[zmm_dst as usize] = self.zmm[zmm_src as usize];
+            }
+            Instruction::MovF64x2(Xmm(xmm_dst), Xmm(xmm_src)) => {
+                let src = self.f64x8(xmm_src);
+                self.set_f64s(xmm_dst, &src[0..2]);
+            }
+            Instruction::MovI32x8(Ymm(ymm_dst), Ymm(ymm_src)) => {
+                let src = self.zmm[ymm_src as usize];
+                self.zmm[ymm_dst as usize] = [src[0], src[1], src[2], src[3], 0, 0, 0, 0];
+            }
+
+            Instruction::LoadF64x8(Zmm(zmm), Gpr(ref reg_base), imm_offset) => {
+                let addr = self.address(reg_base, imm_offset)?;
+                let src = self.load_f64s(addr, 8)?;
+                self.set_f64s(zmm, &src);
+            }
+            Instruction::LoadF64x1(Xmm(xmm), Gpr(ref reg_base), imm_offset) => {
+                let addr = self.address(reg_base, imm_offset)?;
+                let src = self.load_f64s(addr, 1)?;
+                self.set_f64s(xmm, &src);
+            }
+            Instruction::LoadI32x8(Ymm(ymm), Gpr(ref reg_base), imm_offset) => {
+                let addr = self.address(reg_base, imm_offset)?;
+                if addr % 32 != 0 {
+                    return Err(EmulateError::MisalignedAccess(addr));
+                }
+                let mut qwords = [0; 8];
+                for (i, qword) in qwords.iter_mut().take(4).enumerate() {
+                    let lo = self.memory.read_i32(addr + i as u64 * 8)? as u32 as u64;
+                    let hi = self.memory.read_i32(addr + i as u64 * 8 + 4)? as u32 as u64;
+                    *qword = lo | (hi << 32);
+                }
+                self.zmm[ymm as usize] = qwords;
+            }
+            Instruction::StoreF64x8(Gpr(ref reg_base), imm_offset, Zmm(zmm)) => {
+                let addr = self.address(reg_base, imm_offset)?;
+                self.store_f64s(addr, &self.f64x8(zmm))?;
+            }
+            Instruction::StoreF64x1(Gpr(ref reg_base), imm_offset, Xmm(xmm)) => {
+                let addr = self.address(reg_base, imm_offset)?;
+                self.store_f64s(addr, &self.f64x8(xmm)[0..1])?;
+            }
+            Instruction::GatherF64x8(Zmm(zmm), Gpr(ref reg_base), Ymm(ymm_idx), Mask(k)) => {
+                let base = self.gpr(reg_base)?.value;
+                let idx = self.i32x8(ymm_idx);
+                let mut dst = self.f64x8(zmm);
+                for (lane, (x, i)) in dst.iter_mut().zip(idx.iter()).enumerate() {
+                    if self.k[k as usize] & (1 << lane) != 0 {
+                        let addr = base.wrapping_add((*i as i64 * 8) as u64);
+                        *x = self.memory.read_f64(addr)?;
+                    }
+                }
+                self.k[k as usize] = 0;
+                self.zmm[zmm as usize] = dst.map(f64::to_bits);
+            }
+
+            Instruction::AddF64x8(Zmm(zmm_dst), Zmm(zmm_src0), Zmm(zmm_src1)) => {
+                self.vector_op(zmm_dst, zmm_src0, self.f64x8(zmm_src1), 8, |a, b, _| a + b)
+            }
+            Instruction::AddF64x4(Ymm(ymm_dst), Ymm(ymm_src0), Ymm(ymm_src1)) => {
+                self.vector_op(ymm_dst, ymm_src0, self.f64x8(ymm_src1), 4, |a, b, _| a + b)
+            }
+            Instruction::AddF64x2(Xmm(xmm_dst), Xmm(xmm_src0), Xmm(xmm_src1)) => {
+                self.vector_op(xmm_dst, xmm_src0, self.f64x8(xmm_src1), 2, |a, b, _| a + b)
+            }
+            Instruction::AddF64x1(Xmm(xmm_dst), Xmm(xmm_src0), Xmm(xmm_src1)) => {
+                self.scalar_op(xmm_dst, xmm_src0, self.f64x8(xmm_src1)[0], |a, b, _| a + b)
+            }
+            Instruction::LoadAddF64x8(
+                Zmm(zmm_dst),
+                Zmm(zmm_src0),
+                Gpr(ref reg_base1),
+                imm_offset1,
+            ) => {
+                let src1 = self.load_operand(reg_base1, imm_offset1, 8)?;
+                self.vector_op(zmm_dst, zmm_src0, src1, 8, |a, b, _| a + b)
+            }
+            Instruction::LoadAddF64x1(
+                Xmm(xmm_dst),
+                Xmm(xmm_src0),
+                Gpr(ref reg_base1),
+                imm_offset1,
+            ) => {
+                let src1 = self.load_operand(reg_base1, imm_offset1, 1)?[0];
+                self.scalar_op(xmm_dst, xmm_src0, src1, |a, b, _| a + b)
+            }
+            Instruction::MulF64x8(Zmm(zmm_dst), Zmm(zmm_src0), Zmm(zmm_src1)) => {
+                self.vector_op(zmm_dst, zmm_src0, self.f64x8(zmm_src1), 8, |a, b, _| a * b)
+            }
+            Instruction::MulF64x1(Xmm(xmm_dst), Xmm(xmm_src0), Xmm(xmm_src1)) => {
+                self.scalar_op(xmm_dst, xmm_src0, self.f64x8(xmm_src1)[0], |a, b, _| a * b)
+            }
+            Instruction::LoadMulF64x8(
+                Zmm(zmm_dst),
+                Zmm(zmm_src0),
+                Gpr(ref reg_base1),
+                imm_offset1,
+            ) => {
+                let src1 = self.load_operand(reg_base1, imm_offset1, 8)?;
+                self.vector_op(zmm_dst, zmm_src0, src1, 8, |a, b, _| a * b)
+            }
+            Instruction::LoadMulF64x1(
+                Xmm(xmm_dst),
+                Xmm(xmm_src0),
+                Gpr(ref reg_base1),
+                imm_offset1,
+            ) => {
+                let src1 = self.load_operand(reg_base1, imm_offset1, 1)?[0];
+                self.scalar_op(xmm_dst, xmm_src0, src1, |a, b, _| a * b)
+            }
+            Instruction::MulAddF64x8(Zmm(zmm_dst), Zmm(zmm_src0), Zmm(zmm_src1)) => {
+                let src1 = self.f64x8(zmm_src1);
+                self.vector_op(zmm_dst, zmm_src0, src1, 8, |a, b, c| a.mul_add(b, c))
+            }
+            Instruction::MulAddF64x1(Xmm(xmm_dst), Xmm(xmm_src0), Xmm(xmm_src1)) => {
+                let src1 = self.f64x8(xmm_src1)[0];
+                self.scalar_fma_op(xmm_dst, xmm_src0, src1, |a, b, c| a.mul_add(b, c))
+            }
+            Instruction::LoadMulAddF64x8(
+                Zmm(zmm_dst),
+                Zmm(zmm_src0),
+                Gpr(ref reg_base1),
+                imm_offset1,
+            ) => {
+                let src1 = self.load_operand(reg_base1, imm_offset1, 8)?;
+                self.vector_op(zmm_dst, zmm_src0, src1, 8, |a, b, c| a.mul_add(b, c))
+            }
+            Instruction::LoadMulAddF64x1(
+                Xmm(xmm_dst),
+                Xmm(xmm_src0),
+                Gpr(ref reg_base1),
+                imm_offset1,
+            ) => {
+                let src1 = self.load_operand(reg_base1, imm_offset1, 1)?[0];
+                self.scalar_fma_op(xmm_dst, xmm_src0, src1, |a, b, c| a.mul_add(b, c))
+            }
+            Instruction::NMulSubF64x8(Zmm(zmm_dst), Zmm(zmm_src0), Zmm(zmm_src1)) => {
+                let src1 = self.f64x8(zmm_src1);
+                self.vector_op(zmm_dst, zmm_src0, src1, 8, |a, b, c| (-a).mul_add(b, -c))
+            }
+            Instruction::LoadNMulSubF64x8(
+                Zmm(zmm_dst),
+                Zmm(zmm_src0),
+                Gpr(ref reg_base1),
+                imm_offset1,
+            ) => {
+                let src1 = self.load_operand(reg_base1, imm_offset1, 8)?;
+                self.vector_op(zmm_dst, zmm_src0, src1, 8, |a, b, c| (-a).mul_add(b, -c))
+            }
+            Instruction::DivF64x8(Zmm(zmm_dst), Zmm(zmm_src0), Zmm(zmm_src1)) => {
+                self.vector_op(zmm_dst, zmm_src0, self.f64x8(zmm_src1), 8, |a, b, _| a / b)
+            }
+            Instruction::DivF64x1(Xmm(xmm_dst), Xmm(xmm_src0), Xmm(xmm_src1)) => {
+                self.scalar_op(xmm_dst, xmm_src0, self.f64x8(xmm_src1)[0], |a, b, _| a / b)
+            }
+            Instruction::LoadDivF64x8(
+                Zmm(zmm_dst),
+                Zmm(zmm_src0),
+                Gpr(ref reg_base1),
+                imm_offset1,
+            ) => {
+                let src1 = self.load_operand(reg_base1, imm_offset1, 8)?;
+                self.vector_op(zmm_dst, zmm_src0, src1, 8, |a, b, _| a / b)
+            }
+            Instruction::LoadDivF64x1(
+                Xmm(xmm_dst),
+                Xmm(xmm_src0),
+                Gpr(ref reg_base1),
+                imm_offset1,
+            ) => {
+                let src1 = self.load_operand(reg_base1, imm_offset1, 1)?[0];
+                self.scalar_op(xmm_dst, xmm_src0, src1, |a, b, _| a / b)
+            }
+
+            Instruction::LUMix4F64x8(Zmm(zmm_dst), Ymm(ymm_src0), Zmm(zmm_src1)) => {
+                let src0 = self.zmm[ymm_src0 as usize];
+                let src1 = self.zmm[zmm_src1 as usize];
+                self.zmm[zmm_dst as usize] = [
+                    src0[0], src0[1], src0[2], src0[3], src1[4], src1[5], src1[6], src1[7],
+                ];
+            }
+            Instruction::ULMix4F64x8(Zmm(zmm_dst), Zmm(zmm_src0), Zmm(zmm_src1)) => {
+                let src0 = self.zmm[zmm_src0 as usize];
+                let src1 = self.zmm[zmm_src1 as usize];
+                self.zmm[zmm_dst as usize] = [
+                    src0[4], src0[5], src0[6], src0[7], src1[0], src1[1], src1[2], src1[3],
+                ];
+            }
+            Instruction::Mix2F64x8Mask(Zmm(zmm_dst), Zmm(zmm_src), Mask(k)) => {
+                // vpermpd $0x4e swaps the 128-bit halves of each 256-bit lane
+                let src = self.zmm[zmm_src as usize];
+                let mut dst = self.zmm[zmm_dst as usize];
+                for (lane, x) in dst.iter_mut().enumerate() {
+                    if self.k[k as usize] & (1 << lane) != 0 {
+                        *x = src[lane ^ 2];
+                    }
+                }
+                self.zmm[zmm_dst as usize] = dst;
+            }
+            Instruction::LUMix1F64x8(Zmm(zmm_dst), Zmm(zmm_src0), Zmm(zmm_src1)) => {
+                let src0 = self.zmm[zmm_src0 as usize];
+                let src1 = self.zmm[zmm_src1 as usize];
+                self.zmm[zmm_dst as usize] =
+                    std::array::from_fn(|i| if i % 2 == 0 { src0[i] } else { src1[i] });
+            }
+            Instruction::ULMix1F64x8(Zmm(zmm_dst), Zmm(zmm_src0), Zmm(zmm_src1)) => {
+                let src0 = self.zmm[zmm_src0 as usize];
+                let src1 = self.zmm[zmm_src1 as usize];
+                self.zmm[zmm_dst as usize] =
+                    std::array::from_fn(|i| if i % 2 == 0 { src0[i + 1] } else { src1[i - 1] });
+            }
+            Instruction::ExtractU4F64x8(Ymm(ymm_dst), Zmm(zmm_src)) => {
+                let src = self.zmm[zmm_src as usize];
+                self.zmm[ymm_dst as usize] = [src[4], src[5], src[6], src[7], 0, 0, 0, 0];
+            }
+            Instruction::ExtractU2F64x4(Xmm(xmm_dst), Ymm(ymm_src)) => {
+                let src = self.zmm[ymm_src as usize];
+                self.zmm[xmm_dst as usize] = [src[2], src[3], 0, 0, 0, 0, 0, 0];
+            }
+            Instruction::Fold1AddF64x2(Xmm(xmm_dst), Xmm(xmm_src)) => {
+                let src = self.f64x8(xmm_src);
+                let sum = src[0] + src[1];
+                self.set_f64s(xmm_dst, &[sum, sum]);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn gpr(&self, name: &Name) -> Result<Variable, EmulateError> {
+        self.gprs
+            .get(name)
+            .copied()
+            .ok_or_else(|| EmulateError::UnboundOperand(name.clone()))
+    }
+
+    fn set_gpr(&mut self, name: &Name, value: u64, update_flag: bool) {
+        let gpr = self.gprs.get_mut(name).unwrap();
+        gpr.value = value & gpr.width.mask();
+        if update_flag {
+            self.zero_flag = gpr.value == 0;
+        }
+    }
+
+    fn address(&self, reg_base: &Name, imm_offset: i16) -> Result<u64, EmulateError> {
+        let base = self.gpr(reg_base)?.value;
+        Ok(base.wrapping_add(imm_offset as i64 as u64))
+    }
+
+    fn f64x8(&self, zmm: u8) -> [f64; 8] {
+        self.zmm[zmm as usize].map(f64::from_bits)
+    }
+
+    fn i32x8(&self, ymm: u8) -> [i32; 8] {
+        let qwords = self.zmm[ymm as usize];
+        std::array::from_fn(|i| (qwords[i / 2] >> (32 * (i % 2))) as u32 as i32)
+    }
+
+    // writes the lower lanes and zeroes the rest of the register, as every
+    // VEX/EVEX encoded instruction does.
+    fn set_f64s(&mut self, zmm: u8, src: &[f64]) {
+        let mut qwords = [0; 8];
+        for (qword, x) in qwords.iter_mut().zip(src.iter()) {
+            *qword = x.to_bits();
+        }
+        self.zmm[zmm as usize] = qwords;
+    }
+
+    fn load_f64s(&self, addr: u64, len: usize) -> Result<Vec<f64>, EmulateError> {
+        self.memory.read_f64s(addr, len)
+    }
+
+    fn load_operand(
+        &self,
+        reg_base: &Name,
+        imm_offset: i16,
+        len: usize,
+    ) -> Result<[f64; 8], EmulateError> {
+        let addr = self.address(reg_base, imm_offset)?;
+        let mut operand = [0.0; 8];
+        operand[..len].copy_from_slice(&self.load_f64s(addr, len)?);
+        Ok(operand)
+    }
+
+    fn store_f64s(&mut self, addr: u64, src: &[f64]) -> Result<(), EmulateError> {
+        for (i, x) in src.iter().enumerate() {
+            self.memory
+                .write_f64(addr + (i * size_of::<f64>()) as u64, *x)?;
+        }
+        Ok(())
+    }
+
+    // `op(src0, src1, dst)` on the lower `lanes` lanes, upper lanes zeroed
+    fn vector_op(
+        &mut self,
+        dst: u8,
+        src0: u8,
+        src1: [f64; 8],
+        lanes: usize,
+        op: fn(f64, f64, f64) -> f64,
+    ) {
+        let src0 = self.f64x8(src0);
+        let dst_value = self.f64x8(dst);
+        let res: Vec<f64> = (0..lanes)
+            .map(|i| op(src0[i], src1[i], dst_value[i]))
+            .collect();
+        self.set_f64s(dst, &res);
+    }
+
+    // scalar double ops keep lane 1 of the first source and zero the upper lanes
+    fn scalar_op(&mut self, dst: u8, src0: u8, src1: f64, op: fn(f64, f64, f64) -> f64) {
+        let src0 = self.f64x8(src0);
+        let dst_value = self.f64x8(dst);
+        let res = op(src0[0], src1, dst_value[0]);
+        self.set_f64s(dst, &[res, src0[1]]);
+    }
+
+    // fma forms keep lane 1 of the destination instead
+    fn scalar_fma_op(&mut self, dst: u8, src0: u8, src1: f64, op: fn(f64, f64, f64) -> f64) {
+        let src0 = self.f64x8(src0);
+        let dst_value = self.f64x8(dst);
+        let res = op(src0[0], src1, dst_value[0]);
+        self.set_f64s(dst, &[res, dst_value[1]]);
+    }
+}
+
+#[derive(Debug)]
+pub enum EmulateError {
+    UnboundOperand(Name),
+    UndefinedLabel(Name),
+    DuplicateLabel(Name),
+    SegmentationFault(u64),
+    MisalignedAccess(u64),
+    StepLimitExceeded(u64),
+}
+
+impl fmt::Display for EmulateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnboundOperand(name) => write!(f, "operand `{}` is not bound", name),
+            Self::UndefinedLabel(name) => write!(f, "label `{}` is not defined", name),
+            Self::DuplicateLabel(name) => write!(f, "label `{}` is defined twice", name),
+            Self::SegmentationFault(addr) => write!(f, "invalid memory access at 0x{:x}", addr),
+            Self::MisalignedAccess(addr) => write!(f, "misaligned memory access at 0x{:x}", addr),
+            Self::StepLimitExceeded(limit) => write!(f, "step limit {} exceeded", limit),
+        }
+    }
+}
+
+impl std::error::Error for EmulateError {}
@@ -0,0 +1,584 @@
+// x86-64 machine code for `Instruction`.
+//
+// Covers exactly the forms the generators emit: a few general purpose
+// instructions, opmask setup and the AVX/AVX-512F vector instructions.
+// Vector instructions take the VEX form whenever it exists and every
+// register is below 16 without masking, like GNU as does, and the EVEX form
+// otherwise, with disp8*N compressed displacements. A jump back to a label
+// within reach is short; forward jumps, which the generators never emit, are
+// always near.
+//
+// Registers are given by number (rax = 0, ..., r15 = 15; zmm0 = 0, ...).
+
+use crate::assembly::{Instruction, PrefetchType};
+use crate::gas::{GasError, Width};
+use crate::name::Name;
+use crate::register::{Gpr, Mask, Xmm, Ymm, Zmm};
+
+use std::collections::HashMap;
+
+const RSP: u8 = 4;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Len {
+    L128,
+    L256,
+    L512,
+}
+
+impl Len {
+    fn bits(&self) -> u8 {
+        match self {
+            Len::L128 => 0,
+            Len::L256 => 1,
+            Len::L512 => 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Rm {
+    Reg(u8),
+    Mem {
+        base: u8,
+        disp: i32,
+    },
+    Vsib {
+        base: u8,
+        index: u8,
+        scale: u8,
+        disp: i32,
+    },
+}
+
+impl Rm {
+    fn is_high(&self) -> bool {
+        match *self {
+            Rm::Reg(reg) => reg >= 16,
+            Rm::Mem { .. } => false,
+            Rm::Vsib { index, .. } => index >= 16,
+        }
+    }
+}
+
+// opcode of a VEX/EVEX instruction; `vex_w` / `evex_w` are None when the
+// instruction has no such form
+struct Op {
+    name: &'static str,
+    pp: u8,
+    map: u8,
+    opcode: u8,
+    vex_w: Option<bool>,
+    evex_w: Option<bool>,
+}
+
+const fn op(
+    name: &'static str,
+    pp: u8,
+    map: u8,
+    opcode: u8,
+    vex_w: Option<bool>,
+    evex_w: Option<bool>,
+) -> Op {
+    Op {
+        name,
+        pp,
+        map,
+        opcode,
+        vex_w,
+        evex_w,
+    }
+}
+
+// pp: 1 = 66, 2 = F3, 3 = F2; map: 1 = 0F, 2 = 0F38, 3 = 0F3A
+const VMOVUPD_LOAD: Op = op("vmovupd", 1, 1, 0x10, Some(false), Some(true));
+const VMOVUPD_STORE: Op = op("vmovupd", 1, 1, 0x11, Some(false), Some(true));
+const VMOVSD_LOAD: Op = op("vmovsd", 3, 1, 0x10, Some(false), Some(true));
+const VMOVSD_STORE: Op = op("vmovsd", 3, 1, 0x11, Some(false), Some(true));
+const VMOVDQA_LOAD: Op = op("vmovdqa", 1, 1, 0x6f, Some(false), None);
+const VGATHERDPD: Op = op("vgatherdpd", 1, 2, 0x92, None, Some(true));
+const VADDPD: Op = op("vaddpd", 1, 1, 0x58, Some(false), Some(true));
+const VADDSD: Op = op("vaddsd", 3, 1, 0x58, Some(false), Some(true));
+const VMULPD: Op = op("vmulpd", 1, 1, 0x59, Some(false), Some(true));
+const VMULSD: Op = op("vmulsd", 3, 1, 0x59, Some(false), Some(true));
+const VDIVPD: Op = op("vdivpd", 1, 1, 0x5e, Some(false), Some(true));
+const VDIVSD: Op = op("vdivsd", 3, 1, 0x5e, Some(false), Some(true));
+const VFMADD231PD: Op = op("vfmadd231pd", 1, 2, 0xb8, Some(true), Some(true));
+const VFMADD231SD: Op = op("vfmadd231sd", 1, 2, 0xb9, Some(true), Some(true));
+const VFNMSUB231PD: Op = op("vfnmsub231pd", 1, 2, 0xbe, Some(true), Some(true));
+const VINSERTF64X4: Op = op("vinsertf64x4", 1, 3, 0x1a, None, Some(true));
+const VALIGNQ: Op = op("valignq", 1, 3, 0x03, None, Some(true));
+const VPERMPD: Op = op("vpermpd", 1, 3, 0x01, None, Some(true));
+const VSHUFPD: Op = op("vshufpd", 1, 1, 0xc6, Some(false), Some(true));
+const VEXTRACTF64X4: Op = op("vextractf64x4", 1, 3, 0x1b, None, Some(true));
+const VEXTRACTF128: Op = op("vextractf128", 1, 3, 0x19, Some(false), None);
+const VHADDPD: Op = op("vhaddpd", 1, 1, 0x7c, Some(false), None);
+const KXNORW: Op = op("kxnorw", 0, 1, 0x46, Some(false), None);
+const KMOVW: Op = op("kmovw", 0, 1, 0x92, Some(false), None);
+const KNOTW: Op = op("knotw", 0, 1, 0x44, Some(false), None);
+
+pub(crate) struct Encoder {
+    code: Vec<u8>,
+    labels: HashMap<Name, usize>,
+    // position of a rel32 and the label it refers to
+    fixups: Vec<(usize, Name)>,
+}
+
+impl Encoder {
+    pub(crate) fn new() -> Self {
+        Encoder {
+            code: Vec::new(),
+            labels: HashMap::new(),
+            fixups: Vec::new(),
+        }
+    }
+
+    pub(crate) fn finish(mut self) -> Result<Vec<u8>, GasError> {
+        for (pos, label) in self.fixups.iter() {
+            let target = *self
+                .labels
+                .get(label)
+                .ok_or_else(|| GasError::UndefinedLabel(label.clone()))?;
+            let rel = target as i32 - (*pos as i32 + 4);
+            self.code[*pos..*pos + 4].copy_from_slice(&rel.to_le_bytes());
+        }
+
+        Ok(self.code)
+    }
+
+    pub(crate) fn push(&mut self, reg: u8) {
+        self.rex(false, 0, 0, reg);
+        self.code.push(0x50 + (reg & 7));
+    }
+
+    pub(crate) fn pop(&mut self, reg: u8) {
+        self.rex(false, 0, 0, reg);
+        self.code.push(0x58 + (reg & 7));
+    }
+
+    // mov disp(%rsp), reg
+    pub(crate) fn load_stack(&mut self, reg: u8, width: Width, disp: i32) {
+        let rm = Rm::Mem { base: RSP, disp };
+        self.rex(width == Width::Pointer, reg, 0, RSP);
+        self.code.push(0x8b);
+        self.modrm(reg, &rm, 1);
+    }
+
+    // mov src, dst
+    pub(crate) fn move_reg(&mut self, dst: u8, src: u8, width: Width) {
+        self.rex(width == Width::Pointer, src, 0, dst);
+        self.code.push(0x89);
+        self.modrm(src, &Rm::Reg(dst), 1);
+    }
+
+    pub(crate) fn epilogue_start(&mut self) {
+        // vzeroupper; xor %eax, %eax
+        self.code.extend_from_slice(&[0xc5, 0xf8, 0x77, 0x31, 0xc0]);
+    }
+
+    pub(crate) fn ret(&mut self) {
+        self.code.push(0xc3);
+    }
+
+    pub(crate) fn instruction(
+        &mut self,
+        inst: &Instruction,
+        bind: &dyn Fn(&Name) -> Result<(u8, Width), GasError>,
+    ) -> Result<(), GasError> {
+        let mem = |name: &Name, disp: i16| -> Result<Rm, GasError> {
+            let (base, _) = bind(name)?;
+            Ok(Rm::Mem {
+                base,
+                disp: disp as i32,
+            })
+        };
+
+        match *inst {
+            Instruction::Comment(_) => {}
+            Instruction::Nop => self.code.push(0x90),
+            Instruction::Label(ref name) => {
+                if self.labels.insert(name.clone(), self.code.len()).is_some() {
+                    return Err(GasError::DuplicateLabel(name.clone()));
+                }
+            }
+            Instruction::JumpNotZero(ref label) => match self.labels.get(label) {
+                Some(&target) if target as i32 - (self.code.len() as i32 + 2) >= -128 => {
+                    let rel = target as i32 - (self.code.len() as i32 + 2);
+                    self.code.extend_from_slice(&[0x75, rel as i8 as u8]);
+                }
+                Some(&target) => {
+                    let rel = target as i32 - (self.code.len() as i32 + 6);
+                    self.code.extend_from_slice(&[0x0f, 0x85]);
+                    self.code.extend_from_slice(&rel.to_le_bytes());
+                }
+                None => {
+                    self.code.extend_from_slice(&[0x0f, 0x85]);
+                    self.fixups.push((self.code.len(), label.clone()));
+                    self.code.extend_from_slice(&[0; 4]);
+                }
+            },
+
+            Instruction::MaskOn(Mask(k)) => {
+                self.vex_or_evex(&KXNORW, Len::L256, k, 0, Rm::Reg(0), 0, 1)?
+            }
+            Instruction::MaskSet(Mask(k), Gpr(ref reg_name)) => {
+                let (reg, _) = bind(reg_name)?;
+                self.vex_or_evex(&KMOVW, Len::L128, k, 0, Rm::Reg(reg), 0, 1)?
+            }
+            Instruction::MaskNot(Mask(k_dst), Mask(k_src)) => {
+                self.vex_or_evex(&KNOTW, Len::L128, k_dst, 0, Rm::Reg(k_src), 0, 1)?
+            }
+
+            Instruction::AddImmediate(Gpr(ref reg_name), imm) => {
+                self.arith_immediate(bind(reg_name)?, 0, imm)
+            }
+            Instruction::SubImmediate(Gpr(ref reg_name), imm) => {
+                self.arith_immediate(bind(reg_name)?, 5, imm)
+            }
+            Instruction::SetImmediate(Gpr(ref reg_name), imm) => {
+                // movl, so only for 32-bit operands
+                let (reg, width) = bind(reg_name)?;
+                if width == Width::Pointer {
+                    return Err(GasError::NoEncoding(inst.to_string()));
+                }
+                self.rex(false, 0, 0, reg);
+                self.code.push(0xb8 + (reg & 7));
+                self.code.extend_from_slice(&(imm as i32).to_le_bytes());
+            }
+            Instruction::ShiftRight(Gpr(ref reg_name), imm) => {
+                let (reg, width) = bind(reg_name)?;
+                self.rex(width == Width::Pointer, 0, 0, reg);
+                match imm {
+                    1 => {
+                        self.code.push(0xd1);
+                        self.modrm(7, &Rm::Reg(reg), 1);
+                    }
+                    _ => {
+                        self.code.push(0xc1);
+                        self.modrm(7, &Rm::Reg(reg), 1);
+                        self.code.push(imm);
+                    }
+                }
+            }
+            Instruction::MovReg(Gpr(ref reg_dst), Gpr(ref reg_src)) => {
+                let (dst, dst_width) = bind(reg_dst)?;
+                let (src, src_width) = bind(reg_src)?;
+                if dst_width != src_width {
+                    return Err(GasError::NoEncoding(inst.to_string()));
+                }
+                self.move_reg(dst, src, dst_width);
+            }
+
+            Instruction::MovF64x8(Zmm(dst), Zmm(src)) => self.move_vector(Len::L512, dst, src)?,
+            Instruction::MovF64x2(Xmm(dst), Xmm(src)) => self.move_vector(Len::L128, dst, src)?,
+            Instruction::MovI32x8(Ymm(dst), Ymm(src)) => self.move_vector(Len::L256, dst, src)?,
+
+            Instruction::LoadF64x8(Zmm(zmm), Gpr(ref reg_base), imm_offset) => {
+                let rm = mem(reg_base, imm_offset)?;
+                self.vex_or_evex(&VMOVUPD_LOAD, Len::L512, zmm, 0, rm, 0, 64)?
+            }
+            Instruction::LoadF64x1(Xmm(xmm), Gpr(ref reg_base), imm_offset) => {
+                let rm = mem(reg_base, imm_offset)?;
+                self.vex_or_evex(&VMOVSD_LOAD, Len::L128, xmm, 0, rm, 0, 8)?
+            }
+            Instruction::LoadI32x8(Ymm(ymm), Gpr(ref reg_base), imm_offset) => {
+                let rm = mem(reg_base, imm_offset)?;
+                self.vex_or_evex(&VMOVDQA_LOAD, Len::L256, ymm, 0, rm, 0, 32)?
+            }
+            Instruction::StoreF64x8(Gpr(ref reg_base), imm_offset, Zmm(zmm)) => {
+                let rm = mem(reg_base, imm_offset)?;
+                self.vex_or_evex(&VMOVUPD_STORE, Len::L512, zmm, 0, rm, 0, 64)?
+            }
+            Instruction::StoreF64x1(Gpr(ref reg_base), imm_offset, Xmm(xmm)) => {
+                let rm = mem(reg_base, imm_offset)?;
+                self.vex_or_evex(&VMOVSD_STORE, Len::L128, xmm, 0, rm, 0, 8)?
+            }
+            Instruction::GatherF64x8(Zmm(zmm), Gpr(ref reg_base), Ymm(ymm_idx), Mask(k)) => {
+                let (base, _) = bind(reg_base)?;
+                let rm = Rm::Vsib {
+                    base,
+                    index: ymm_idx,
+                    scale: 3,
+                    disp: 0,
+                };
+                self.vex_or_evex(&VGATHERDPD, Len::L512, zmm, 0, rm, k, 8)?
+            }
+            Instruction::Prefetch(prefetch_type, Gpr(ref reg_base), imm_offset) => {
+                let hint = match prefetch_type {
+                    PrefetchType::NTA => 0,
+                    PrefetchType::T0 => 1,
+                    PrefetchType::T1 => 2,
+                    PrefetchType::T2 => 3,
+                };
+                let rm = mem(reg_base, imm_offset)?;
+                if let Rm::Mem { base, .. } = rm {
+                    self.rex(false, 0, 0, base);
+                }
+                self.code.extend_from_slice(&[0x0f, 0x18]);
+                self.modrm(hint, &rm, 1);
+            }
+
+            Instruction::AddF64x8(Zmm(dst), Zmm(src0), Zmm(src1)) => {
+                self.vex_or_evex(&VADDPD, Len::L512, dst, src0, Rm::Reg(src1), 0, 64)?
+            }
+            Instruction::AddF64x4(Ymm(dst), Ymm(src0), Ymm(src1)) => {
+                self.vex_or_evex(&VADDPD, Len::L256, dst, src0, Rm::Reg(src1), 0, 32)?
+            }
+            Instruction::AddF64x2(Xmm(dst), Xmm(src0), Xmm(src1)) => {
+                self.vex_or_evex(&VADDPD, Len::L128, dst, src0, Rm::Reg(src1), 0, 16)?
+            }
+            Instruction::AddF64x1(Xmm(dst), Xmm(src0), Xmm(src1)) => {
+                self.vex_or_evex(&VADDSD, Len::L128, dst, src0, Rm::Reg(src1), 0, 8)?
+            }
+            Instruction::LoadAddF64x8(Zmm(dst), Zmm(src0), Gpr(ref reg_base1), imm_offset1) => {
+                let rm = mem(reg_base1, imm_offset1)?;
+                self.vex_or_evex(&VADDPD, Len::L512, dst, src0, rm, 0, 64)?
+            }
+            Instruction::LoadAddF64x1(Xmm(dst), Xmm(src0), Gpr(ref reg_base1), imm_offset1) => {
+                let rm = mem(reg_base1, imm_offset1)?;
+                self.vex_or_evex(&VADDSD, Len::L128, dst, src0, rm, 0, 8)?
+            }
+            Instruction::MulF64x8(Zmm(dst), Zmm(src0), Zmm(src1)) => {
+                self.vex_or_evex(&VMULPD, Len::L512, dst, src0, Rm::Reg(src1), 0, 64)?
+            }
+            Instruction::MulF64x1(Xmm(dst), Xmm(src0), Xmm(src1)) => {
+                self.vex_or_evex(&VMULSD, Len::L128, dst, src0, Rm::Reg(src1), 0, 8)?
+            }
+            Instruction::LoadMulF64x8(Zmm(dst), Zmm(src0), Gpr(ref reg_base1), imm_offset1) => {
+                let rm = mem(reg_base1, imm_offset1)?;
+                self.vex_or_evex(&VMULPD, Len::L512, dst, src0, rm, 0, 64)?
+            }
+            Instruction::LoadMulF64x1(Xmm(dst), Xmm(src0), Gpr(ref reg_base1), imm_offset1) => {
+                let rm = mem(reg_base1, imm_offset1)?;
+                self.vex_or_evex(&VMULSD, Len::L128, dst, src0, rm, 0, 8)?
+            }
+            Instruction::MulAddF64x8(Zmm(dst), Zmm(src0), Zmm(src1)) => {
+                self.vex_or_evex(&VFMADD231PD, Len::L512, dst, src0, Rm::Reg(src1), 0, 64)?
+            }
+            Instruction::MulAddF64x1(Xmm(dst), Xmm(src0), Xmm(src1)) => {
+                self.vex_or_evex(&VFMADD231SD, Len::L128, dst, src0, Rm::Reg(src1), 0, 8)?
+            }
+            Instruction::LoadMulAddF64x8(Zmm(dst), Zmm(src0), Gpr(ref reg_base1), imm_offset1) => {
+                let rm = mem(reg_base1, imm_offset1)?;
+                self.vex_or_evex(&VFMADD231PD, Len::L512, dst, src0, rm, 0, 64)?
+            }
+            Instruction::LoadMulAddF64x1(Xmm(dst), Xmm(src0), Gpr(ref reg_base1), imm_offset1) => {
+                let rm = mem(reg_base1, imm_offset1)?;
+                self.vex_or_evex(&VFMADD231SD, Len::L128, dst, src0, rm, 0, 8)?
+            }
+            Instruction::NMulSubF64x8(Zmm(dst), Zmm(src0), Zmm(src1)) => {
+                self.vex_or_evex(&VFNMSUB231PD, Len::L512, dst, src0, Rm::Reg(src1), 0, 64)?
+            }
+            Instruction::LoadNMulSubF64x8(Zmm(dst), Zmm(src0), Gpr(ref reg_base1), imm_offset1) => {
+                let rm = mem(reg_base1, imm_offset1)?;
+                self.vex_or_evex(&VFNMSUB231PD, Len::L512, dst, src0, rm, 0, 64)?
+            }
+            Instruction::DivF64x8(Zmm(dst), Zmm(src0), Zmm(src1)) => {
+                self.vex_or_evex(&VDIVPD, Len::L512, dst, src0, Rm::Reg(src1), 0, 64)?
+            }
+            Instruction::DivF64x1(Xmm(dst), Xmm(src0), Xmm(src1)) => {
+                self.vex_or_evex(&VDIVSD, Len::L128, dst, src0, Rm::Reg(src1), 0, 8)?
+            }
+            Instruction::LoadDivF64x8(Zmm(dst), Zmm(src0), Gpr(ref reg_base1), imm_offset1) => {
+                let rm = mem(reg_base1, imm_offset1)?;
+                self.vex_or_evex(&VDIVPD, Len::L512, dst, src0, rm, 0, 64)?
+            }
+            Instruction::LoadDivF64x1(Xmm(dst), Xmm(src0), Gpr(ref reg_base1), imm_offset1) => {
+                let rm = mem(reg_base1, imm_offset1)?;
+                self.vex_or_evex(&VDIVSD, Len::L128, dst, src0, rm, 0, 8)?
+            }
+
+            Instruction::LUMix4F64x8(Zmm(zmm_dst), Ymm(ymm_src0), Zmm(zmm_src1)) => {
+                let rm = Rm::Reg(ymm_src0);
+                self.vex_or_evex(&VINSERTF64X4, Len::L512, zmm_dst, zmm_src1, rm, 0, 32)?;
+                self.code.push(0x0);
+            }
+            Instruction::ULMix4F64x8(Zmm(zmm_dst), Zmm(zmm_src0), Zmm(zmm_src1)) => {
+                let rm = Rm::Reg(zmm_src0);
+                self.vex_or_evex(&VALIGNQ, Len::L512, zmm_dst, zmm_src1, rm, 0, 64)?;
+                self.code.push(0x4);
+            }
+            Instruction::Mix2F64x8Mask(Zmm(zmm_dst), Zmm(zmm_src), Mask(k)) => {
+                self.vex_or_evex(&VPERMPD, Len::L512, zmm_dst, 0, Rm::Reg(zmm_src), k, 64)?;
+                self.code.push(0x4e);
+            }
+            Instruction::LUMix1F64x8(Zmm(zmm_dst), Zmm(zmm_src0), Zmm(zmm_src1)) => {
+                let rm = Rm::Reg(zmm_src1);
+                self.vex_or_evex(&VSHUFPD, Len::L512, zmm_dst, zmm_src0, rm, 0, 64)?;
+                self.code.push(0xaa);
+            }
+            Instruction::ULMix1F64x8(Zmm(zmm_dst), Zmm(zmm_src0), Zmm(zmm_src1)) => {
+                let rm = Rm::Reg(zmm_src1);
+                self.vex_or_evex(&VSHUFPD, Len::L512, zmm_dst, zmm_src0, rm, 0, 64)?;
+                self.code.push(0x55);
+            }
+            Instruction::ExtractU4F64x8(Ymm(ymm_dst), Zmm(zmm_src)) => {
+                let rm = Rm::Reg(ymm_dst);
+                self.vex_or_evex(&VEXTRACTF64X4, Len::L512, zmm_src, 0, rm, 0, 32)?;
+                self.code.push(0x1);
+            }
+            Instruction::ExtractU2F64x4(Xmm(xmm_dst), Ymm(ymm_src)) => {
+                let rm = Rm::Reg(xmm_dst);
+                self.vex_or_evex(&VEXTRACTF128, Len::L256, ymm_src, 0, rm, 0, 16)?;
+                self.code.push(0x1);
+            }
+            Instruction::Fold1AddF64x2(Xmm(xmm_dst), Xmm(xmm_src)) => {
+                let rm = Rm::Reg(xmm_src);
+                self.vex_or_evex(&VHADDPD, Len::L128, xmm_dst, xmm_src, rm, 0, 16)?
+            }
+        }
+
+        Ok(())
+    }
+
+    // vmovupd between registers; like GNU as, the store form is taken when it
+    // saves the 3-byte VEX prefix
+    fn move_vector(&mut self, len: Len, dst: u8, src: u8) -> Result<(), GasError> {
+        let n = match len {
+            Len::L128 => 16,
+            Len::L256 => 32,
+            Len::L512 => 64,
+        };
+        match len != Len::L512 && (8..16).contains(&src) && dst < 8 {
+            true => self.vex_or_evex(&VMOVUPD_STORE, len, src, 0, Rm::Reg(dst), 0, n),
+            false => self.vex_or_evex(&VMOVUPD_LOAD, len, dst, 0, Rm::Reg(src), 0, n),
+        }
+    }
+
+    // add/sub ($imm, reg), `ext` is the /digit of the opcode
+    fn arith_immediate(&mut self, (reg, width): (u8, Width), ext: u8, imm: i16) {
+        self.rex(width == Width::Pointer, 0, 0, reg);
+        match (i8::try_from(imm), reg) {
+            (Ok(imm), _) => {
+                self.code.push(0x83);
+                self.modrm(ext, &Rm::Reg(reg), 1);
+                self.code.push(imm as u8);
+            }
+            // short form for the accumulator
+            (Err(_), 0) => {
+                self.code.push(0x05 + (ext << 3));
+                self.code.extend_from_slice(&(imm as i32).to_le_bytes());
+            }
+            (Err(_), _) => {
+                self.code.push(0x81);
+                self.modrm(ext, &Rm::Reg(reg), 1);
+                self.code.extend_from_slice(&(imm as i32).to_le_bytes());
+            }
+        }
+    }
+
+    // REX prefix, omitted when it would be 0x40
+    fn rex(&mut self, w: bool, reg: u8, index: u8, base: u8) {
+        let rex =
+            0x40 | (w as u8) << 3 | (reg >> 3 & 1) << 2 | (index >> 3 & 1) << 1 | base >> 3 & 1;
+        if rex != 0x40 {
+            self.code.push(rex);
+        }
+    }
+
+    // ModRM, SIB and displacement; `n` is the disp8 scale (1 without EVEX)
+    fn modrm(&mut self, reg: u8, rm: &Rm, n: i32) {
+        let reg = (reg & 7) << 3;
+        let (base, disp) = match *rm {
+            Rm::Reg(r) => {
+                self.code.push(0xc0 | reg | (r & 7));
+                return;
+            }
+            Rm::Mem { base, disp } | Rm::Vsib { base, disp, .. } => (base, disp),
+        };
+
+        let disp8 = match disp % n == 0 {
+            true => i8::try_from(disp / n).ok(),
+            false => None,
+        };
+        // rbp/r13 as base has no form without displacement
+        let modrm_mod = match (disp, disp8) {
+            (0, _) if base & 7 != 5 => 0x00,
+            (_, Some(_)) => 0x40,
+            (_, None) => 0x80,
+        };
+
+        match *rm {
+            Rm::Vsib { index, scale, .. } => {
+                self.code.push(modrm_mod | reg | 4);
+                self.code.push(scale << 6 | (index & 7) << 3 | (base & 7));
+            }
+            _ if base & 7 == RSP => {
+                self.code.push(modrm_mod | reg | 4);
+                self.code.push(0x24);
+            }
+            _ => self.code.push(modrm_mod | reg | (base & 7)),
+        }
+
+        match modrm_mod {
+            0x40 => self.code.push(disp8.unwrap() as u8),
+            0x80 => self.code.extend_from_slice(&disp.to_le_bytes()),
+            _ => {}
+        }
+    }
+
+    // `reg`, `vvvv` and `rm` are the ModRM.reg, VEX.vvvv and ModRM.rm operands;
+    // `k` is the opmask (0 for none) and `n` the EVEX disp8 scale
+    #[allow(clippy::too_many_arguments)]
+    fn vex_or_evex(
+        &mut self,
+        op: &Op,
+        len: Len,
+        reg: u8,
+        vvvv: u8,
+        rm: Rm,
+        k: u8,
+        n: i32,
+    ) -> Result<(), GasError> {
+        let fits_vex = len != Len::L512 && k == 0 && reg < 16 && vvvv < 16 && !rm.is_high();
+
+        match (op.vex_w, op.evex_w) {
+            (Some(w), _) if fits_vex => self.vex(op, w, len, reg, vvvv, &rm),
+            (_, Some(w)) => self.evex(op, w, len, reg, vvvv, &rm, k, n),
+            _ => return Err(GasError::NoEncoding(op.name.to_string())),
+        }
+
+        Ok(())
+    }
+
+    fn vex(&mut self, op: &Op, w: bool, len: Len, reg: u8, vvvv: u8, rm: &Rm) {
+        let (x, b) = match *rm {
+            Rm::Reg(r) => (0, r >> 3 & 1),
+            Rm::Mem { base, .. } => (0, base >> 3 & 1),
+            Rm::Vsib { base, index, .. } => (index >> 3 & 1, base >> 3 & 1),
+        };
+        let r = reg >> 3 & 1;
+        let tail = (!vvvv & 0xf) << 3 | len.bits() << 2 | op.pp;
+
+        match op.map == 1 && !w && x == 0 && b == 0 {
+            true => self.code.extend_from_slice(&[0xc5, (r ^ 1) << 7 | tail]),
+            false => self.code.extend_from_slice(&[
+                0xc4,
+                (r ^ 1) << 7 | (x ^ 1) << 6 | (b ^ 1) << 5 | op.map,
+                (w as u8) << 7 | tail,
+            ]),
+        }
+        self.code.push(op.opcode);
+        self.modrm(reg, rm, 1);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn evex(&mut self, op: &Op, w: bool, len: Len, reg: u8, vvvv: u8, rm: &Rm, k: u8, n: i32) {
+        // X extends ModRM.rm to 32 registers, V' the VSIB index
+        let (x, b, v_high) = match *rm {
+            Rm::Reg(r) => (r >> 4 & 1, r >> 3 & 1, vvvv >> 4 & 1),
+            Rm::Mem { base, .. } => (0, base >> 3 & 1, vvvv >> 4 & 1),
+            Rm::Vsib { base, index, .. } => (index >> 3 & 1, base >> 3 & 1, index >> 4 & 1),
+        };
+        let (r, r_high) = (reg >> 3 & 1, reg >> 4 & 1);
+
+        self.code.extend_from_slice(&[
+            0x62,
+            (r ^ 1) << 7 | (x ^ 1) << 6 | (b ^ 1) << 5 | (r_high ^ 1) << 4 | op.map,
+            (w as u8) << 7 | (!vvvv & 0xf) << 3 | 1 << 2 | op.pp,
+            len.bits() << 5 | (v_high ^ 1) << 3 | k,
+        ]);
+        self.code.push(op.opcode);
+        self.modrm(reg, rm, n);
+    }
+}
@@ -0,0 +1,802 @@
+// Standalone GNU assembler (.S), NASM and ELF object output for `Assembly`.
+//
+// `Assembly::print` emits an `asm volatile(...)` block whose `%[NAME]`
+// operands are bound to C variables by the compiler. `Function` instead binds
+// every named operand to a fixed general purpose register following the
+// System V AMD64 ABI, and wraps the instructions in a prologue/epilogue so the
+// kernel can be assembled and linked on its own:
+//
+//   - arguments are taken in C parameter order; the first six live in
+//     rdi, rsi, rdx, rcx, r8 and r9, the others are loaded from the stack
+//   - locals (and the remaining stack arguments) get rax, r10, r11 first and
+//     then the callee-saved rbx, rbp, r12-r15, which are pushed and popped
+//   - operands beyond those share a register with operands they are never
+//     live with, so a kernel may name more pointers and counters than there
+//     are registers as long as few of them are live at once
+//   - the function returns 0 in eax, like the C wrappers of kernel-generator
+//
+// The operands are the ones declared on the `Function`, followed by the ones
+// the `Assembly` declares itself (see `Assembly::argument_int`).
+//
+// Vector and mask registers are all caller-saved, so nothing is saved for
+// them; `vzeroupper` is issued before returning.
+//
+// `print` writes AT&T syntax for GNU as, `print_toplevel` the same function
+// in a file-scope `asm` declaration of C++, `print_nasm` Intel syntax for
+// NASM, `assemble` the machine code and `object` a relocatable ELF object.
+// `print_inline` writes the `asm volatile(...)` block of the C wrappers
+// itself: only the arguments are bound to C variables, and the locals take
+// registers that the block clobbers, shared the same way.
+
+use crate::assembly::{self, Instruction};
+use crate::elf;
+use crate::encoder::Encoder;
+use crate::intel::Intel;
+use crate::liveness;
+use crate::name::Name;
+use crate::Assembly;
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fmt::Write;
+
+const ARGUMENT_REGISTERS: [Gpr; 6] = [Gpr::Rdi, Gpr::Rsi, Gpr::Rdx, Gpr::Rcx, Gpr::R8, Gpr::R9];
+const SCRATCH_REGISTERS: [Gpr; 9] = [
+    Gpr::Rax,
+    Gpr::R10,
+    Gpr::R11,
+    Gpr::Rbx,
+    Gpr::Rbp,
+    Gpr::R12,
+    Gpr::R13,
+    Gpr::R14,
+    Gpr::R15,
+];
+
+// registers the inline-asm block clobbers for its locals, leaving rbp to a
+// frame pointer and the rest to the arguments
+const INLINE_REGISTERS: [Gpr; 8] = [
+    Gpr::Rax,
+    Gpr::R10,
+    Gpr::R11,
+    Gpr::Rbx,
+    Gpr::R12,
+    Gpr::R13,
+    Gpr::R14,
+    Gpr::R15,
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Gpr {
+    Rax,
+    Rbx,
+    Rcx,
+    Rdx,
+    Rsi,
+    Rdi,
+    Rbp,
+    R8,
+    R9,
+    R10,
+    R11,
+    R12,
+    R13,
+    R14,
+    R15,
+}
+
+impl Gpr {
+    fn number(&self) -> u8 {
+        match self {
+            Gpr::Rax => 0,
+            Gpr::Rcx => 1,
+            Gpr::Rdx => 2,
+            Gpr::Rbx => 3,
+            Gpr::Rbp => 5,
+            Gpr::Rsi => 6,
+            Gpr::Rdi => 7,
+            Gpr::R8 => 8,
+            Gpr::R9 => 9,
+            Gpr::R10 => 10,
+            Gpr::R11 => 11,
+            Gpr::R12 => 12,
+            Gpr::R13 => 13,
+            Gpr::R14 => 14,
+            Gpr::R15 => 15,
+        }
+    }
+
+    fn is_callee_saved(&self) -> bool {
+        matches!(
+            self,
+            Gpr::Rbx | Gpr::Rbp | Gpr::R12 | Gpr::R13 | Gpr::R14 | Gpr::R15
+        )
+    }
+
+    fn name(&self, width: Width) -> &'static str {
+        match width {
+            Width::Int => match self {
+                Gpr::Rax => "eax",
+                Gpr::Rbx => "ebx",
+                Gpr::Rcx => "ecx",
+                Gpr::Rdx => "edx",
+                Gpr::Rsi => "esi",
+                Gpr::Rdi => "edi",
+                Gpr::Rbp => "ebp",
+                Gpr::R8 => "r8d",
+                Gpr::R9 => "r9d",
+                Gpr::R10 => "r10d",
+                Gpr::R11 => "r11d",
+                Gpr::R12 => "r12d",
+                Gpr::R13 => "r13d",
+                Gpr::R14 => "r14d",
+                Gpr::R15 => "r15d",
+            },
+            Width::Pointer => match self {
+                Gpr::Rax => "rax",
+                Gpr::Rbx => "rbx",
+                Gpr::Rcx => "rcx",
+                Gpr::Rdx => "rdx",
+                Gpr::Rsi => "rsi",
+                Gpr::Rdi => "rdi",
+                Gpr::Rbp => "rbp",
+                Gpr::R8 => "r8",
+                Gpr::R9 => "r9",
+                Gpr::R10 => "r10",
+                Gpr::R11 => "r11",
+                Gpr::R12 => "r12",
+                Gpr::R13 => "r13",
+                Gpr::R14 => "r14",
+                Gpr::R15 => "r15",
+            },
+        }
+    }
+}
+
+// `int` operands are 32-bit, pointers 64-bit, as in the C wrappers
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Width {
+    Int,
+    Pointer,
+}
+
+enum Initializer {
+    Stack(usize),
+    Register(Gpr),
+}
+
+#[derive(Clone)]
+pub(crate) enum Source {
+    Argument,
+    Uninitialized,
+    Copy(Name),
+}
+
+#[derive(Clone)]
+pub(crate) struct Operand {
+    pub(crate) name: Name,
+    pub(crate) width: Width,
+    pub(crate) source: Source,
+}
+
+impl Operand {
+    pub(crate) fn argument(name: &str, width: Width) -> Self {
+        Operand {
+            name: Name::from(name),
+            width,
+            source: Source::Argument,
+        }
+    }
+
+    pub(crate) fn local(name: &str, width: Width) -> Self {
+        Operand {
+            name: Name::from(name),
+            width,
+            source: Source::Uninitialized,
+        }
+    }
+
+    // a copy has the width of its source among `operands`
+    pub(crate) fn copy(operands: &[Operand], name: &str, src: &str) -> Self {
+        let width = operands
+            .iter()
+            .find(|operand| &*operand.name == src)
+            .map_or(Width::Pointer, |operand| operand.width);
+        Operand {
+            name: Name::from(name),
+            width,
+            source: Source::Copy(Name::from(src)),
+        }
+    }
+}
+
+pub struct Function {
+    symbol: Name,
+    operands: Vec<Operand>,
+}
+
+impl Function {
+    pub fn new(symbol: &str) -> Self {
+        Function {
+            symbol: Name::from(symbol),
+            operands: Vec::new(),
+        }
+    }
+
+    // next `int` parameter of the C signature
+    pub fn int(self, name: &str) -> Self {
+        self.operand(Operand::argument(name, Width::Int))
+    }
+
+    // next pointer parameter of the C signature
+    pub fn pointer(self, name: &str) -> Self {
+        self.operand(Operand::argument(name, Width::Pointer))
+    }
+
+    pub fn local_int(self, name: &str) -> Self {
+        self.operand(Operand::local(name, Width::Int))
+    }
+
+    pub fn local_pointer(self, name: &str) -> Self {
+        self.operand(Operand::local(name, Width::Pointer))
+    }
+
+    // local initialized with the value of the operand `src`
+    pub fn local_copy(self, name: &str, src: &str) -> Self {
+        let operand = Operand::copy(&self.operands, name, src);
+        self.operand(operand)
+    }
+
+    fn operand(mut self, operand: Operand) -> Self {
+        self.operands.push(operand);
+        self
+    }
+
+    // the operands above followed by the ones `asm` declares and they do
+    // not, which is all of them for a kernel of the template generators
+    fn declared(&self, asm: &Assembly) -> Function {
+        let operands = asm
+            .operands()
+            .iter()
+            .filter(|operand| !self.operands.iter().any(|o| o.name == operand.name))
+            .cloned();
+        Function {
+            symbol: self.symbol.clone(),
+            operands: self.operands.iter().cloned().chain(operands).collect(),
+        }
+    }
+
+    fn check_operands(&self) -> Result<(), GasError> {
+        for (i, operand) in self.operands.iter().enumerate() {
+            if self.operands[..i].iter().any(|o| o.name == operand.name) {
+                return Err(GasError::DuplicateOperand(operand.name.clone()));
+            }
+            if let Source::Copy(src) = &operand.source {
+                if !self.operands[..i].iter().any(|o| o.name == *src) {
+                    return Err(GasError::UnboundOperand(src.to_string()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // operands keep the registers of the fixed binding above; the ones left
+    // without a register take one whose operands are never live with them
+    fn allocate(&self, asm: &Assembly) -> Result<(Vec<Gpr>, Vec<bool>), GasError> {
+        self.check_operands()?;
+
+        let mut argument_registers = ARGUMENT_REGISTERS.iter();
+        let mut scratch_registers = SCRATCH_REGISTERS.iter();
+
+        let homes: Vec<Option<Gpr>> = self
+            .operands
+            .iter()
+            .map(|operand| {
+                let reg = match operand.source {
+                    Source::Argument => argument_registers
+                        .next()
+                        .or_else(|| scratch_registers.next()),
+                    Source::Uninitialized | Source::Copy(_) => scratch_registers.next(),
+                };
+                reg.copied()
+            })
+            .collect();
+        if homes.iter().all(|reg| reg.is_some()) {
+            let initialized = vec![true; homes.len()];
+            return Ok((homes.into_iter().flatten().collect(), initialized));
+        }
+
+        // the rest share a register with operands they are never live with
+        let (interference, entry) = self.interference(asm);
+        let mut regs: Vec<Gpr> = Vec::new();
+        let mut initialized: Vec<bool> = Vec::new();
+        for (i, home) in homes.iter().enumerate() {
+            let reg = match home {
+                Some(reg) => Some(*reg),
+                None => SCRATCH_REGISTERS
+                    .iter()
+                    .chain(ARGUMENT_REGISTERS.iter())
+                    .copied()
+                    .find(|reg| {
+                        regs.iter()
+                            .enumerate()
+                            .all(|(j, other)| other != reg || !interference.contains(&(i, j)))
+                    }),
+            };
+            regs.push(reg.ok_or(GasError::TooManyOperands)?);
+            initialized.push(home.is_some() || entry[i]);
+        }
+
+        Ok((regs, initialized))
+    }
+
+    // registers of the locals of the inline-asm block, where arguments have
+    // none, and whether the copies among them are needed at entry
+    fn allocate_inline(&self, asm: &Assembly) -> Result<(Vec<Option<Gpr>>, Vec<bool>), GasError> {
+        self.check_operands()?;
+
+        let (interference, entry) = self.interference(asm);
+        let mut regs: Vec<Option<Gpr>> = Vec::new();
+        for (i, operand) in self.operands.iter().enumerate() {
+            let reg = match operand.source {
+                Source::Argument => None,
+                Source::Uninitialized | Source::Copy(_) => {
+                    let reg = INLINE_REGISTERS.iter().copied().find(|reg| {
+                        regs.iter().enumerate().all(|(j, other)| {
+                            *other != Some(*reg) || !interference.contains(&(i, j))
+                        })
+                    });
+                    Some(reg.ok_or(GasError::TooManyOperands)?)
+                }
+            };
+            regs.push(reg);
+        }
+
+        Ok((regs, entry))
+    }
+
+    // pairs of operands, by index into `operands`, that cannot share a
+    // register, and the operands whose value at entry is needed
+    fn interference(&self, asm: &Assembly) -> (HashSet<(usize, usize)>, Vec<bool>) {
+        let arr = asm.instructions();
+        let live_out = liveness::live_out(arr);
+        let index = |operand: &assembly::Operand| match operand {
+            assembly::Operand::Gpr(name) => self.operands.iter().position(|o| o.name == *name),
+            _ => None,
+        };
+
+        let mut pairs: Vec<(usize, usize)> = Vec::new();
+        for (i, inst) in arr.iter().enumerate() {
+            // a copy may end up as a move onto itself
+            let moved = match *inst {
+                Instruction::MovReg(_, ref src) => index(&src.into()),
+                _ => None,
+            };
+            for def in inst.defs().iter().filter_map(index) {
+                let live = live_out[i].iter().filter_map(index);
+                pairs.extend(
+                    live.filter(|&j| j != def && Some(j) != moved)
+                        .map(|j| (def, j)),
+                );
+            }
+        }
+
+        // arguments and copies are all set up before the first instruction
+        let live_in: HashSet<usize> = match arr.first() {
+            None => HashSet::new(),
+            Some(inst) => {
+                let defs = inst.defs();
+                let iter_out = live_out[0].iter().filter(|operand| !defs.contains(operand));
+                let uses = inst.uses();
+                uses.iter().chain(iter_out).filter_map(index).collect()
+            }
+        };
+        let mut entry: Vec<bool> = self
+            .operands
+            .iter()
+            .enumerate()
+            .map(|(i, operand)| match operand.source {
+                Source::Argument | Source::Copy(_) => live_in.contains(&i),
+                Source::Uninitialized => false,
+            })
+            .collect();
+        for (i, operand) in self.operands.iter().enumerate() {
+            if let (Source::Copy(src), true) = (&operand.source, entry[i]) {
+                let j = self.operands.iter().position(|o| o.name == *src).unwrap();
+                entry[j] = true;
+            }
+        }
+        for i in 0..entry.len() {
+            for j in 0..entry.len() {
+                if i != j && entry[i] && entry[j] {
+                    pairs.push((i, j));
+                }
+            }
+        }
+
+        let interference = pairs.iter().flat_map(|&(i, j)| [(i, j), (j, i)]).collect();
+        (interference, entry)
+    }
+
+    pub fn print(&self, asm: &Assembly) -> Result<String, GasError> {
+        let text = self.declared(asm).print_symbol(asm)?;
+        Ok(format!(
+            "    .text\n{}    .section .note.GNU-stack,\"\",@progbits\n",
+            text
+        ))
+    }
+
+    // the same function in a file-scope `asm` declaration of C++, which
+    // restores the section the compiler was in. the C++ side declares the
+    // function with the arguments in the same order.
+    pub fn print_toplevel(&self, asm: &Assembly) -> Result<String, GasError> {
+        let text = self.declared(asm).print_symbol(asm)?;
+        Ok(format!(
+            "asm(R\"asm(\n    .pushsection .text\n{}    .popsection\n)asm\");\n",
+            text
+        ))
+    }
+
+    // the function from its symbol on, without a section
+    fn print_symbol(&self, asm: &Assembly) -> Result<String, GasError> {
+        let (regs, initialized) = self.allocate(asm)?;
+        let saved = self.saved_registers(&regs);
+
+        let mut output = String::new();
+        writeln!(output, "    .globl {}", self.symbol).unwrap();
+        writeln!(output, "    .type {}, @function", self.symbol).unwrap();
+        writeln!(output, "    .p2align 4").unwrap();
+        writeln!(output, "{}:", self.symbol).unwrap();
+        writeln!(output, "    .cfi_startproc").unwrap();
+
+        // prologue
+        for reg in saved.iter() {
+            writeln!(output, "    push %{}", reg.name(Width::Pointer)).unwrap();
+            writeln!(output, "    .cfi_adjust_cfa_offset 8").unwrap();
+            writeln!(
+                output,
+                "    .cfi_rel_offset %{}, 0",
+                reg.name(Width::Pointer)
+            )
+            .unwrap();
+        }
+
+        for (i, init) in self.initializers(&regs, &initialized, saved.len()) {
+            let (reg, width) = (regs[i], self.operands[i].width);
+            match init {
+                Initializer::Stack(offset) => writeln!(
+                    output,
+                    "    mov{} 0x{:x}(%rsp), %{}",
+                    suffix(width),
+                    offset,
+                    reg.name(width)
+                ),
+                Initializer::Register(src) => {
+                    writeln!(output, "    mov %{}, %{}", src.name(width), reg.name(width))
+                }
+            }
+            .unwrap();
+        }
+        writeln!(output).unwrap();
+
+        // body, with the labels as local labels
+        let local_labels = asm.local_labels();
+        for (inst, local) in asm.instructions().iter().zip(local_labels) {
+            match (inst, local) {
+                (Instruction::Label(ref name), Some(local)) => {
+                    writeln!(output, "{} /* {} */", local, name).unwrap()
+                }
+                (Instruction::JumpNotZero(ref label), Some(local)) => {
+                    writeln!(output, "    {} /* {} */", local, label).unwrap()
+                }
+                (Instruction::Comment(ref comment), _) => match &**comment {
+                    "" => writeln!(output).unwrap(),
+                    _ => writeln!(
+                        output,
+                        "    /* {} */",
+                        comment.strip_suffix(" //").unwrap_or(comment)
+                    )
+                    .unwrap(),
+                },
+                _ => writeln!(
+                    output,
+                    "    {}",
+                    self.substitute(&inst.to_string(), &regs, "%")?
+                )
+                .unwrap(),
+            }
+        }
+        writeln!(output).unwrap();
+
+        // epilogue
+        writeln!(output, "    vzeroupper").unwrap();
+        writeln!(output, "    xor %eax, %eax").unwrap();
+        for reg in saved.iter().rev() {
+            writeln!(output, "    pop %{}", reg.name(Width::Pointer)).unwrap();
+            writeln!(output, "    .cfi_adjust_cfa_offset -8").unwrap();
+            writeln!(output, "    .cfi_restore %{}", reg.name(Width::Pointer)).unwrap();
+        }
+        writeln!(output, "    ret").unwrap();
+        writeln!(output, "    .cfi_endproc").unwrap();
+        writeln!(output, "    .size {}, .-{}", self.symbol, self.symbol).unwrap();
+
+        Ok(output)
+    }
+
+    // the `asm volatile(...)` block of a C wrapper whose parameters are the
+    // arguments in lower case
+    pub fn print_inline(&self, asm: &Assembly, tab: usize) -> Result<String, GasError> {
+        let function = self.declared(asm);
+        let (regs, entry) = function.allocate_inline(asm)?;
+
+        let mut operands: Vec<(String, &str)> = Vec::new();
+        let mut registers: Vec<(&str, String)> = Vec::new();
+        let mut prologue: Vec<String> = Vec::new();
+        let mut gprs: Vec<&str> = Vec::new();
+        for (i, operand) in function.operands.iter().enumerate() {
+            let reg = match regs[i] {
+                Some(reg) => format!("%%{}", reg.name(operand.width)),
+                None => {
+                    operands.push((operand.name.to_lowercase(), &operand.name));
+                    continue;
+                }
+            };
+            // copies come after their source, which is bound by now
+            if let (Source::Copy(src), true) = (&operand.source, entry[i]) {
+                let src = match registers.iter().find(|(name, _)| *name == &**src) {
+                    Some((_, reg)) => reg.clone(),
+                    None => format!("%[{}]", src),
+                };
+                prologue.push(format!("mov {}, {}", src, reg));
+            }
+            registers.push((&operand.name, reg));
+        }
+        for reg in regs.iter().flatten() {
+            if !gprs.contains(&reg.name(Width::Pointer)) {
+                gprs.push(reg.name(Width::Pointer));
+            }
+        }
+
+        Ok(asm.print_bound(tab, &operands, &registers, &prologue, &gprs))
+    }
+
+    // same function in Intel syntax for NASM
+    pub fn print_nasm(&self, asm: &Assembly) -> Result<String, GasError> {
+        let function = self.declared(asm);
+        let (regs, initialized) = function.allocate(asm)?;
+        let saved = function.saved_registers(&regs);
+
+        let mut output = String::new();
+        writeln!(output, "    section .text").unwrap();
+        writeln!(output, "    global {}:function", function.symbol).unwrap();
+        writeln!(output, "    align 16").unwrap();
+        writeln!(output, "{}:", function.symbol).unwrap();
+
+        // prologue
+        for reg in saved.iter() {
+            writeln!(output, "    push {}", reg.name(Width::Pointer)).unwrap();
+        }
+        for (i, init) in function.initializers(&regs, &initialized, saved.len()) {
+            let (reg, width) = (regs[i], function.operands[i].width);
+            match init {
+                Initializer::Stack(offset) => writeln!(
+                    output,
+                    "    mov {}, {} [rsp + 0x{:x}]",
+                    reg.name(width),
+                    size(width),
+                    offset
+                ),
+                Initializer::Register(src) => {
+                    writeln!(output, "    mov {}, {}", reg.name(width), src.name(width))
+                }
+            }
+            .unwrap();
+        }
+        writeln!(output).unwrap();
+
+        // body, with the labels as local labels, which NASM scopes to the
+        // function symbol above
+        let labels: Vec<&Name> = asm
+            .instructions()
+            .iter()
+            .filter_map(|inst| match inst {
+                Instruction::Label(name) => Some(name),
+                _ => None,
+            })
+            .collect();
+        for inst in asm.instructions().iter() {
+            let intel = Intel(inst).to_string();
+            match inst {
+                Instruction::Comment(comment) if comment.is_empty() => writeln!(output).unwrap(),
+                Instruction::Comment(_) => writeln!(output, "    {}", intel).unwrap(),
+                Instruction::Label(name) => writeln!(output, ".{}:", name).unwrap(),
+                Instruction::JumpNotZero(label) if labels.contains(&label) => {
+                    writeln!(output, "    jnz .{}", label).unwrap()
+                }
+                _ => writeln!(output, "    {}", function.substitute(&intel, &regs, "")?).unwrap(),
+            }
+        }
+        writeln!(output).unwrap();
+
+        // epilogue
+        writeln!(output, "    vzeroupper").unwrap();
+        writeln!(output, "    xor eax, eax").unwrap();
+        for reg in saved.iter().rev() {
+            writeln!(output, "    pop {}", reg.name(Width::Pointer)).unwrap();
+        }
+        writeln!(output, "    ret").unwrap();
+        writeln!(output).unwrap();
+        writeln!(
+            output,
+            "    section .note.GNU-stack noalloc noexec nowrite progbits"
+        )
+        .unwrap();
+
+        Ok(output)
+    }
+
+    // same function as machine code
+    pub fn assemble(&self, asm: &Assembly) -> Result<Vec<u8>, GasError> {
+        let function = self.declared(asm);
+        let (regs, initialized) = function.allocate(asm)?;
+        let saved = function.saved_registers(&regs);
+        let mut encoder = Encoder::new();
+
+        // prologue
+        for reg in saved.iter() {
+            encoder.push(reg.number());
+        }
+        for (i, init) in function.initializers(&regs, &initialized, saved.len()) {
+            let (reg, width) = (regs[i], function.operands[i].width);
+            match init {
+                Initializer::Stack(offset) => {
+                    encoder.load_stack(reg.number(), width, offset as i32)
+                }
+                Initializer::Register(src) => encoder.move_reg(reg.number(), src.number(), width),
+            }
+        }
+
+        // body
+        let bind = |name: &Name| {
+            let i = function
+                .operands
+                .iter()
+                .position(|operand| operand.name == *name)
+                .ok_or_else(|| GasError::UnboundOperand(name.to_string()))?;
+            Ok((regs[i].number(), function.operands[i].width))
+        };
+        for inst in asm.instructions().iter() {
+            encoder.instruction(inst, &bind)?;
+        }
+
+        // epilogue
+        encoder.epilogue_start();
+        for reg in saved.iter().rev() {
+            encoder.pop(reg.number());
+        }
+        encoder.ret();
+
+        encoder.finish()
+    }
+
+    // relocatable ELF object exporting the function
+    pub fn object(&self, asm: &Assembly) -> Result<Vec<u8>, GasError> {
+        let text = self.assemble(asm)?;
+        Ok(elf::relocatable(&self.symbol, &text))
+    }
+
+    fn saved_registers(&self, regs: &[Gpr]) -> Vec<Gpr> {
+        SCRATCH_REGISTERS
+            .iter()
+            .filter(|reg| reg.is_callee_saved() && regs.contains(reg))
+            .copied()
+            .collect()
+    }
+
+    // operands to set up after pushing `n_saved` registers: stack arguments
+    // and copies, by index into `operands`
+    fn initializers(
+        &self,
+        regs: &[Gpr],
+        initialized: &[bool],
+        n_saved: usize,
+    ) -> Vec<(usize, Initializer)> {
+        let mut n_arguments = 0;
+        let mut inits = Vec::new();
+
+        for (i, operand) in self.operands.iter().enumerate() {
+            match operand.source {
+                Source::Argument => {
+                    // skip the pushed registers and the return address
+                    if initialized[i] && n_arguments >= ARGUMENT_REGISTERS.len() {
+                        let offset = 8 * (1 + n_saved + n_arguments - ARGUMENT_REGISTERS.len());
+                        inits.push((i, Initializer::Stack(offset)));
+                    }
+                    n_arguments += 1;
+                }
+                Source::Uninitialized => {}
+                Source::Copy(ref src) if initialized[i] => {
+                    let j = self.operands.iter().position(|o| o.name == *src).unwrap();
+                    inits.push((i, Initializer::Register(regs[j])));
+                }
+                Source::Copy(_) => {}
+            }
+        }
+
+        inits
+    }
+
+    // rewrites the inline-asm template syntax: `%%` -> `%`, `%{`/`%}` -> `{`/`}`
+    // and `%[NAME]` -> the register bound to NAME, written after `prefix`
+    fn substitute(&self, template: &str, regs: &[Gpr], prefix: &str) -> Result<String, GasError> {
+        let mut output = String::with_capacity(template.len());
+        let mut rest = template;
+
+        while let Some(i) = rest.find('%') {
+            output.push_str(&rest[..i]);
+            rest = &rest[i + 1..];
+
+            match rest.chars().next() {
+                Some(c @ ('%' | '{' | '}')) => {
+                    output.push(c);
+                    rest = &rest[1..];
+                }
+                Some('[') => {
+                    let end = rest.find(']').unwrap();
+                    let name = &rest[1..end];
+                    let i = self
+                        .operands
+                        .iter()
+                        .position(|operand| &*operand.name == name)
+                        .ok_or_else(|| GasError::UnboundOperand(name.to_string()))?;
+                    output.push_str(prefix);
+                    output.push_str(regs[i].name(self.operands[i].width));
+                    rest = &rest[end + 1..];
+                }
+                _ => output.push('%'),
+            }
+        }
+        output.push_str(rest);
+
+        Ok(output)
+    }
+}
+
+fn suffix(width: Width) -> &'static str {
+    match width {
+        Width::Int => "l",
+        Width::Pointer => "q",
+    }
+}
+
+fn size(width: Width) -> &'static str {
+    match width {
+        Width::Int => "dword",
+        Width::Pointer => "qword",
+    }
+}
+
+#[derive(Debug)]
+pub enum GasError {
+    TooManyOperands,
+    DuplicateOperand(Name),
+    UnboundOperand(String),
+    UndefinedLabel(Name),
+    DuplicateLabel(Name),
+    NoEncoding(String),
+}
+
+impl fmt::Display for GasError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooManyOperands => write!(f, "more live operands than general purpose registers"),
+            Self::DuplicateOperand(name) => write!(f, "operand `{}` is declared twice", name),
+            Self::UnboundOperand(name) => write!(f, "operand `{}` is not bound", name),
+            Self::UndefinedLabel(name) => write!(f, "label `{}` is not defined", name),
+            Self::DuplicateLabel(name) => write!(f, "label `{}` is defined twice", name),
+            Self::NoEncoding(inst) => write!(f, "no encoding for `{}`", inst),
+        }
+    }
+}
+
+impl std::error::Error for GasError {}
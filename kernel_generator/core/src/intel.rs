@@ -0,0 +1,237 @@
+// Intel syntax for `Instruction`, as accepted by NASM.
+//
+// Named operands are kept as `%[NAME]` and masks as `%{...%}` like the AT&T
+// `Display`, so the same template rewriting applies; registers carry no `%`
+// prefix, the destination comes first and memory operands are `[base + disp]`.
+
+use crate::assembly::{Instruction, PrefetchType};
+use crate::register::Gpr;
+
+use std::fmt;
+
+pub(crate) struct Intel<'a>(pub(crate) &'a Instruction);
+
+// [%[NAME]], [%[NAME] + 0x40] or [%[NAME] - 0x40]
+struct Memory<'a>(&'a Gpr, i16);
+
+impl fmt::Display for Memory<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.1 {
+            0 => write!(f, "[%[{}]]", self.0),
+            imm if imm > 0 => write!(f, "[%[{}] + 0x{:x}]", self.0, imm),
+            imm => write!(f, "[%[{}] - 0x{:x}]", self.0, -(imm as i32)),
+        }
+    }
+}
+
+// 0x40 or -0x40
+struct Immediate(i16);
+
+impl fmt::Display for Immediate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 >= 0 {
+            true => write!(f, "0x{:x}", self.0),
+            false => write!(f, "-0x{:x}", -(self.0 as i32)),
+        }
+    }
+}
+
+impl fmt::Display for Intel<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self.0 {
+            Instruction::Comment(ref comment) => match &**comment {
+                "" => write!(f, ""),
+                // the stage markers close with `//` for inline asm
+                _ => write!(f, "; {}", comment.strip_suffix(" //").unwrap_or(comment)),
+            },
+            Instruction::Nop => write!(f, "nop"),
+            Instruction::Label(ref name) => write!(f, "{}:", name),
+            Instruction::JumpNotZero(ref label) => write!(f, "jnz {}", label),
+
+            Instruction::MaskOn(k) => write!(f, "kxnorw {}, k0, k0", k),
+            Instruction::MaskSet(k, ref reg_name) => write!(f, "kmovw {}, %[{}]", k, reg_name),
+            Instruction::MaskNot(k_dst, k_src) => write!(f, "knotw {}, {}", k_dst, k_src),
+
+            Instruction::AddImmediate(ref reg_name, imm) => {
+                write!(f, "add %[{}], {}", reg_name, Immediate(imm))
+            }
+            Instruction::SubImmediate(ref reg_name, imm) => {
+                write!(f, "sub %[{}], {}", reg_name, Immediate(imm))
+            }
+            Instruction::SetImmediate(ref reg_name, imm) => {
+                write!(f, "mov %[{}], {}", reg_name, Immediate(imm))
+            }
+            Instruction::ShiftRight(ref reg_name, imm) => match imm {
+                1 => write!(f, "sar %[{}], 1", reg_name),
+                _ => write!(f, "sar %[{}], 0x{:x}", reg_name, imm),
+            },
+            Instruction::MovReg(ref reg_dst, ref reg_src) => {
+                write!(f, "mov %[{}], %[{}]", reg_dst, reg_src)
+            }
+
+            Instruction::MovF64x8(zmm_dst, zmm_src) => {
+                write!(f, "vmovupd {}, {}", zmm_dst, zmm_src)
+            }
+            Instruction::MovF64x2(xmm_dst, xmm_src) => {
+                write!(f, "vmovupd {}, {}", xmm_dst, xmm_src)
+            }
+            Instruction::MovI32x8(ymm_dst, ymm_src) => {
+                write!(f, "vmovupd {}, {}", ymm_dst, ymm_src)
+            }
+
+            Instruction::LoadF64x8(zmm, ref reg_base, imm_offset) => {
+                write!(f, "vmovupd {}, {}", zmm, Memory(reg_base, imm_offset))
+            }
+            Instruction::LoadF64x1(xmm, ref reg_base, imm_offset) => {
+                write!(f, "vmovsd {}, {}", xmm, Memory(reg_base, imm_offset))
+            }
+            Instruction::LoadI32x8(ymm, ref reg_base, imm_offset) => {
+                write!(f, "vmovdqa {}, {}", ymm, Memory(reg_base, imm_offset))
+            }
+            Instruction::StoreF64x8(ref reg_base, imm_offset, zmm) => {
+                write!(f, "vmovupd {}, {}", Memory(reg_base, imm_offset), zmm)
+            }
+            Instruction::StoreF64x1(ref reg_base, imm_offset, xmm) => {
+                write!(f, "vmovsd {}, {}", Memory(reg_base, imm_offset), xmm)
+            }
+            Instruction::GatherF64x8(zmm, ref reg_base, ymm_idx, k) => write!(
+                f,
+                "vgatherdpd {}%{{{}%}}, [%[{}] + {}*8]",
+                zmm, k, reg_base, ymm_idx
+            ),
+            Instruction::Prefetch(prefetch_type, ref reg_base, imm_offset) => {
+                let inst = match prefetch_type {
+                    PrefetchType::NTA => "prefetchnta",
+                    PrefetchType::T0 => "prefetcht0",
+                    PrefetchType::T1 => "prefetcht1",
+                    PrefetchType::T2 => "prefetcht2",
+                };
+                write!(f, "{} {}", inst, Memory(reg_base, imm_offset))
+            }
+
+            Instruction::AddF64x8(dst, src0, src1) => {
+                write!(f, "vaddpd {}, {}, {}", dst, src0, src1)
+            }
+            Instruction::AddF64x4(dst, src0, src1) => {
+                write!(f, "vaddpd {}, {}, {}", dst, src0, src1)
+            }
+            Instruction::AddF64x2(dst, src0, src1) => {
+                write!(f, "vaddpd {}, {}, {}", dst, src0, src1)
+            }
+            Instruction::AddF64x1(dst, src0, src1) => {
+                write!(f, "vaddsd {}, {}, {}", dst, src0, src1)
+            }
+            Instruction::LoadAddF64x8(dst, src0, ref reg_base1, imm_offset1) => write!(
+                f,
+                "vaddpd {}, {}, {}",
+                dst,
+                src0,
+                Memory(reg_base1, imm_offset1)
+            ),
+            Instruction::LoadAddF64x1(dst, src0, ref reg_base1, imm_offset1) => write!(
+                f,
+                "vaddsd {}, {}, {}",
+                dst,
+                src0,
+                Memory(reg_base1, imm_offset1)
+            ),
+            Instruction::MulF64x8(dst, src0, src1) => {
+                write!(f, "vmulpd {}, {}, {}", dst, src0, src1)
+            }
+            Instruction::MulF64x1(dst, src0, src1) => {
+                write!(f, "vmulsd {}, {}, {}", dst, src0, src1)
+            }
+            Instruction::LoadMulF64x8(dst, src0, ref reg_base1, imm_offset1) => write!(
+                f,
+                "vmulpd {}, {}, {}",
+                dst,
+                src0,
+                Memory(reg_base1, imm_offset1)
+            ),
+            Instruction::LoadMulF64x1(dst, src0, ref reg_base1, imm_offset1) => write!(
+                f,
+                "vmulsd {}, {}, {}",
+                dst,
+                src0,
+                Memory(reg_base1, imm_offset1)
+            ),
+            Instruction::MulAddF64x8(dst, src0, src1) => {
+                write!(f, "vfmadd231pd {}, {}, {}", dst, src0, src1)
+            }
+            Instruction::MulAddF64x1(dst, src0, src1) => {
+                write!(f, "vfmadd231sd {}, {}, {}", dst, src0, src1)
+            }
+            Instruction::LoadMulAddF64x8(dst, src0, ref reg_base1, imm_offset1) => write!(
+                f,
+                "vfmadd231pd {}, {}, {}",
+                dst,
+                src0,
+                Memory(reg_base1, imm_offset1)
+            ),
+            Instruction::LoadMulAddF64x1(dst, src0, ref reg_base1, imm_offset1) => write!(
+                f,
+                "vfmadd231sd {}, {}, {}",
+                dst,
+                src0,
+                Memory(reg_base1, imm_offset1)
+            ),
+            Instruction::NMulSubF64x8(dst, src0, src1) => {
+                write!(f, "vfnmsub231pd {}, {}, {}", dst, src0, src1)
+            }
+            Instruction::LoadNMulSubF64x8(dst, src0, ref reg_base1, imm_offset1) => write!(
+                f,
+                "vfnmsub231pd {}, {}, {}",
+                dst,
+                src0,
+                Memory(reg_base1, imm_offset1)
+            ),
+            Instruction::DivF64x8(dst, src0, src1) => {
+                write!(f, "vdivpd {}, {}, {}", dst, src0, src1)
+            }
+            Instruction::DivF64x1(dst, src0, src1) => {
+                write!(f, "vdivsd {}, {}, {}", dst, src0, src1)
+            }
+            Instruction::LoadDivF64x8(dst, src0, ref reg_base1, imm_offset1) => write!(
+                f,
+                "vdivpd {}, {}, {}",
+                dst,
+                src0,
+                Memory(reg_base1, imm_offset1)
+            ),
+            Instruction::LoadDivF64x1(dst, src0, ref reg_base1, imm_offset1) => write!(
+                f,
+                "vdivsd {}, {}, {}",
+                dst,
+                src0,
+                Memory(reg_base1, imm_offset1)
+            ),
+
+            Instruction::LUMix4F64x8(zmm_dst, ymm_src0, zmm_src1) => write!(
+                f,
+                "vinsertf64x4 {}, {}, {}, 0x0",
+                zmm_dst, zmm_src1, ymm_src0
+            ),
+            Instruction::ULMix4F64x8(zmm_dst, zmm_src0, zmm_src1) => {
+                write!(f, "valignq {}, {}, {}, 0x4", zmm_dst, zmm_src1, zmm_src0)
+            }
+            Instruction::Mix2F64x8Mask(zmm_dst, zmm_src, k) => {
+                write!(f, "vpermpd {}%{{{}%}}, {}, 0x4e", zmm_dst, k, zmm_src)
+            }
+            Instruction::LUMix1F64x8(zmm_dst, zmm_src0, zmm_src1) => {
+                write!(f, "vshufpd {}, {}, {}, 0xaa", zmm_dst, zmm_src0, zmm_src1)
+            }
+            Instruction::ULMix1F64x8(zmm_dst, zmm_src0, zmm_src1) => {
+                write!(f, "vshufpd {}, {}, {}, 0x55", zmm_dst, zmm_src0, zmm_src1)
+            }
+            Instruction::ExtractU4F64x8(ymm_dst, zmm_src) => {
+                write!(f, "vextractf64x4 {}, {}, 0x1", ymm_dst, zmm_src)
+            }
+            Instruction::ExtractU2F64x4(xmm_dst, ymm_src) => {
+                write!(f, "vextractf128 {}, {}, 0x1", xmm_dst, ymm_src)
+            }
+            Instruction::Fold1AddF64x2(xmm_dst, xmm_src) => {
+                write!(f, "vhaddpd {}, {}, {}", xmm_dst, xmm_src, xmm_src)
+            }
+        }
+    }
+}
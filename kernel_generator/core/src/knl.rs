@@ -0,0 +1,431 @@
+// Static cycle estimate for the loops of a generated kernel on Knights Landing.
+//
+// A loop is the code between a `Label` and the last `JumpNotZero` back to it.
+// When an earlier `JumpNotZero` jumps to the same label, it is the inner loop
+// of a rowblock: its body runs `trip_count` times per outer iteration, where
+// the trip count is the immediate loaded into its counter before the label.
+//
+// One outer iteration is costed twice: by the throughput of every unit it
+// occupies, and by the longest dependency chain carried from one iteration to
+// the next. The larger bound is the estimate. The table is for one core of a
+// Xeon Phi 7250 with every access hitting L1; cache misses, memory
+// dependences and hyperthreads are not modelled, so the numbers are meant
+// for ranking configurations against each other, not for predicting runtime.
+
+use crate::assembly::{Instruction, Operand};
+use crate::name::Name;
+use crate::Assembly;
+
+use std::collections::HashMap;
+use std::fmt;
+
+pub(crate) const DECODE_WIDTH: f64 = 2.0;
+pub(crate) const N_VPUS: f64 = 2.0;
+pub(crate) const N_LOAD_PORTS: f64 = 2.0;
+pub(crate) const N_STORE_PORTS: f64 = 1.0;
+pub(crate) const N_ALUS: f64 = 2.0;
+
+// cycles the unit is busy, not pipelined
+const GATHER_CYCLES: f64 = 8.0;
+const DIV_F64X8_CYCLES: f64 = 32.0;
+const DIV_F64X1_CYCLES: f64 = 16.0;
+
+const FMA_LATENCY: f64 = 6.0;
+const SHUFFLE_LATENCY: f64 = 3.0;
+const MOVE_LATENCY: f64 = 2.0;
+pub(crate) const LOAD_LATENCY: f64 = 5.0;
+const GATHER_LATENCY: f64 = 20.0;
+const DIV_F64X8_LATENCY: f64 = 32.0;
+const DIV_F64X1_LATENCY: f64 = 16.0;
+const ALU_LATENCY: f64 = 1.0;
+
+const NONZEROS_PER_GATHER: u32 = 8;
+
+// outer iterations replayed to find the carried dependency chain
+const N_REPLAYS: usize = 8;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Bottleneck {
+    Frontend,
+    Vpu,
+    Divider,
+    Load,
+    Store,
+    Gather,
+    Integer,
+    Latency,
+}
+
+impl fmt::Display for Bottleneck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Frontend => write!(f, "frontend"),
+            Self::Vpu => write!(f, "vpu"),
+            Self::Divider => write!(f, "divider"),
+            Self::Load => write!(f, "load"),
+            Self::Store => write!(f, "store"),
+            Self::Gather => write!(f, "gather"),
+            Self::Integer => write!(f, "integer"),
+            Self::Latency => write!(f, "latency"),
+        }
+    }
+}
+
+pub struct LoopEstimate {
+    pub label: Name,
+    pub trip_count: u32,
+    pub n_instructions: usize,
+    pub n_nonzeros: u32,
+    pub cycles: f64,
+    pub bottleneck: Bottleneck,
+}
+
+impl LoopEstimate {
+    pub fn cycles_per_nonzero(&self) -> Option<f64> {
+        match self.n_nonzeros {
+            0 => None,
+            n => Some(self.cycles / n as f64),
+        }
+    }
+}
+
+impl fmt::Display for LoopEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {:.1} cycles per iteration, bound by {}",
+            self.label, self.cycles, self.bottleneck
+        )?;
+        match self.cycles_per_nonzero() {
+            Some(cycles) => write!(f, ", {:.3} cycles per nonzero", cycles),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ModelError {
+    UndefinedLabel(Name),
+    UnknownTripCount(Name),
+    TooManyJumps(Name),
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UndefinedLabel(label) => write!(f, "jnz to undefined label `{}`", label),
+            Self::UnknownTripCount(label) => {
+                write!(
+                    f,
+                    "trip count of inner loop `{}` is not an immediate",
+                    label
+                )
+            }
+            Self::TooManyJumps(label) => {
+                write!(f, "more than two loops jump back to `{}`", label)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ModelError {}
+
+pub fn estimate(asm: &Assembly) -> Result<Vec<LoopEstimate>, ModelError> {
+    let arr = asm.instructions();
+
+    let mut labels: Vec<(&Name, usize, Vec<usize>)> = Vec::new();
+    for (i, inst) in arr.iter().enumerate() {
+        match *inst {
+            Instruction::Label(ref name) => labels.push((name, i, Vec::new())),
+            Instruction::JumpNotZero(ref name) => {
+                match labels.iter_mut().rev().find(|(label, _, _)| *label == name) {
+                    Some((_, _, jumps)) => jumps.push(i),
+                    None => return Err(ModelError::UndefinedLabel(name.clone())),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    labels
+        .into_iter()
+        .filter(|(_, _, jumps)| !jumps.is_empty())
+        .map(|(label, start, jumps)| match jumps[..] {
+            [end] => Ok(estimate_loop(
+                label,
+                1,
+                arr[start + 1..=end].iter().collect(),
+            )),
+            [inner_end, end] => {
+                let trip_count = trip_count(arr, start, inner_end)
+                    .ok_or_else(|| ModelError::UnknownTripCount(label.clone()))?;
+                let body = &arr[start + 1..=inner_end];
+                let tail = &arr[inner_end + 1..=end];
+                let trace = (0..trip_count)
+                    .flat_map(|_| body.iter())
+                    .chain(tail.iter())
+                    .collect();
+                Ok(estimate_loop(label, trip_count, trace))
+            }
+            _ => Err(ModelError::TooManyJumps(label.clone())),
+        })
+        .collect()
+}
+
+// the counter is the register decremented right before the inner jnz, and
+// the trip count is the last immediate moved into it before the label.
+fn trip_count(arr: &[Instruction], start: usize, inner_end: usize) -> Option<u32> {
+    let (counter, step) = arr[start + 1..inner_end]
+        .iter()
+        .rev()
+        .find(|inst| !matches!(inst, Instruction::Comment(_)))
+        .and_then(|inst| match *inst {
+            Instruction::SubImmediate(ref reg, step) if step > 0 => Some((reg, step)),
+            _ => None,
+        })?;
+
+    arr[..start]
+        .iter()
+        .rev()
+        .find_map(|inst| match *inst {
+            Instruction::SetImmediate(ref reg, imm) if reg == counter => Some(imm),
+            _ => None,
+        })
+        .filter(|&imm| imm > 0)
+        .map(|imm| (imm as u32).div_ceil(step as u32))
+}
+
+fn estimate_loop(label: &Name, trip_count: u32, trace: Vec<&Instruction>) -> LoopEstimate {
+    let mut usage = Usage::default();
+    let mut n_instructions = 0;
+    let mut n_nonzeros = 0;
+    for inst in trace.iter() {
+        let cost = cost(inst);
+        if let Some(unit) = cost.unit {
+            usage.add(unit, cost.occupancy, cost.load);
+            n_instructions += 1;
+        }
+        if let Instruction::GatherF64x8(..) = inst {
+            n_nonzeros += NONZEROS_PER_GATHER;
+        }
+    }
+
+    let (cycles, bottleneck) = usage.bound();
+    let latency = carried_latency(&trace);
+    let (cycles, bottleneck) = match latency > cycles {
+        true => (latency, Bottleneck::Latency),
+        false => (cycles, bottleneck),
+    };
+
+    LoopEstimate {
+        label: label.clone(),
+        trip_count,
+        n_instructions,
+        n_nonzeros,
+        cycles,
+        bottleneck,
+    }
+}
+
+// replays the trace with unlimited units, so every instruction issues as soon
+// as its operands are ready. the growth of the finish time per replay is the
+// latency of the carried chain.
+fn carried_latency(trace: &[&Instruction]) -> f64 {
+    let mut ready: HashMap<Operand, f64> = HashMap::new();
+    let mut finish = [0.0; N_REPLAYS];
+
+    for replay in 0..N_REPLAYS {
+        let mut end: f64 = 0.0;
+        for inst in trace.iter() {
+            let cost = cost(inst);
+            let start = inst
+                .uses()
+                .iter()
+                .map(|operand| {
+                    let time = ready.get(operand).copied().unwrap_or(0.0);
+                    match (operand, cost.load) {
+                        (Operand::Gpr(_), true) => time + LOAD_LATENCY,
+                        _ => time,
+                    }
+                })
+                .fold(0.0, f64::max);
+            let done = start + cost.latency;
+            for operand in inst.defs() {
+                ready.insert(operand, done);
+            }
+            end = end.max(done);
+        }
+        finish[replay] = match replay {
+            0 => end,
+            _ => end.max(finish[replay - 1]),
+        };
+    }
+
+    let half = N_REPLAYS / 2;
+    (finish[N_REPLAYS - 1] - finish[half - 1]) / (N_REPLAYS - half) as f64
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum Unit {
+    Frontend,
+    Vpu,
+    Divider,
+    Store,
+    Load,
+    Gather,
+    Integer,
+}
+
+pub(crate) struct Cost {
+    pub(crate) unit: Option<Unit>,
+    pub(crate) occupancy: f64,
+    pub(crate) load: bool,
+    pub(crate) latency: f64,
+}
+
+impl Cost {
+    const fn new(unit: Unit, latency: f64) -> Self {
+        Cost {
+            unit: Some(unit),
+            occupancy: 1.0,
+            load: false,
+            latency,
+        }
+    }
+
+    // a memory operand takes a load port, and its base register is needed
+    // the load latency before the operation itself
+    const fn load(mut self) -> Self {
+        self.load = true;
+        self
+    }
+
+    const fn occupancy(mut self, cycles: f64) -> Self {
+        self.occupancy = cycles;
+        self
+    }
+}
+
+pub(crate) fn cost(inst: &Instruction) -> Cost {
+    match inst {
+        Instruction::Comment(_) | Instruction::Label(_) => Cost {
+            unit: None,
+            occupancy: 0.0,
+            load: false,
+            latency: 0.0,
+        },
+        Instruction::Nop => Cost::new(Unit::Frontend, 0.0),
+        Instruction::JumpNotZero(_) => Cost::new(Unit::Integer, 0.0),
+
+        Instruction::MaskOn(_) | Instruction::MaskSet(_, _) | Instruction::MaskNot(_, _) => {
+            Cost::new(Unit::Vpu, MOVE_LATENCY)
+        }
+
+        Instruction::AddImmediate(_, _)
+        | Instruction::SubImmediate(_, _)
+        | Instruction::SetImmediate(_, _)
+        | Instruction::ShiftRight(_, _)
+        | Instruction::MovReg(_, _) => Cost::new(Unit::Integer, ALU_LATENCY),
+
+        Instruction::MovF64x8(_, _) | Instruction::MovF64x2(_, _) | Instruction::MovI32x8(_, _) => {
+            Cost::new(Unit::Vpu, MOVE_LATENCY)
+        }
+
+        Instruction::LoadF64x8(_, _, _)
+        | Instruction::LoadF64x1(_, _, _)
+        | Instruction::LoadI32x8(_, _, _) => Cost::new(Unit::Load, LOAD_LATENCY),
+        Instruction::Prefetch(_, _, _) => Cost::new(Unit::Load, 0.0),
+        Instruction::StoreF64x8(_, _, _) | Instruction::StoreF64x1(_, _, _) => {
+            Cost::new(Unit::Store, 0.0)
+        }
+        Instruction::GatherF64x8(_, _, _, _) => {
+            Cost::new(Unit::Gather, GATHER_LATENCY).occupancy(GATHER_CYCLES)
+        }
+
+        Instruction::AddF64x8(_, _, _)
+        | Instruction::AddF64x4(_, _, _)
+        | Instruction::AddF64x2(_, _, _)
+        | Instruction::AddF64x1(_, _, _)
+        | Instruction::MulF64x8(_, _, _)
+        | Instruction::MulF64x1(_, _, _)
+        | Instruction::MulAddF64x8(_, _, _)
+        | Instruction::MulAddF64x1(_, _, _)
+        | Instruction::NMulSubF64x8(_, _, _)
+        | Instruction::Fold1AddF64x2(_, _) => Cost::new(Unit::Vpu, FMA_LATENCY),
+        Instruction::LoadAddF64x8(_, _, _, _)
+        | Instruction::LoadAddF64x1(_, _, _, _)
+        | Instruction::LoadMulF64x8(_, _, _, _)
+        | Instruction::LoadMulF64x1(_, _, _, _)
+        | Instruction::LoadMulAddF64x8(_, _, _, _)
+        | Instruction::LoadMulAddF64x1(_, _, _, _)
+        | Instruction::LoadNMulSubF64x8(_, _, _, _) => Cost::new(Unit::Vpu, FMA_LATENCY).load(),
+
+        Instruction::DivF64x8(_, _, _) => {
+            Cost::new(Unit::Divider, DIV_F64X8_LATENCY).occupancy(DIV_F64X8_CYCLES)
+        }
+        Instruction::DivF64x1(_, _, _) => {
+            Cost::new(Unit::Divider, DIV_F64X1_LATENCY).occupancy(DIV_F64X1_CYCLES)
+        }
+        Instruction::LoadDivF64x8(_, _, _, _) => Cost::new(Unit::Divider, DIV_F64X8_LATENCY)
+            .occupancy(DIV_F64X8_CYCLES)
+            .load(),
+        Instruction::LoadDivF64x1(_, _, _, _) => Cost::new(Unit::Divider, DIV_F64X1_LATENCY)
+            .occupancy(DIV_F64X1_CYCLES)
+            .load(),
+
+        Instruction::LUMix4F64x8(_, _, _)
+        | Instruction::ULMix4F64x8(_, _, _)
+        | Instruction::Mix2F64x8Mask(_, _, _)
+        | Instruction::LUMix1F64x8(_, _, _)
+        | Instruction::ULMix1F64x8(_, _, _)
+        | Instruction::ExtractU4F64x8(_, _)
+        | Instruction::ExtractU2F64x4(_, _) => Cost::new(Unit::Vpu, SHUFFLE_LATENCY),
+    }
+}
+
+// busy cycles of each unit, already divided by the number of units
+#[derive(Default)]
+struct Usage {
+    frontend: f64,
+    vpu: f64,
+    divider: f64,
+    load: f64,
+    store: f64,
+    gather: f64,
+    integer: f64,
+}
+
+impl Usage {
+    fn add(&mut self, unit: Unit, occupancy: f64, load: bool) -> () {
+        self.frontend += 1.0 / DECODE_WIDTH;
+        if load {
+            self.load += 1.0 / N_LOAD_PORTS;
+        }
+        match unit {
+            Unit::Frontend => {}
+            Unit::Vpu => self.vpu += occupancy / N_VPUS,
+            Unit::Divider => self.divider += occupancy,
+            Unit::Load => self.load += occupancy / N_LOAD_PORTS,
+            Unit::Store => self.store += occupancy / N_STORE_PORTS,
+            Unit::Gather => self.gather += occupancy,
+            Unit::Integer => self.integer += occupancy / N_ALUS,
+        }
+    }
+
+    fn bound(&self) -> (f64, Bottleneck) {
+        [
+            (self.frontend, Bottleneck::Frontend),
+            (self.vpu, Bottleneck::Vpu),
+            (self.divider, Bottleneck::Divider),
+            (self.load, Bottleneck::Load),
+            (self.store, Bottleneck::Store),
+            (self.gather, Bottleneck::Gather),
+            (self.integer, Bottleneck::Integer),
+        ]
+        .into_iter()
+        .fold((0.0, Bottleneck::Frontend), |acc, x| match x.0 > acc.0 {
+            true => x,
+            false => acc,
+        })
+    }
+}
@@ -1,57 +1,151 @@
 mod assembly;
+pub mod config;
+mod elf;
+mod emulator;
+mod encoder;
+pub mod gas;
+mod intel;
+pub mod knl;
+mod liveness;
+pub mod name;
+mod peephole;
+pub mod pipeline;
+pub mod reference;
+mod register;
+mod rulebook;
+mod schedule;
 pub mod sparse_matrix;
+mod spill;
 pub mod tools;
+pub mod trace;
 
 pub use assembly::{Assembly, PrefetchType};
-
-use std::collections::HashMap;
+pub use emulator::{EmulateError, Emulator, Memory};
+pub use liveness::{Register, VerifyError};
+pub use name::Name;
+pub use peephole::PeepholeReport;
+pub use pipeline::Pipeline;
+pub use register::{Gpr, Mask, Xmm, Ymm, Zmm, N_MASKS, N_REGISTERS};
+pub use spill::{SpillReport, SPILL_AREA_BYTES};
+pub use trace::{Firing, Trace};
+
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
 
+// hands out physical registers first, then spilled ones
 pub struct RegisterPool {
-    avail: [bool; 32],
+    avail: [bool; N_REGISTERS],
+    masks: MaskPool,
 }
 
 impl RegisterPool {
-    pub fn new(avail_registers: [bool; 32]) -> Self {
+    pub fn new(avail_registers: [bool; N_REGISTERS], avail_masks: [bool; N_MASKS]) -> Self {
         RegisterPool {
             avail: avail_registers,
+            masks: MaskPool::new(avail_masks),
+        }
+    }
+
+    pub fn get(&mut self) -> Result<Zmm, GenerateError> {
+        for i in 0..N_REGISTERS {
+            if self.avail[i] == true {
+                self.avail[i] = false;
+                return Ok(Zmm::new(i as u8));
+            }
+        }
+        Err(GenerateError::NoRegister)
+    }
+
+    pub fn alloc(&mut self, zmm: Zmm) -> Result<(), GenerateError> {
+        match self.avail[zmm.index() as usize] {
+            true => {
+                self.avail[zmm.index() as usize] = false;
+                Ok(())
+            }
+            false => Err(GenerateError::RegisterTaken(zmm)),
         }
     }
 
-    pub fn get(&mut self) -> u8 {
-        for i in 0..32 {
+    pub fn free(&mut self, zmm: Zmm) -> () {
+        self.avail[zmm.index() as usize] = true;
+    }
+
+    pub fn avail_list(&self) -> &[bool; N_REGISTERS] {
+        &self.avail
+    }
+
+    pub fn masks(&mut self) -> &mut MaskPool {
+        &mut self.masks
+    }
+}
+
+// hands out k1-k7, lowest first; avail[i] stands for k(i + 1)
+pub struct MaskPool {
+    avail: [bool; N_MASKS],
+}
+
+impl MaskPool {
+    pub fn new(avail_masks: [bool; N_MASKS]) -> Self {
+        MaskPool { avail: avail_masks }
+    }
+
+    pub fn get(&mut self) -> Result<Mask, GenerateError> {
+        for i in 0..N_MASKS {
             if self.avail[i] == true {
                 self.avail[i] = false;
-                return i as u8;
+                return Ok(Mask::new(i as u8 + 1));
             }
         }
-        panic!("[RegisterPool] no avail register remain.");
+        Err(GenerateError::NoMask)
     }
 
-    pub fn alloc(&mut self, i: u8) -> () {
-        assert!(i < 32);
-        assert!(self.avail[i as usize] == true);
-        self.avail[i as usize] = false;
+    pub fn alloc(&mut self, k: Mask) -> Result<(), GenerateError> {
+        match self.avail[k.index() as usize - 1] {
+            true => {
+                self.avail[k.index() as usize - 1] = false;
+                Ok(())
+            }
+            false => Err(GenerateError::MaskTaken(k)),
+        }
     }
 
-    pub fn free(&mut self, i: u8) -> () {
-        assert!(i < 32);
-        self.avail[i as usize] = true;
+    pub fn free(&mut self, k: Mask) -> () {
+        self.avail[k.index() as usize - 1] = true;
     }
 
-    pub fn avail_list(&self) -> &[bool; 32] {
+    pub fn avail_list(&self) -> &[bool; N_MASKS] {
         &self.avail
     }
 }
 
+#[derive(Clone, Copy, Debug)]
 pub struct State {
     pub id: u32,
-    pub idx: u8,
-    pub reg: u8,
+    pub idx: u32,
+    pub reg: Option<Zmm>,
 }
 
-pub struct Rule<T: ?Sized> {
-    pub condition: Condition,
+impl State {
+    // a state without a register, from a variant of the state enum of a
+    // rulebook, which converts into its id
+    pub fn new(id: impl Into<u32>, idx: u32) -> Self {
+        State {
+            id: id.into(),
+            idx,
+            reg: None,
+        }
+    }
+
+    // the register a rule expects the state to carry
+    pub fn register(&self) -> Result<Zmm, GenerateError> {
+        self.reg.ok_or(GenerateError::MissingRegister(*self))
+    }
+}
+
+pub struct Rule<T: ?Sized + 'static> {
+    // how errors and traces refer to the rule, unique within its rulebook
+    pub name: &'static str,
+    pub condition: Condition<T>,
     pub callback: fn(
         config: &T,
         &mut RegisterPool,
@@ -59,60 +153,212 @@ pub struct Rule<T: ?Sized> {
     ) -> Result<(Assembly, Vec<State>), GenerateError>,
 }
 
-pub enum Condition {
-    Single { id: u32 },
-    SameId { id: u32, n_states: u8, idx_dist: u8 },
-    SameIdx { id0: u32, id1: u32 },
+// rules are tried by descending priority, and in rulebook order within a
+// priority. a rule is priority 0 unless its condition is wrapped in
+// `Priority`
+pub enum Condition<T: ?Sized + 'static> {
+    Single {
+        id: u32,
+    },
+    SameId {
+        id: u32,
+        n_states: u8,
+        idx_dist: u8,
+    },
+    SameIdx {
+        id0: u32,
+        id1: u32,
+    },
+    // one state of each id, all at the same idx, in the order of `ids`
+    Join {
+        ids: &'static [u32],
+    },
+    // `condition`, for the configs `guard` accepts
+    Guard {
+        guard: fn(&T) -> bool,
+        condition: &'static Condition<T>,
+    },
+    Priority {
+        priority: i32,
+        condition: &'static Condition<T>,
+    },
 }
 
+impl<T: ?Sized + 'static> Condition<T> {
+    // a condition no set of states can meet
+    fn is_valid(&self) -> bool {
+        match *self {
+            Condition::Single { .. } => true,
+            Condition::SameId {
+                n_states, idx_dist, ..
+            } => n_states > 0 && idx_dist > 0,
+            Condition::SameIdx { id0, id1 } => id0 != id1,
+            Condition::Join { ids } => {
+                !ids.is_empty() && ids.iter().collect::<HashSet<&u32>>().len() == ids.len()
+            }
+            Condition::Guard { condition, .. } => condition.is_valid(),
+            Condition::Priority { condition, .. } => condition.is_valid(),
+        }
+    }
+
+    fn priority(&self) -> i32 {
+        match *self {
+            Condition::Guard { condition, .. } => condition.priority(),
+            Condition::Priority { priority, .. } => priority,
+            _ => 0,
+        }
+    }
+
+    fn accepts(&self, config: &T) -> bool {
+        match *self {
+            Condition::Guard { guard, condition } => guard(config) && condition.accepts(config),
+            Condition::Priority { condition, .. } => condition.accepts(config),
+            _ => true,
+        }
+    }
+
+    // ids of the states the condition takes
+    fn ids(&self) -> Vec<u32> {
+        match *self {
+            Condition::Single { id } | Condition::SameId { id, .. } => Vec::from([id]),
+            Condition::SameIdx { id0, id1 } => Vec::from([id0, id1]),
+            Condition::Join { ids } => ids.to_vec(),
+            Condition::Guard { condition, .. } | Condition::Priority { condition, .. } => {
+                condition.ids()
+            }
+        }
+    }
+}
+
+// guards are functions, so only the shape of the condition is printed
+impl<T: ?Sized + 'static> fmt::Debug for Condition<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Condition::Single { id } => f.debug_struct("Single").field("id", id).finish(),
+            Condition::SameId {
+                id,
+                n_states,
+                idx_dist,
+            } => f
+                .debug_struct("SameId")
+                .field("id", id)
+                .field("n_states", n_states)
+                .field("idx_dist", idx_dist)
+                .finish(),
+            Condition::SameIdx { id0, id1 } => f
+                .debug_struct("SameIdx")
+                .field("id0", id0)
+                .field("id1", id1)
+                .finish(),
+            Condition::Join { ids } => f.debug_struct("Join").field("ids", ids).finish(),
+            Condition::Guard { condition, .. } => f
+                .debug_struct("Guard")
+                .field("condition", condition)
+                .finish(),
+            Condition::Priority {
+                priority,
+                condition,
+            } => f
+                .debug_struct("Priority")
+                .field("priority", priority)
+                .field("condition", condition)
+                .finish(),
+        }
+    }
+}
+
+// two rules of the same priority that can take states of the same ids, so
+// that only their order in the rulebook decides which one fires
+#[derive(Debug)]
+pub struct Overlap {
+    pub rules: (usize, usize),
+    pub names: (&'static str, &'static str),
+    pub ids: Vec<u32>,
+}
+
+impl fmt::Display for Overlap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ids: Vec<String> = self.ids.iter().map(|id| id.to_string()).collect();
+        write!(
+            f,
+            "rules `{}` and `{}` can both take states of id {}",
+            self.names.0,
+            self.names.1,
+            ids.join(", ")
+        )
+    }
+}
+
+fn overlaps<T: Generate + ?Sized>(generator: &T) -> Vec<Overlap> {
+    let rulebook = generator.rulebook();
+    let mut overlaps = Vec::new();
+    for (i, rule0) in rulebook.iter().enumerate() {
+        for (j, rule1) in rulebook.iter().enumerate().skip(i + 1) {
+            let (cond0, cond1) = (&rule0.condition, &rule1.condition);
+            if cond0.priority() != cond1.priority()
+                || !cond0.accepts(generator)
+                || !cond1.accepts(generator)
+            {
+                continue;
+            }
+
+            let ids1 = cond1.ids();
+            let mut ids: Vec<u32> = cond0
+                .ids()
+                .into_iter()
+                .filter(|id| ids1.contains(id))
+                .collect();
+            ids.sort();
+            ids.dedup();
+            if !ids.is_empty() {
+                overlaps.push(Overlap {
+                    rules: (i, j),
+                    names: (rule0.name, rule1.name),
+                    ids,
+                });
+            }
+        }
+    }
+
+    overlaps
+}
+
+// live states by id, each ordered by idx so that matching always picks the
+// lowest indices first
 struct StateManager {
-    state_map: HashMap<u32, [Option<u8>; 32]>,
+    state_map: HashMap<u32, BTreeMap<u32, Option<Zmm>>>,
 }
 
 impl StateManager {
-    fn new(states: Vec<State>) -> Self {
-        let mut state_manager = StateManager {
+    fn new() -> Self {
+        StateManager {
             state_map: HashMap::new(),
-        };
-        state_manager.insert_states(states);
-
-        state_manager
+        }
     }
 
-    fn insert_states(&mut self, states: Vec<State>) -> () {
+    fn insert_states(&mut self, states: Vec<State>) -> Result<(), GenerateError> {
         for state in states.into_iter() {
-            match self.state_map.get_mut(&state.id) {
-                Some(arr) => {
-                    assert!(arr[state.idx as usize].is_none());
-                    arr[state.idx as usize] = Some(state.reg);
-                }
-                None => {
-                    let mut arr = [None; 32];
-                    arr[state.idx as usize] = Some(state.reg);
-                    self.state_map.insert(state.id, arr);
-                }
+            let arr = self.state_map.entry(state.id).or_default();
+            if arr.contains_key(&state.idx) {
+                return Err(GenerateError::DuplicateState {
+                    id: state.id,
+                    idx: state.idx,
+                });
             }
+            arr.insert(state.idx, state.reg);
         }
+
+        Ok(())
     }
 
     fn take_single_state(&mut self, state_id: u32) -> Option<State> {
-        match self.state_map.get_mut(&state_id) {
-            Some(arr) => {
-                for i in 0..32 {
-                    if arr[i].is_some() {
-                        let state = State {
-                            id: state_id,
-                            idx: i as u8,
-                            reg: arr[i].take().unwrap(),
-                        };
-
-                        return Some(state);
-                    }
-                }
-                None
-            }
-            None => None,
-        }
+        let (idx, reg) = self.state_map.get_mut(&state_id)?.pop_first()?;
+
+        Some(State {
+            id: state_id,
+            idx,
+            reg,
+        })
     }
 
     fn take_same_id_states(
@@ -121,159 +367,504 @@ impl StateManager {
         n_states: u8,
         idx_dist: u8,
     ) -> Option<Vec<State>> {
-        assert!(n_states > 0);
-        assert!(idx_dist > 0);
-
-        match self.state_map.get_mut(&state_id) {
-            Some(arr) => {
-                for i in 0..32 - (n_states - 1) * idx_dist {
-                    let idx_iter = (0..n_states).map(|x| x * idx_dist + i);
-
-                    let all_avail = idx_iter
-                        .clone()
-                        .map(|idx| arr[idx as usize].is_some())
-                        .fold(true, |acc, x| acc & x);
-
-                    if all_avail {
-                        let states = idx_iter
-                            .map(|idx| {
-                                let state = State {
-                                    id: state_id,
-                                    idx: idx,
-                                    reg: arr[idx as usize].take().unwrap(),
-                                };
-                                state
-                            })
-                            .collect();
-                        return Some(states);
-                    }
+        let arr = self.state_map.get_mut(&state_id)?;
+        let nth = |first: u32, n: u8| first.checked_add(n as u32 * idx_dist as u32);
+        let first = arr.keys().copied().find(|&first| {
+            (1..n_states).all(|n| match nth(first, n) {
+                Some(idx) => arr.contains_key(&idx),
+                None => false,
+            })
+        })?;
+
+        let states = (0..n_states)
+            .map(|n| {
+                let idx = nth(first, n).unwrap();
+                State {
+                    id: state_id,
+                    idx,
+                    reg: arr.remove(&idx).unwrap(),
                 }
-                None
-            }
-            None => None,
-        }
+            })
+            .collect();
+
+        Some(states)
     }
 
-    fn take_same_idx_states(&mut self, state_id0: u32, state_id1: u32) -> Option<Vec<State>> {
-        assert!(state_id0 != state_id1);
+    fn take_joined_states(&mut self, state_ids: &[u32]) -> Option<Vec<State>> {
+        let (first, rest) = state_ids.split_first()?;
+        let rest: Vec<&BTreeMap<u32, Option<Zmm>>> = rest
+            .iter()
+            .map(|id| self.state_map.get(id))
+            .collect::<Option<_>>()?;
+        let idx = self
+            .state_map
+            .get(first)?
+            .keys()
+            .copied()
+            .find(|idx| rest.iter().all(|arr| arr.contains_key(idx)))?;
 
-        let idx = match (
-            self.state_map.get(&state_id0),
-            self.state_map.get(&state_id1),
-        ) {
-            (Some(arr0), Some(arr1)) => Some((arr0, arr1)),
-            (_, _) => None,
-        }
-        .map(|(arr0, arr1)| {
-            for i in 0..32 {
-                if arr0[i].is_some() && arr1[i].is_some() {
-                    return Some(i);
-                }
-            }
-            None
-        })
-        .unwrap_or(None);
-
-        match idx {
-            Some(idx) => {
-                let arr = self.state_map.get_mut(&state_id0).unwrap();
-                let s0 = State {
-                    id: state_id0,
-                    idx: idx as u8,
-                    reg: arr[idx as usize].take().unwrap(),
-                };
-
-                let arr = self.state_map.get_mut(&state_id1).unwrap();
-                let s1 = State {
-                    id: state_id1,
-                    idx: idx as u8,
-                    reg: arr[idx as usize].take().unwrap(),
-                };
-
-                Some(Vec::from([s0, s1]))
-            }
-            None => None,
+        let states = state_ids
+            .iter()
+            .map(|&id| State {
+                id,
+                idx,
+                reg: self.state_map.get_mut(&id).unwrap().remove(&idx).unwrap(),
+            })
+            .collect();
+
+        Some(states)
+    }
+
+    fn take_states<T: ?Sized>(
+        &mut self,
+        condition: &Condition<T>,
+        config: &T,
+    ) -> Option<Vec<State>> {
+        match *condition {
+            Condition::Single { id } => self.take_single_state(id).map(|s| Vec::from([s])),
+            Condition::SameId {
+                id,
+                n_states,
+                idx_dist,
+            } => self.take_same_id_states(id, n_states, idx_dist),
+            Condition::SameIdx { id0, id1 } => self.take_joined_states(&[id0, id1]),
+            Condition::Join { ids } => self.take_joined_states(ids),
+            Condition::Guard { guard, condition } => match guard(config) {
+                true => self.take_states(condition, config),
+                false => None,
+            },
+            Condition::Priority { condition, .. } => self.take_states(condition, config),
         }
     }
 
-    fn is_empty(&self) -> bool {
-        let mut iter = self
+    fn remaining(&self) -> Vec<State> {
+        let mut states: Vec<State> = self
             .state_map
             .iter()
-            .map(|(_, arr)| arr)
-            .flatten()
-            .flatten();
+            .flat_map(|(&id, arr)| arr.iter().map(move |(&idx, &reg)| State { id, idx, reg }))
+            .collect();
+        states.sort_by_key(|state| (state.id, state.idx));
 
-        iter.next().is_none()
+        states
     }
 }
 
-pub trait Generate {
+pub trait Generate: 'static {
     fn rulebook<'a>(&'a self) -> &'a [Rule<Self>];
-    fn avail_registers(&self) -> [bool; 32];
+    fn avail_registers(&self) -> [bool; N_REGISTERS];
+    fn avail_masks(&self) -> [bool; N_MASKS] {
+        [true; N_MASKS]
+    }
     fn initial_states(&self) -> Vec<State>;
 
+    // a static check of the rulebook for this config
+    fn overlaps(&self) -> Vec<Overlap> {
+        overlaps(self)
+    }
+
     fn generate(&self) -> Result<Assembly, GenerateError> {
-        let rulebook = self.rulebook();
-        let mut register_pool = RegisterPool::new(self.avail_registers());
-        let states = self.initial_states();
-        let mut state_manager = StateManager::new(states);
-        let mut asm = Assembly::new();
-
-        loop {
-            let mut is_updated = false;
-            for rule in rulebook.iter() {
-                let states = match rule.condition {
-                    Condition::Single { id } => {
-                        state_manager.take_single_state(id).map(|s| Vec::from([s]))
-                    }
-                    Condition::SameId {
-                        id,
-                        n_states,
-                        idx_dist,
-                    } => state_manager.take_same_id_states(id, n_states, idx_dist),
-                    Condition::SameIdx { id0, id1 } => state_manager.take_same_idx_states(id0, id1),
-                };
-
-                if let Some(states) = states {
-                    let (res_asm, next_states) =
-                        (rule.callback)(self, &mut register_pool, &states)?;
-                    state_manager.insert_states(next_states);
-                    asm = asm.append(res_asm);
-
-                    is_updated = true;
-                    break;
-                }
+        run(self)
+    }
+
+    // scratch area for the registers a layout spills, which the kernel must
+    // not move. a generator without one cannot spill.
+    fn spill_area(&self) -> Option<&str> {
+        None
+    }
+
+    // `generate` for a whole kernel: spilled registers are moved to the
+    // spill area, and the result is checked by `Assembly::verify`, in
+    // release builds as well so that both emit the same kernels. a fragment
+    // reads registers that other fragments set up, so only generators of
+    // whole kernels should call this.
+    fn generate_kernel(&self) -> Result<(Assembly, SpillReport), GenerateError> {
+        let asm = self.generate()?;
+        let (asm, report) = match (asm.n_spilled(), self.spill_area()) {
+            (0, _) => (asm, SpillReport::default()),
+            (_, Some(name)) => asm.spill(name)?,
+            (n, None) => {
+                return Err(GenerateError::RegisterOverflow {
+                    parameters: Vec::new(),
+                    need: register::N_PHYSICAL_REGISTERS as usize + n,
+                    avail: register::N_PHYSICAL_REGISTERS as usize,
+                })
             }
+        };
+        asm.verify().map_err(GenerateError::Verify)?;
+
+        Ok((asm, report))
+    }
+}
+
+// the rule engine behind `Generate::generate`, one firing at a time so that
+// several engines can share a register pool. a failure is wrapped with the
+// generator, and the rule and states that caused it.
+pub(crate) struct Engine<'g, T: Generate + ?Sized> {
+    generator: &'g T,
+    name: &'static str,
+    state_manager: StateManager,
+    // rule indices in the order they are tried
+    order: Vec<usize>,
+    trace: Option<Trace>,
+}
+
+impl<'g, T: Generate + ?Sized> Engine<'g, T> {
+    pub(crate) fn new(generator: &'g T) -> Result<Self, GenerateError> {
+        let name = std::any::type_name::<T>();
+        let rulebook = generator.rulebook();
+        let trace = match trace::is_recording() {
+            true => Some(Trace {
+                generator: name,
+                initial: Vec::new(),
+                firings: Vec::new(),
+                overlaps: generator.overlaps(),
+            }),
+            false => None,
+        };
+        // a stable sort keeps the rulebook order within a priority
+        let mut order: Vec<usize> = (0..rulebook.len()).collect();
+        order.sort_by_key(|&i| std::cmp::Reverse(rulebook[i].condition.priority()));
+        let mut engine = Engine {
+            generator,
+            name,
+            state_manager: StateManager::new(),
+            order,
+            trace,
+        };
+
+        for (i, rule) in rulebook.iter().enumerate() {
+            if !rule.condition.is_valid() {
+                let err = GenerateError::InvalidCondition(format!("{:?}", rule.condition));
+                return Err(err.context(name, Some(rule.name), Vec::new()));
+            }
+            if rulebook[..i].iter().any(|other| other.name == rule.name) {
+                let err = GenerateError::DuplicateRule(rule.name);
+                return Err(err.context(name, None, Vec::new()));
+            }
+        }
+
+        let states = generator.initial_states();
+        if let Some(trace) = engine.trace.as_mut() {
+            trace.initial = states.clone();
+        }
+        engine
+            .state_manager
+            .insert_states(states)
+            .map_err(|err| err.context(name, None, Vec::new()))?;
+
+        Ok(engine)
+    }
+
+    // fires the first rule that matches, if any
+    pub(crate) fn step(
+        &mut self,
+        register_pool: &mut RegisterPool,
+    ) -> Result<Option<Assembly>, GenerateError> {
+        let (generator, name) = (self.generator, self.name);
+        let rulebook = generator.rulebook();
+        for &i in self.order.iter() {
+            let rule = &rulebook[i];
+            let states = match self.state_manager.take_states(&rule.condition, generator) {
+                Some(states) => states,
+                None => continue,
+            };
+
+            if self.trace.is_some() {
+                trace::begin_firing();
+            }
+            let res = (rule.callback)(generator, register_pool, &states);
+            let nested = match self.trace.is_some() {
+                true => trace::end_firing(),
+                false => Vec::new(),
+            };
+            let (asm, next_states) =
+                res.map_err(|err| err.context(name, Some(rule.name), states.clone()))?;
+
+            if let Some(trace) = self.trace.as_mut() {
+                trace.firings.push(Firing {
+                    rule: i,
+                    name: rule.name,
+                    condition: format!("{:?}", rule.condition),
+                    consumed: states.clone(),
+                    produced: next_states.clone(),
+                    instructions: asm
+                        .instructions()
+                        .iter()
+                        .map(|inst| inst.to_string())
+                        .collect(),
+                    nested,
+                });
+            }
+            self.state_manager
+                .insert_states(next_states)
+                .map_err(|err| err.context(name, Some(rule.name), states))?;
+
+            return Ok(Some(asm));
+        }
+
+        Ok(None)
+    }
 
-            if is_updated == false {
-                break;
+    // once no rule matches, every state must have been consumed
+    pub(crate) fn finish(&self) -> Result<(), GenerateError> {
+        let remaining = self.state_manager.remaining();
+        match remaining.is_empty() {
+            true => Ok(()),
+            false => {
+                let err = GenerateError::UnconsumedStates(remaining);
+                Err(err.context(self.name, None, Vec::new()))
             }
         }
+    }
+}
 
-        assert!(state_manager.is_empty(), "Not every state is consumed");
-        assert!(
-            register_pool.avail_list() == &self.avail_registers(),
-            "Register pool is changed"
-        );
+// a failed run keeps how far it got in the trace
+impl<T: Generate + ?Sized> Drop for Engine<'_, T> {
+    fn drop(&mut self) -> () {
+        if let Some(trace) = self.trace.take() {
+            trace::finish(trace);
+        }
+    }
+}
 
-        Ok(asm)
+// registers and masks that are not as available in `register_pool` as they
+// were when it was made
+pub(crate) fn check_pool(
+    register_pool: &mut RegisterPool,
+    avail_registers: [bool; N_REGISTERS],
+    avail_masks: [bool; N_MASKS],
+) -> Result<(), GenerateError> {
+    let registers: Vec<Zmm> = (0..N_REGISTERS)
+        .filter(|&i| register_pool.avail_list()[i] != avail_registers[i])
+        .map(|i| Zmm::new(i as u8))
+        .collect();
+    let masks: Vec<Mask> = (0..N_MASKS)
+        .filter(|&i| register_pool.masks().avail_list()[i] != avail_masks[i])
+        .map(|i| Mask::new(i as u8 + 1))
+        .collect();
+    match registers.is_empty() && masks.is_empty() {
+        true => Ok(()),
+        false => Err(GenerateError::PoolChanged { registers, masks }),
     }
 }
 
+fn run<T: Generate + ?Sized>(generator: &T) -> Result<Assembly, GenerateError> {
+    let mut engine = Engine::new(generator)?;
+    let (avail_registers, avail_masks) = (generator.avail_registers(), generator.avail_masks());
+    let mut register_pool = RegisterPool::new(avail_registers, avail_masks);
+
+    let mut asm = Assembly::new();
+    while let Some(res_asm) = engine.step(&mut register_pool)? {
+        asm = asm.append(res_asm);
+    }
+    engine.finish()?;
+    check_pool(&mut register_pool, avail_registers, avail_masks)
+        .map_err(|err| err.context(engine.name, None, Vec::new()))?;
+
+    Ok(asm)
+}
+
 #[derive(Debug)]
 pub enum GenerateError {
-    RegisterOverflow,
-    IllegalUnrollFactor,
+    // more vector registers than there are, spilled ones included, for the
+    // parameters that decide how many a kernel needs
+    RegisterOverflow {
+        parameters: Vec<(&'static str, String)>,
+        need: usize,
+        avail: usize,
+    },
+    // `move_reg f` unrolls nanokernels until every block is back in place,
+    // and `parameter` has to be a multiple of their number
+    IllegalUnrollFactor {
+        parameter: &'static str,
+        value: String,
+        n_kernels_unrolled: u8,
+    },
+    // the spill area is moved by the kernel, so its slots do not stay put
+    MovingSpillArea(Name),
+    Verify(Vec<VerifyError>),
+    // the register pool has no register or spill slot left
+    NoRegister,
+    NoMask,
+    RegisterTaken(Zmm),
+    MaskTaken(Mask),
+    DuplicateState {
+        id: u32,
+        idx: u32,
+    },
+    InvalidCondition(String),
+    // two rules of a rulebook under the same name
+    DuplicateRule(&'static str),
+    UnconsumedStates(Vec<State>),
+    // registers and masks a generator did not give back to its pool, or gave
+    // back without having them
+    PoolChanged {
+        registers: Vec<Zmm>,
+        masks: Vec<Mask>,
+    },
+    // fused fragments run in turns, so neither may have a label or a jump
+    FusedBranch(Name),
+    // fixed registers, outside the pool, that both fused fragments use
+    SharedFixedRegisters(Vec<Zmm>),
+    // a rule fired for a parameter the generator was built without
+    MissingParameter(&'static str),
+    MissingRegister(State),
+    // a parameter value the generators have no code for
+    Unsupported {
+        parameter: &'static str,
+        value: String,
+    },
+    // `source` failed inside `generator`, firing `rule` on `states` if any
+    Context {
+        generator: &'static str,
+        rule: Option<&'static str>,
+        states: Vec<State>,
+        source: Box<GenerateError>,
+    },
+}
+
+impl GenerateError {
+    pub fn context(
+        self,
+        generator: &'static str,
+        rule: Option<&'static str>,
+        states: Vec<State>,
+    ) -> Self {
+        GenerateError::Context {
+            generator,
+            rule,
+            states,
+            source: Box::new(self),
+        }
+    }
+
+    // the failure without the generators it went through
+    pub fn root(&self) -> &GenerateError {
+        match self {
+            Self::Context { source, .. } => source.root(),
+            _ => self,
+        }
+    }
 }
 
 impl fmt::Display for GenerateError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::RegisterOverflow => write!(f, "vector register overflow"),
-            Self::IllegalUnrollFactor => write!(f, "illegal unroll factor"),
+            Self::RegisterOverflow {
+                parameters,
+                need,
+                avail,
+            } => {
+                let parameters: Vec<String> = parameters
+                    .iter()
+                    .map(|(parameter, value)| format!("{} = {}", parameter, value))
+                    .collect();
+                match parameters.is_empty() {
+                    true => write!(f, "{} vector registers", need)?,
+                    false => write!(
+                        f,
+                        "{} need {} vector registers",
+                        parameters.join(", "),
+                        need
+                    )?,
+                }
+                let physical = register::N_PHYSICAL_REGISTERS as usize;
+                match avail.checked_sub(physical) {
+                    Some(slots) if slots > 0 => write!(
+                        f,
+                        ", more than the {} registers plus {} spill slots there are",
+                        physical, slots
+                    ),
+                    _ => write!(f, ", more than the {} registers there are", avail),
+                }
+            }
+            Self::IllegalUnrollFactor {
+                parameter,
+                value,
+                n_kernels_unrolled,
+            } => write!(
+                f,
+                "{} = {} is not a multiple of the {} nanokernels move_reg f unrolls",
+                parameter, value, n_kernels_unrolled
+            ),
+            Self::MovingSpillArea(name) => {
+                write!(f, "the spill area `{}` moves inside the kernel", name)
+            }
+            Self::Verify(errors) => {
+                write!(f, "liveness verification failed")?;
+                for err in errors.iter() {
+                    write!(f, "\n    {}", err)?;
+                }
+                Ok(())
+            }
+            Self::NoRegister => write!(f, "no vector register or spill slot is left"),
+            Self::NoMask => write!(f, "no mask register is left"),
+            Self::RegisterTaken(zmm) => write!(f, "{} is already taken", zmm),
+            Self::MaskTaken(k) => write!(f, "{} is already taken", k),
+            Self::DuplicateState { id, idx } => {
+                write!(f, "state {}:{} is produced while it is pending", id, idx)
+            }
+            Self::InvalidCondition(condition) => {
+                write!(f, "condition `{}` can never be met", condition)
+            }
+            Self::DuplicateRule(rule) => write!(f, "two rules are named `{}`", rule),
+            Self::UnconsumedStates(states) => {
+                write!(f, "states {} are never consumed", states_to_string(states))
+            }
+            Self::PoolChanged { registers, masks } => {
+                let regs: Vec<String> = registers.iter().map(|zmm| zmm.to_string()).collect();
+                let ks: Vec<String> = masks.iter().map(|k| k.to_string()).collect();
+                write!(
+                    f,
+                    "the pool does not end as it started, {} differ",
+                    [regs, ks].concat().join(", ")
+                )
+            }
+            Self::FusedBranch(label) => write!(
+                f,
+                "`{}` branches in a fused fragment, which must be straight-line code",
+                label
+            ),
+            Self::SharedFixedRegisters(registers) => {
+                let regs: Vec<String> = registers.iter().map(|zmm| zmm.to_string()).collect();
+                write!(f, "both fused fragments use {}", regs.join(", "))
+            }
+            Self::MissingParameter(parameter) => {
+                write!(f, "a rule needs `{}`, which is not set", parameter)
+            }
+            Self::MissingRegister(state) => {
+                write!(f, "state {}:{} carries no register", state.id, state.idx)
+            }
+            Self::Unsupported { parameter, value } => {
+                write!(f, "{} = {} is not implemented", parameter, value)
+            }
+            Self::Context {
+                generator,
+                rule,
+                states,
+                source,
+            } => {
+                write!(f, "{}", generator)?;
+                if let Some(rule) = rule {
+                    write!(f, ", rule `{}`", rule)?;
+                }
+                if !states.is_empty() {
+                    write!(f, " on states {}", states_to_string(states))?;
+                }
+                write!(f, ": {}", source)
+            }
         }
     }
 }
 
+// `id:idx` of each state
+fn states_to_string(states: &[State]) -> String {
+    let states: Vec<String> = states
+        .iter()
+        .map(|state| format!("{}:{}", state.id, state.idx))
+        .collect();
+
+    format!("[{}]", states.join(", "))
+}
+
 impl std::error::Error for GenerateError {}
@@ -0,0 +1,332 @@
+// Liveness of registers, masks and flags over a whole kernel.
+//
+// Control flow is a straight line where `JumpNotZero` may also continue at
+// its label. Nothing is live at the end of the kernel: results are stored to
+// memory, and named operands are never read back by the caller.
+
+use crate::assembly::{Instruction, Operand};
+use crate::name::Name;
+use crate::register::{Gpr, Mask, Zmm};
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+// operands live right after each instruction
+pub(crate) fn live_out(arr: &[Instruction]) -> Vec<HashSet<Operand>> {
+    let n = arr.len();
+    let uses: Vec<Vec<Operand>> = arr.iter().map(|inst| inst.uses()).collect();
+    let defs: Vec<Vec<Operand>> = arr.iter().map(|inst| inst.defs()).collect();
+
+    let labels: HashMap<&str, usize> = arr
+        .iter()
+        .enumerate()
+        .filter_map(|(i, inst)| match *inst {
+            Instruction::Label(ref name) => Some((&**name, i)),
+            _ => None,
+        })
+        .collect();
+
+    let mut live_in: Vec<HashSet<Operand>> = vec![HashSet::new(); n];
+    let mut live_out: Vec<HashSet<Operand>> = vec![HashSet::new(); n];
+
+    // loops only jump backwards, so a few sweeps from the end converge
+    let mut is_updated = true;
+    while is_updated {
+        is_updated = false;
+        for i in (0..n).rev() {
+            let mut out: HashSet<Operand> = match i + 1 < n {
+                true => live_in[i + 1].clone(),
+                false => HashSet::new(),
+            };
+            if let Instruction::JumpNotZero(label) = &arr[i] {
+                if let Some(&target) = labels.get(&**label) {
+                    out.extend(live_in[target].iter().cloned());
+                }
+            }
+
+            let mut inn: HashSet<Operand> = out
+                .iter()
+                .filter(|operand| !defs[i].contains(operand))
+                .cloned()
+                .collect();
+            inn.extend(uses[i].iter().cloned());
+
+            if inn != live_in[i] || out != live_out[i] {
+                live_in[i] = inn;
+                live_out[i] = out;
+                is_updated = true;
+            }
+        }
+    }
+
+    live_out
+}
+
+// a vector register or mask the verifier reports on
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Register {
+    Zmm(Zmm),
+    Mask(Mask),
+}
+
+impl Register {
+    fn from_operand(operand: &Operand) -> Option<Self> {
+        match *operand {
+            Operand::Vector(i) => Some(Register::Zmm(Zmm::new(i))),
+            Operand::Mask(i) => Some(Register::Mask(Mask::new(i))),
+            Operand::Gpr(_) | Operand::Flags => None,
+        }
+    }
+
+    // bit of the register in a `u64` set
+    const fn bit(self) -> u64 {
+        match self {
+            Register::Zmm(zmm) => 1 << zmm.index(),
+            Register::Mask(k) => 1 << (32 + k.index()),
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Register::Zmm(zmm) => write!(f, "{}", zmm),
+            Register::Mask(k) => write!(f, "{}", k),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum VerifyError {
+    UseBeforeDef {
+        index: usize,
+        instruction: String,
+        reg: Register,
+    },
+    DeadWrite {
+        index: usize,
+        instruction: String,
+        reg: Register,
+    },
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UseBeforeDef {
+                index,
+                instruction,
+                reg,
+            } => write!(
+                f,
+                "{} may be read before it is written at #{} `{}`",
+                reg, index, instruction
+            ),
+            Self::DeadWrite {
+                index,
+                instruction,
+                reg,
+            } => write!(
+                f,
+                "{} is written but never read at #{} `{}`",
+                reg, index, instruction
+            ),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+// registers an instruction really reads. a gather also merges into its
+// destination, but every gather runs with a full mask, so the old value is
+// never observed.
+fn reads(inst: &Instruction) -> Vec<Register> {
+    let merged = match *inst {
+        Instruction::GatherF64x8(zmm, _, _, _) => Some(Register::Zmm(zmm)),
+        _ => None,
+    };
+
+    inst.uses()
+        .iter()
+        .filter_map(Register::from_operand)
+        .filter(|&reg| Some(reg) != merged)
+        .collect()
+}
+
+// registers an instruction writes on purpose. a gather clears its mask as
+// a side effect, which is not a value anyone means to read.
+fn writes(inst: &Instruction) -> Vec<Register> {
+    let cleared = match *inst {
+        Instruction::GatherF64x8(_, _, _, k) => Some(Register::Mask(k)),
+        _ => None,
+    };
+
+    inst.defs()
+        .iter()
+        .filter_map(Register::from_operand)
+        .filter(|&reg| Some(reg) != cleared)
+        .collect()
+}
+
+// counters beyond this are treated as unknown, which keeps the walk finite
+const MAX_COUNTER: i64 = 1 << 16;
+
+// one point of the walk: where it is, which registers may hold garbage, the
+// values of counters set from immediates, and whether the zero flag is known
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Walk {
+    pc: usize,
+    undefined: u64,
+    counters: Vec<(Name, i64)>,
+    zero: Option<bool>,
+}
+
+impl Walk {
+    fn counter(&self, reg: &Gpr) -> Option<i64> {
+        self.counters
+            .iter()
+            .find(|(name, _)| name == reg.name())
+            .map(|&(_, value)| value)
+    }
+
+    fn set_counter(&mut self, reg: &Gpr, value: Option<i64>) -> () {
+        self.counters.retain(|(name, _)| name != reg.name());
+        if let Some(value) = value.filter(|value| value.abs() <= MAX_COUNTER) {
+            self.counters.push((reg.name().clone(), value));
+            self.counters.sort();
+        }
+    }
+
+    fn update_counter(&mut self, reg: &Gpr, f: impl Fn(i64) -> i64) -> () {
+        let value = self.counter(reg).map(f);
+        self.set_counter(reg, value);
+        self.zero = value.map(|value| value == 0);
+    }
+}
+
+// reads of garbage are found by walking every path of the kernel, following
+// jumps whose counters are known from immediates, so a loop that fills its
+// registers over a fixed number of iterations is not reported. a move only
+// copies garbage along; reading it anywhere else is the error.
+fn undefined_reads(arr: &[Instruction]) -> Vec<(usize, Register)> {
+    let labels: HashMap<&str, usize> = arr
+        .iter()
+        .enumerate()
+        .filter_map(|(i, inst)| match *inst {
+            Instruction::Label(ref name) => Some((&**name, i)),
+            _ => None,
+        })
+        .collect();
+
+    let mut found: Vec<(usize, Register)> = Vec::new();
+    let mut visited: HashSet<Walk> = HashSet::new();
+    let mut stack = Vec::from([Walk {
+        pc: 0,
+        undefined: u64::MAX,
+        counters: Vec::new(),
+        zero: None,
+    }]);
+
+    while let Some(mut walk) = stack.pop() {
+        if walk.pc >= arr.len() || !visited.insert(walk.clone()) {
+            continue;
+        }
+        let inst = &arr[walk.pc];
+
+        let moved = match *inst {
+            Instruction::MovF64x8(dst, src) => Some((dst.index(), src.index())),
+            Instruction::MovF64x2(dst, src) => Some((dst.index(), src.index())),
+            Instruction::MovI32x8(dst, src) => Some((dst.index(), src.index())),
+            _ => None,
+        };
+        match moved {
+            Some((dst, src)) => {
+                let dst = Register::Zmm(Zmm::new(dst)).bit();
+                let src = Register::Zmm(Zmm::new(src)).bit();
+                walk.undefined = match walk.undefined & src {
+                    0 => walk.undefined & !dst,
+                    _ => walk.undefined | dst,
+                };
+            }
+            None => {
+                for reg in reads(inst) {
+                    if walk.undefined & reg.bit() != 0 && !found.contains(&(walk.pc, reg)) {
+                        found.push((walk.pc, reg));
+                    }
+                }
+                for operand in inst.defs().iter() {
+                    if let Some(reg) = Register::from_operand(operand) {
+                        walk.undefined &= !reg.bit();
+                    }
+                }
+            }
+        }
+
+        match *inst {
+            Instruction::SetImmediate(ref reg, imm) => walk.set_counter(reg, Some(imm as i64)),
+            Instruction::AddImmediate(ref reg, imm) => walk.update_counter(reg, |x| x + imm as i64),
+            Instruction::SubImmediate(ref reg, imm) => walk.update_counter(reg, |x| x - imm as i64),
+            Instruction::ShiftRight(ref reg, imm) => walk.update_counter(reg, |x| x >> imm),
+            Instruction::MovReg(ref dst, ref src) => {
+                let value = walk.counter(src);
+                walk.set_counter(dst, value);
+            }
+            _ => {}
+        }
+
+        let target = match *inst {
+            Instruction::JumpNotZero(ref label) => labels.get(&**label).copied(),
+            _ => None,
+        };
+        match (target, walk.zero) {
+            (None, _) | (Some(_), Some(true)) => {
+                walk.pc += 1;
+                stack.push(walk);
+            }
+            (Some(target), Some(false)) => {
+                walk.pc = target;
+                stack.push(walk);
+            }
+            (Some(target), None) => {
+                let mut jumped = walk.clone();
+                jumped.pc = target;
+                stack.push(jumped);
+                walk.pc += 1;
+                stack.push(walk);
+            }
+        }
+    }
+
+    found.sort_by_key(|&(i, reg)| (i, reg.bit()));
+    found
+}
+
+pub(crate) fn verify(arr: &[Instruction]) -> Vec<VerifyError> {
+    let mut errors: Vec<VerifyError> = undefined_reads(arr)
+        .into_iter()
+        .map(|(i, reg)| VerifyError::UseBeforeDef {
+            index: i,
+            instruction: arr[i].to_string(),
+            reg,
+        })
+        .collect();
+
+    let live_out = live_out(arr);
+    for (i, inst) in arr.iter().enumerate() {
+        let live = live_out[i]
+            .iter()
+            .filter_map(Register::from_operand)
+            .fold(0, |acc, reg| acc | reg.bit());
+        for reg in writes(inst) {
+            if live & reg.bit() == 0 {
+                errors.push(VerifyError::DeadWrite {
+                    index: i,
+                    instruction: inst.to_string(),
+                    reg,
+                });
+            }
+        }
+    }
+
+    errors
+}
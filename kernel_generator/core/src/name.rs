@@ -0,0 +1,16 @@
+// Names made at runtime.
+//
+// Labels, operands and comments are `Name`s, which instructions share rather
+// than copy: a name computed at runtime, such as a loop label with the level
+// of a multigrid hierarchy in it, lives as long as the instructions and
+// generators that refer to it, and no longer. Builders take any `&str`, a
+// literal or a name put together with `format!`.
+
+use std::rc::Rc;
+
+pub type Name = Rc<str>;
+
+// `prefix` and `name` put together
+pub fn prefixed(prefix: &str, name: &str) -> Name {
+    Name::from(format!("{}{}", prefix, name))
+}
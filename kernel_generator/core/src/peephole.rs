@@ -0,0 +1,228 @@
+// Peephole optimizations over a whole kernel.
+//
+// - a load whose register is read once, by an operation that has a memory
+//   form, is folded into that operation. The base register must not change
+//   and nothing may be stored in between, and the register must be dead
+//   afterwards.
+// - a vector move whose destination is dead, or which moves a register onto
+//   itself, is removed.
+// - adjacent adds of immediates to the same register are merged.
+
+use crate::assembly::{Instruction, Operand};
+use crate::liveness;
+use crate::register::Gpr;
+
+use std::fmt;
+
+// instructions saved by each optimization
+#[derive(Default)]
+pub struct PeepholeReport {
+    pub folded_loads: usize,
+    pub dead_moves: usize,
+    pub merged_adds: usize,
+}
+
+impl PeepholeReport {
+    pub fn saved(&self) -> usize {
+        self.folded_loads + self.dead_moves + self.merged_adds
+    }
+}
+
+impl fmt::Display for PeepholeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "saved {} instructions ({} folded loads, {} dead moves, {} merged adds)",
+            self.saved(),
+            self.folded_loads,
+            self.dead_moves,
+            self.merged_adds
+        )
+    }
+}
+
+pub(crate) fn optimize(arr: Vec<Instruction>) -> (Vec<Instruction>, PeepholeReport) {
+    let mut report = PeepholeReport::default();
+
+    let (arr, n_folded) = fold_loads(arr);
+    report.folded_loads = n_folded;
+
+    // removing a move can make the move that fed it dead as well
+    let mut arr = arr;
+    loop {
+        let (res, n_removed) = remove_dead_moves(arr);
+        arr = res;
+        report.dead_moves += n_removed;
+        if n_removed == 0 {
+            break;
+        }
+    }
+
+    let (arr, n_merged) = merge_adds(arr);
+    report.merged_adds = n_merged;
+
+    (arr, report)
+}
+
+fn fold_loads(arr: Vec<Instruction>) -> (Vec<Instruction>, usize) {
+    let live_out = liveness::live_out(&arr);
+    let mut folds: Vec<(usize, usize, Instruction)> = Vec::new();
+
+    for i in 0..arr.len() {
+        let (reg, base, offset) = match arr[i] {
+            Instruction::LoadF64x8(zmm, ref base, offset) => (Operand::from(zmm), base, offset),
+            Instruction::LoadF64x1(xmm, ref base, offset) => (Operand::from(xmm), base, offset),
+            _ => continue,
+        };
+
+        // the first instruction after the load that touches its register
+        let mut user = None;
+        for (j, inst) in arr.iter().enumerate().skip(i + 1) {
+            if inst.uses().contains(&reg) || inst.defs().contains(&reg) {
+                user = Some(j);
+                break;
+            }
+            let is_store = matches!(
+                inst,
+                Instruction::StoreF64x8(_, _, _) | Instruction::StoreF64x1(_, _, _)
+            );
+            let is_branch = matches!(inst, Instruction::Label(_) | Instruction::JumpNotZero(_));
+            if is_store || is_branch || inst.defs().contains(&Operand::from(base)) {
+                break;
+            }
+        }
+
+        let j = match user {
+            Some(j) if !live_out[j].contains(&reg) => j,
+            _ => continue,
+        };
+        if folds.iter().any(|&(_, k, _)| k == j) {
+            continue;
+        }
+        if let Some(inst) = fold(&arr[j], reg, base.clone(), offset) {
+            folds.push((i, j, inst));
+        }
+    }
+
+    let n_folded = folds.len();
+    let mut arr: Vec<Option<Instruction>> = arr.into_iter().map(Some).collect();
+    for (i, j, inst) in folds.into_iter() {
+        arr[i] = None;
+        arr[j] = Some(inst);
+    }
+
+    (arr.into_iter().flatten().collect(), n_folded)
+}
+
+// the memory form of `inst` with `reg` read from `base` + `offset` instead,
+// if `reg` is one of its sources exactly once. add, mul and fma are
+// commutative in their two sources, division only takes a memory divisor.
+fn fold(inst: &Instruction, reg: Operand, base: Gpr, offset: i16) -> Option<Instruction> {
+    if inst.uses().iter().filter(|&x| *x == reg).count() != 1 {
+        return None;
+    }
+    let is = |x: Operand| x == reg;
+
+    match *inst {
+        Instruction::AddF64x8(dst, src0, src1) if is(src1.into()) => {
+            Some(Instruction::LoadAddF64x8(dst, src0, base, offset))
+        }
+        Instruction::AddF64x8(dst, src0, src1) if is(src0.into()) => {
+            Some(Instruction::LoadAddF64x8(dst, src1, base, offset))
+        }
+        Instruction::AddF64x1(dst, src0, src1) if is(src1.into()) => {
+            Some(Instruction::LoadAddF64x1(dst, src0, base, offset))
+        }
+        Instruction::MulF64x8(dst, src0, src1) if is(src1.into()) => {
+            Some(Instruction::LoadMulF64x8(dst, src0, base, offset))
+        }
+        Instruction::MulF64x8(dst, src0, src1) if is(src0.into()) => {
+            Some(Instruction::LoadMulF64x8(dst, src1, base, offset))
+        }
+        Instruction::MulF64x1(dst, src0, src1) if is(src1.into()) => {
+            Some(Instruction::LoadMulF64x1(dst, src0, base, offset))
+        }
+        Instruction::MulAddF64x8(dst, src0, src1) if is(src1.into()) => {
+            Some(Instruction::LoadMulAddF64x8(dst, src0, base, offset))
+        }
+        Instruction::MulAddF64x8(dst, src0, src1) if is(src0.into()) => {
+            Some(Instruction::LoadMulAddF64x8(dst, src1, base, offset))
+        }
+        Instruction::MulAddF64x1(dst, src0, src1) if is(src1.into()) => {
+            Some(Instruction::LoadMulAddF64x1(dst, src0, base, offset))
+        }
+        Instruction::NMulSubF64x8(dst, src0, src1) if is(src1.into()) => {
+            Some(Instruction::LoadNMulSubF64x8(dst, src0, base, offset))
+        }
+        Instruction::NMulSubF64x8(dst, src0, src1) if is(src0.into()) => {
+            Some(Instruction::LoadNMulSubF64x8(dst, src1, base, offset))
+        }
+        Instruction::DivF64x8(dst, src0, src1) if is(src1.into()) => {
+            Some(Instruction::LoadDivF64x8(dst, src0, base, offset))
+        }
+        Instruction::DivF64x1(dst, src0, src1) if is(src1.into()) => {
+            Some(Instruction::LoadDivF64x1(dst, src0, base, offset))
+        }
+        _ => None,
+    }
+}
+
+fn remove_dead_moves(arr: Vec<Instruction>) -> (Vec<Instruction>, usize) {
+    let live_out = liveness::live_out(&arr);
+
+    let mut n_removed = 0;
+    let res = arr
+        .into_iter()
+        .zip(live_out.iter())
+        .filter(|(inst, live)| {
+            let is_dead = match *inst {
+                Instruction::MovF64x8(dst, src) => dst == src || !live.contains(&dst.into()),
+                Instruction::MovF64x2(dst, _) => !live.contains(&dst.into()),
+                Instruction::MovI32x8(dst, _) => !live.contains(&dst.into()),
+                _ => false,
+            };
+            if is_dead {
+                n_removed += 1;
+            }
+            !is_dead
+        })
+        .map(|(inst, _)| inst)
+        .collect();
+
+    (res, n_removed)
+}
+
+// the flags of the merged add match the second one in everything `jnz`
+// reads, but two adds that cancel out are only dropped when the flags are
+// not read at all.
+fn merge_adds(arr: Vec<Instruction>) -> (Vec<Instruction>, usize) {
+    let live_out = liveness::live_out(&arr);
+
+    let mut n_merged = 0;
+    let mut res: Vec<Instruction> = Vec::with_capacity(arr.len());
+    for (inst, live) in arr.into_iter().zip(live_out.iter()) {
+        let merged = match (res.last(), &inst) {
+            (
+                Some(&Instruction::AddImmediate(ref reg0, imm0)),
+                &Instruction::AddImmediate(ref reg1, imm1),
+            ) if reg0 == reg1 => imm0.checked_add(imm1).map(|imm| (reg0.clone(), imm)),
+            _ => None,
+        };
+
+        match merged {
+            Some((_, 0)) if !live.contains(&Operand::Flags) => {
+                res.pop();
+                n_merged += 2;
+            }
+            Some((reg, imm)) if imm != 0 => {
+                *res.last_mut().unwrap() = Instruction::AddImmediate(reg, imm);
+                n_merged += 1;
+            }
+            _ => {
+                res.push(inst);
+            }
+        }
+    }
+
+    (res, n_merged)
+}
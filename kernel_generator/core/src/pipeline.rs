@@ -0,0 +1,179 @@
+// Kernels made of other kernels.
+//
+// A `Pipeline` runs its stages one after the other, each between comment
+// banners with its name, and is a `Generate` itself, so that it can be a
+// stage of another pipeline or the fragment a rule emits. A stage is either
+// one generator or a fusion of two, whose rule engines fire in turns on one
+// register pool so that their instructions interleave. Fused generators get
+// the registers and masks that both of them have available. Fusing fails if
+// they use the same fixed registers, the ones outside their pools, or if
+// either emits a label or a jump, as its loop would be interleaved as well.
+
+use crate::assembly::{Instruction, Operand};
+use crate::{
+    check_pool, Assembly, Condition, Engine, Generate, GenerateError, Name, RegisterPool, Rule,
+    State, Zmm, N_MASKS, N_REGISTERS,
+};
+
+pub struct Pipeline {
+    stages: Vec<Stage>,
+}
+
+struct Stage {
+    name: Name,
+    generate: Box<dyn Fn() -> Result<Assembly, GenerateError>>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Pipeline { stages: Vec::new() }
+    }
+
+    pub fn then<T: Generate>(mut self, name: &str, generator: T) -> Self {
+        self.stages.push(Stage {
+            name: Name::from(name),
+            generate: Box::new(move || generator.generate()),
+        });
+
+        self
+    }
+
+    pub fn fuse<A: Generate, B: Generate>(mut self, name: &str, a: A, b: B) -> Self {
+        self.stages.push(Stage {
+            name: Name::from(name),
+            generate: Box::new(move || fuse(&a, &b)),
+        });
+
+        self
+    }
+}
+
+fn fuse<A: Generate, B: Generate>(a: &A, b: &B) -> Result<Assembly, GenerateError> {
+    let name = std::any::type_name::<(A, B)>();
+    let (avail_a, avail_b) = (a.avail_registers(), b.avail_registers());
+    let mut avail_registers = avail_a;
+    for (avail, b) in avail_registers.iter_mut().zip(avail_b) {
+        *avail &= b;
+    }
+    let mut avail_masks = a.avail_masks();
+    for (avail, b) in avail_masks.iter_mut().zip(b.avail_masks()) {
+        *avail &= b;
+    }
+    let mut register_pool = RegisterPool::new(avail_registers, avail_masks);
+
+    let mut engine_a = Engine::new(a)?;
+    let mut engine_b = Engine::new(b)?;
+    let mut asm = Assembly::new();
+    let (mut fixed_a, mut fixed_b) = (Vec::new(), Vec::new());
+    loop {
+        let asm_a = engine_a.step(&mut register_pool)?;
+        let asm_b = engine_b.step(&mut register_pool)?;
+        if asm_a.is_none() && asm_b.is_none() {
+            break;
+        }
+        for (res_asm, avail, fixed) in [
+            (asm_a, &avail_a, &mut fixed_a),
+            (asm_b, &avail_b, &mut fixed_b),
+        ] {
+            if let Some(res_asm) = res_asm {
+                fixed_registers(&res_asm, avail, fixed)
+                    .map_err(|err| err.context(name, None, Vec::new()))?;
+                asm = asm.append(res_asm);
+            }
+        }
+    }
+    engine_a.finish()?;
+    engine_b.finish()?;
+    check_pool(&mut register_pool, avail_registers, avail_masks)
+        .map_err(|err| err.context(name, None, Vec::new()))?;
+
+    let shared: Vec<Zmm> = fixed_a
+        .into_iter()
+        .filter(|zmm| fixed_b.contains(zmm))
+        .collect();
+    match shared.is_empty() {
+        true => Ok(asm),
+        false => Err(GenerateError::SharedFixedRegisters(shared).context(name, None, Vec::new())),
+    }
+}
+
+// adds the vector registers `asm` uses outside `avail` to `fixed`, which are
+// the ones a fragment picks itself rather than takes from the pool, and
+// fails on a branch, which fusing would take apart
+fn fixed_registers(
+    asm: &Assembly,
+    avail: &[bool; N_REGISTERS],
+    fixed: &mut Vec<Zmm>,
+) -> Result<(), GenerateError> {
+    for inst in asm.instructions() {
+        if let Instruction::Label(name) | Instruction::JumpNotZero(name) = inst {
+            return Err(GenerateError::FusedBranch(name.clone()));
+        }
+        for operand in [inst.uses(), inst.defs()].concat() {
+            if let Operand::Vector(i) = operand {
+                let zmm = Zmm(i);
+                if !avail[i as usize] && !fixed.contains(&zmm) {
+                    fixed.push(zmm);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+enum StateType {
+    Running,
+}
+
+const RULEBOOK: &'static [Rule<Pipeline>] = &[Rule {
+    name: "Running",
+    condition: Condition::Single {
+        id: StateType::Running as u32,
+    },
+    callback: |pipeline: &Pipeline, _rp: &mut RegisterPool, states: &Vec<State>| {
+        let idx = states[0].idx;
+        let stage = &pipeline.stages[idx as usize];
+
+        let asm = Assembly::new()
+            .comment(&format!("--- {} start --- //", stage.name))
+            .append((stage.generate)()?)
+            .comment(&format!("---  {} end  --- //", stage.name))
+            .empty_line();
+        let states = match idx as usize + 1 < pipeline.stages.len() {
+            true => Vec::from([State {
+                id: StateType::Running as u32,
+                idx: idx + 1,
+                reg: None,
+            }]),
+            false => Vec::new(),
+        };
+
+        Ok((asm, states))
+    },
+}];
+
+impl Generate for Pipeline {
+    fn rulebook(&self) -> &'static [Rule<Self>] {
+        RULEBOOK
+    }
+
+    fn avail_registers(&self) -> [bool; N_REGISTERS] {
+        [false; N_REGISTERS]
+    }
+
+    fn avail_masks(&self) -> [bool; N_MASKS] {
+        [false; N_MASKS]
+    }
+
+    fn initial_states(&self) -> Vec<State> {
+        match self.stages.is_empty() {
+            true => Vec::new(),
+            false => Vec::from([State {
+                id: StateType::Running as u32,
+                idx: 0,
+                reg: None,
+            }]),
+        }
+    }
+}
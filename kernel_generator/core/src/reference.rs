@@ -0,0 +1,252 @@
+// Reference kernels over the 32-slot ELL layout, with the same semantics as
+// `compute_spmv_ref`, `compute_sptrsv_ref` and `compute_symgs_ref` of
+// kernel_tuner.
+//
+// Every row owns `ROW_SLOTS` slots: the lower part (columns <= row, so the
+// stencil center included) starts at slot 0 and the upper part at slot
+// `LU_SPLIT`. Unused slots hold column 0 and value 0.0. With
+// `LUStatus::Excluded` a row owns only `LU_SPLIT` slots holding a single part.
+// With `DiagonalStatus::Excluded` and `ExcludedReciprocal` the stencil center
+// is left out of the slots and kept in `diag` only; the kernels below add it
+// back, so every layout computes the same products.
+
+use crate::sparse_matrix::*;
+
+pub const ROW_SLOTS: usize = 32;
+pub const LU_SPLIT: usize = 16;
+
+#[derive(Clone, Copy)]
+pub enum Part {
+    Full,
+    Lower,
+    Upper,
+}
+
+impl Part {
+    fn slots(&self, lu: LUStatus) -> std::ops::Range<usize> {
+        match (lu, self) {
+            (LUStatus::Default, Part::Full) => 0..ROW_SLOTS,
+            (LUStatus::Default, Part::Lower) => 0..LU_SPLIT,
+            (LUStatus::Default, Part::Upper) => LU_SPLIT..ROW_SLOTS,
+            (LUStatus::Excluded, _) => 0..LU_SPLIT,
+        }
+    }
+}
+
+fn row_slots(lu: LUStatus) -> usize {
+    match lu {
+        LUStatus::Default => ROW_SLOTS,
+        LUStatus::Excluded => LU_SPLIT,
+    }
+}
+
+pub struct ELLMatrix {
+    info: ELLInfo,
+    nrow: usize,
+    cols: Vec<i32>,
+    vals: Vec<f64>,
+    diag: Vec<f64>,
+}
+
+impl ELLMatrix {
+    pub fn new(info: ELLInfo, nrow: usize, cols: Vec<i32>, vals: Vec<f64>, diag: Vec<f64>) -> Self {
+        assert!(cols.len() == nrow * row_slots(info.lu));
+        assert!(vals.len() == nrow * row_slots(info.lu));
+        assert!(diag.len() == nrow);
+
+        ELLMatrix {
+            info,
+            nrow,
+            cols,
+            vals,
+            diag,
+        }
+    }
+
+    // 27-point stencil on an n x n x n grid, as `SparseMatrix::change_to_problem`.
+    pub fn hpcg(info: ELLInfo, n: usize) -> Self {
+        Self::stencil(info, n, |row, col| match row == col {
+            true => 26.0,
+            false => -1.0,
+        })
+    }
+
+    // 27-point stencil whose values are given by `value(row, col)`
+    // always built with `LUStatus::Default`; see `part` for the other layout
+    pub fn stencil(info: ELLInfo, n: usize, mut value: impl FnMut(usize, usize) -> f64) -> Self {
+        let info = ELLInfo::new(info.diag, LUStatus::Default, info.ordering);
+        let nrow = n * n * n;
+        let mut cols = vec![0; nrow * ROW_SLOTS];
+        let mut vals = vec![0.0; nrow * ROW_SLOTS];
+        let mut diag = vec![0.0; nrow];
+
+        for row in 0..nrow {
+            let (iz, iy, ix) = (row / (n * n), (row / n) % n, row % n);
+            let mut lnnz = 0;
+            let mut unnz = 0;
+
+            for t in 0..27 {
+                let (sz, sy, sx) = (t / 9, (t / 3) % 3, t % 3);
+                let (z, y, x) = (iz + sz, iy + sy, ix + sx);
+                if z < 1 || y < 1 || x < 1 || z > n || y > n || x > n {
+                    continue;
+                }
+
+                let col = (z - 1) * n * n + (y - 1) * n + (x - 1);
+                let val = value(row, col);
+                if col == row {
+                    diag[row] = match info.diag {
+                        DiagonalStatus::Default | DiagonalStatus::Excluded => val,
+                        DiagonalStatus::ExcludedReciprocal => 1.0 / val,
+                    };
+                    if !matches!(info.diag, DiagonalStatus::Default) {
+                        continue;
+                    }
+                }
+
+                let slot = match col <= row {
+                    true => {
+                        lnnz += 1;
+                        lnnz - 1
+                    }
+                    false => {
+                        unnz += 1;
+                        LU_SPLIT + unnz - 1
+                    }
+                };
+
+                cols[row * ROW_SLOTS + slot] = col as i32;
+                vals[row * ROW_SLOTS + slot] = val;
+            }
+        }
+
+        Self::new(info, nrow, cols, vals, diag)
+    }
+
+    // copy of one part only, stored with `LUStatus::Excluded`
+    pub fn part(&self, part: Part) -> Self {
+        assert!(!matches!(part, Part::Full));
+
+        let info = ELLInfo::new(self.info.diag, LUStatus::Excluded, self.info.ordering);
+        let mut cols = Vec::with_capacity(self.nrow * LU_SPLIT);
+        let mut vals = Vec::with_capacity(self.nrow * LU_SPLIT);
+
+        for row in 0..self.nrow {
+            let base = row * row_slots(self.info.lu);
+            for slot in part.slots(self.info.lu) {
+                cols.push(self.cols[base + slot]);
+                vals.push(self.vals[base + slot]);
+            }
+        }
+
+        Self::new(info, self.nrow, cols, vals, self.diag.clone())
+    }
+
+    pub fn info(&self) -> ELLInfo {
+        self.info
+    }
+
+    pub fn nrow(&self) -> usize {
+        self.nrow
+    }
+
+    pub fn cols(&self) -> &[i32] {
+        &self.cols
+    }
+
+    pub fn vals(&self) -> &[f64] {
+        &self.vals
+    }
+
+    pub fn diag(&self) -> &[f64] {
+        &self.diag
+    }
+
+    pub fn row(&self, row: usize, part: Part) -> impl Iterator<Item = (usize, f64)> + '_ {
+        let base = row * row_slots(self.info.lu);
+        part.slots(self.info.lu).map(move |slot| {
+            let i = base + slot;
+            (self.cols[i] as usize, self.vals[i])
+        })
+    }
+
+    // a_ii when it is not in the slots, and 0.0 when it is
+    fn excluded_diagonal(&self, row: usize) -> f64 {
+        match self.info.diag {
+            DiagonalStatus::Default => 0.0,
+            DiagonalStatus::Excluded => self.diag[row],
+            DiagonalStatus::ExcludedReciprocal => 1.0 / self.diag[row],
+        }
+    }
+
+    // sum / a_ii, with a_ii taken as the `DiagonalStatus` says
+    fn scale_by_diagonal(&self, row: usize, sum: f64) -> f64 {
+        match self.info.diag {
+            DiagonalStatus::Default => {
+                let (_, a_ii) = self
+                    .row(row, Part::Lower)
+                    .find(|(col, val)| *col == row && *val != 0.0)
+                    .expect("diagonal is not stored in the lower part");
+                sum / a_ii
+            }
+            DiagonalStatus::Excluded => sum / self.diag[row],
+            DiagonalStatus::ExcludedReciprocal => sum * self.diag[row],
+        }
+    }
+}
+
+// y = Ax over the slots of `part`
+pub fn spmv(a: &ELLMatrix, part: Part, x: &[f64], y: &mut [f64]) {
+    for (i, y) in y.iter_mut().enumerate().take(a.nrow()) {
+        let diagonal = match part {
+            Part::Full | Part::Lower => a.excluded_diagonal(i) * x[i],
+            Part::Upper => 0.0,
+        };
+        *y = a.row(i, part).map(|(col, val)| val * x[col]).sum::<f64>() + diagonal;
+    }
+}
+
+// x = trsv(D+L, r+p) & p = r-Lx, updating x in place (compute_sptrsv_ref)
+pub fn sptrsv_forward(a: &ELLMatrix, r: &[f64], p: &mut [f64], x: &mut [f64]) {
+    sptrsv_forward_rows(a, a.nrow(), r, p, x)
+}
+
+// sptrsv_forward over the first `n` rows, as a static iteration count does
+pub fn sptrsv_forward_rows(a: &ELLMatrix, n: usize, r: &[f64], p: &mut [f64], x: &mut [f64]) {
+    for i in 0..n {
+        let mut sum = r[i];
+        for (col, val) in a.row(i, Part::Lower) {
+            sum -= val * x[col];
+        }
+        sum -= a.excluded_diagonal(i) * x[i];
+
+        x[i] += a.scale_by_diagonal(i, sum + p[i]);
+        p[i] = sum;
+    }
+}
+
+// x = trsv(D+U, p), from the last row to the first
+pub fn sptrsv_backward(a: &ELLMatrix, p: &[f64], x: &mut [f64]) {
+    sptrsv_backward_rows(a, a.nrow(), p, x)
+}
+
+// sptrsv_backward over the last `n` rows
+pub fn sptrsv_backward_rows(a: &ELLMatrix, n: usize, p: &[f64], x: &mut [f64]) {
+    for i in (a.nrow() - n..a.nrow()).rev() {
+        let mut sum = p[i];
+        for (col, val) in a.row(i, Part::Upper) {
+            sum -= val * x[col];
+        }
+
+        x[i] = a.scale_by_diagonal(i, sum);
+    }
+}
+
+// p = -Ux, forward sweep, then backward sweep (compute_symgs_ref)
+pub fn symgs(a: &ELLMatrix, r: &[f64], p: &mut [f64], x: &mut [f64]) {
+    spmv(a, Part::Upper, x, p);
+    p.iter_mut().for_each(|p| *p = -*p);
+
+    sptrsv_forward(a, r, p, x);
+    sptrsv_backward(a, p, x);
+}
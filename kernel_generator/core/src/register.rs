@@ -0,0 +1,160 @@
+// Typed operands for `Instruction`.
+//
+// zmm, ymm and xmm are views of the same 32 vector registers, so each converts
+// to the others without changing the register number. Masks are k1-k7; k0 is
+// only used implicitly. General purpose registers are the named operands
+// (`%[NAME]`) bound by the compiler or by `gas::Function`.
+//
+// Vector register numbers from 32 up are spilled registers. They live in a
+// scratch area in memory, and `Assembly::spill` rewrites every access to one
+// into a reload or a store through a free physical register.
+
+use crate::name::Name;
+
+use std::fmt;
+
+// vector register numbers, spilled ones included
+pub const N_REGISTERS: usize = 64;
+
+// vector registers that exist in hardware
+pub(crate) const N_PHYSICAL_REGISTERS: u8 = 32;
+
+// opmask registers k1-k7
+pub const N_MASKS: usize = 7;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Zmm(pub(crate) u8);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Ymm(pub(crate) u8);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Xmm(pub(crate) u8);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Mask(pub(crate) u8);
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Gpr(pub(crate) Name);
+
+impl Zmm {
+    pub const fn new(i: u8) -> Self {
+        assert!(
+            (i as usize) < N_REGISTERS,
+            "there are only 32 vector registers plus 32 spill slots"
+        );
+        Zmm(i)
+    }
+
+    pub const fn index(self) -> u8 {
+        self.0
+    }
+
+    pub const fn is_spilled(self) -> bool {
+        self.0 >= N_PHYSICAL_REGISTERS
+    }
+
+    pub const fn ymm(self) -> Ymm {
+        Ymm(self.0)
+    }
+
+    pub const fn xmm(self) -> Xmm {
+        Xmm(self.0)
+    }
+}
+
+impl Ymm {
+    pub const fn new(i: u8) -> Self {
+        assert!(
+            (i as usize) < N_REGISTERS,
+            "there are only 32 vector registers plus 32 spill slots"
+        );
+        Ymm(i)
+    }
+
+    pub const fn index(self) -> u8 {
+        self.0
+    }
+
+    pub const fn zmm(self) -> Zmm {
+        Zmm(self.0)
+    }
+
+    pub const fn xmm(self) -> Xmm {
+        Xmm(self.0)
+    }
+}
+
+impl Xmm {
+    pub const fn new(i: u8) -> Self {
+        assert!(
+            (i as usize) < N_REGISTERS,
+            "there are only 32 vector registers plus 32 spill slots"
+        );
+        Xmm(i)
+    }
+
+    pub const fn index(self) -> u8 {
+        self.0
+    }
+
+    pub const fn zmm(self) -> Zmm {
+        Zmm(self.0)
+    }
+
+    pub const fn ymm(self) -> Ymm {
+        Ymm(self.0)
+    }
+}
+
+impl Mask {
+    pub const fn new(i: u8) -> Self {
+        assert!(i >= 1 && i < 8, "only k1-k7 can be used as a mask");
+        Mask(i)
+    }
+
+    pub const fn index(self) -> u8 {
+        self.0
+    }
+}
+
+impl Gpr {
+    pub fn new(name: &str) -> Self {
+        Gpr(Name::from(name))
+    }
+
+    pub fn name(&self) -> &Name {
+        &self.0
+    }
+}
+
+impl fmt::Display for Zmm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "zmm{}", self.0)
+    }
+}
+
+impl fmt::Display for Ymm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ymm{}", self.0)
+    }
+}
+
+impl fmt::Display for Xmm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "xmm{}", self.0)
+    }
+}
+
+impl fmt::Display for Mask {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "k{}", self.0)
+    }
+}
+
+// the operand name; printers wrap it in `%[...]`
+impl fmt::Display for Gpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
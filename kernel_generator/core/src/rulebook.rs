@@ -0,0 +1,172 @@
+// Rulebooks in a declarative syntax.
+//
+// `rulebook!` writes a rulebook as a list of rules
+//
+//     LoadingVal[i: u8] => [ValLoaded[i]] |config, rp| { asm }
+//
+// instead of `Rule` literals, for a state enum whose variants are the state
+// ids. Left of `=>` are the states the rule takes:
+//
+// - `A[i]`: one state of A (`Condition::Single`)
+// - `A[i] + A[i + 2]`: two states of A, 2 apart (`Condition::SameId`)
+// - `A[i] + B[i] + ...`: one state of each at the same idx (`Condition::Join`)
+//
+// `i` is bound to the idx of the first state, cast to the type after a colon
+// if there is one, or can be `_`. `A[i](reg)` binds the register of the state
+// as well. Right of `=>` are the states the rule produces, with an optional
+// register each: `B[i](reg)` passes one on, and `B[i](new reg)` takes `reg`
+// from the register pool before the body runs. The body is a block that
+// evaluates to the `Assembly` of the rule and may use `?`.
+//
+// A rule is named after the states it takes, such as `A + A at +2`.
+// `#[guard(f)]` and `#[priority(p)]` in front of a rule wrap its condition,
+// and a plain `Rule { .. }` can stand between the others for what the syntax
+// does not cover, such as a number of produced states that depends on the
+// config.
+
+#[macro_export]
+macro_rules! rulebook {
+    ($states:ident; $($rules:tt)*) => {
+        $crate::rulebook!(@rules $states; []; $($rules)*)
+    };
+
+    (@rules $states:ident; [$($done:expr,)*];) => {
+        &[$($done),*]
+    };
+
+    (@rules $states:ident; [$($done:expr,)*]; Rule { $($rule:tt)* } $($rest:tt)*) => {
+        $crate::rulebook!(@rules $states; [$($done,)* $crate::Rule { $($rule)* },]; $($rest)*)
+    };
+
+    (@rules $states:ident; [$($done:expr,)*];
+        $(#[$($attr:tt)*])*
+        $id:ident [$idx:tt $(: $ty:ty)?] $(($reg:ident))?
+        => [$($outputs:tt)*] |$config:ident, $rp:ident| $body:block
+        $($rest:tt)*
+    ) => {
+        $crate::rulebook!(@rules $states; [$($done,)* $crate::Rule {
+            name: stringify!($id),
+            condition: $crate::rulebook!(@wrap
+                $crate::Condition::Single { id: $states::$id as u32 };
+                $(#[$($attr)*])*
+            ),
+            callback: $crate::rulebook!(@callback $states;
+                [$idx $(: $ty)?]; [($($reg)?)]; [$($outputs)*]; $config, $rp, $body
+            ),
+        },]; $($rest)*)
+    };
+
+    (@rules $states:ident; [$($done:expr,)*];
+        $(#[$($attr:tt)*])*
+        $id0:ident [$idx:tt $(: $ty:ty)?] $(($reg0:ident))?
+        + $id1:ident [$idx1:tt + $dist:literal] $(($reg1:ident))?
+        => [$($outputs:tt)*] |$config:ident, $rp:ident| $body:block
+        $($rest:tt)*
+    ) => {
+        $crate::rulebook!(@rules $states; [$($done,)* $crate::Rule {
+            name: concat!(stringify!($id0), " + ", stringify!($id1), " at +", $dist),
+            condition: $crate::rulebook!(@wrap
+                $crate::Condition::SameId {
+                    id: $states::$id0 as u32,
+                    n_states: 2,
+                    idx_dist: $dist,
+                };
+                $(#[$($attr)*])*
+            ),
+            callback: {
+                const _: () = assert!(
+                    $states::$id0 as u32 == $states::$id1 as u32,
+                    "`A[i] + B[i + n]` takes two states of the same id"
+                );
+                $crate::rulebook!(@callback $states;
+                    [$idx $(: $ty)?]; [($($reg0)?) ($($reg1)?)]; [$($outputs)*];
+                    $config, $rp, $body
+                )
+            },
+        },]; $($rest)*)
+    };
+
+    (@rules $states:ident; [$($done:expr,)*];
+        $(#[$($attr:tt)*])*
+        $id:ident [$idx:tt $(: $ty:ty)?] $(($reg:ident))?
+        $(+ $ids:ident [$idxs:tt] $(($regs:ident))?)+
+        => [$($outputs:tt)*] |$config:ident, $rp:ident| $body:block
+        $($rest:tt)*
+    ) => {
+        $crate::rulebook!(@rules $states; [$($done,)* $crate::Rule {
+            name: concat!(stringify!($id) $(, " + ", stringify!($ids))+),
+            condition: $crate::rulebook!(@wrap
+                $crate::Condition::Join {
+                    ids: &[$states::$id as u32, $($states::$ids as u32),+],
+                };
+                $(#[$($attr)*])*
+            ),
+            callback: $crate::rulebook!(@callback $states;
+                [$idx $(: $ty)?]; [($($reg)?) $(($($regs)?))+]; [$($outputs)*];
+                $config, $rp, $body
+            ),
+        },]; $($rest)*)
+    };
+
+    (@wrap $condition:expr;) => {
+        $condition
+    };
+
+    (@wrap $condition:expr; #[guard($guard:expr)] $($rest:tt)*) => {
+        $crate::rulebook!(@wrap
+            $crate::Condition::Guard { guard: $guard, condition: &$condition };
+            $($rest)*
+        )
+    };
+
+    (@wrap $condition:expr; #[priority($priority:expr)] $($rest:tt)*) => {
+        $crate::rulebook!(@wrap
+            $crate::Condition::Priority { priority: $priority, condition: &$condition };
+            $($rest)*
+        )
+    };
+
+    (@callback $states:ident;
+        [$idx:tt $(: $ty:ty)?];
+        [$(($($reg:ident)?))+];
+        [$($output:ident [$output_idx:expr] $(($($output_reg:tt)*))?),*];
+        $config:ident, $rp:ident, $body:block
+    ) => {
+        |$config, $rp: &mut $crate::RegisterPool, states: &Vec<$crate::State>| {
+            let $idx = states[0].idx $(as $ty)?;
+            let mut inputs = states.iter();
+            $(
+                let _input = inputs.next().unwrap();
+                $(let $reg = _input.register()?;)?
+            )+
+            $($($crate::rulebook!(@alloc $rp; $($output_reg)*);)?)*
+
+            let asm: $crate::Assembly = $body;
+            let states = Vec::from([$($crate::State {
+                id: $states::$output as u32,
+                idx: ($output_idx) as u32,
+                reg: $crate::rulebook!(@reg $($($output_reg)*)?),
+            }),*]);
+
+            Ok((asm, states))
+        }
+    };
+
+    (@alloc $rp:ident; new $reg:ident) => {
+        let $reg = $rp.get()?;
+    };
+
+    (@alloc $rp:ident; $($reg:tt)*) => {};
+
+    (@reg) => {
+        None
+    };
+
+    (@reg new $reg:ident) => {
+        Some($reg)
+    };
+
+    (@reg $reg:expr) => {
+        Some($reg)
+    };
+}
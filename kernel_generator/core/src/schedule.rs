@@ -0,0 +1,194 @@
+// List scheduling of generated code on the KNL model of `knl`.
+//
+// Code is split into regions at labels, jumps, comments and nops, so loop
+// structure and manual padding stay where the generator put them. Inside a
+// region an instruction depends on every earlier instruction it shares a
+// register, mask or flag with, and on every earlier memory access when
+// either of the two is a store. Each cycle up to two ready instructions with
+// free units are issued, preferring the longest latency path to the end of
+// the region and then the original order.
+
+use crate::assembly::{Instruction, Operand};
+use crate::knl::{self, Cost, Unit};
+use crate::knl::{DECODE_WIDTH, N_ALUS, N_LOAD_PORTS, N_STORE_PORTS, N_VPUS};
+
+pub(crate) fn schedule(arr: Vec<Instruction>) -> Vec<Instruction> {
+    let mut res = Vec::with_capacity(arr.len());
+    let mut region = Vec::new();
+
+    for inst in arr.into_iter() {
+        match inst {
+            Instruction::Comment(_)
+            | Instruction::Nop
+            | Instruction::Label(_)
+            | Instruction::JumpNotZero(_) => {
+                res.extend(schedule_region(std::mem::take(&mut region)));
+                res.push(inst);
+            }
+            _ => region.push(inst),
+        }
+    }
+    res.extend(schedule_region(region));
+
+    res
+}
+
+fn schedule_region(region: Vec<Instruction>) -> Vec<Instruction> {
+    let n = region.len();
+    if n < 2 {
+        return region;
+    }
+
+    let costs: Vec<Cost> = region.iter().map(knl::cost).collect();
+    let uses: Vec<Vec<Operand>> = region.iter().map(|inst| inst.uses()).collect();
+    let defs: Vec<Vec<Operand>> = region.iter().map(|inst| inst.defs()).collect();
+
+    // succs[j] holds (i, latency) for every i that has to wait for j
+    let mut succs: Vec<Vec<(usize, f64)>> = vec![Vec::new(); n];
+    let mut n_preds = vec![0; n];
+    for i in 0..n {
+        for j in 0..i {
+            let shares = |a: &[Operand], b: &[Operand]| a.iter().any(|x| b.contains(x));
+            let raw = shares(&defs[j], &uses[i]);
+            let war = shares(&uses[j], &defs[i]);
+            let waw = shares(&defs[j], &defs[i]);
+            let memory = is_memory(&costs[j])
+                && is_memory(&costs[i])
+                && (is_store(&costs[j]) || is_store(&costs[i]));
+
+            if raw || war || waw || memory {
+                let latency = match raw {
+                    true => costs[j].latency,
+                    false => 0.0,
+                };
+                succs[j].push((i, latency));
+                n_preds[i] += 1;
+            }
+        }
+    }
+
+    // longest latency path from the issue of each instruction to the end
+    let mut heights = vec![0.0; n];
+    for i in (0..n).rev() {
+        heights[i] = succs[i]
+            .iter()
+            .map(|&(s, latency)| latency + heights[s])
+            .fold(costs[i].latency, f64::max);
+    }
+
+    let mut ready_at = vec![0.0; n];
+    let mut issued = vec![false; n];
+    let mut order = Vec::with_capacity(n);
+    let mut units = Units::new();
+    let mut cycle = 0.0;
+
+    while order.len() < n {
+        units.next_cycle();
+
+        loop {
+            let candidate = (0..n)
+                .filter(|&i| !issued[i] && n_preds[i] == 0 && ready_at[i] <= cycle)
+                .filter(|&i| units.fits(&costs[i], cycle))
+                .fold(None, |acc: Option<usize>, i| match acc {
+                    Some(best) if heights[best] >= heights[i] => Some(best),
+                    _ => Some(i),
+                });
+
+            match candidate {
+                Some(i) => {
+                    units.take(&costs[i], cycle);
+                    issued[i] = true;
+                    order.push(i);
+                    for &(s, latency) in succs[i].iter() {
+                        n_preds[s] -= 1;
+                        ready_at[s] = f64::max(ready_at[s], cycle + latency);
+                    }
+                }
+                None => break,
+            }
+        }
+
+        cycle += 1.0;
+    }
+
+    let mut region: Vec<Option<Instruction>> = region.into_iter().map(Some).collect();
+    order
+        .into_iter()
+        .map(|i| region[i].take().unwrap())
+        .collect()
+}
+
+fn is_memory(cost: &Cost) -> bool {
+    cost.load || matches!(cost.unit, Some(Unit::Load | Unit::Store | Unit::Gather))
+}
+
+fn is_store(cost: &Cost) -> bool {
+    matches!(cost.unit, Some(Unit::Store))
+}
+
+// slots taken in the current cycle, and when the unpipelined units free up
+struct Units {
+    decode: f64,
+    vpu: f64,
+    load: f64,
+    store: f64,
+    integer: f64,
+    gather_free_at: f64,
+    divider_free_at: f64,
+}
+
+impl Units {
+    fn new() -> Self {
+        Units {
+            decode: 0.0,
+            vpu: 0.0,
+            load: 0.0,
+            store: 0.0,
+            integer: 0.0,
+            gather_free_at: 0.0,
+            divider_free_at: 0.0,
+        }
+    }
+
+    fn next_cycle(&mut self) -> () {
+        self.decode = 0.0;
+        self.vpu = 0.0;
+        self.load = 0.0;
+        self.store = 0.0;
+        self.integer = 0.0;
+    }
+
+    fn fits(&self, cost: &Cost, cycle: f64) -> bool {
+        let load_fits = match cost.load {
+            true => self.load < N_LOAD_PORTS,
+            false => true,
+        };
+        let unit_fits = match cost.unit {
+            None | Some(Unit::Frontend) => true,
+            Some(Unit::Vpu) => self.vpu < N_VPUS,
+            Some(Unit::Load) => self.load < N_LOAD_PORTS,
+            Some(Unit::Store) => self.store < N_STORE_PORTS,
+            Some(Unit::Integer) => self.integer < N_ALUS,
+            Some(Unit::Gather) => self.gather_free_at <= cycle,
+            Some(Unit::Divider) => self.divider_free_at <= cycle,
+        };
+
+        self.decode < DECODE_WIDTH && load_fits && unit_fits
+    }
+
+    fn take(&mut self, cost: &Cost, cycle: f64) -> () {
+        self.decode += 1.0;
+        if cost.load {
+            self.load += 1.0;
+        }
+        match cost.unit {
+            None | Some(Unit::Frontend) => {}
+            Some(Unit::Vpu) => self.vpu += 1.0,
+            Some(Unit::Load) => self.load += 1.0,
+            Some(Unit::Store) => self.store += 1.0,
+            Some(Unit::Integer) => self.integer += 1.0,
+            Some(Unit::Gather) => self.gather_free_at = cycle + cost.occupancy,
+            Some(Unit::Divider) => self.divider_free_at = cycle + cost.occupancy,
+        }
+    }
+}
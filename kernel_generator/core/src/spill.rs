@@ -0,0 +1,152 @@
+// Spilled vector registers over a whole kernel.
+//
+// Every spilled register (numbers from 32 up) has a 64-byte slot in a scratch
+// area that the kernel never moves, register 32 at offset 0 and register 63,
+// the last one, at offset 0x7c0. An instruction that reads one is preceded
+// by a reload of its slot into a physical register, one that writes it is
+// followed by a store, and the instruction itself works on the physical
+// register. A full-width move between a spilled and a physical register
+// becomes the load or the store itself. The physical registers are picked per
+// instruction among those dead around it, so code that touches spilled
+// registers has to leave a few registers free there.
+
+use crate::assembly::{Instruction, Operand};
+use crate::liveness;
+use crate::register::{Gpr, Zmm, N_PHYSICAL_REGISTERS, N_REGISTERS};
+use crate::GenerateError;
+
+use std::fmt;
+
+const SLOT_BYTES: i16 = 64;
+
+// bytes the spill area has to hold for a slot of every spilled register,
+// which the `tmp` of the kernel tuner drivers is allocated with
+pub const SPILL_AREA_BYTES: usize = 2048;
+
+const _: () = assert!(
+    SLOT_BYTES as usize * (N_REGISTERS - N_PHYSICAL_REGISTERS as usize) <= SPILL_AREA_BYTES
+);
+
+// what spilling costs a kernel
+#[derive(Default)]
+pub struct SpillReport {
+    pub registers: usize,
+    pub bytes: usize,
+    pub reloads: usize,
+    pub stores: usize,
+}
+
+impl fmt::Display for SpillReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "spilled {} registers to {} bytes ({} reloads, {} stores)",
+            self.registers, self.bytes, self.reloads, self.stores
+        )
+    }
+}
+
+fn slot(i: u8) -> i16 {
+    SLOT_BYTES * (i - N_PHYSICAL_REGISTERS) as i16
+}
+
+// spilled register numbers an instruction touches, each once
+fn spilled_operands(inst: &Instruction) -> Vec<u8> {
+    let mut res: Vec<u8> = Vec::new();
+    for operand in inst.uses().into_iter().chain(inst.defs()) {
+        match operand {
+            Operand::Vector(i) if i >= N_PHYSICAL_REGISTERS && !res.contains(&i) => res.push(i),
+            _ => {}
+        }
+    }
+    res
+}
+
+pub(crate) fn spill(
+    arr: Vec<Instruction>,
+    base: Gpr,
+) -> Result<(Vec<Instruction>, SpillReport), GenerateError> {
+    let mut spilled: Vec<u8> = arr.iter().flat_map(spilled_operands).collect();
+    spilled.sort();
+    spilled.dedup();
+
+    let mut report = SpillReport::default();
+    let last = match spilled.last() {
+        Some(&last) => last,
+        None => return Ok((arr, report)),
+    };
+    report.registers = spilled.len();
+    report.bytes = (slot(last) + SLOT_BYTES) as usize;
+
+    // the slots stay where they are only if the base does
+    if arr
+        .iter()
+        .any(|inst| inst.defs().contains(&Operand::from(&base)))
+    {
+        return Err(GenerateError::MovingSpillArea(base.name().clone()));
+    }
+
+    let live_out = liveness::live_out(&arr);
+    let mut res: Vec<Instruction> = Vec::with_capacity(arr.len());
+    for (inst, live) in arr.into_iter().zip(live_out.iter()) {
+        let touched = spilled_operands(&inst);
+        if touched.is_empty() {
+            res.push(inst);
+            continue;
+        }
+
+        match inst {
+            Instruction::MovF64x8(dst, src) if !src.is_spilled() => {
+                res.push(Instruction::StoreF64x8(
+                    base.clone(),
+                    slot(dst.index()),
+                    src,
+                ));
+                report.stores += 1;
+                continue;
+            }
+            Instruction::MovF64x8(dst, src) if !dst.is_spilled() => {
+                res.push(Instruction::LoadF64x8(dst, base.clone(), slot(src.index())));
+                report.reloads += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        let uses = inst.uses();
+        let defs = inst.defs();
+        let is_free = |r: u8| {
+            let operand = Operand::Vector(r);
+            !uses.contains(&operand) && !defs.contains(&operand) && !live.contains(&operand)
+        };
+        let free: Vec<u8> = (0..N_PHYSICAL_REGISTERS).filter(|&r| is_free(r)).collect();
+        if free.len() < touched.len() {
+            return Err(GenerateError::RegisterOverflow {
+                parameters: Vec::new(),
+                need: N_PHYSICAL_REGISTERS as usize - free.len() + touched.len(),
+                avail: N_PHYSICAL_REGISTERS as usize,
+            });
+        }
+
+        for (&i, &r) in touched.iter().zip(free.iter()) {
+            if uses.contains(&Operand::Vector(i)) {
+                res.push(Instruction::LoadF64x8(Zmm(r), base.clone(), slot(i)));
+                report.reloads += 1;
+            }
+        }
+        res.push(
+            inst.map_vectors(|i| match touched.iter().position(|&t| t == i) {
+                Some(k) => free[k],
+                None => i,
+            }),
+        );
+        for (&i, &r) in touched.iter().zip(free.iter()) {
+            if defs.contains(&Operand::Vector(i)) {
+                res.push(Instruction::StoreF64x8(base.clone(), slot(i), Zmm(r)));
+                report.stores += 1;
+            }
+        }
+    }
+
+    Ok((res, report))
+}
@@ -20,7 +20,7 @@ pub fn lcm(numbers: &[u8]) -> u8 {
         }
 
         let tmp = gcd(res, *x);
-        res = res / tmp * x;
+        res = (res / tmp).saturating_mul(*x);
     }
 
     res
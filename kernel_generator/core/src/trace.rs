@@ -0,0 +1,320 @@
+// Opt-in trace of the rule engine.
+//
+// `record` runs a closure and collects every `Generate::generate` called
+// inside it, nested generators included: each rule firing keeps its
+// condition, the states it consumed and produced, the instructions its
+// callback emitted, and the traces of the generators the callback ran.
+// Nothing is recorded outside of `record`.
+//
+// `to_json` dumps the traces as is; `to_dot` draws them as a Graphviz state
+// graph, where firings are boxes and every state is an edge from the firing
+// that produced it to the one that consumed it. States left unconsumed point
+// at a red node.
+//
+// Each trace also keeps the overlapping rules of its generator, which
+// `overlaps` lists for all of them.
+
+use crate::{Overlap, State};
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+thread_local! {
+    // traces of the `generate` calls in progress, innermost last
+    static FRAMES: RefCell<Vec<Vec<Trace>>> = const { RefCell::new(Vec::new()) };
+}
+
+// one `Generate::generate` call
+pub struct Trace {
+    pub generator: &'static str,
+    pub initial: Vec<State>,
+    pub firings: Vec<Firing>,
+    // see `Generate::overlaps`
+    pub overlaps: Vec<Overlap>,
+}
+
+pub struct Firing {
+    // index into the rulebook
+    pub rule: usize,
+    pub name: &'static str,
+    pub condition: String,
+    pub consumed: Vec<State>,
+    pub produced: Vec<State>,
+    pub instructions: Vec<String>,
+    pub nested: Vec<Trace>,
+}
+
+// runs `f` and returns the traces of the generators it ran
+pub fn record<R>(f: impl FnOnce() -> R) -> (R, Vec<Trace>) {
+    FRAMES.with(|frames| frames.borrow_mut().push(Vec::new()));
+    let res = f();
+    let traces = FRAMES.with(|frames| frames.borrow_mut().pop().unwrap());
+
+    (res, traces)
+}
+
+pub(crate) fn is_recording() -> bool {
+    FRAMES.with(|frames| !frames.borrow().is_empty())
+}
+
+// collects the generators a rule callback runs
+pub(crate) fn begin_firing() -> () {
+    FRAMES.with(|frames| frames.borrow_mut().push(Vec::new()));
+}
+
+pub(crate) fn end_firing() -> Vec<Trace> {
+    FRAMES.with(|frames| frames.borrow_mut().pop().unwrap())
+}
+
+pub(crate) fn finish(trace: Trace) -> () {
+    FRAMES.with(|frames| frames.borrow_mut().last_mut().unwrap().push(trace));
+}
+
+// "<generator>: <overlap>" for every traced generator, nested ones included,
+// without repeats
+pub fn overlaps(traces: &[Trace]) -> Vec<String> {
+    let mut overlaps = Vec::new();
+    collect_overlaps(&mut overlaps, traces);
+    overlaps.sort();
+    overlaps.dedup();
+
+    overlaps
+        .into_iter()
+        .map(|(generator, _, overlap)| format!("{}: {}", generator, overlap))
+        .collect()
+}
+
+// keyed by generator and rules, so that they sort by rule number
+fn collect_overlaps(
+    overlaps: &mut Vec<(&'static str, (usize, usize), String)>,
+    traces: &[Trace],
+) -> () {
+    for trace in traces.iter() {
+        for overlap in trace.overlaps.iter() {
+            overlaps.push((trace.generator, overlap.rules, overlap.to_string()));
+        }
+        for firing in trace.firings.iter() {
+            collect_overlaps(overlaps, &firing.nested);
+        }
+    }
+}
+
+pub fn to_json(traces: &[Trace]) -> String {
+    let mut output = String::new();
+    write_traces(&mut output, traces, 0);
+    output.push('\n');
+
+    output
+}
+
+fn write_traces(output: &mut String, traces: &[Trace], depth: usize) -> () {
+    let indent = "  ".repeat(depth);
+    if traces.is_empty() {
+        output.push_str("[]");
+        return;
+    }
+
+    output.push_str("[\n");
+    for (i, trace) in traces.iter().enumerate() {
+        writeln!(output, "{}  {{", indent).unwrap();
+        writeln!(
+            output,
+            "{}    \"generator\": {},",
+            indent,
+            quote(trace.generator)
+        )
+        .unwrap();
+        writeln!(
+            output,
+            "{}    \"initial\": {},",
+            indent,
+            states_json(&trace.initial)
+        )
+        .unwrap();
+        let overlaps: Vec<String> = trace
+            .overlaps
+            .iter()
+            .map(|overlap| {
+                let ids: Vec<String> = overlap.ids.iter().map(|id| id.to_string()).collect();
+                format!(
+                    "{{\"rules\": [{}, {}], \"names\": [{}, {}], \"ids\": [{}]}}",
+                    overlap.rules.0,
+                    overlap.rules.1,
+                    quote(overlap.names.0),
+                    quote(overlap.names.1),
+                    ids.join(", ")
+                )
+            })
+            .collect();
+        writeln!(
+            output,
+            "{}    \"overlaps\": [{}],",
+            indent,
+            overlaps.join(", ")
+        )
+        .unwrap();
+        write!(output, "{}    \"firings\": [", indent).unwrap();
+        for (j, firing) in trace.firings.iter().enumerate() {
+            let instructions: Vec<String> = firing.instructions.iter().map(|s| quote(s)).collect();
+
+            write!(output, "\n{}      {{", indent).unwrap();
+            write!(output, "\"rule\": {}, ", firing.rule).unwrap();
+            write!(output, "\"name\": {}, ", quote(firing.name)).unwrap();
+            write!(output, "\"condition\": {}, ", quote(&firing.condition)).unwrap();
+            write!(output, "\"consumed\": {}, ", states_json(&firing.consumed)).unwrap();
+            write!(output, "\"produced\": {}, ", states_json(&firing.produced)).unwrap();
+            write!(output, "\"instructions\": [{}], ", instructions.join(", ")).unwrap();
+            write!(output, "\"nested\": ").unwrap();
+            write_traces(output, &firing.nested, depth + 3);
+            output.push('}');
+            if j + 1 < trace.firings.len() {
+                output.push(',');
+            }
+        }
+        match trace.firings.is_empty() {
+            true => writeln!(output, "]").unwrap(),
+            false => writeln!(output, "\n{}    ]", indent).unwrap(),
+        }
+        write!(output, "{}  }}", indent).unwrap();
+        if i + 1 < traces.len() {
+            output.push(',');
+        }
+        output.push('\n');
+    }
+    write!(output, "{}]", indent).unwrap();
+}
+
+fn states_json(states: &[State]) -> String {
+    let states: Vec<String> = states
+        .iter()
+        .map(|state| {
+            let reg = match state.reg {
+                Some(reg) => quote(&reg.to_string()),
+                None => String::from("null"),
+            };
+            format!(
+                "{{\"id\": {}, \"idx\": {}, \"reg\": {}}}",
+                state.id, state.idx, reg
+            )
+        })
+        .collect();
+
+    format!("[{}]", states.join(", "))
+}
+
+fn quote(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(quoted, "\\u{:04x}", c as u32).unwrap(),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+
+    quoted
+}
+
+pub fn to_dot(traces: &[Trace]) -> String {
+    let mut output = String::new();
+    writeln!(output, "digraph trace {{").unwrap();
+    writeln!(output, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+    for (i, trace) in traces.iter().enumerate() {
+        write_cluster(&mut output, trace, &format!("t{}", i), 1);
+    }
+    writeln!(output, "}}").unwrap();
+
+    output
+}
+
+// `prefix` keeps the node names of nested generators apart
+fn write_cluster(output: &mut String, trace: &Trace, prefix: &str, depth: usize) -> () {
+    let indent = "    ".repeat(depth);
+    writeln!(output, "{}subgraph cluster_{} {{", indent, prefix).unwrap();
+    writeln!(output, "{}    label={};", indent, quote(trace.generator)).unwrap();
+    writeln!(
+        output,
+        "{}    {}_initial [label=\"initial\", shape=ellipse];",
+        indent, prefix
+    )
+    .unwrap();
+
+    // the node that produced each live state, by (id, idx)
+    let mut producers: HashMap<(u32, u32), String> = HashMap::new();
+    for state in trace.initial.iter() {
+        producers.insert((state.id, state.idx), format!("{}_initial", prefix));
+    }
+
+    for (i, firing) in trace.firings.iter().enumerate() {
+        let node = format!("{}_f{}", prefix, i);
+        let label = format!(
+            "#{} rule {} `{}`\n{}\n{} instructions",
+            i,
+            firing.rule,
+            firing.name,
+            firing.condition,
+            firing.instructions.len()
+        );
+        writeln!(output, "{}    {} [label={}];", indent, node, quote(&label)).unwrap();
+
+        for state in firing.consumed.iter() {
+            if let Some(producer) = producers.remove(&(state.id, state.idx)) {
+                writeln!(
+                    output,
+                    "{}    {} -> {} [label={}];",
+                    indent,
+                    producer,
+                    node,
+                    quote(&state_label(state))
+                )
+                .unwrap();
+            }
+        }
+        for state in firing.produced.iter() {
+            producers.insert((state.id, state.idx), node.clone());
+        }
+
+        for (j, nested) in firing.nested.iter().enumerate() {
+            let nested_prefix = format!("{}_{}_{}", prefix, i, j);
+            write_cluster(output, nested, &nested_prefix, depth + 1);
+            writeln!(
+                output,
+                "{}    {} -> {}_initial [style=dashed];",
+                indent, node, nested_prefix
+            )
+            .unwrap();
+        }
+    }
+
+    if !producers.is_empty() {
+        writeln!(
+            output,
+            "{}    {}_unconsumed [label=\"unconsumed\", shape=ellipse, color=red];",
+            indent, prefix
+        )
+        .unwrap();
+        let mut leftover: Vec<((u32, u32), String)> = producers.into_iter().collect();
+        leftover.sort();
+        for ((id, idx), producer) in leftover {
+            writeln!(
+                output,
+                "{}    {} -> {}_unconsumed [label=\"{}:{}\", color=red];",
+                indent, producer, prefix, id, idx
+            )
+            .unwrap();
+        }
+    }
+    writeln!(output, "{}}}", indent).unwrap();
+}
+
+fn state_label(state: &State) -> String {
+    match state.reg {
+        Some(reg) => format!("{}:{} {}", state.id, state.idx, reg),
+        None => format!("{}:{}", state.id, state.idx),
+    }
+}
@@ -0,0 +1,145 @@
+// `Join`, `Guard` and `Priority` conditions, and `Generate::overlaps`.
+
+extern crate core;
+
+use core::trace::{self, Firing};
+use core::*;
+
+struct Generator {
+    guarded: bool,
+}
+
+enum StateType {
+    A,
+    B,
+    C,
+}
+
+fn consume(
+    _config: &Generator,
+    _rp: &mut RegisterPool,
+    _states: &Vec<State>,
+) -> Result<(Assembly, Vec<State>), GenerateError> {
+    Ok((Assembly::new(), Vec::new()))
+}
+
+const RULEBOOK: &'static [Rule<Generator>] = &[
+    Rule {
+        name: "A + B + C",
+        condition: Condition::Join {
+            ids: &[
+                StateType::A as u32,
+                StateType::B as u32,
+                StateType::C as u32,
+            ],
+        },
+        callback: consume,
+    },
+    Rule {
+        name: "A",
+        condition: Condition::Priority {
+            priority: -1,
+            condition: &Condition::Single {
+                id: StateType::A as u32,
+            },
+        },
+        callback: consume,
+    },
+    Rule {
+        name: "guarded B",
+        condition: Condition::Guard {
+            guard: |config: &Generator| config.guarded,
+            condition: &Condition::Single {
+                id: StateType::B as u32,
+            },
+        },
+        callback: consume,
+    },
+    Rule {
+        name: "B",
+        condition: Condition::Single {
+            id: StateType::B as u32,
+        },
+        callback: consume,
+    },
+    Rule {
+        name: "C",
+        condition: Condition::Single {
+            id: StateType::C as u32,
+        },
+        callback: consume,
+    },
+];
+
+impl Generate for Generator {
+    fn rulebook(&self) -> &'static [Rule<Self>] {
+        RULEBOOK
+    }
+
+    fn avail_registers(&self) -> [bool; N_REGISTERS] {
+        [true; N_REGISTERS]
+    }
+
+    // a at 0..4, b at 1..5 and c at 2..6
+    fn initial_states(&self) -> Vec<State> {
+        let mut states = Vec::new();
+        let ids = [StateType::A, StateType::B, StateType::C].map(|id| id as u32);
+        for (first, id) in ids.into_iter().enumerate() {
+            for idx in first as u32..first as u32 + 4 {
+                states.push(State { id, idx, reg: None });
+            }
+        }
+
+        states
+    }
+}
+
+fn firings(guarded: bool) -> Vec<Firing> {
+    let (asm, mut traces) = trace::record(|| Generator { guarded }.generate());
+    assert!(asm.is_ok());
+    traces.remove(0).firings
+}
+
+#[test]
+fn join_takes_one_state_of_each_id_at_the_same_idx() {
+    let firings = firings(true);
+    let joins: Vec<&Firing> = firings.iter().filter(|firing| firing.rule == 0).collect();
+    assert_eq!(joins.len(), 2);
+    for (firing, idx) in joins.iter().zip([2, 3]) {
+        let states: Vec<(u32, u32)> = firing
+            .consumed
+            .iter()
+            .map(|state| (state.id, state.idx))
+            .collect();
+        assert_eq!(states, [(0, idx), (1, idx), (2, idx)]);
+    }
+}
+
+#[test]
+fn lower_priority_rules_fire_last() {
+    let firings = firings(true);
+    let rules: Vec<usize> = firings.iter().map(|firing| firing.rule).collect();
+    assert_eq!(rules, [0, 0, 2, 2, 4, 4, 1, 1]);
+}
+
+#[test]
+fn guards_see_the_config() {
+    let rules: Vec<usize> = firings(false).iter().map(|firing| firing.rule).collect();
+    assert_eq!(rules, [0, 0, 3, 3, 4, 4, 1, 1]);
+}
+
+#[test]
+fn overlaps() {
+    let overlaps = Generator { guarded: true }.overlaps();
+    let rules: Vec<(usize, usize)> = overlaps.iter().map(|overlap| overlap.rules).collect();
+    assert_eq!(rules, [(0, 2), (0, 3), (0, 4), (2, 3)]);
+    assert_eq!(
+        overlaps[3].to_string(),
+        "rules `guarded B` and `B` can both take states of id 1"
+    );
+
+    // the guard rules 2 out, and rule 1 has a priority of its own
+    let overlaps = Generator { guarded: false }.overlaps();
+    let rules: Vec<(usize, usize)> = overlaps.iter().map(|overlap| overlap.rules).collect();
+    assert_eq!(rules, [(0, 3), (0, 4)]);
+}
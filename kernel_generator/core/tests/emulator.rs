@@ -0,0 +1,129 @@
+// Single instructions run in the emulator against values worked out by hand.
+
+extern crate core;
+
+use core::{Assembly, Emulator, Mask, Memory, Zmm};
+
+const A: [f64; 8] = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0];
+const B: [f64; 8] = [10.0, 20.0, 30.0, 40.0, 50.0, 60.0, 70.0, 80.0];
+const C: [f64; 8] = [0.5, 0.25, 0.125, 1.0, 2.0, 4.0, 8.0, 16.0];
+
+// zmm0, zmm1 and zmm2 loaded with A, B and C, then `asm`
+fn run(asm: Assembly) -> Emulator {
+    let mut memory = Memory::new();
+    let data: Vec<f64> = A.iter().chain(B.iter()).chain(C.iter()).copied().collect();
+    let data = memory.alloc_f64(&data);
+    let col = memory.alloc_i32(&[7, 0, 6, 1, 5, 2, 4, 3]);
+
+    let asm = Assembly::new()
+        .load_f64x8(Zmm::new(0), "DATA", 0)
+        .load_f64x8(Zmm::new(1), "DATA", 0x40)
+        .load_f64x8(Zmm::new(2), "DATA", 0x80)
+        .load_i32x8(Zmm::new(3).ymm(), "COL", 0)
+        .append(asm);
+    let mut emulator = Emulator::new(memory)
+        .pointer("DATA", data)
+        .pointer("COL", col)
+        .int("CNT", 0);
+    emulator.run(&asm).unwrap();
+    emulator
+}
+
+#[test]
+fn vfnmsub231pd_subtracts_the_product_from_the_negated_destination() {
+    let emulator = run(Assembly::new().nmulsub_f64x8(Zmm::new(2), Zmm::new(0), Zmm::new(1)));
+    let expected: Vec<f64> = (0..8).map(|i| -(A[i] * B[i]) - C[i]).collect();
+    assert_eq!(emulator.zmm_f64x8(Zmm::new(2)).to_vec(), expected);
+}
+
+#[test]
+fn valignq_takes_the_upper_half_of_one_source_and_the_lower_of_the_other() {
+    // vinsertf64x4 zmm0 into zmm4, valignq zmm0:zmm1 into zmm1, then the sum
+    let emulator = run(Assembly::new().mix4add_f64x8(Zmm::new(4), Zmm::new(0), Zmm::new(1)));
+    assert_eq!(
+        emulator.zmm_f64x8(Zmm::new(1)),
+        [5.0, 6.0, 7.0, 8.0, 10.0, 20.0, 30.0, 40.0]
+    );
+    assert_eq!(
+        emulator.zmm_f64x8(Zmm::new(4)),
+        [6.0, 8.0, 10.0, 12.0, 60.0, 80.0, 100.0, 120.0]
+    );
+}
+
+#[test]
+fn vpermpd_swaps_the_halves_of_each_256_bit_lane_under_its_mask() {
+    let (k0, k1) = (Mask::new(1), Mask::new(2));
+    let emulator = run(Assembly::new().init_mix2mask("CNT", k0, k1).mix2add_f64x8(
+        Zmm::new(4),
+        Zmm::new(0),
+        Zmm::new(1),
+        k0,
+        k1,
+    ));
+    // k0 = 0x33 takes lanes 0, 1, 4 and 5 of zmm0 from two lanes up
+    assert_eq!(emulator.mask(k0), 0x33);
+    assert_eq!(
+        emulator.zmm_f64x8(Zmm::new(1)),
+        [3.0, 4.0, 30.0, 40.0, 7.0, 8.0, 70.0, 80.0]
+    );
+    // and not k0 the other lanes of zmm0 from the old zmm1
+    assert_eq!(
+        emulator.zmm_f64x8(Zmm::new(0)),
+        [1.0, 2.0, 10.0, 20.0, 5.0, 6.0, 50.0, 60.0]
+    );
+    assert_eq!(
+        emulator.zmm_f64x8(Zmm::new(4)),
+        [4.0, 6.0, 40.0, 60.0, 12.0, 14.0, 120.0, 140.0]
+    );
+}
+
+#[test]
+fn gathers_clear_their_mask_and_leave_masked_lanes_alone() {
+    let k = Mask::new(3);
+    let emulator =
+        run(Assembly::new()
+            .mask_on(k)
+            .gather_f64x8(Zmm::new(5), "DATA", Zmm::new(3).ymm(), k));
+    assert_eq!(
+        emulator.zmm_f64x8(Zmm::new(5)),
+        [8.0, 1.0, 7.0, 2.0, 6.0, 3.0, 5.0, 4.0]
+    );
+    assert_eq!(emulator.mask(k), 0);
+
+    let (k0, k1) = (Mask::new(1), Mask::new(2));
+    let emulator = run(Assembly::new().init_mix2mask("CNT", k0, k1).gather_f64x8(
+        Zmm::new(2),
+        "DATA",
+        Zmm::new(3).ymm(),
+        k0,
+    ));
+    assert_eq!(
+        emulator.zmm_f64x8(Zmm::new(2)),
+        [8.0, 1.0, 0.125, 1.0, 6.0, 3.0, 8.0, 16.0]
+    );
+    assert_eq!(emulator.mask(k0), 0);
+    assert_ne!(emulator.mask(k1), 0);
+}
+
+#[test]
+fn declared_locals_are_bound_unless_the_caller_binds_them() {
+    let asm = Assembly::new()
+        .argument_int("N")
+        .argument_pointer("X")
+        .local_int("I")
+        .local_copy("N2", "N")
+        .local_copy("X2", "X")
+        .add_immediate("I", 3)
+        .add_immediate("N2", 1)
+        .add_immediate("X2", 0x40);
+    let mut emulator = Emulator::new(Memory::new())
+        .int("N", 7)
+        .pointer("X", 0x1000)
+        .pointer("X2", 0x2000);
+    emulator.run(&asm).unwrap();
+
+    assert_eq!(emulator.read_int("I"), Some(3));
+    assert_eq!(emulator.read_int("N2"), Some(8));
+    assert_eq!(emulator.read_pointer("X2"), Some(0x2040));
+    assert_eq!(emulator.read_int("N"), Some(7));
+}
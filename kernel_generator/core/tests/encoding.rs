@@ -0,0 +1,573 @@
+// Golden encodings for `gas::Function::assemble`. The expected bytes are
+// what GNU as produces for the AT&T printout (`gas::Function::print`) of the
+// same function.
+
+extern crate core;
+
+use core::gas::{Function, GasError};
+use core::{Assembly, Mask, PrefetchType, Xmm, Ymm, Zmm};
+
+// push rbx; push rbp; push r12; push r13
+const PROLOGUE: &[u8] = &[0x53, 0x55, 0x41, 0x54, 0x41, 0x55];
+// vzeroupper; xor eax, eax; pop r13; pop r12; pop rbp; pop rbx; ret
+const EPILOGUE: &[u8] = &[
+    0xc5, 0xf8, 0x77, 0x31, 0xc0, 0x41, 0x5d, 0x41, 0x5c, 0x5d, 0x5b, 0xc3,
+];
+
+// N: edi, A: rsi, B: rdx, C: rcx, D: r8, E: r9,
+// I: eax, F: r10, G: r11, J: ebx, K: rbp, L: r12, H: r13
+fn function() -> Function {
+    Function::new("f")
+        .int("N")
+        .pointer("A")
+        .pointer("B")
+        .pointer("C")
+        .pointer("D")
+        .pointer("E")
+        .local_int("I")
+        .local_pointer("F")
+        .local_pointer("G")
+        .local_int("J")
+        .local_pointer("K")
+        .local_pointer("L")
+        .local_pointer("H")
+}
+
+fn cases() -> Vec<(&'static str, Assembly, &'static [u8])> {
+    let mut long_loop = Assembly::new().label("long_loop");
+    for i in 0..16 {
+        long_loop = long_loop.load_f64x8(Zmm::new(i), "A", i as i16 * 0x40);
+    }
+    let long_loop = long_loop.sub_immediate("N", 1).jump_nz("long_loop");
+
+    vec![
+        (
+            "vmovupd zmm load",
+            Assembly::new().load_f64x8(Zmm::new(1), "A", 0),
+            &[0x62, 0xf1, 0xfd, 0x48, 0x10, 0x0e],
+        ),
+        (
+            "vmovupd zmm load, disp8*64, high registers",
+            Assembly::new().load_f64x8(Zmm::new(17), "D", 0x40),
+            &[0x62, 0xc1, 0xfd, 0x48, 0x10, 0x48, 0x01],
+        ),
+        (
+            "vmovupd zmm load, smallest disp8*64",
+            Assembly::new().load_f64x8(Zmm::new(3), "A", -0x2000),
+            &[0x62, 0xf1, 0xfd, 0x48, 0x10, 0x5e, 0x80],
+        ),
+        (
+            "vmovupd zmm load, disp32 past disp8*64",
+            Assembly::new().load_f64x8(Zmm::new(3), "A", 0x2000),
+            &[0x62, 0xf1, 0xfd, 0x48, 0x10, 0x9e, 0x00, 0x20, 0x00, 0x00],
+        ),
+        (
+            "vmovupd zmm load, unscalable disp",
+            Assembly::new().load_f64x8(Zmm::new(0), "A", 0x20),
+            &[0x62, 0xf1, 0xfd, 0x48, 0x10, 0x86, 0x20, 0x00, 0x00, 0x00],
+        ),
+        (
+            "vmovupd zmm load, rbp base",
+            Assembly::new().load_f64x8(Zmm::new(2), "K", 0),
+            &[0x62, 0xf1, 0xfd, 0x48, 0x10, 0x55, 0x00],
+        ),
+        (
+            "vmovupd zmm load, r12 base",
+            Assembly::new().load_f64x8(Zmm::new(2), "L", 0x40),
+            &[0x62, 0xd1, 0xfd, 0x48, 0x10, 0x54, 0x24, 0x01],
+        ),
+        (
+            "vmovupd zmm load, r13 base",
+            Assembly::new().load_f64x8(Zmm::new(2), "H", 0),
+            &[0x62, 0xd1, 0xfd, 0x48, 0x10, 0x55, 0x00],
+        ),
+        (
+            "vmovupd zmm store",
+            Assembly::new().store_f64x8("E", -0x40, Zmm::new(31)),
+            &[0x62, 0x41, 0xfd, 0x48, 0x11, 0x79, 0xff],
+        ),
+        (
+            "vmovupd zmm move",
+            Assembly::new().move_f64x8(Zmm::new(20), Zmm::new(3)),
+            &[0x62, 0xe1, 0xfd, 0x48, 0x10, 0xe3],
+        ),
+        (
+            "vmovupd xmm move",
+            Assembly::new()
+                .move_f64x2(Xmm::new(2), Xmm::new(3))
+                .move_f64x2(Xmm::new(18), Xmm::new(3)),
+            &[0xc5, 0xf9, 0x10, 0xd3, 0x62, 0xe1, 0xfd, 0x08, 0x10, 0xd3],
+        ),
+        (
+            "vmovupd ymm move",
+            Assembly::new()
+                .move_i32x8(Ymm::new(2), Ymm::new(13))
+                .move_i32x8(Ymm::new(2), Ymm::new(29)),
+            &[0xc5, 0x7d, 0x11, 0xea, 0x62, 0x91, 0xfd, 0x28, 0x10, 0xd5],
+        ),
+        (
+            "vmovsd load",
+            Assembly::new().load_f64x1(Xmm::new(2), "B", 8),
+            &[0xc5, 0xfb, 0x10, 0x52, 0x08],
+        ),
+        (
+            "vmovsd load, evex disp8*8",
+            Assembly::new().load_f64x1(Xmm::new(20), "B", 0x3f8),
+            &[0x62, 0xe1, 0xff, 0x08, 0x10, 0x62, 0x7f],
+        ),
+        (
+            "vmovsd store",
+            Assembly::new()
+                .store_f64x1("F", 0x10, Xmm::new(4))
+                .store_f64x1("F", 0x10, Xmm::new(24)),
+            &[
+                0xc4, 0xc1, 0x7b, 0x11, 0x62, 0x10, 0x62, 0x41, 0xff, 0x08, 0x11, 0x42, 0x02,
+            ],
+        ),
+        (
+            "vmovdqa load",
+            Assembly::new()
+                .load_i32x8(Ymm::new(5), "D", 0x80)
+                .load_i32x8(Ymm::new(0), "A", 0),
+            &[
+                0xc4, 0xc1, 0x7d, 0x6f, 0xa8, 0x80, 0x00, 0x00, 0x00, 0xc5, 0xfd, 0x6f, 0x06,
+            ],
+        ),
+        (
+            "vgatherdpd",
+            Assembly::new().gather_f64x8(Zmm::new(12), "C", Ymm::new(0), Mask::new(1)),
+            &[0x62, 0x72, 0xfd, 0x49, 0x92, 0x24, 0xc1],
+        ),
+        (
+            "vgatherdpd, high registers",
+            Assembly::new().gather_f64x8(Zmm::new(28), "G", Ymm::new(19), Mask::new(7)),
+            &[0x62, 0x42, 0xfd, 0x47, 0x92, 0x24, 0xdb],
+        ),
+        (
+            "prefetch",
+            Assembly::new()
+                .prefetch(PrefetchType::T0, "A", 0x1000)
+                .prefetch(PrefetchType::T1, "B", 0x40)
+                .prefetch(PrefetchType::T2, "C", 0x4000)
+                .prefetch(PrefetchType::NTA, "D", -0x40),
+            &[
+                0x0f, 0x18, 0x8e, 0x00, 0x10, 0x00, 0x00, 0x0f, 0x18, 0x52, 0x40, 0x0f, 0x18, 0x99,
+                0x00, 0x40, 0x00, 0x00, 0x41, 0x0f, 0x18, 0x40, 0xc0,
+            ],
+        ),
+        (
+            "vaddpd",
+            Assembly::new()
+                .add_f64x8(Zmm::new(1), Zmm::new(2), Zmm::new(3))
+                .add_f64x8(Zmm::new(17), Zmm::new(25), Zmm::new(31))
+                .loadadd_f64x8(Zmm::new(1), Zmm::new(2), "A", 0x40),
+            &[
+                0x62, 0xf1, 0xed, 0x48, 0x58, 0xcb, 0x62, 0x81, 0xb5, 0x40, 0x58, 0xcf, 0x62, 0xf1,
+                0xed, 0x48, 0x58, 0x4e, 0x01,
+            ],
+        ),
+        (
+            "vaddsd",
+            Assembly::new()
+                .add_f64x1(Xmm::new(3), Xmm::new(4), Xmm::new(5))
+                .add_f64x1(Xmm::new(18), Xmm::new(4), Xmm::new(5))
+                .loadadd_f64x1(Xmm::new(3), Xmm::new(3), "B", 0x10)
+                .loadadd_f64x1(Xmm::new(3), Xmm::new(19), "B", 0x10),
+            &[
+                0xc5, 0xdb, 0x58, 0xdd, 0x62, 0xe1, 0xdf, 0x08, 0x58, 0xd5, 0xc5, 0xe3, 0x58, 0x5a,
+                0x10, 0x62, 0xf1, 0xe7, 0x00, 0x58, 0x5a, 0x02,
+            ],
+        ),
+        (
+            "vmulpd / vmulsd",
+            Assembly::new()
+                .mul_f64x8(Zmm::new(1), Zmm::new(2), Zmm::new(3))
+                .mul_f64x1(Xmm::new(1), Xmm::new(2), Xmm::new(3))
+                .loadmul_f64x8(Zmm::new(4), Zmm::new(5), "D", 0x80)
+                .loadmul_f64x1(Xmm::new(4), Xmm::new(5), "D", 0x8),
+            &[
+                0x62, 0xf1, 0xed, 0x48, 0x59, 0xcb, 0xc5, 0xeb, 0x59, 0xcb, 0x62, 0xd1, 0xd5, 0x48,
+                0x59, 0x60, 0x02, 0xc4, 0xc1, 0x53, 0x59, 0x60, 0x08,
+            ],
+        ),
+        (
+            "vfmadd231pd / vfmadd231sd",
+            Assembly::new()
+                .muladd_f64x8(Zmm::new(1), Zmm::new(2), Zmm::new(3))
+                .muladd_f64x1(Xmm::new(1), Xmm::new(2), Xmm::new(3))
+                .muladd_f64x1(Xmm::new(1), Xmm::new(2), Xmm::new(30))
+                .loadmuladd_f64x8(Zmm::new(4), Zmm::new(5), "E", 0x40)
+                .loadmuladd_f64x1(Xmm::new(4), Xmm::new(5), "E", -0x8),
+            &[
+                0x62, 0xf2, 0xed, 0x48, 0xb8, 0xcb, 0xc4, 0xe2, 0xe9, 0xb9, 0xcb, 0x62, 0x92, 0xed,
+                0x08, 0xb9, 0xce, 0x62, 0xd2, 0xd5, 0x48, 0xb8, 0x61, 0x01, 0xc4, 0xc2, 0xd1, 0xb9,
+                0x61, 0xf8,
+            ],
+        ),
+        (
+            "vfnmsub231pd",
+            Assembly::new()
+                .nmulsub_f64x8(Zmm::new(6), Zmm::new(7), Zmm::new(8))
+                .loadnmulsub_f64x8(Zmm::new(22), Zmm::new(7), "F", 0x100),
+            &[
+                0x62, 0xd2, 0xc5, 0x48, 0xbe, 0xf0, 0x62, 0xc2, 0xc5, 0x48, 0xbe, 0x72, 0x04,
+            ],
+        ),
+        (
+            "vdivpd / vdivsd",
+            Assembly::new()
+                .div_f64x8(Zmm::new(1), Zmm::new(2), Zmm::new(3))
+                .div_f64x1(Xmm::new(1), Xmm::new(2), Xmm::new(3))
+                .loaddiv_f64x8(Zmm::new(9), Zmm::new(10), "G", 0)
+                .loaddiv_f64x1(Xmm::new(9), Xmm::new(10), "G", 0x18),
+            &[
+                0x62, 0xf1, 0xed, 0x48, 0x5e, 0xcb, 0xc5, 0xeb, 0x5e, 0xcb, 0x62, 0x51, 0xad, 0x48,
+                0x5e, 0x0b, 0xc4, 0x41, 0x2b, 0x5e, 0x4b, 0x18,
+            ],
+        ),
+        (
+            "mix4add",
+            Assembly::new().mix4add_f64x8(Zmm::new(1), Zmm::new(2), Zmm::new(3)),
+            &[
+                0x62, 0xf3, 0xe5, 0x48, 0x1a, 0xca, 0x00, 0x62, 0xf3, 0xe5, 0x48, 0x03, 0xda, 0x04,
+                0x62, 0xf1, 0xf5, 0x48, 0x58, 0xcb,
+            ],
+        ),
+        (
+            "mix2add",
+            Assembly::new()
+                .init_mix2mask("I", Mask::new(1), Mask::new(2))
+                .mix2add_f64x8(
+                    Zmm::new(1),
+                    Zmm::new(2),
+                    Zmm::new(3),
+                    Mask::new(1),
+                    Mask::new(2),
+                ),
+            &[
+                0xb8, 0x33, 0x00, 0x00, 0x00, 0xc5, 0xf8, 0x92, 0xc8, 0xc5, 0xf8, 0x44, 0xd1, 0x62,
+                0xf1, 0xfd, 0x48, 0x10, 0xcb, 0x62, 0xf3, 0xfd, 0x49, 0x01, 0xda, 0x4e, 0x62, 0xf3,
+                0xfd, 0x4a, 0x01, 0xd1, 0x4e, 0x62, 0xf1, 0xed, 0x48, 0x58, 0xcb,
+            ],
+        ),
+        (
+            "mix1add",
+            Assembly::new().mix1add_f64x8(Zmm::new(1), Zmm::new(2), Zmm::new(3)),
+            &[
+                0x62, 0xf1, 0xed, 0x48, 0xc6, 0xcb, 0xaa, 0x62, 0xf1, 0xed, 0x48, 0xc6, 0xd3, 0x55,
+                0x62, 0xf1, 0xf5, 0x48, 0x58, 0xca,
+            ],
+        ),
+        (
+            "fold",
+            Assembly::new()
+                .fold4add_f64x8(Ymm::new(4), Zmm::new(5))
+                .fold2add_f64x4(Xmm::new(6), Ymm::new(4))
+                .fold1add_f64x2(Xmm::new(7), Xmm::new(6))
+                .fold4add_f64x8(Ymm::new(20), Zmm::new(21)),
+            &[
+                0x62, 0xf3, 0xfd, 0x48, 0x1b, 0xec, 0x01, 0xc5, 0xdd, 0x58, 0xe5, 0xc4, 0xe3, 0x7d,
+                0x19, 0xe6, 0x01, 0xc5, 0xc9, 0x58, 0xf4, 0xc5, 0xc9, 0x7c, 0xfe, 0x62, 0xa3, 0xfd,
+                0x48, 0x1b, 0xec, 0x01, 0x62, 0xa1, 0xdd, 0x20, 0x58, 0xe5,
+            ],
+        ),
+        (
+            "kxnorw",
+            Assembly::new().mask_on(Mask::new(1)).mask_on(Mask::new(4)),
+            &[0xc5, 0xfc, 0x46, 0xc8, 0xc5, 0xfc, 0x46, 0xe0],
+        ),
+        (
+            "kmovw from r8d",
+            Assembly::new().init_mix2mask("J", Mask::new(3), Mask::new(4)),
+            &[
+                0xbb, 0x33, 0x00, 0x00, 0x00, 0xc5, 0xf8, 0x92, 0xdb, 0xc5, 0xf8, 0x44, 0xe3,
+            ],
+        ),
+        (
+            "add / sub",
+            Assembly::new()
+                .add_immediate("N", 1)
+                .add_immediate("A", 0x180)
+                .add_immediate("I", 0x180)
+                .add_immediate("D", -0x40)
+                .sub_immediate("N", 1)
+                .sub_immediate("D", 0x200)
+                .sub_immediate("I", 0x200),
+            &[
+                0x83, 0xc7, 0x01, 0x48, 0x81, 0xc6, 0x80, 0x01, 0x00, 0x00, 0x05, 0x80, 0x01, 0x00,
+                0x00, 0x49, 0x83, 0xc0, 0xc0, 0x83, 0xef, 0x01, 0x49, 0x81, 0xe8, 0x00, 0x02, 0x00,
+                0x00, 0x2d, 0x00, 0x02, 0x00, 0x00,
+            ],
+        ),
+        (
+            "mov immediate",
+            Assembly::new()
+                .set_immediate("I", 8)
+                .set_immediate("N", -1)
+                .set_immediate("J", 0x100),
+            &[
+                0xb8, 0x08, 0x00, 0x00, 0x00, 0xbf, 0xff, 0xff, 0xff, 0xff, 0xbb, 0x00, 0x01, 0x00,
+                0x00,
+            ],
+        ),
+        (
+            "sar",
+            Assembly::new().shift_right("N", 1).shift_right("J", 3),
+            &[0xd1, 0xff, 0xc1, 0xfb, 0x03],
+        ),
+        (
+            "mov",
+            Assembly::new().move_reg("F", "A").move_reg("J", "N"),
+            &[0x49, 0x89, 0xf2, 0x89, 0xfb],
+        ),
+        ("nop", Assembly::new().nop(2), &[0x90, 0x90]),
+        (
+            "short loop",
+            Assembly::new()
+                .label("loop")
+                .sub_immediate("N", 1)
+                .jump_nz("loop"),
+            &[0x83, 0xef, 0x01, 0x75, 0xfb],
+        ),
+        (
+            "near loop",
+            long_loop,
+            &[
+                0x62, 0xf1, 0xfd, 0x48, 0x10, 0x06, 0x62, 0xf1, 0xfd, 0x48, 0x10, 0x4e, 0x01, 0x62,
+                0xf1, 0xfd, 0x48, 0x10, 0x56, 0x02, 0x62, 0xf1, 0xfd, 0x48, 0x10, 0x5e, 0x03, 0x62,
+                0xf1, 0xfd, 0x48, 0x10, 0x66, 0x04, 0x62, 0xf1, 0xfd, 0x48, 0x10, 0x6e, 0x05, 0x62,
+                0xf1, 0xfd, 0x48, 0x10, 0x76, 0x06, 0x62, 0xf1, 0xfd, 0x48, 0x10, 0x7e, 0x07, 0x62,
+                0x71, 0xfd, 0x48, 0x10, 0x46, 0x08, 0x62, 0x71, 0xfd, 0x48, 0x10, 0x4e, 0x09, 0x62,
+                0x71, 0xfd, 0x48, 0x10, 0x56, 0x0a, 0x62, 0x71, 0xfd, 0x48, 0x10, 0x5e, 0x0b, 0x62,
+                0x71, 0xfd, 0x48, 0x10, 0x66, 0x0c, 0x62, 0x71, 0xfd, 0x48, 0x10, 0x6e, 0x0d, 0x62,
+                0x71, 0xfd, 0x48, 0x10, 0x76, 0x0e, 0x62, 0x71, 0xfd, 0x48, 0x10, 0x7e, 0x0f, 0x83,
+                0xef, 0x01, 0x75, 0x8c,
+            ],
+        ),
+    ]
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[test]
+fn golden_encodings() {
+    let mut failures = Vec::new();
+
+    for (name, asm, expected) in cases() {
+        let code = function().assemble(&asm).unwrap();
+        assert!(code.starts_with(PROLOGUE) && code.ends_with(EPILOGUE));
+
+        let body = &code[PROLOGUE.len()..code.len() - EPILOGUE.len()];
+        if body != expected {
+            failures.push(format!(
+                "{}\n    expected {}\n    actual   {}",
+                name,
+                hex(expected),
+                hex(body)
+            ));
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn stack_arguments() {
+    // P, M and Q are passed on the stack, M2 and A2 are copies
+    let function = Function::new("f")
+        .int("N")
+        .pointer("A")
+        .pointer("B")
+        .pointer("C")
+        .pointer("D")
+        .pointer("E")
+        .pointer("P")
+        .int("M")
+        .pointer("Q")
+        .local_copy("M2", "M")
+        .local_copy("A2", "A")
+        .local_int("I");
+    let asm = Assembly::new()
+        .add_immediate("P", 0x40)
+        .sub_immediate("M", 1)
+        .add_immediate("Q", 8)
+        .move_reg("A2", "Q")
+        .add_immediate("M2", 1);
+
+    #[rustfmt::skip]
+    let expected: &[u8] = &[
+        0x53, 0x55, 0x41, 0x54,                     // push rbx; push rbp; push r12
+        0x48, 0x8b, 0x44, 0x24, 0x20,               // mov rax, [rsp + 0x20]
+        0x44, 0x8b, 0x54, 0x24, 0x28,               // mov r10d, [rsp + 0x28]
+        0x4c, 0x8b, 0x5c, 0x24, 0x30,               // mov r11, [rsp + 0x30]
+        0x44, 0x89, 0xd3,                           // mov ebx, r10d
+        0x48, 0x89, 0xf5,                           // mov rbp, rsi
+        0x48, 0x83, 0xc0, 0x40,                     // add rax, 0x40
+        0x41, 0x83, 0xea, 0x01,                     // sub r10d, 0x1
+        0x49, 0x83, 0xc3, 0x08,                     // add r11, 0x8
+        0x4c, 0x89, 0xdd,                           // mov rbp, r11
+        0x83, 0xc3, 0x01,                           // add ebx, 0x1
+        0xc5, 0xf8, 0x77, 0x31, 0xc0,               // vzeroupper; xor eax, eax
+        0x41, 0x5c, 0x5d, 0x5b, 0xc3,               // pop r12; pop rbp; pop rbx; ret
+    ];
+    assert_eq!(hex(&function.assemble(&asm).unwrap()), hex(expected));
+}
+
+#[test]
+fn declared_operands() {
+    // the operands of `stack_arguments`, declared by the kernel instead
+    let function = Function::new("f");
+    let asm = Assembly::new()
+        .argument_int("N")
+        .argument_pointer("A")
+        .argument_pointer("B")
+        .argument_pointer("C")
+        .argument_pointer("D")
+        .argument_pointer("E")
+        .argument_pointer("P")
+        .argument_int("M")
+        .argument_pointer("Q")
+        .local_copy("M2", "M")
+        .local_copy("A2", "A")
+        .local_int("I");
+    let body = Assembly::new()
+        .add_immediate("P", 0x40)
+        .sub_immediate("M", 1)
+        .add_immediate("Q", 8)
+        .move_reg("A2", "Q")
+        .add_immediate("M2", 1);
+    let declared = function.assemble(&asm.append(body)).unwrap();
+
+    let function = Function::new("f")
+        .int("N")
+        .pointer("A")
+        .pointer("B")
+        .pointer("C")
+        .pointer("D")
+        .pointer("E")
+        .pointer("P")
+        .int("M")
+        .pointer("Q")
+        .local_copy("M2", "M")
+        .local_copy("A2", "A")
+        .local_int("I");
+    let body = Assembly::new()
+        .add_immediate("P", 0x40)
+        .sub_immediate("M", 1)
+        .add_immediate("Q", 8)
+        .move_reg("A2", "Q")
+        .add_immediate("M2", 1);
+    assert_eq!(hex(&declared), hex(&function.assemble(&body).unwrap()));
+
+    // a copy of an operand nobody declares
+    let asm = Assembly::new().local_copy("A2", "A").add_immediate("A2", 1);
+    let res = Function::new("f").assemble(&asm);
+    assert!(matches!(res, Err(GasError::UnboundOperand(ref name)) if name == "A"));
+}
+
+#[test]
+fn no_encoding() {
+    // vhaddpd and vextractf128 have no EVEX form, movl only takes 32-bit registers
+    let asms = [
+        Assembly::new().fold1add_f64x2(Xmm::new(20), Xmm::new(21)),
+        Assembly::new().fold2add_f64x4(Xmm::new(16), Ymm::new(17)),
+        Assembly::new().set_immediate("A", 1),
+    ];
+    for asm in asms {
+        let res = function().assemble(&asm);
+        assert!(matches!(res, Err(GasError::NoEncoding(_))));
+    }
+
+    let res = function().assemble(&Assembly::new().jump_nz("nowhere"));
+    assert!(matches!(res, Err(GasError::UndefinedLabel(ref label)) if &**label == "nowhere"));
+}
+
+#[test]
+fn shared_registers() {
+    // M and O take r14 and r15, Z is left without a register of its own
+    let function = function().local_int("M").local_int("O").local_int("Z");
+
+    // I is dead once Z is set, so Z can take eax
+    let asm = Assembly::new()
+        .set_immediate("I", 1)
+        .sub_immediate("I", 1)
+        .set_immediate("Z", 2)
+        .sub_immediate("Z", 1);
+    let text = function.print(&asm).unwrap();
+    assert!(text.contains("movl $0x2, %eax"), "{}", text);
+
+    // every register is live while Z is
+    let names = [
+        "N", "A", "B", "C", "D", "E", "I", "F", "G", "J", "K", "L", "H", "M", "O",
+    ];
+    let mut asm = Assembly::new();
+    for name in ["I", "J", "M", "O", "Z"] {
+        asm = asm.set_immediate(name, 1);
+    }
+    for name in names.iter().chain(["Z"].iter()) {
+        asm = asm.add_immediate(name, 1);
+    }
+    let res = function.print(&asm);
+    assert!(matches!(res, Err(GasError::TooManyOperands)));
+}
+
+#[test]
+fn elf_object() {
+    let asm = Assembly::new()
+        .label("loop")
+        .load_f64x8(Zmm::new(1), "A", 0x40)
+        .add_immediate("N", 1)
+        .jump_nz("loop");
+    let text = function().assemble(&asm).unwrap();
+    let elf = function().object(&asm).unwrap();
+
+    let u16_at = |pos: usize| u16::from_le_bytes(elf[pos..pos + 2].try_into().unwrap());
+    let u32_at = |pos: usize| u32::from_le_bytes(elf[pos..pos + 4].try_into().unwrap());
+    let u64_at = |pos: usize| u64::from_le_bytes(elf[pos..pos + 8].try_into().unwrap()) as usize;
+
+    // ELF64, little endian, relocatable, x86-64
+    assert_eq!(&elf[..6], &[0x7f, b'E', b'L', b'F', 2, 1]);
+    assert_eq!(u16_at(16), 1);
+    assert_eq!(u16_at(18), 0x3e);
+
+    let shoff = u64_at(40);
+    let shnum = u16_at(60) as usize;
+    let section = |i: usize| shoff + i * 64;
+    let shstrtab = u64_at(section(u16_at(62) as usize) + 24);
+    let name = |offset: usize| {
+        let end = elf[offset..].iter().position(|&b| b == 0).unwrap();
+        std::str::from_utf8(&elf[offset..offset + end]).unwrap()
+    };
+    let find = |wanted: &str| {
+        (0..shnum)
+            .map(section)
+            .find(|&sh| name(shstrtab + u32_at(sh) as usize) == wanted)
+            .unwrap()
+    };
+
+    let text_sh = find(".text");
+    let (offset, size) = (u64_at(text_sh + 24), u64_at(text_sh + 32));
+    assert_eq!(&elf[offset..offset + size], &text[..]);
+
+    // the function is a global FUNC symbol covering the whole .text
+    let symtab_sh = find(".symtab");
+    let strtab = u64_at(section(u32_at(symtab_sh + 40) as usize) + 24);
+    let (symtab, symtab_size) = (u64_at(symtab_sh + 24), u64_at(symtab_sh + 32));
+    let sym = (symtab..symtab + symtab_size)
+        .step_by(24)
+        .find(|&sym| name(strtab + u32_at(sym) as usize) == "f")
+        .unwrap();
+    assert_eq!(elf[sym + 4], 0x12);
+    assert_eq!(u64_at(sym + 8), 0);
+    assert_eq!(u64_at(sym + 16), text.len());
+
+    // labels are resolved in place and leave no symbols behind: only the null
+    // symbol, the .text section and the function
+    assert_eq!(symtab_size, 3 * 24);
+}
@@ -0,0 +1,257 @@
+// Failures of `Generate::generate` come back as errors naming where they
+// happened.
+
+extern crate core;
+
+use core::*;
+
+// which mistake the rulebook makes
+#[derive(Clone, Copy, PartialEq)]
+enum Mistake {
+    None,
+    LeaveState,
+    DuplicateState,
+    KeepRegister,
+    BadCondition,
+    SkipLoad,
+    DropRegister,
+    RepeatName,
+}
+
+struct Generator {
+    mistake: Mistake,
+}
+
+enum StateType {
+    Starting,
+    Storing,
+    Stored,
+}
+
+const RULEBOOK: &'static [Rule<Generator>] = &[
+    Rule {
+        name: "Starting",
+        condition: Condition::Single {
+            id: StateType::Starting as u32,
+        },
+        callback: |config: &Generator, rp: &mut RegisterPool, _states: &Vec<State>| {
+            let reg = rp.get()?;
+            let asm = match config.mistake {
+                Mistake::SkipLoad => Assembly::new(),
+                _ => Assembly::new().load_f64x8(reg, "A", 0),
+            };
+            let mut states = Vec::from([State {
+                id: StateType::Storing as u32,
+                idx: 0,
+                reg: match config.mistake {
+                    Mistake::DropRegister => None,
+                    _ => Some(reg),
+                },
+            }]);
+            match config.mistake {
+                Mistake::LeaveState => states.push(State {
+                    id: StateType::Stored as u32,
+                    idx: 3,
+                    reg: None,
+                }),
+                Mistake::DuplicateState => states.push(State {
+                    id: StateType::Storing as u32,
+                    idx: 0,
+                    reg: None,
+                }),
+                _ => {}
+            }
+
+            Ok((asm, states))
+        },
+    },
+    Rule {
+        name: "Storing",
+        condition: Condition::Single {
+            id: StateType::Storing as u32,
+        },
+        callback: |config: &Generator, rp: &mut RegisterPool, states: &Vec<State>| {
+            let reg = states[0].register()?;
+            if config.mistake != Mistake::KeepRegister {
+                rp.free(reg);
+            }
+
+            let asm = Assembly::new().store_f64x8("Y", 0, reg);
+            let states = Vec::new();
+
+            Ok((asm, states))
+        },
+    },
+];
+
+const BAD_RULEBOOK: &'static [Rule<Generator>] = &[Rule {
+    name: "Starting + Starting at +0",
+    condition: Condition::SameId {
+        id: StateType::Starting as u32,
+        n_states: 2,
+        idx_dist: 0,
+    },
+    callback: |_config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
+        Ok((Assembly::new(), Vec::new()))
+    },
+}];
+
+const REPEATING_RULEBOOK: &'static [Rule<Generator>] = &[
+    Rule {
+        name: "Starting",
+        condition: Condition::Single {
+            id: StateType::Starting as u32,
+        },
+        callback: |_config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
+            Ok((Assembly::new(), Vec::new()))
+        },
+    },
+    Rule {
+        name: "Starting",
+        condition: Condition::Single {
+            id: StateType::Storing as u32,
+        },
+        callback: |_config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
+            Ok((Assembly::new(), Vec::new()))
+        },
+    },
+];
+
+impl Generate for Generator {
+    fn rulebook(&self) -> &'static [Rule<Self>] {
+        match self.mistake {
+            Mistake::BadCondition => BAD_RULEBOOK,
+            Mistake::RepeatName => REPEATING_RULEBOOK,
+            _ => RULEBOOK,
+        }
+    }
+
+    fn avail_registers(&self) -> [bool; N_REGISTERS] {
+        [true; N_REGISTERS]
+    }
+
+    fn initial_states(&self) -> Vec<State> {
+        Vec::from([State {
+            id: StateType::Starting as u32,
+            idx: 0,
+            reg: None,
+        }])
+    }
+}
+
+fn generate(mistake: Mistake) -> Result<Assembly, GenerateError> {
+    Generator { mistake }.generate()
+}
+
+fn fail(mistake: Mistake) -> GenerateError {
+    match generate(mistake) {
+        Ok(_) => panic!("the rulebook is expected to fail"),
+        Err(err) => err,
+    }
+}
+
+#[test]
+fn correct_rulebook() {
+    assert!(generate(Mistake::None).is_ok());
+}
+
+#[test]
+fn unconsumed_states() {
+    let err = fail(Mistake::LeaveState);
+    match err.root() {
+        GenerateError::UnconsumedStates(states) => {
+            assert_eq!(states.len(), 1);
+            assert_eq!((states[0].id, states[0].idx), (2, 3));
+        }
+        _ => panic!("{}", err),
+    }
+    assert!(err
+        .to_string()
+        .starts_with("generate::Generator: states [2:3]"));
+}
+
+#[test]
+fn duplicate_state_names_the_rule() {
+    let err = fail(Mistake::DuplicateState);
+    match &err {
+        GenerateError::Context {
+            rule: Some("Starting"),
+            states,
+            source,
+            ..
+        } => {
+            assert_eq!(states.len(), 1);
+            assert!(matches!(
+                **source,
+                GenerateError::DuplicateState { id: 1, idx: 0 }
+            ));
+        }
+        _ => panic!("{}", err),
+    }
+    assert!(err
+        .to_string()
+        .starts_with("generate::Generator, rule `Starting` on states [0:0]"));
+}
+
+#[test]
+fn state_without_its_register() {
+    let err = fail(Mistake::DropRegister);
+    assert!(matches!(
+        err.root(),
+        GenerateError::MissingRegister(State { id: 1, idx: 0, .. })
+    ));
+    assert!(err.to_string().ends_with("state 1:0 carries no register"));
+}
+
+#[test]
+fn kept_register() {
+    let err = fail(Mistake::KeepRegister);
+    match err.root() {
+        GenerateError::PoolChanged { registers, masks } => {
+            assert_eq!(registers, &[Zmm::new(0)]);
+            assert!(masks.is_empty());
+        }
+        _ => panic!("{}", err),
+    }
+}
+
+#[test]
+fn bad_condition() {
+    let err = fail(Mistake::BadCondition);
+    assert!(matches!(err.root(), GenerateError::InvalidCondition(_)));
+}
+
+#[test]
+fn repeated_rule_name() {
+    let err = fail(Mistake::RepeatName);
+    assert!(matches!(
+        err.root(),
+        GenerateError::DuplicateRule("Starting")
+    ));
+}
+
+#[test]
+fn exhausted_register_pool() {
+    let mut rp = RegisterPool::new([false; N_REGISTERS], [true; N_MASKS]);
+    assert!(matches!(rp.get(), Err(GenerateError::NoRegister)));
+
+    rp.free(Zmm::new(5));
+    assert!(rp.alloc(Zmm::new(5)).is_ok());
+    assert!(matches!(
+        rp.alloc(Zmm::new(5)),
+        Err(GenerateError::RegisterTaken(_))
+    ));
+}
+
+// a kernel storing a register it never loaded, in release builds as well
+#[test]
+fn unwritten_register_fails_verification() {
+    let generator = Generator {
+        mistake: Mistake::SkipLoad,
+    };
+    assert!(generator.generate().is_ok());
+    match generator.generate_kernel() {
+        Err(err) => assert!(matches!(err.root(), GenerateError::Verify(_)), "{}", err),
+        Ok(_) => panic!("the kernel is expected to fail verification"),
+    }
+}
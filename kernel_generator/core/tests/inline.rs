@@ -0,0 +1,50 @@
+// `gas::Function::print_inline`, the inline-asm block that binds only the
+// arguments to C variables.
+
+extern crate core;
+
+use core::gas::Function;
+use core::Assembly;
+
+fn function() -> Function {
+    Function::new("f")
+        .int("NROW")
+        .pointer("X")
+        .local_int("I")
+        .local_copy("IMM_X", "X")
+        .local_int("J")
+}
+
+#[test]
+fn locals_take_clobbered_registers() {
+    // IMM_X is needed at entry, I and J are set before they are read
+    let asm = Assembly::new()
+        .set_immediate("I", 4)
+        .sub_immediate("I", 1)
+        .add_immediate("IMM_X", 0x40)
+        .sub_immediate("NROW", 1)
+        .set_immediate("J", 2)
+        .sub_immediate("J", 1)
+        .move_reg("X", "IMM_X");
+    let text = function().print_inline(&asm, 0).unwrap();
+    let lines: Vec<&str> = text.lines().map(|line| line.trim()).collect();
+
+    assert!(lines[1].starts_with("\" mov %[X], %%r10 "), "{}", text);
+    // I and J are never live together, so J takes eax as well
+    assert!(text.contains("\" movl $0x4, %%eax "), "{}", text);
+    assert!(text.contains("\" movl $0x2, %%eax "), "{}", text);
+    assert!(text.contains("\" add $0x40, %%r10 "), "{}", text);
+    assert!(text.contains("\" mov %%r10, %[X] "), "{}", text);
+    assert_eq!(lines[lines.len() - 4], ": [NROW]\"+r\"(nrow), [X]\"+r\"(x)");
+    assert_eq!(lines[lines.len() - 2], ": \"rax\", \"r10\"");
+}
+
+#[test]
+fn copies_not_needed_at_entry_are_left_out() {
+    let asm = Assembly::new()
+        .move_reg("IMM_X", "X")
+        .add_immediate("IMM_X", 0x40)
+        .move_reg("X", "IMM_X");
+    let text = function().print_inline(&asm, 0).unwrap();
+    assert_eq!(text.matches("mov %[X], %%").count(), 1, "{}", text);
+}
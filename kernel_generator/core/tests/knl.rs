@@ -0,0 +1,75 @@
+// Expected bounds for `knl::estimate` on small hand-written loops.
+
+extern crate core;
+
+use core::knl::{self, Bottleneck, ModelError};
+use core::{Assembly, Mask, Ymm, Zmm};
+
+#[test]
+fn fma_chain_is_latency_bound() {
+    let asm = Assembly::new()
+        .set_immediate("I", 0x10)
+        .label("loop")
+        .loadmuladd_f64x8(Zmm::new(0), Zmm::new(1), "A", 0)
+        .add_immediate("A", 0x40)
+        .sub_immediate("I", 1)
+        .jump_nz("loop");
+
+    let estimates = knl::estimate(&asm).unwrap();
+    assert_eq!(estimates.len(), 1);
+    assert_eq!(estimates[0].bottleneck, Bottleneck::Latency);
+    assert_eq!(estimates[0].cycles, 6.0);
+    assert_eq!(estimates[0].cycles_per_nonzero(), None);
+}
+
+#[test]
+fn independent_fmas_are_decode_bound() {
+    let mut asm = Assembly::new().set_immediate("I", 0x10).label("loop");
+    for i in 0..16 {
+        asm = asm.loadmuladd_f64x8(Zmm::new(i), Zmm::new(16 + i), "A", 0);
+    }
+    let asm = asm.sub_immediate("I", 1).jump_nz("loop");
+
+    let estimates = knl::estimate(&asm).unwrap();
+    assert_eq!(estimates[0].bottleneck, Bottleneck::Frontend);
+    assert_eq!(estimates[0].cycles, 9.0);
+}
+
+#[test]
+fn inner_loop_runs_trip_count_times_per_rowblock() {
+    let mut asm = Assembly::new().set_immediate("I", 2).label("loop");
+    for i in 0..4 {
+        asm = asm
+            .mask_on(Mask::new(i + 1))
+            .load_i32x8(Ymm::new(i), "COL", i as i16 * 0x20)
+            .gather_f64x8(Zmm::new(16 + i), "X", Ymm::new(i), Mask::new(i + 1))
+            .loadmuladd_f64x8(Zmm::new(8), Zmm::new(16 + i), "VAL", i as i16 * 0x40);
+    }
+    let asm = asm
+        .add_immediate("COL", 0x80)
+        .add_immediate("VAL", 0x100)
+        .sub_immediate("I", 1)
+        .jump_nz("loop")
+        .store_f64x8("Y", 0, Zmm::new(8))
+        .add_immediate("Y", 0x40)
+        .set_immediate("I", 2)
+        .sub_immediate("N", 1)
+        .jump_nz("loop");
+
+    let estimates = knl::estimate(&asm).unwrap();
+    assert_eq!(estimates.len(), 1);
+    assert_eq!(estimates[0].trip_count, 2);
+    assert_eq!(estimates[0].n_nonzeros, 64);
+    assert_eq!(estimates[0].bottleneck, Bottleneck::Gather);
+    assert_eq!(estimates[0].cycles_per_nonzero(), Some(1.0));
+}
+
+#[test]
+fn undefined_label_is_rejected() {
+    let asm = Assembly::new().sub_immediate("I", 1).jump_nz("loop");
+
+    match knl::estimate(&asm) {
+        Err(ModelError::UndefinedLabel(ref label)) if &**label == "loop" => {}
+        _ => panic!("expected an undefined label"),
+    }
+}
@@ -0,0 +1,37 @@
+// `MaskPool` hands out k1-k7 and takes them back.
+
+extern crate core;
+
+use core::{GenerateError, Mask, MaskPool, N_MASKS};
+
+#[test]
+fn masks_are_handed_out_lowest_first() {
+    let mut pool = MaskPool::new([true; N_MASKS]);
+    let masks: Vec<Mask> = (0..N_MASKS).map(|_| pool.get().unwrap()).collect();
+
+    assert_eq!(masks, (1..8).map(Mask::new).collect::<Vec<Mask>>());
+    assert_eq!(pool.avail_list(), &[false; N_MASKS]);
+}
+
+#[test]
+fn reserved_masks_are_skipped() {
+    let mut avail = [true; N_MASKS];
+    avail[0] = false;
+    let mut pool = MaskPool::new(avail);
+
+    pool.alloc(Mask::new(2)).unwrap();
+    assert_eq!(pool.get().unwrap(), Mask::new(3));
+    assert!(matches!(
+        pool.alloc(Mask::new(3)),
+        Err(GenerateError::MaskTaken(_))
+    ));
+
+    pool.free(Mask::new(2));
+    assert_eq!(pool.get().unwrap(), Mask::new(2));
+}
+
+#[test]
+fn exhausted_pool_fails() {
+    let mut pool = MaskPool::new([false; N_MASKS]);
+    assert!(matches!(pool.get(), Err(GenerateError::NoMask)));
+}
@@ -0,0 +1,72 @@
+// Names made at runtime, and kernels with prefixed labels run one after the
+// other in the emulator.
+
+extern crate core;
+
+use core::name;
+use core::*;
+
+// y += n * a, in a loop
+fn kernel(n: i16) -> Assembly {
+    Assembly::new()
+        .set_immediate("I", n)
+        .load_f64x8(Zmm::new(0), "Y", 0)
+        .label("loop")
+        .loadadd_f64x8(Zmm::new(0), Zmm::new(0), "A", 0)
+        .sub_immediate("I", 1)
+        .jump_nz("loop")
+        .store_f64x8("Y", 0, Zmm::new(0))
+}
+
+fn run(asm: &Assembly) -> Result<Vec<f64>, EmulateError> {
+    let mut memory = Memory::new();
+    let a = memory.alloc_f64(&[1.0; 8]);
+    let y = memory.alloc_f64(&[0.0; 8]);
+
+    let mut emulator = Emulator::new(memory)
+        .pointer("A", a)
+        .pointer("Y", y)
+        .int("I", 0);
+    emulator.run(asm)?;
+    emulator.memory().read_f64s(y, 8)
+}
+
+// the same kernel, with its loop label named at runtime
+fn named_kernel(level: usize) -> Assembly {
+    let label = format!("loop_spmv_{}", level);
+    Assembly::new()
+        .set_immediate("I", 2)
+        .load_f64x8(Zmm::new(0), "Y", 0)
+        .label(&label)
+        .loadadd_f64x8(Zmm::new(0), Zmm::new(0), "A", 0)
+        .sub_immediate("I", 1)
+        .jump_nz(&label)
+        .store_f64x8("Y", 0, Zmm::new(0))
+}
+
+#[test]
+fn names_made_at_runtime_outlive_their_strings() {
+    assert_eq!(&*name::prefixed("loop_spmv_", "3"), "loop_spmv_3");
+
+    let asm = named_kernel(2).append(named_kernel(3));
+    assert_eq!(run(&asm).unwrap(), [4.0; 8]);
+    assert!(asm.print(0, &[], &[]).contains("// loop_spmv_3"));
+}
+
+#[test]
+fn prefixed_labels_do_not_collide() {
+    let asm = kernel(2).append(kernel(3));
+    assert!(matches!(
+        run(&asm),
+        Err(EmulateError::DuplicateLabel(ref label)) if &**label == "loop"
+    ));
+
+    let asm = kernel(2)
+        .prefix_labels("first_")
+        .append(kernel(3).prefix_labels("second_"));
+    assert_eq!(run(&asm).unwrap(), [5.0; 8]);
+
+    let text = asm.print(0, &[], &[]);
+    assert!(text.contains("// first_loop"));
+    assert!(text.contains("// second_loop"));
+}
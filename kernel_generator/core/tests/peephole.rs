@@ -0,0 +1,99 @@
+// `Assembly::peephole` on small hand-written kernels.
+
+extern crate core;
+
+use core::{Assembly, Zmm};
+
+fn print(asm: &Assembly) -> String {
+    asm.print(0, &[], &[])
+}
+
+#[test]
+fn dead_load_register_is_folded() {
+    let asm = Assembly::new()
+        .load_f64x8(Zmm::new(1), "A", 0x40)
+        .mul_f64x8(Zmm::new(0), Zmm::new(2), Zmm::new(1))
+        .store_f64x8("Y", 0, Zmm::new(0));
+
+    let (asm, report) = asm.peephole();
+    assert_eq!(report.folded_loads, 1);
+    assert_eq!(report.saved(), 1);
+    assert!(print(&asm).contains("vmulpd 0x40(%[A]), %%zmm2, %%zmm0"));
+}
+
+#[test]
+fn live_or_clobbered_load_is_kept() {
+    // zmm1 is read again by the store
+    let live = Assembly::new()
+        .load_f64x8(Zmm::new(1), "A", 0)
+        .add_f64x8(Zmm::new(0), Zmm::new(0), Zmm::new(1))
+        .store_f64x8("Y", 0, Zmm::new(1));
+    // the base moves before the use
+    let moved = Assembly::new()
+        .load_f64x8(Zmm::new(1), "A", 0)
+        .add_immediate("A", 0x40)
+        .add_f64x8(Zmm::new(0), Zmm::new(0), Zmm::new(1))
+        .store_f64x8("Y", 0, Zmm::new(0));
+    // memory may change before the use
+    let stored = Assembly::new()
+        .load_f64x8(Zmm::new(1), "A", 0)
+        .store_f64x8("Y", 0, Zmm::new(2))
+        .add_f64x8(Zmm::new(0), Zmm::new(0), Zmm::new(1))
+        .store_f64x8("Y", 0, Zmm::new(0));
+
+    for asm in [live, moved, stored] {
+        assert_eq!(asm.peephole().1.folded_loads, 0);
+    }
+}
+
+#[test]
+fn register_read_in_next_iteration_is_live() {
+    let asm = Assembly::new()
+        .set_immediate("I", 4)
+        .label("loop")
+        .add_f64x8(Zmm::new(0), Zmm::new(0), Zmm::new(1))
+        .load_f64x8(Zmm::new(1), "A", 0)
+        .mul_f64x8(Zmm::new(2), Zmm::new(2), Zmm::new(1))
+        .move_f64x8(Zmm::new(3), Zmm::new(2))
+        .add_immediate("A", 0x40)
+        .sub_immediate("I", 1)
+        .jump_nz("loop")
+        .store_f64x8("Y", 0, Zmm::new(0))
+        .store_f64x8("Y", 0x40, Zmm::new(2));
+
+    let (_, report) = asm.peephole();
+    assert_eq!(report.folded_loads, 0);
+    // zmm3 is never read
+    assert_eq!(report.dead_moves, 1);
+}
+
+#[test]
+fn chained_dead_moves_are_removed() {
+    let asm = Assembly::new()
+        .move_f64x8(Zmm::new(1), Zmm::new(0))
+        .move_f64x8(Zmm::new(2), Zmm::new(1))
+        .move_f64x8(Zmm::new(3), Zmm::new(3))
+        .store_f64x8("Y", 0, Zmm::new(0));
+
+    let (_, report) = asm.peephole();
+    assert_eq!(report.dead_moves, 3);
+}
+
+#[test]
+fn adjacent_adds_are_merged() {
+    let asm = Assembly::new()
+        .add_immediate("A", 0x40)
+        .add_immediate("A", 0x40)
+        .add_immediate("B", 0x40)
+        .add_immediate("B", -0x40)
+        .add_immediate("C", 0x40)
+        .add_immediate("D", 0x40)
+        .add_immediate("C", 0x40);
+
+    let (asm, report) = asm.peephole();
+    assert_eq!(report.merged_adds, 3);
+    let code = print(&asm);
+    assert!(code.contains("add $0x80, %[A]"));
+    assert!(!code.contains("%[B]"));
+    assert_eq!(code.matches("%[C]").count(), 2);
+}
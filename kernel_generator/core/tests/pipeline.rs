@@ -0,0 +1,224 @@
+// `Pipeline` stages, run in the emulator: copies of two blocks each, one on
+// its own and two fused on a pool of just enough registers for both.
+
+extern crate core;
+
+use core::*;
+
+const N_BLOCKS: u32 = 2;
+
+struct Copy {
+    src: &'static str,
+    dst: &'static str,
+    avail_registers: [bool; N_REGISTERS],
+}
+
+impl Copy {
+    fn new(src: &'static str, dst: &'static str) -> Self {
+        let mut avail_registers = [false; N_REGISTERS];
+        for avail in avail_registers.iter_mut().take(2 * N_BLOCKS as usize) {
+            *avail = true;
+        }
+
+        Copy {
+            src,
+            dst,
+            avail_registers,
+        }
+    }
+}
+
+enum StateType {
+    Loading,
+    Storing,
+}
+
+const RULEBOOK: &'static [Rule<Copy>] = rulebook! {
+    StateType;
+
+    Loading[i] => [Storing[i](new reg)] |config, _rp| {
+        Assembly::new().load_f64x8(reg, config.src, i as i16 * 0x40)
+    }
+
+    Storing[i](reg) => [] |config, rp| {
+        rp.free(reg);
+
+        Assembly::new().store_f64x8(config.dst, i as i16 * 0x40, reg)
+    }
+};
+
+impl Generate for Copy {
+    fn rulebook(&self) -> &'static [Rule<Self>] {
+        RULEBOOK
+    }
+
+    fn avail_registers(&self) -> [bool; N_REGISTERS] {
+        self.avail_registers
+    }
+
+    fn initial_states(&self) -> Vec<State> {
+        (0..N_BLOCKS)
+            .map(|idx| State {
+                id: StateType::Loading as u32,
+                idx,
+                reg: None,
+            })
+            .collect()
+    }
+}
+
+// a copy of one block through a register it picks itself, in a loop if
+// `looping`
+struct Block {
+    reg: u8,
+    looping: bool,
+}
+
+enum BlockStateType {
+    Copying,
+}
+
+impl From<BlockStateType> for u32 {
+    fn from(id: BlockStateType) -> u32 {
+        id as u32
+    }
+}
+
+const BLOCK_RULEBOOK: &'static [Rule<Block>] = rulebook! {
+    BlockStateType;
+
+    Copying[_] => [] |config, _rp| {
+        let reg = Zmm::new(config.reg);
+        let asm = Assembly::new().load_f64x8(reg, "A", 0).store_f64x8("X", 0, reg);
+        match config.looping {
+            true => Assembly::new().label("loop").append(asm).jump_nz("loop"),
+            false => asm,
+        }
+    }
+};
+
+impl Generate for Block {
+    fn rulebook(&self) -> &'static [Rule<Self>] {
+        BLOCK_RULEBOOK
+    }
+
+    fn avail_registers(&self) -> [bool; N_REGISTERS] {
+        [false; N_REGISTERS]
+    }
+
+    fn initial_states(&self) -> Vec<State> {
+        Vec::from([State::new(BlockStateType::Copying, 0)])
+    }
+}
+
+fn fuse_blocks(a: Block, b: Block) -> Result<Assembly, GenerateError> {
+    Pipeline::new().fuse("blocks", a, b).generate()
+}
+
+fn pipeline() -> Pipeline {
+    Pipeline::new().then("copy", Copy::new("A", "X")).fuse(
+        "copies",
+        Copy::new("B", "Y"),
+        Copy::new("C", "Z"),
+    )
+}
+
+#[test]
+fn banners_enclose_the_stages() {
+    let asm = pipeline().generate().unwrap();
+    let text = asm.print(0, &[], &[]);
+    let lines: Vec<&str> = text.lines().map(|line| line.trim()).collect();
+
+    let copy = lines
+        .iter()
+        .position(|line| *line == "// --- copy start --- //")
+        .unwrap();
+    assert_eq!(
+        lines[copy + 2 * N_BLOCKS as usize + 1],
+        "// ---  copy end  --- //"
+    );
+    assert_eq!(lines[copy + 2 * N_BLOCKS as usize + 2], "");
+    assert_eq!(
+        lines[copy + 2 * N_BLOCKS as usize + 3],
+        "// --- copies start --- //"
+    );
+    assert!(lines.contains(&"// ---  copies end  --- //"));
+}
+
+#[test]
+fn fused_stages_interleave_on_one_pool() {
+    let asm = pipeline().generate().unwrap();
+    let text = asm.print(0, &[], &[]);
+
+    // the loads of B and C take turns
+    let loads: Vec<&str> = text
+        .lines()
+        .skip_while(|line| !line.contains("copies start"))
+        .filter_map(|line| match line.contains("[B]), %%zmm") {
+            true => Some("B"),
+            false => match line.contains("[C]), %%zmm") {
+                true => Some("C"),
+                false => None,
+            },
+        })
+        .collect();
+    assert_eq!(loads, ["B", "C", "B", "C"]);
+
+    let mut memory = Memory::new();
+    let mut inputs = Vec::new();
+    for k in 0..3 {
+        let data: Vec<f64> = (0..N_BLOCKS * 8).map(|i| (k * 100 + i) as f64).collect();
+        inputs.push((memory.alloc_f64(&data), data));
+    }
+    let outputs: Vec<u64> = (0..3)
+        .map(|_| memory.alloc(N_BLOCKS as usize * 64))
+        .collect();
+
+    let mut emulator = Emulator::new(memory)
+        .pointer("A", inputs[0].0)
+        .pointer("B", inputs[1].0)
+        .pointer("C", inputs[2].0)
+        .pointer("X", outputs[0])
+        .pointer("Y", outputs[1])
+        .pointer("Z", outputs[2]);
+    emulator.run(&asm).unwrap();
+    for ((_, data), &output) in inputs.iter().zip(outputs.iter()) {
+        let copied = emulator
+            .memory()
+            .read_f64s(output, N_BLOCKS as usize * 8)
+            .unwrap();
+        assert_eq!(&copied, data);
+    }
+}
+
+#[test]
+fn fused_fragments_keep_to_their_own_fixed_registers() {
+    let block = |reg| Block {
+        reg,
+        looping: false,
+    };
+    assert!(fuse_blocks(block(0), block(1)).is_ok());
+
+    let err = fuse_blocks(block(0), block(0)).err().unwrap();
+    assert_eq!(err.root().to_string(), "both fused fragments use zmm0");
+}
+
+#[test]
+fn fused_fragments_are_straight_line_code() {
+    let err = fuse_blocks(
+        Block {
+            reg: 0,
+            looping: true,
+        },
+        Block {
+            reg: 1,
+            looping: false,
+        },
+    )
+    .err()
+    .unwrap();
+    assert_eq!(
+        err.root().to_string(),
+        "`loop` branches in a fused fragment, which must be straight-line code"
+    );
+}
@@ -0,0 +1,109 @@
+// Labels of `Assembly::print` and of the GAS and NASM printouts, which are
+// local so that a kernel can be inlined more than once or share a translation
+// unit with other kernels, and the clobber list of `Assembly::print`.
+
+extern crate core;
+
+use core::gas::Function;
+use core::{Assembly, Zmm};
+
+// the instruction lines, without the quotes and padding
+fn lines(asm: &Assembly) -> Vec<String> {
+    asm.print(0, &[], &[])
+        .lines()
+        .filter(|line| line.starts_with('"'))
+        .map(|line| {
+            let (code, comment) = line.split_once("\\t\\n\"").unwrap();
+            let code = code.trim_matches(|c: char| c == '"' || c == ' ');
+            format!("{}{}", code, comment)
+        })
+        .collect()
+}
+
+#[test]
+fn labels_are_numbered_in_order() {
+    let asm = Assembly::new()
+        .label("loop_outer")
+        .label("loop_inner")
+        .add_f64x8(Zmm::new(0), Zmm::new(0), Zmm::new(1))
+        .sub_immediate("J", 1)
+        .jump_nz("loop_inner")
+        .sub_immediate("I", 1)
+        .jump_nz("loop_outer");
+
+    assert_eq!(
+        lines(&asm),
+        [
+            "1: // loop_outer",
+            "2: // loop_inner",
+            "vaddpd %%zmm1, %%zmm0, %%zmm0",
+            "sub $0x1, %[J]",
+            "jnz 2b // loop_inner",
+            "sub $0x1, %[I]",
+            "jnz 1b // loop_outer",
+        ]
+    );
+}
+
+#[test]
+fn jumps_point_forward_or_outside() {
+    let asm = Assembly::new()
+        .sub_immediate("I", 1)
+        .jump_nz("skip")
+        .add_f64x8(Zmm::new(0), Zmm::new(0), Zmm::new(1))
+        .label("skip")
+        .jump_nz("elsewhere");
+
+    assert_eq!(
+        lines(&asm),
+        [
+            "sub $0x1, %[I]",
+            "jnz 1f // skip",
+            "vaddpd %%zmm1, %%zmm0, %%zmm0",
+            "1: // skip",
+            "jnz elsewhere",
+        ]
+    );
+}
+
+#[test]
+fn clobbers_are_the_registers_written() {
+    let asm = Assembly::new()
+        .load_f64x8(Zmm::new(3), "A", 0)
+        .add_f64x8(Zmm::new(7), Zmm::new(3), Zmm::new(4))
+        .store_f64x8("Y", 0, Zmm::new(7));
+
+    let output = asm.print(0, &[], &[]);
+    let clobbers = output.lines().rev().nth(1).unwrap().trim();
+    assert_eq!(clobbers, ": \"zmm3\", \"zmm7\"");
+}
+
+// a counted loop as a function of its own
+fn counted(symbol: &str) -> (Function, Assembly) {
+    let function = Function::new(symbol).int("I");
+    let asm = Assembly::new()
+        .label("loop")
+        .sub_immediate("I", 1)
+        .jump_nz("loop");
+    (function, asm)
+}
+
+#[test]
+fn gas_labels_are_local() {
+    let (function, asm) = counted("f");
+    let text = function.print(&asm).unwrap();
+
+    assert!(text.contains("\n1: /* loop */\n"), "{}", text);
+    assert!(text.contains("    jnz 1b /* loop */\n"), "{}", text);
+    assert!(!text.contains("loop:"), "{}", text);
+}
+
+#[test]
+fn nasm_labels_are_local() {
+    let (function, asm) = counted("f");
+    let text = function.print_nasm(&asm).unwrap();
+
+    assert!(text.contains("\nf:\n"), "{}", text);
+    assert!(text.contains("\n.loop:\n"), "{}", text);
+    assert!(text.contains("    jnz .loop\n"), "{}", text);
+}
@@ -0,0 +1,108 @@
+// Reference kernels on matrices small enough to solve by hand.
+
+extern crate core;
+
+use core::reference::*;
+use core::sparse_matrix::*;
+
+fn info(diag: DiagonalStatus) -> ELLInfo {
+    ELLInfo::new(diag, LUStatus::Default, GridPointOrdering::Default)
+}
+
+// L = [2 0; 1 4] and U = [0 1; 0 0], with the diagonal in the slots only for
+// `DiagonalStatus::Default`
+fn two_rows(diag: DiagonalStatus) -> ELLMatrix {
+    let in_slots = matches!(diag, DiagonalStatus::Default);
+    let mut cols = vec![0; 2 * ROW_SLOTS];
+    let mut vals = vec![0.0; 2 * ROW_SLOTS];
+    let mut set = |row: usize, slot: usize, col: i32, val: f64| {
+        cols[row * ROW_SLOTS + slot] = col;
+        vals[row * ROW_SLOTS + slot] = val;
+    };
+    set(0, LU_SPLIT, 1, 1.0);
+    set(1, 0, 0, 1.0);
+    if in_slots {
+        set(0, 1, 0, 2.0);
+        set(1, 1, 1, 4.0);
+    }
+    let diagonal = match diag {
+        DiagonalStatus::ExcludedReciprocal => vec![0.5, 0.25],
+        DiagonalStatus::Default | DiagonalStatus::Excluded => vec![2.0, 4.0],
+    };
+    ELLMatrix::new(info(diag), 2, cols, vals, diagonal)
+}
+
+const LAYOUTS: [DiagonalStatus; 3] = [
+    DiagonalStatus::Default,
+    DiagonalStatus::Excluded,
+    DiagonalStatus::ExcludedReciprocal,
+];
+
+#[test]
+fn spmv_of_a_fully_coupled_grid() {
+    // on a 2^3 grid every point neighbours every other: A = 27I - 11^T
+    for diag in LAYOUTS {
+        let a = ELLMatrix::hpcg(info(diag), 2);
+        let mut y = vec![0.0; 8];
+        spmv(&a, Part::Full, &[1.0; 8], &mut y);
+        assert_eq!(y, [19.0; 8]);
+
+        let mut x = vec![0.0; 8];
+        x[0] = 1.0;
+        spmv(&a, Part::Full, &x, &mut y);
+        assert_eq!(y, [26.0, -1.0, -1.0, -1.0, -1.0, -1.0, -1.0, -1.0]);
+
+        spmv(&a, Part::Upper, &[1.0; 8], &mut y);
+        assert_eq!(y, [-7.0, -6.0, -5.0, -4.0, -3.0, -2.0, -1.0, 0.0]);
+        spmv(&a, Part::Lower, &[1.0; 8], &mut y);
+        assert_eq!(y, [26.0, 25.0, 24.0, 23.0, 22.0, 21.0, 20.0, 19.0]);
+    }
+}
+
+#[test]
+fn stencil_keeps_an_excluded_diagonal_out_of_the_slots() {
+    let a = ELLMatrix::hpcg(info(DiagonalStatus::Default), 2);
+    assert!(a
+        .row(0, Part::Lower)
+        .any(|(col, val)| col == 0 && val == 26.0));
+    assert_eq!(a.diag(), [26.0; 8]);
+
+    let a = ELLMatrix::hpcg(info(DiagonalStatus::Excluded), 2);
+    assert!(a.row(0, Part::Full).all(|(_, val)| val != 26.0));
+    assert_eq!(a.diag(), [26.0; 8]);
+
+    let a = ELLMatrix::hpcg(info(DiagonalStatus::ExcludedReciprocal), 2);
+    assert!(a.row(0, Part::Full).all(|(_, val)| val != 26.0));
+    assert_eq!(a.diag(), [1.0 / 26.0; 8]);
+}
+
+#[test]
+fn sptrsv_solves_both_triangles() {
+    for diag in LAYOUTS {
+        let a = two_rows(diag);
+
+        // [2 0; 1 4] x = [4 10]
+        let (mut p, mut x) = (vec![0.0; 2], vec![0.0; 2]);
+        sptrsv_forward(&a, &[4.0, 10.0], &mut p, &mut x);
+        assert_eq!(x, [2.0, 2.0]);
+        assert_eq!(p, [4.0, 8.0]);
+
+        // [2 1; 0 4] x = [4 8]
+        sptrsv_backward(&a, &p, &mut x);
+        assert_eq!(x, [1.0, 2.0]);
+    }
+}
+
+#[test]
+fn symgs_sweeps_forward_then_backward() {
+    for diag in LAYOUTS {
+        let a = two_rows(diag);
+        let (mut p, mut x) = (vec![0.0; 2], vec![1.0; 2]);
+        symgs(&a, &[4.0, 10.0], &mut p, &mut x);
+        // p = -Ux = [-1 0]; forward: x0 = 1 + (4 - 2 - 1) / 2 = 1.5,
+        // x1 = 1 + (10 - 1.5 - 4) / 4 = 2.125, p = [2 4.5]; backward:
+        // x1 = 4.5 / 4, x0 = (2 - x1) / 2
+        assert_eq!(p, [2.0, 4.5]);
+        assert_eq!(x, [0.4375, 1.125]);
+    }
+}
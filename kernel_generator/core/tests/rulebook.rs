@@ -0,0 +1,134 @@
+// A rulebook written with `rulebook!`, run in the emulator: y is the sum of
+// the blocks of a, squared first if the config says so.
+
+extern crate core;
+
+use core::*;
+
+const N_BLOCKS: u32 = 4;
+
+struct Generator {
+    square: bool,
+}
+
+enum StateType {
+    Loading,
+    Loaded,
+    Squaring,
+    Adding,
+}
+
+const RULEBOOK: &'static [Rule<Generator>] = rulebook! {
+    StateType;
+
+    Loading[i] => [Loaded[i](new reg)] |_config, _rp| {
+        Assembly::new().load_f64x8(reg, "A", i as i16 * 0x40)
+    }
+
+    #[guard(|config: &Generator| config.square)]
+    Loaded[i](reg) + Squaring[i] => [Adding[i](reg)] |_config, _rp| {
+        Assembly::new().mul_f64x8(reg, reg, reg)
+    }
+
+    Loaded[i](reg) => [Adding[i](reg)] |_config, _rp| {
+        Assembly::new()
+    }
+
+    Adding[i](reg0) + Adding[i + 1](reg1) => [Adding[i + 1](reg1)] |_config, rp| {
+        rp.free(reg0);
+
+        Assembly::new().add_f64x8(reg1, reg0, reg1)
+    }
+
+    #[priority(-1)]
+    Adding[_](reg) => [] |_config, rp| {
+        rp.free(reg);
+
+        Assembly::new().store_f64x8("Y", 0, reg)
+    }
+
+    Rule {
+
+        name: "Squaring",
+        condition: Condition::Single {
+            id: StateType::Squaring as u32,
+        },
+        callback: |_config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
+            Ok((Assembly::new(), Vec::new()))
+        },
+    }
+};
+
+impl Generate for Generator {
+    fn rulebook(&self) -> &'static [Rule<Self>] {
+        RULEBOOK
+    }
+
+    fn avail_registers(&self) -> [bool; N_REGISTERS] {
+        [true; N_REGISTERS]
+    }
+
+    fn initial_states(&self) -> Vec<State> {
+        let mut states = Vec::new();
+        for id in [StateType::Loading, StateType::Squaring] {
+            let id = id as u32;
+            for idx in 0..N_BLOCKS {
+                states.push(State { id, idx, reg: None });
+            }
+        }
+
+        states
+    }
+}
+
+fn run(square: bool) -> Vec<f64> {
+    let asm = Generator { square }.generate().unwrap();
+
+    let mut memory = Memory::new();
+    let data: Vec<f64> = (0..N_BLOCKS * 8).map(|i| i as f64).collect();
+    let a = memory.alloc_f64(&data);
+    let y = memory.alloc(64);
+
+    let mut emulator = Emulator::new(memory).pointer("A", a).pointer("Y", y);
+    emulator.run(&asm).unwrap();
+    emulator.memory().read_f64s(y, 8).unwrap()
+}
+
+#[test]
+fn conditions() {
+    let conditions: Vec<String> = RULEBOOK
+        .iter()
+        .map(|rule| format!("{:?}", rule.condition))
+        .collect();
+    assert_eq!(
+        conditions,
+        [
+            "Single { id: 0 }",
+            "Guard { condition: Join { ids: [1, 2] } }",
+            "Single { id: 1 }",
+            "SameId { id: 3, n_states: 2, idx_dist: 1 }",
+            "Priority { priority: -1, condition: Single { id: 3 } }",
+            "Single { id: 2 }",
+        ]
+    );
+}
+
+#[test]
+fn sum() {
+    let expected: Vec<f64> = (0..8)
+        .map(|j| (0..N_BLOCKS).map(|i| (i * 8 + j) as f64).sum())
+        .collect();
+    assert_eq!(run(false), expected);
+}
+
+#[test]
+fn guarded_rule() {
+    let expected: Vec<f64> = (0..8)
+        .map(|j| {
+            (0..N_BLOCKS)
+                .map(|i| ((i * 8 + j) * (i * 8 + j)) as f64)
+                .sum()
+        })
+        .collect();
+    assert_eq!(run(true), expected);
+}
@@ -0,0 +1,102 @@
+// `Assembly::spill` on small hand-written kernels, checked in the emulator.
+
+extern crate core;
+
+use core::{Assembly, Emulator, GenerateError, Memory, Zmm, SPILL_AREA_BYTES};
+
+// y = a + b + a * b, with a, b and the result in spilled registers
+fn kernel(a: Zmm, b: Zmm, res: Zmm) -> Assembly {
+    Assembly::new()
+        .load_f64x8(a, "A", 0)
+        .load_f64x8(b, "A", 0x40)
+        .add_f64x8(res, a, b)
+        .muladd_f64x8(res, a, b)
+        .store_f64x8("Y", 0, res)
+}
+
+fn run(asm: &Assembly) -> Vec<f64> {
+    let mut memory = Memory::new();
+    let data: Vec<f64> = (0..16).map(|i| i as f64).collect();
+    let a = memory.alloc_f64(&data);
+    let y = memory.alloc(64);
+    let tmp = memory.alloc(SPILL_AREA_BYTES);
+
+    let mut emulator = Emulator::new(memory)
+        .pointer("A", a)
+        .pointer("Y", y)
+        .pointer("TMP", tmp);
+    emulator.run(asm).unwrap();
+    emulator.memory().read_f64s(y, 8).unwrap()
+}
+
+#[test]
+fn spilled_registers_are_reloaded_and_stored() {
+    let asm = kernel(Zmm::new(40), Zmm::new(33), Zmm::new(63));
+    assert_eq!(asm.n_spilled(), 3);
+
+    let (asm, report) = asm.spill("TMP").unwrap();
+    assert_eq!(asm.n_spilled(), 0);
+    assert_eq!(report.registers, 3);
+    // register 63 takes the last slot of the spill area
+    assert_eq!(report.bytes, SPILL_AREA_BYTES);
+    // the fma reads all three, and the store reads the result
+    assert_eq!(report.reloads, 2 + 3 + 1);
+    assert_eq!(report.stores, 4);
+
+    let expected = run(&kernel(Zmm::new(0), Zmm::new(1), Zmm::new(2)));
+    assert_eq!(run(&asm), expected);
+    assert!(asm.verify().is_ok());
+}
+
+#[test]
+fn moves_become_loads_and_stores() {
+    let asm = Assembly::new()
+        .load_f64x8(Zmm::new(0), "A", 0)
+        .move_f64x8(Zmm::new(32), Zmm::new(0))
+        .move_f64x8(Zmm::new(1), Zmm::new(32))
+        .store_f64x8("Y", 0, Zmm::new(1));
+
+    let (asm, report) = asm.spill("TMP").unwrap();
+    assert_eq!((report.reloads, report.stores), (1, 1));
+    assert_eq!(run(&asm), (0..8).map(|i| i as f64).collect::<Vec<f64>>());
+}
+
+#[test]
+fn spilling_without_free_registers_overflows() {
+    let mut asm = Assembly::new();
+    for i in 0..32 {
+        asm = asm.load_f64x8(Zmm::new(i), "A", 0);
+    }
+    asm = asm.add_f64x8(Zmm::new(40), Zmm::new(0), Zmm::new(1));
+    for i in 0..32 {
+        asm = asm.add_f64x8(Zmm::new(0), Zmm::new(0), Zmm::new(i));
+    }
+    let asm = asm
+        .store_f64x8("Y", 0, Zmm::new(0))
+        .store_f64x8("Y", 0, Zmm::new(40));
+
+    // the 32 physical registers are live, and zmm40 needs one more
+    let err = asm.spill("TMP").err().unwrap();
+    assert!(matches!(
+        err,
+        GenerateError::RegisterOverflow {
+            need: 33,
+            avail: 32,
+            ..
+        }
+    ));
+    assert_eq!(
+        err.to_string(),
+        "33 vector registers, more than the 32 registers there are"
+    );
+}
+
+#[test]
+fn moving_spill_area_is_rejected() {
+    let asm = kernel(Zmm::new(40), Zmm::new(1), Zmm::new(2)).add_immediate("TMP", 0x40);
+
+    assert!(matches!(
+        asm.spill("TMP"),
+        Err(GenerateError::MovingSpillArea(ref name)) if &**name == "TMP"
+    ));
+}
@@ -0,0 +1,116 @@
+// State indices are not limited to the number of registers: a reduction over
+// more blocks than that, spread far apart.
+
+extern crate core;
+
+use core::trace;
+use core::*;
+
+const N_BLOCKS: u32 = 40;
+const IDX_DIST: u8 = 250;
+
+struct Generator {}
+
+enum StateType {
+    Loading,
+    Adding,
+}
+
+const RULEBOOK: &'static [Rule<Generator>] = &[
+    Rule {
+        name: "Loading",
+        condition: Condition::Single {
+            id: StateType::Loading as u32,
+        },
+        callback: |_config: &Generator, rp: &mut RegisterPool, states: &Vec<State>| {
+            let reg = rp.get()?;
+            let asm = Assembly::new().load_f64x8(reg, "A", 0);
+            let states = Vec::from([State {
+                id: StateType::Adding as u32,
+                idx: states[0].idx,
+                reg: Some(reg),
+            }]);
+
+            Ok((asm, states))
+        },
+    },
+    Rule {
+        name: "Adding + Adding at +250",
+        condition: Condition::SameId {
+            id: StateType::Adding as u32,
+            n_states: 2,
+            idx_dist: IDX_DIST,
+        },
+        callback: |_config: &Generator, rp: &mut RegisterPool, states: &Vec<State>| {
+            let reg0 = states[0].reg.unwrap();
+            let reg1 = states[1].reg.unwrap();
+            rp.free(reg0);
+
+            let asm = Assembly::new().add_f64x8(reg1, reg0, reg1);
+            let states = Vec::from([State {
+                id: StateType::Adding as u32,
+                idx: states[1].idx,
+                reg: Some(reg1),
+            }]);
+
+            Ok((asm, states))
+        },
+    },
+    Rule {
+        name: "Adding",
+        condition: Condition::Single {
+            id: StateType::Adding as u32,
+        },
+        callback: |_config: &Generator, rp: &mut RegisterPool, states: &Vec<State>| {
+            let reg = states[0].reg.unwrap();
+            rp.free(reg);
+
+            let asm = Assembly::new().store_f64x8("Y", 0, reg);
+            let states = Vec::new();
+
+            Ok((asm, states))
+        },
+    },
+];
+
+impl Generate for Generator {
+    fn rulebook(&self) -> &'static [Rule<Self>] {
+        RULEBOOK
+    }
+
+    fn avail_registers(&self) -> [bool; N_REGISTERS] {
+        [true; N_REGISTERS]
+    }
+
+    fn initial_states(&self) -> Vec<State> {
+        (0..N_BLOCKS)
+            .map(|i| State {
+                id: StateType::Loading as u32,
+                idx: i * IDX_DIST as u32,
+                reg: None,
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn reduction_over_far_apart_indices() {
+    let (asm, traces) = trace::record(|| Generator {}.generate());
+    assert!(asm.is_ok());
+
+    let firings = &traces[0].firings;
+    assert_eq!(firings.len() as u32, 2 * N_BLOCKS);
+
+    // the lowest indices are matched first
+    let pairs: Vec<(u32, u32)> = firings
+        .iter()
+        .filter(|firing| firing.rule == 1)
+        .map(|firing| (firing.consumed[0].idx, firing.consumed[1].idx))
+        .collect();
+    assert_eq!(pairs.len() as u32, N_BLOCKS - 1);
+    for (i, &pair) in pairs.iter().enumerate() {
+        let idx = i as u32 * IDX_DIST as u32;
+        assert_eq!(pair, (idx, idx + IDX_DIST as u32));
+    }
+    assert_eq!(firings.last().unwrap().rule, 2);
+}
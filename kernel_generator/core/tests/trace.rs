@@ -0,0 +1,125 @@
+// `trace::record` on a small rulebook that runs a nested generator.
+
+extern crate core;
+
+use core::trace::{self, Trace};
+use core::*;
+
+struct Generator {
+    nested: bool,
+}
+
+enum StateType {
+    Splitting,
+    Adding,
+}
+
+const RULEBOOK: &'static [Rule<Generator>] = &[
+    Rule {
+        name: "Splitting",
+        condition: Condition::Single {
+            id: StateType::Splitting as u32,
+        },
+        callback: |config: &Generator, rp: &mut RegisterPool, _states: &Vec<State>| {
+            let asm = match config.nested {
+                true => Generator { nested: false }.generate()?,
+                false => Assembly::new(),
+            };
+            let mut states = Vec::new();
+            for idx in 0..2 {
+                states.push(State {
+                    id: StateType::Adding as u32,
+                    idx,
+                    reg: Some(rp.get()?),
+                });
+            }
+
+            Ok((asm, states))
+        },
+    },
+    Rule {
+        name: "Adding + Adding at +1",
+        condition: Condition::SameId {
+            id: StateType::Adding as u32,
+            n_states: 2,
+            idx_dist: 1,
+        },
+        callback: |_config: &Generator, rp: &mut RegisterPool, states: &Vec<State>| {
+            let reg0 = states[0].reg.unwrap();
+            let reg1 = states[1].reg.unwrap();
+            rp.free(reg0);
+            rp.free(reg1);
+
+            let asm = Assembly::new().add_f64x8(reg0, reg0, reg1);
+            let states = Vec::new();
+
+            Ok((asm, states))
+        },
+    },
+];
+
+impl Generate for Generator {
+    fn rulebook(&self) -> &'static [Rule<Self>] {
+        RULEBOOK
+    }
+
+    fn avail_registers(&self) -> [bool; N_REGISTERS] {
+        [true; N_REGISTERS]
+    }
+
+    fn initial_states(&self) -> Vec<State> {
+        Vec::from([State {
+            id: StateType::Splitting as u32,
+            idx: 0,
+            reg: None,
+        }])
+    }
+}
+
+fn record() -> Vec<Trace> {
+    let (asm, traces) = trace::record(|| Generator { nested: true }.generate());
+    assert!(asm.is_ok());
+    traces
+}
+
+#[test]
+fn firings_are_recorded_with_nested_generators() {
+    let traces = record();
+    assert_eq!(traces.len(), 1);
+
+    let firings = &traces[0].firings;
+    assert_eq!(firings.len(), 2);
+    assert_eq!(firings[0].rule, 0);
+    assert_eq!(firings[0].consumed.len(), 1);
+    assert_eq!(firings[0].produced.len(), 2);
+    assert_eq!(firings[0].nested.len(), 1);
+    assert_eq!(firings[0].nested[0].firings.len(), 2);
+
+    assert_eq!(firings[1].rule, 1);
+    assert_eq!(firings[1].name, "Adding + Adding at +1");
+    assert!(firings[1].condition.starts_with("SameId"));
+    assert_eq!(firings[1].instructions.len(), 1);
+    assert!(firings[1].nested.is_empty());
+}
+
+#[test]
+fn nothing_is_recorded_outside_of_record() {
+    assert!(Generator { nested: true }.generate().is_ok());
+    assert_eq!(record().len(), 1);
+}
+
+#[test]
+fn exports() {
+    let traces = record();
+
+    let json = trace::to_json(&traces);
+    assert!(json.contains("\"rule\": 1, \"name\": \"Adding + Adding at +1\""));
+    assert!(json.contains("\"reg\": \"zmm0\""));
+    assert_eq!(json.matches("\"generator\"").count(), 2);
+
+    let dot = trace::to_dot(&traces);
+    assert!(dot.starts_with("digraph trace {"));
+    assert!(dot.contains("t0_f0 -> t0_f1 [label=\"1:0 zmm0\"];"));
+    assert!(dot.contains("t0_f0 -> t0_0_0_initial [style=dashed];"));
+    assert!(!dot.contains("unconsumed"));
+}
@@ -0,0 +1,106 @@
+// `Assembly::verify` and `Assembly::clobbers` on small hand-written kernels.
+
+extern crate core;
+
+use core::{Assembly, Mask, Register, VerifyError, Zmm};
+
+#[test]
+fn read_before_write_is_reported() {
+    let asm = Assembly::new()
+        .add_f64x8(Zmm::new(0), Zmm::new(1), Zmm::new(2))
+        .store_f64x8("Y", 0, Zmm::new(0));
+
+    let errors = asm.verify().unwrap_err();
+    assert_eq!(errors.len(), 2);
+    for err in errors.iter() {
+        assert!(matches!(err, VerifyError::UseBeforeDef { index: 0, .. }));
+    }
+}
+
+#[test]
+fn registers_filled_by_a_loop_are_defined() {
+    // zmm0 and zmm1 hold garbage in the first iteration, but the loop runs
+    // often enough to fill both before the sum reads them
+    let asm = Assembly::new()
+        .set_immediate("I", 2)
+        .label("loop")
+        .move_f64x8(Zmm::new(0), Zmm::new(1))
+        .load_f64x8(Zmm::new(1), "A", 0)
+        .add_immediate("A", 0x40)
+        .sub_immediate("I", 1)
+        .jump_nz("loop")
+        .add_f64x8(Zmm::new(2), Zmm::new(0), Zmm::new(1))
+        .store_f64x8("Y", 0, Zmm::new(2));
+    assert!(asm.verify().is_ok());
+
+    // one iteration leaves zmm0 unfilled
+    let asm = Assembly::new()
+        .set_immediate("I", 1)
+        .label("loop")
+        .move_f64x8(Zmm::new(0), Zmm::new(1))
+        .load_f64x8(Zmm::new(1), "A", 0)
+        .add_immediate("A", 0x40)
+        .sub_immediate("I", 1)
+        .jump_nz("loop")
+        .add_f64x8(Zmm::new(2), Zmm::new(0), Zmm::new(1))
+        .store_f64x8("Y", 0, Zmm::new(2));
+    let errors = asm.verify().unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].to_string().starts_with("zmm0 may be read"));
+}
+
+#[test]
+fn dead_write_is_reported() {
+    let asm = Assembly::new()
+        .load_f64x8(Zmm::new(0), "A", 0)
+        .load_f64x8(Zmm::new(1), "A", 0x40)
+        .store_f64x8("Y", 0, Zmm::new(0));
+
+    let errors = asm.verify().unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(
+        errors[0],
+        VerifyError::DeadWrite {
+            index: 1,
+            reg: Register::Zmm(zmm),
+            ..
+        } if zmm == Zmm::new(1)
+    ));
+}
+
+#[test]
+fn clobbers_are_the_written_registers() {
+    let asm = Assembly::new()
+        .load_f64x8(Zmm::new(3), "A", 0)
+        .mul_f64x8(Zmm::new(5), Zmm::new(3), Zmm::new(3))
+        .store_f64x8("Y", 0, Zmm::new(5));
+
+    assert_eq!(
+        asm.clobbers(),
+        vec![Register::Zmm(Zmm::new(3)), Register::Zmm(Zmm::new(5))]
+    );
+    assert!(asm.verify().is_ok());
+}
+
+#[test]
+fn masks_are_clobbered_where_they_are_set() {
+    let (k0, k1, k2) = (Mask::new(1), Mask::new(2), Mask::new(5));
+    let asm = Assembly::new()
+        .init_mix2mask("CNT", k0, k1)
+        .mix2add_f64x8(Zmm::new(0), Zmm::new(1), Zmm::new(2), k0, k1)
+        .load_i32x8(Zmm::new(3).ymm(), "COL", 0)
+        .mask_on(k2)
+        .gather_f64x8(Zmm::new(4), "X", Zmm::new(3).ymm(), k2)
+        .add_f64x8(Zmm::new(0), Zmm::new(0), Zmm::new(4))
+        .store_f64x8("Y", 0, Zmm::new(0));
+
+    let masks: Vec<Register> = asm
+        .clobbers()
+        .into_iter()
+        .filter(|reg| matches!(reg, Register::Mask(_)))
+        .collect();
+    assert_eq!(
+        masks,
+        [Register::Mask(k0), Register::Mask(k1), Register::Mask(k2)]
+    );
+}
@@ -1,326 +1,261 @@
-use core::{sparse_matrix, PrefetchType};
+use core::config::{Config, ConfigError, Value};
+use template::check::Report;
 use template::*;
 
+use std::cmp::Ordering;
 use std::fmt;
 
-pub enum GeneratorType {
-    Spmv(spmv::Generator),
-    Sptrsv(sptrsv::Generator),
-    Symgs(symgs::Generator),
+pub enum KernelConfig {
+    Spmv(spmv::Config),
+    Sptrsv(sptrsv::Config),
+    Symgs(symgs::Config),
 }
 
-pub fn parse_arguments(args: &[String]) -> Result<GeneratorType, ArgumentError> {
+pub fn parse_arguments(args: &[String]) -> Result<KernelConfig, ArgumentError> {
     if args.len() == 0 {
-        return Err(ArgumentError::InvalidArgument);
+        return Err(ArgumentError::NoKernel);
     }
 
     match args[0].as_str() {
-        "spmv" | "SPMV" => parse_spmv_arguments(&args[1..]).map(|g| GeneratorType::Spmv(g)),
+        "spmv" | "SPMV" => parse_config(spmv::Config::new(), &args[1..]).map(KernelConfig::Spmv),
         "trsv" | "TRSV" | "sptrsv" | "SPTRSV" => {
-            parse_sptrsv_arguments(&args[1..]).map(|g| GeneratorType::Sptrsv(g))
+            parse_config(sptrsv::Config::new(), &args[1..]).map(KernelConfig::Sptrsv)
         }
-        "symgs" | "SYMGS" => parse_symgs_arguments(&args[1..]).map(|g| GeneratorType::Symgs(g)),
-        _ => Err(ArgumentError::InvalidArgument),
+        "symgs" | "SYMGS" => {
+            parse_config(symgs::Config::new(), &args[1..]).map(KernelConfig::Symgs)
+        }
+        kernel => Err(ArgumentError::UnknownKernel(kernel.to_string())),
     }
 }
 
-fn parse_spmv_arguments(args: &[String]) -> Result<spmv::Generator, ArgumentError> {
-    let mut iter = args.iter();
-    let mut next = || {
-        iter.next()
-            .map_or(Err(ArgumentError::NotEnoughArguments), |s| Ok(s))
-    };
-
-    let matrix_format = {
-        // TODO: support ELL_Info
-        let ell_info = sparse_matrix::ELLInfo::new(
-            sparse_matrix::DiagonalStatus::Default,
-            sparse_matrix::LUStatus::Default,
-            sparse_matrix::GridPointOrdering::Default,
-        );
-        sparse_matrix::SparseMatrixFormat::ELL(ell_info)
-    };
-    let action = spmv::Action::AssignPosAx;
-    let direction = Direction::Forward;
-
-    let col_prefetch_info = ArgumentParser::parse_prefetch_info(next()?, next()?)?;
-    let col_preload_dist = ArgumentParser::parse_u8(next()?)?;
-
-    let val_prefetch_info = ArgumentParser::parse_prefetch_info(next()?, next()?)?;
-    let val_preload_dist = ArgumentParser::parse_option_u8(next()?)?;
-
-    let x_preload_dist = ArgumentParser::parse_u8(next()?)?;
-
-    let rowblock_size = ArgumentParser::parse_u8(next()?)?;
-    let n_nops = ArgumentParser::parse_u8(next()?)?;
-    let store_to_tmp = ArgumentParser::parse_bool(next()?)?;
-    let move_reg = ArgumentParser::parse_bool(next()?)?;
-    let move_base = ArgumentParser::parse_bool(next()?)?;
-
-    if next().is_ok() {
-        return Err(ArgumentError::TooManyArguments);
+// named flags if the arguments start with one or there are none, and every
+// parameter in the order of `Config::fields` otherwise
+fn parse_config<C: Config>(config: C, args: &[String]) -> Result<C, ArgumentError> {
+    match args.first().is_none_or(|arg| arg.starts_with("--")) {
+        true => parse_flags(config, args),
+        false => parse_positional(config, args),
     }
+}
 
-    let spmv_generator = spmv::Generator::new(
-        matrix_format,
-        action,
-        direction,
-        "NROW",
-        col_prefetch_info,
-        col_preload_dist,
-        "COL",
-        val_prefetch_info,
-        val_preload_dist,
-        "VAL",
-        x_preload_dist,
-        "X",
-        "TMP",
-        "I",
-        "loop_spmv",
-        "Y",
-        rowblock_size,
-        n_nops,
-        store_to_tmp,
-        move_reg,
-        move_base,
-    );
+// the values are checked before their count, so a mistyped one is named
+fn parse_positional<C: Config>(config: C, args: &[String]) -> Result<C, ArgumentError> {
+    let fields = config.fields();
+    let mut config = config;
+    for (i, ((key, current), arg)) in fields.iter().zip(args).enumerate() {
+        config = config.set(key, &to_value(arg, current)).map_err(|err| {
+            ArgumentError::InvalidArgument {
+                position: i + 1,
+                err,
+            }
+        })?;
+    }
 
-    Ok(spmv_generator)
+    match args.len().cmp(&fields.len()) {
+        Ordering::Less => Err(ArgumentError::NotEnoughArguments(fields[args.len()].0)),
+        Ordering::Greater => Err(ArgumentError::TooManyArguments),
+        Ordering::Equal => Ok(config),
+    }
 }
 
-fn parse_sptrsv_arguments(args: &[String]) -> Result<sptrsv::Generator, ArgumentError> {
+// `--col-pft T2` or `--col-pft=T2`, on top of the file of `--config` if there
+// is one
+fn parse_flags<C: Config>(config: C, args: &[String]) -> Result<C, ArgumentError> {
+    let mut config_path = None;
+    let mut flags = Vec::new();
     let mut iter = args.iter();
-    let mut next = || {
-        iter.next()
-            .map_or(Err(ArgumentError::NotEnoughArguments), |s| Ok(s))
-    };
+    while let Some(arg) = iter.next() {
+        let flag = arg
+            .strip_prefix("--")
+            .ok_or_else(|| ArgumentError::UnknownFlag(arg.clone()))?;
+        let (name, value) = match flag.split_once('=') {
+            Some((name, value)) => (name, value.to_string()),
+            None => match iter.next() {
+                Some(value) => (flag, value.clone()),
+                None => return Err(ArgumentError::MissingValue(arg.clone())),
+            },
+        };
+        match name {
+            "config" => config_path = Some(value),
+            _ => flags.push((name, value)),
+        }
+    }
 
-    let matrix_format = {
-        // TODO: support ELL_Info
-        let ell_info = sparse_matrix::ELLInfo::new(
-            sparse_matrix::DiagonalStatus::Excluded,
-            sparse_matrix::LUStatus::Default,
-            sparse_matrix::GridPointOrdering::Default,
-        );
-        sparse_matrix::SparseMatrixFormat::ELL(ell_info)
+    let mut config = match config_path {
+        Some(path) => read_config_file(config, &path)?,
+        None => config,
     };
-
-    let direction = ArgumentParser::parse_direction(next()?)?;
-    let static_iter = ArgumentParser::parse_option_u8(next()?)?;
-
-    let col_prefetch_info = ArgumentParser::parse_prefetch_info(next()?, next()?)?;
-    let col_preload_dist = ArgumentParser::parse_u8(next()?)?;
-
-    let val_prefetch_info = ArgumentParser::parse_prefetch_info(next()?, next()?)?;
-    let val_preload_dist = ArgumentParser::parse_option_u8(next()?)?;
-
-    let x_preload_dist = ArgumentParser::parse_u8(next()?)?;
-
-    let rowblock_size = ArgumentParser::parse_u8(next()?)?;
-    let n_nops = ArgumentParser::parse_u8(next()?)?;
-    let store_to_tmp = ArgumentParser::parse_bool(next()?)?;
-    let move_reg = ArgumentParser::parse_bool(next()?)?;
-    let move_base = ArgumentParser::parse_bool(next()?)?;
-
-    if next().is_ok() {
-        return Err(ArgumentError::TooManyArguments);
+    let fields = config.fields();
+    for (name, arg) in flags {
+        let key = name.replace('-', "_");
+        let value = match fields.iter().find(|(field, _)| *field == key) {
+            Some((_, current)) => to_value(&arg, current),
+            None => return Err(ArgumentError::UnknownFlag(format!("--{}", name))),
+        };
+        config = config
+            .set(&key, &value)
+            .map_err(ArgumentError::InvalidFlag)?;
     }
 
-    let sptrsv_generator = sptrsv::Generator::new(
-        matrix_format,
-        direction,
-        static_iter,
-        "NROW",
-        0,
-        col_prefetch_info,
-        col_preload_dist,
-        "COL",
-        0,
-        val_prefetch_info,
-        val_preload_dist,
-        "VAL",
-        x_preload_dist,
-        "X",
-        "IMM_X",
-        "TMP",
-        "I",
-        "loop_sptrsv",
-        "P",
-        "D",
-        "R",
-        rowblock_size,
-        n_nops,
-        store_to_tmp,
-        move_reg,
-        move_base,
-        false,
-    );
-
-    Ok(sptrsv_generator)
+    Ok(config)
 }
 
-fn parse_symgs_arguments(args: &[String]) -> Result<symgs::Generator, ArgumentError> {
-    let mut iter = args.iter();
-    let mut next = || {
-        iter.next()
-            .map_or(Err(ArgumentError::NotEnoughArguments), |s| Ok(s))
+// JSON if the name says so, TOML otherwise
+fn read_config_file<C: Config>(config: C, path: &str) -> Result<C, ArgumentError> {
+    let file_error = |reason: String| ArgumentError::ConfigFile {
+        path: path.to_string(),
+        reason,
     };
 
-    let matrix_format = {
-        // TODO: support ELL_Info
-        let ell_info = sparse_matrix::ELLInfo::new(
-            sparse_matrix::DiagonalStatus::Excluded,
-            sparse_matrix::LUStatus::Default,
-            sparse_matrix::GridPointOrdering::Default,
-        );
-        sparse_matrix::SparseMatrixFormat::ELL(ell_info)
+    let text = std::fs::read_to_string(path).map_err(|err| file_error(err.to_string()))?;
+    let config = match path.ends_with(".json") {
+        true => config.read_json(&text),
+        false => config.read_toml(&text),
     };
-
-    let sptrsv_static_iter = ArgumentParser::parse_option_u8(next()?)?;
-
-    let col_prefetch_info = ArgumentParser::parse_prefetch_info(next()?, next()?)?;
-    let col_preload_dist = ArgumentParser::parse_u8(next()?)?;
-
-    let val_prefetch_info = ArgumentParser::parse_prefetch_info(next()?, next()?)?;
-    let val_preload_dist = ArgumentParser::parse_option_u8(next()?)?;
-
-    let x_preload_dist = ArgumentParser::parse_u8(next()?)?;
-
-    let spmv_rowblock_size = ArgumentParser::parse_u8(next()?)?;
-    let sptrsv_rowblock_size = ArgumentParser::parse_u8(next()?)?;
-    let n_nops_c = ArgumentParser::parse_u8(next()?)?;
-    let n_nops_f0 = ArgumentParser::parse_u8(next()?)?;
-    let n_nops_f1 = ArgumentParser::parse_u8(next()?)?;
-    let n_nops_f2 = ArgumentParser::parse_u8(next()?)?;
-    let n_nops_b0 = ArgumentParser::parse_u8(next()?)?;
-    let n_nops_b1 = ArgumentParser::parse_u8(next()?)?;
-    let n_nops_b2 = ArgumentParser::parse_u8(next()?)?;
-    let store_to_tmp = ArgumentParser::parse_bool(next()?)?;
-    let move_reg = ArgumentParser::parse_bool(next()?)?;
-    let move_base = ArgumentParser::parse_bool(next()?)?;
-
-    if next().is_ok() {
-        return Err(ArgumentError::TooManyArguments);
-    }
-
-    let symgs_generator = symgs::Generator::new(
-        matrix_format,
-        sptrsv_static_iter,
-        "NROW",
-        "IMM_NROW",
-        col_prefetch_info,
-        col_preload_dist,
-        "UCOL",
-        "LCOL",
-        val_prefetch_info,
-        val_preload_dist,
-        "UVAL",
-        "LVAL",
-        x_preload_dist,
-        "X",
-        "IMM_X",
-        "TMP",
-        "I",
-        "loop_c",
-        "loop_f0",
-        "loop_f1",
-        "loop_f2",
-        "loop_b0",
-        "loop_b1",
-        "loop_b2",
-        "P",
-        "IMM_P",
-        "D",
-        "R",
-        spmv_rowblock_size,
-        sptrsv_rowblock_size,
-        n_nops_c,
-        n_nops_f0,
-        n_nops_f1,
-        n_nops_f2,
-        n_nops_b0,
-        n_nops_b1,
-        n_nops_b2,
-        store_to_tmp,
-        move_reg,
-        move_base,
-    );
-
-    Ok(symgs_generator)
+    config.map_err(|err| file_error(err.to_string()))
 }
 
-struct ArgumentParser;
-
-impl ArgumentParser {
-    fn parse_prefetch_info(
-        arg0: &str,
-        arg1: &str,
-    ) -> Result<Option<(PrefetchType, u16)>, ArgumentError> {
-        let prefetch_kind = match arg0 {
-            "T0" | "t0" => Ok(Some(PrefetchType::T0)),
-            "T1" | "t1" => Ok(Some(PrefetchType::T1)),
-            "T2" | "t2" => Ok(Some(PrefetchType::T2)),
-            "NTA" | "nta" => Ok(Some(PrefetchType::NTA)),
-            "None" | "none" | "n" => Ok(None),
-            _ => Err(ArgumentError::InvalidArgument),
-        }?;
-
-        let prefetch_dist = arg1
-            .parse::<u16>()
-            .map_err(|_| ArgumentError::InvalidArgument)?;
-
-        Ok(prefetch_kind.map(|kind| (kind, prefetch_dist)))
-    }
-
-    fn parse_u8(arg: &str) -> Result<u8, ArgumentError> {
-        arg.parse::<u8>()
-            .map_err(|_| ArgumentError::InvalidArgument)
-    }
-
-    fn parse_option_u8(arg: &str) -> Result<Option<u8>, ArgumentError> {
-        let val = arg
-            .parse::<i16>()
-            .map_err(|_| ArgumentError::InvalidArgument)?;
-
-        match val {
-            -1 => Ok(None),
-            0..256 => Ok(Some(val as u8)),
-            _ => Err(ArgumentError::InvalidArgument),
-        }
-    }
-
-    fn parse_direction(arg: &str) -> Result<Direction, ArgumentError> {
-        match arg {
-            "F" | "f" | "forward" | "Forward" | "FORWARD" => Ok(Direction::Forward),
-            "B" | "b" | "backward" | "Backward" | "BACKWARD" => Ok(Direction::Backward),
-            _ => Err(ArgumentError::InvalidArgument),
-        }
-    }
-
-    fn parse_bool(arg: &str) -> Result<bool, ArgumentError> {
-        match arg {
-            "T" | "t" | "true" | "True" | "TRUE" => Ok(true),
-            "F" | "f" | "false" | "False" | "FALSE" => Ok(false),
-            _ => Err(ArgumentError::InvalidArgument),
+// an argument as a value of the type of the parameter, in any of the
+// spellings the command line has always taken
+fn to_value(arg: &str, current: &Value) -> Value {
+    match current {
+        Value::Bool(_) => match arg {
+            "T" | "t" | "true" | "True" | "TRUE" => Value::Bool(true),
+            "F" | "f" | "false" | "False" | "FALSE" => Value::Bool(false),
+            _ => Value::Str(arg.to_string()),
+        },
+        Value::Int(_) => match arg.parse() {
+            Ok(value) => Value::Int(value),
+            Err(_) => Value::Str(arg.to_string()),
+        },
+        Value::Str(_) => {
+            let value = match arg {
+                "t0" => "T0",
+                "t1" => "T1",
+                "t2" => "T2",
+                "nta" => "NTA",
+                "none" | "n" => "None",
+                "F" | "f" | "Forward" | "FORWARD" => "forward",
+                "B" | "b" | "Backward" | "BACKWARD" => "backward",
+                arg => arg,
+            };
+            Value::Str(value.to_string())
         }
     }
 }
 
 #[derive(Debug)]
 pub enum ArgumentError {
-    InvalidArgument,
-    NotEnoughArguments,
+    NoKernel,
+    UnknownKernel(String),
+    NotEnoughArguments(&'static str),
     TooManyArguments,
+    // a positional argument, counted from 1 after the kernel
+    InvalidArgument { position: usize, err: ConfigError },
+    UnknownFlag(String),
+    MissingValue(String),
+    InvalidFlag(ConfigError),
+    ConfigFile { path: String, reason: String },
+    // two output formats, in the order they are given
+    ConflictingOptions(String, String),
 }
 
 impl fmt::Display for ArgumentError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::InvalidArgument => write!(f, "invalid argument"),
-            Self::NotEnoughArguments => write!(f, "not enough argument"),
+            Self::NoKernel => write!(f, "no kernel given"),
+            Self::UnknownKernel(kernel) => {
+                write!(
+                    f,
+                    "unknown kernel `{}`, expected spmv, trsv or symgs",
+                    kernel
+                )
+            }
+            Self::NotEnoughArguments(key) => {
+                write!(f, "not enough arguments, <{}> is missing", key)
+            }
             Self::TooManyArguments => write!(f, "too many arguments"),
+            Self::InvalidArgument {
+                position,
+                err:
+                    ConfigError::InvalidValue {
+                        key,
+                        value,
+                        expected,
+                    },
+            } => write!(
+                f,
+                "argument {}, <{}> {}: expected {}",
+                position, key, value, expected
+            ),
+            Self::InvalidArgument { position, err, .. } => {
+                write!(f, "argument {}: {}", position, err)
+            }
+            Self::UnknownFlag(flag) => write!(f, "unknown option `{}`", flag),
+            Self::MissingValue(flag) => write!(f, "`{}` needs a value", flag),
+            Self::InvalidFlag(ConfigError::InvalidValue {
+                key,
+                value,
+                expected,
+            }) => write!(f, "`{}` {}: expected {}", flag(key), value, expected),
+            Self::InvalidFlag(err) => write!(f, "{}", err),
+            Self::ConfigFile { path, reason } => write!(f, "{}: {}", path, reason),
+            Self::ConflictingOptions(first, second) => {
+                write!(f, "`{}` and `{}` cannot be given together", first, second)
+            }
         }
     }
 }
 
 impl std::error::Error for ArgumentError {}
+
+// the flag that sets the parameter `key`
+fn flag(key: &str) -> String {
+    format!("--{}", key.replace('_', "-"))
+}
+
+impl ArgumentError {
+    // errors in how the command line is put together, which the usage helps
+    // with, rather than in the values it gives
+    pub fn is_usage(&self) -> bool {
+        !matches!(
+            self,
+            Self::InvalidArgument { .. } | Self::InvalidFlag(..) | Self::ConfigFile { .. }
+        )
+    }
+}
+
+// a rule the parameters break, with the flags that set what it is about
+pub struct BrokenRule {
+    flags: Vec<(String, Value)>,
+    rule: String,
+}
+
+// the rules of `report`, which generating would fail on as well
+pub fn broken_rules<C: Config>(config: &C, report: &Report) -> Vec<BrokenRule> {
+    let fields = config.fields();
+    report
+        .violations
+        .iter()
+        .map(|violation| BrokenRule {
+            flags: violation
+                .parameters()
+                .iter()
+                .filter_map(|parameter| fields.iter().find(|(key, _)| key == parameter))
+                .map(|(key, value)| (flag(key), value.clone()))
+                .collect(),
+            rule: violation.to_string(),
+        })
+        .collect()
+}
+
+impl fmt::Display for BrokenRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (flag, value)) in self.flags.iter().enumerate() {
+            match i {
+                0 => write!(f, "`{}` {}", flag, value)?,
+                _ => write!(f, ", `{}` {}", flag, value)?,
+            }
+        }
+        write!(f, ": {}", self.rule)
+    }
+}
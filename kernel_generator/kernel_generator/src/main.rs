@@ -3,38 +3,78 @@ extern crate template;
 
 mod argument;
 
-use argument::{ArgumentError, GeneratorType};
-use core::{Generate, GenerateError};
+use argument::{ArgumentError, KernelConfig};
+use core::gas::{self, GasError};
+use core::knl::{self, ModelError};
+use core::trace;
+use core::{Assembly, Generate, GenerateError, SpillReport};
 use template::*;
 
+use std::io::Write;
+
 const HELP_TEXT: &'static str = "\
 Usage:
-kernel-generator spmv \
+kernel-generator [--peephole] [--schedule] [--trace=<file>] [--gas | --nasm | --object | --estimate] \
+<spmv | trsv | symgs> [--config <file>] [--<parameter> <value> ...]
+or, to check the parameters without generating
+kernel-generator check <spmv | trsv | symgs> [--config <file>] [--<parameter> <value> ...]
+or, with every parameter of the kernel in order
+kernel-generator [--peephole] [--schedule] [--trace=<file>] [--gas | --nasm | --object | --estimate] spmv \
 <col_pft> <col_pfd> <col_pld> <val_pft> <val_pfd> <val_pld> <x_pld> \
 <rowblock> <nops> <store_to_tmp> <move_reg> <move_base>
 or
-kernel-generator trsv \
+kernel-generator [--peephole] [--schedule] [--trace=<file>] [--gas | --nasm | --object | --estimate] trsv \
 <direction> <static_iter> \
 <col_pft> <col_pfd> <col_pld> <val_pft> <val_pfd> <val_pld> <x_pld> \
 <rowblock> <nops> <store_to_tmp> <move_reg> <move_base>
 or
-kernel-generator symgs \
+kernel-generator [--peephole] [--schedule] [--trace=<file>] [--gas | --nasm | --object | --estimate] symgs \
 <static_iter> \
 <col_pft> <col_pfd> <col_pld> <val_pft> <val_pfd> <val_pld> <x_pld> \
-<spmv_rowblock> <sptrsv_rowblock> <nops_c> <nops_f0> <nops_f1> <nops_f2> <nops_b0> <nops_b1> <nops_b2> \
-<store_to_tmp> <move_reg> <move_base>";
+<spmv_rowblock> <trsv_rowblock> <nops_c> <nops_f0> <nops_f1> <nops_f2> <nops_b0> <nops_b1> <nops_b2> \
+<store_to_tmp> <move_reg> <move_base>
+The options in [] can also come after the kernel, and --help or -h prints this text.";
+
+// --peephole : fold loads, drop dead moves and merge adds, and report the
+//              instructions saved on stderr
+// --schedule : reorder independent instructions to hide latency on KNL
+// --trace=<file> : write every rule firing of the generators to <file>, as a
+//                  Graphviz graph if it ends with .dot and as JSON otherwise,
+//                  and warn on stderr about rules of the same priority that
+//                  can take the same states
+// --gas : print a standalone GNU assembler (.S) file instead of a C++ file
+//         that declares the function and defines it in file-scope assembly
+// --nasm : print a standalone NASM file in Intel syntax instead
+// --object : write a relocatable ELF object to stdout instead
+// --estimate : print the estimated cycles of each loop on KNL instead
+// --help, -h : print the usage and exit
+//
+// the options above go anywhere, before or after the kernel and its
+// parameters, and at most one of --gas, --nasm, --object and --estimate
+
+// check : print the vector registers each stage of the kernel lays out and
+//         every rule the parameters break, exiting with 1 if there is one,
+//         instead of generating
+
+// --config <file> : start from the parameters of <file>, as JSON if it ends
+//                   with .json and as TOML otherwise, instead of the defaults;
+//                   the ones in a table named after the kernel ([spmv],
+//                   [trsv] or [symgs]) go on top of those outside tables
+// --<parameter> <value> : set a parameter of the kernel, named as below with
+//                         `-` for `_` (--col-pft T2, --rowblock=8); the ones
+//                         left out keep their defaults, marked with ** below
 
 // spmv
 //
 // <col_pft> : column prefetch type [T0, T1, **T2**, NTA, None]
-// <col_pfd> : column prefetch distance (integer > 0, **4096**)
-// <col_pld> : column preload distance [0, **1**, 2, ...]
+// <col_pfd> : column prefetch distance (1 to 32703, **4096**)
+// <col_pld> : column preload distance [0, **1**, 2, ..., 3]
 //
 // <val_pft> : value prefetch type [T0, T1, **T2**, NTA, None]
-// <val_pfd> : value prefetch distance (integer > 0, **4096**)
-// <val_pld> : value preload distance [**-1**, 0, 1, 2, ...] (-1 : fused load-add for value data)
+// <val_pfd> : value prefetch distance (1 to 32703, **4096**)
+// <val_pld> : value preload distance [**-1**, 0, 1, 2, ..., 15] (-1 : fused load-add for value data)
 //
-// <x_pld> : xv preload distance [0, 1, **2**, ...]
+// <x_pld> : xv preload distance [0, 1, **2**, ..., 4]
 //
 // <rowblock> : rowblock size [1, 2, 4, **8**]
 // <nops> : # of nops [0, 1, ...]
@@ -44,18 +84,18 @@ kernel-generator symgs \
 
 // trsv
 // 
-// <direction> : forward / backward (f, b)
-// <static_iter> : additional pre/post trsv that iterates constant time for wavefront (0, 1, 2, ...)
+// <direction> : forward / backward (**f**, b)
+// <static_iter> : additional pre/post trsv that iterates constant time for wavefront [**-1**, 0, 1, 2, ...] (-1 : none)
 //
 // <col_pft> : column prefetch type [T0, T1, **T2**, NTA, None]
-// <col_pfd> : column prefetch distance (integer > 0, **4096**)
-// <col_pld> : column preload distance [0, **1**, 2, ...]
+// <col_pfd> : column prefetch distance (1 to 32703, **4096**)
+// <col_pld> : column preload distance [0, **1**, 2, ..., 7]
 //
 // <val_pft> : value prefetch type [T0, T1, **T2**, NTA, None]
-// <val_pfd> : value prefetch distance (integer > 0, **4096**)
-// <val_pld> : value preload distance [**-1**, 0, 1, 2, ...] (-1 : fused load-add for value data)
+// <val_pfd> : value prefetch distance (1 to 32703, **4096**)
+// <val_pld> : value preload distance [**-1**, 0, 1, 2, ..., 31] (-1 : fused load-add for value data)
 //
-// <x_pld> : xv preload distance [0, 1, **2**, ...]
+// <x_pld> : xv preload distance [0, 1, **2**, ..., 8]
 //
 // <rowblock> : rowblock size [1, 2, 4, **8**]
 // <nops> : # of nops [0, 1, ...]
@@ -65,17 +105,17 @@ kernel-generator symgs \
 
 // symgs
 // 
-// <static_iter> : additional pre/post trsv that iterates constant time for wavefront (0, 1, 2, ...)
+// <static_iter> : additional pre/post trsv that iterates constant time for wavefront [**-1**, 0, 1, 2, ...] (-1 : none)
 //
 // <col_pft> : column prefetch type [T0, T1, **T2**, NTA, None]
-// <col_pfd> : column prefetch distance (integer > 0, **4096**)
-// <col_pld> : column preload distance [0, **1**, 2, ...]
+// <col_pfd> : column prefetch distance (1 to 32703, **4096**)
+// <col_pld> : column preload distance [0, **1**, 2, ..., 7]
 //
 // <val_pft> : value prefetch type [T0, T1, **T2**, NTA, None]
-// <val_pfd> : value prefetch distance (integer > 0, **4096**)
-// <val_pld> : value preload distance [**-1**, 0, 1, 2, ...] (-1 : fused load-add for value data)
+// <val_pfd> : value prefetch distance (1 to 32703, **4096**)
+// <val_pld> : value preload distance [**-1**, 0, 1, 2, ..., 31] (-1 : fused load-add for value data)
 //
-// <x_pld> : xv preload distance [0, 1, **2**, ...]
+// <x_pld> : xv preload distance [0, 1, **2**, ..., 8]
 //
 // <spmv_rowblock> : rowblock size for precomputing spmv [1, 2, 4, **8**]
 // <trsv_rowblock> : rowblock size for forward/backward trsv [1, 2, 4, **8**]
@@ -94,44 +134,239 @@ kernel-generator symgs \
 fn main() {
     let args: Vec<String> = std::env::args().collect();
 
-    let generator = argument::parse_arguments(&args[1..]).unwrap_or_else(|err| {
-        match err {
-            ArgumentError::InvalidArgument => {
-                eprintln!("Error: invalid argument");
-                eprintln!("{}", HELP_TEXT);
-            }
-            ArgumentError::NotEnoughArguments => {
-                eprintln!("Error: not enough arguments");
-                eprintln!("{}", HELP_TEXT);
-            }
-            ArgumentError::TooManyArguments => {
-                eprintln!("Error: too many arguments");
-                eprintln!("{}", HELP_TEXT);
-            }
-        }
+    let (options, args) = parse_options(&args[1..]).unwrap_or_else(|err| {
+        eprintln!("Error: {}", err);
+        eprintln!("{}", HELP_TEXT);
         std::process::exit(1);
     });
+    if options.help {
+        println!("{}", HELP_TEXT);
+        return;
+    }
+
+    if args.first().map(|arg| arg.as_str()) == Some("check") {
+        let report = match parse_arguments(&args[1..]) {
+            KernelConfig::Spmv(config) => config.check(),
+            KernelConfig::Sptrsv(config) => config.check(),
+            KernelConfig::Symgs(config) => config.check(),
+        };
+        print!("{}", report);
+        match report.is_ok() {
+            true => return,
+            false => std::process::exit(1),
+        }
+    }
+
+    let Options {
+        passes,
+        format,
+        trace_path,
+        ..
+    } = options;
 
-    let code = match generator {
-        GeneratorType::Spmv(spmv_generator) => get_spmv_code(spmv_generator),
-        GeneratorType::Sptrsv(sptrsv_generator) => get_sptrsv_code(sptrsv_generator),
-        GeneratorType::Symgs(symgs_generator) => get_symgs_code(symgs_generator),
+    let config = parse_arguments(&args);
+
+    // the rules `check` reports would fail generating too, so they are
+    // reported first with the flags that set them
+    let broken_rules = match &config {
+        KernelConfig::Spmv(config) => argument::broken_rules(config, &config.check()),
+        KernelConfig::Sptrsv(config) => argument::broken_rules(config, &config.check()),
+        KernelConfig::Symgs(config) => argument::broken_rules(config, &config.check()),
+    };
+    if !broken_rules.is_empty() {
+        for rule in broken_rules.iter() {
+            eprintln!("Error: {}", rule);
+        }
+        std::process::exit(1);
+    }
+
+    let get_code = || match config {
+        KernelConfig::Spmv(config) => get_spmv_code(config.generator(), format, passes),
+        KernelConfig::Sptrsv(config) => get_sptrsv_code(config.generator(), format, passes),
+        KernelConfig::Symgs(config) => get_symgs_code(config.generator(), format, passes),
+    };
+
+    // the trace is written even when generating fails, as that is when it helps
+    let code = match trace_path.as_deref() {
+        None => get_code(),
+        Some(path) => {
+            let (code, traces) = trace::record(get_code);
+            let text = match path.ends_with(".dot") {
+                true => trace::to_dot(&traces),
+                false => trace::to_json(&traces),
+            };
+            std::fs::write(path, text).unwrap_or_else(|err| {
+                eprintln!("Error: cannot write the trace to {}: {}", path, err);
+                std::process::exit(1);
+            });
+            for overlap in trace::overlaps(&traces) {
+                eprintln!("Warning: {}", overlap);
+            }
+            code
+        }
     };
 
     let code = code.unwrap_or_else(|err| {
         match err {
-            GenerateError::RegisterOverflow => eprintln!("Error: somehow register overflowed."),
-            GenerateError::IllegalUnrollFactor => {
-                eprintln!("Error: somehow used illegal unroll factor.")
-            }
+            CodeError::Generate(err) => eprintln!("Error: {}", err),
+            CodeError::Gas(err) => eprintln!("Error: {}", err),
+            CodeError::Model(err) => eprintln!("Error: {}", err),
         }
         std::process::exit(1);
     });
 
-    println!("{}", code);
+    match format {
+        OutputFormat::Object => std::io::stdout().write_all(&code).unwrap(),
+        _ => println!("{}", String::from_utf8(code).unwrap()),
+    }
+}
+
+struct Options {
+    passes: Passes,
+    format: OutputFormat,
+    trace_path: Option<String>,
+    help: bool,
+}
+
+// takes the options out of the arguments wherever they are, before the kernel
+// or among its parameters, and leaves the rest in order
+fn parse_options(args: &[String]) -> Result<(Options, Vec<String>), ArgumentError> {
+    let mut options = Options {
+        passes: Passes {
+            peephole: false,
+            schedule: false,
+        },
+        format: OutputFormat::Cxx,
+        trace_path: None,
+        help: false,
+    };
+    let mut format_flag: Option<&str> = None;
+    let mut rest = Vec::new();
+    for arg in args.iter() {
+        let format = match arg.as_str() {
+            "--help" | "-h" => {
+                options.help = true;
+                continue;
+            }
+            "--peephole" => {
+                options.passes.peephole = true;
+                continue;
+            }
+            "--schedule" => {
+                options.passes.schedule = true;
+                continue;
+            }
+            arg if arg.starts_with("--trace=") => {
+                options.trace_path = Some(arg["--trace=".len()..].to_string());
+                continue;
+            }
+            "--gas" => OutputFormat::Gas,
+            "--nasm" => OutputFormat::Nasm,
+            "--object" => OutputFormat::Object,
+            "--estimate" => OutputFormat::Estimate,
+            _ => {
+                rest.push(arg.clone());
+                continue;
+            }
+        };
+        if let Some(flag) = format_flag {
+            return Err(ArgumentError::ConflictingOptions(
+                flag.to_string(),
+                arg.clone(),
+            ));
+        }
+        format_flag = Some(arg);
+        options.format = format;
+    }
+
+    Ok((options, rest))
 }
 
-fn get_spmv_code(spmv_generator: spmv::Generator) -> Result<String, GenerateError> {
+#[derive(Clone, Copy)]
+enum OutputFormat {
+    Cxx,
+    Gas,
+    Nasm,
+    Object,
+    Estimate,
+}
+
+#[derive(Clone, Copy)]
+struct Passes {
+    peephole: bool,
+    schedule: bool,
+}
+
+// the peephole report goes to stderr, so it does not mix with the code
+fn run_passes(kernel: (Assembly, SpillReport), passes: Passes) -> Assembly {
+    let (asm, spill_report) = kernel;
+    if spill_report.registers > 0 {
+        eprintln!("spill: {}", spill_report);
+    }
+
+    let asm = match passes.peephole {
+        true => {
+            let (asm, report) = asm.peephole();
+            eprintln!("peephole: {}", report);
+            asm
+        }
+        false => asm,
+    };
+
+    match passes.schedule {
+        true => asm.schedule(),
+        false => asm,
+    }
+}
+
+enum CodeError {
+    Generate(GenerateError),
+    Gas(GasError),
+    Model(ModelError),
+}
+
+impl From<GenerateError> for CodeError {
+    fn from(err: GenerateError) -> Self {
+        CodeError::Generate(err)
+    }
+}
+
+impl From<GasError> for CodeError {
+    fn from(err: GasError) -> Self {
+        CodeError::Gas(err)
+    }
+}
+
+impl From<ModelError> for CodeError {
+    fn from(err: ModelError) -> Self {
+        CodeError::Model(err)
+    }
+}
+
+fn get_estimate(asm: &Assembly) -> Result<Vec<u8>, CodeError> {
+    let report: Vec<String> = knl::estimate(asm)?
+        .iter()
+        .map(|estimate| estimate.to_string())
+        .collect();
+
+    Ok(report.join("\n").into_bytes())
+}
+
+fn parse_arguments(args: &[String]) -> KernelConfig {
+    argument::parse_arguments(args).unwrap_or_else(|err| {
+        eprintln!("Error: {}", err);
+        if err.is_usage() {
+            eprintln!("{}", HELP_TEXT);
+        }
+        std::process::exit(1);
+    })
+}
+
+fn get_spmv_code(
+    spmv_generator: spmv::Generator,
+    format: OutputFormat,
+    passes: Passes,
+) -> Result<Vec<u8>, CodeError> {
     let header_code = "\
     extern \"C\" int spmv(\
     int nrow, \
@@ -139,20 +374,28 @@ fn get_spmv_code(spmv_generator: spmv::Generator) -> Result<String, GenerateErro
     const double* val, \
     const double* x, \
     double* tmp, \
-    double* y) { \n    \
-    int i;\n\n";
-    let tail_code = "\n    return 0;\n}\n";
+    double* y);\n\n";
 
-    let asm = spmv_generator.generate()?;
+    let asm = run_passes(spmv_generator.generate_kernel()?, passes);
 
-    let variable_names = ["nrow", "col", "x", "val", "tmp", "y", "i"];
-    let asm_names = ["NROW", "COL", "X", "VAL", "TMP", "Y", "I"];
-    let main_code = asm.print(1, &variable_names, &asm_names);
+    let function = gas::Function::new("spmv");
+    match format {
+        OutputFormat::Cxx => {}
+        OutputFormat::Gas => return Ok(function.print(&asm)?.into_bytes()),
+        OutputFormat::Nasm => return Ok(function.print_nasm(&asm)?.into_bytes()),
+        OutputFormat::Object => return Ok(function.object(&asm)?),
+        OutputFormat::Estimate => return get_estimate(&asm),
+    }
+    let main_code = function.print_toplevel(&asm)?;
 
-    Ok(format!("{}{}{}", header_code, main_code, tail_code))
+    Ok(format!("{}{}", header_code, main_code).into_bytes())
 }
 
-fn get_sptrsv_code(sptrsv_generator: sptrsv::Generator) -> Result<String, GenerateError> {
+fn get_sptrsv_code(
+    sptrsv_generator: sptrsv::Generator,
+    format: OutputFormat,
+    passes: Passes,
+) -> Result<Vec<u8>, CodeError> {
     let header_code = "\
     extern \"C\" int sptrsv(\
     int nrow, \
@@ -162,25 +405,28 @@ fn get_sptrsv_code(sptrsv_generator: sptrsv::Generator) -> Result<String, Genera
     double* tmp, \
     double* p, \
     const double* d, \
-    const double* r) {\n    \
-    int i;\n    \
-    double* imm_x = x;\n\n";
-    let tail_code = "\n    return 0;\n}\n";
-
-    let asm = sptrsv_generator.generate()?;
-
-    let variable_names = [
-        "nrow", "col", "val", "x", "imm_x", "tmp", "i", "p", "d", "r",
-    ];
-    let asm_names = [
-        "NROW", "COL", "VAL", "X", "IMM_X", "TMP", "I", "P", "D", "R",
-    ];
-    let main_code = asm.print(1, &variable_names, &asm_names);
-
-    Ok(format!("{}{}{}", header_code, main_code, tail_code))
+    const double* r);\n\n";
+
+    let asm = run_passes(sptrsv_generator.generate_kernel()?, passes);
+
+    let function = gas::Function::new("sptrsv");
+    match format {
+        OutputFormat::Cxx => {}
+        OutputFormat::Gas => return Ok(function.print(&asm)?.into_bytes()),
+        OutputFormat::Nasm => return Ok(function.print_nasm(&asm)?.into_bytes()),
+        OutputFormat::Object => return Ok(function.object(&asm)?),
+        OutputFormat::Estimate => return get_estimate(&asm),
+    }
+    let main_code = function.print_toplevel(&asm)?;
+
+    Ok(format!("{}{}", header_code, main_code).into_bytes())
 }
 
-fn get_symgs_code(symgs_generator: symgs::Generator) -> Result<String, GenerateError> {
+fn get_symgs_code(
+    symgs_generator: symgs::Generator,
+    format: OutputFormat,
+    passes: Passes,
+) -> Result<Vec<u8>, CodeError> {
     let header_code = "\
     extern \"C\" int symgs(\
     int nrow, \
@@ -192,24 +438,19 @@ fn get_symgs_code(symgs_generator: symgs::Generator) -> Result<String, GenerateE
     double* tmp, \
     double* p, \
     const double* d, \
-    const double* r) {\n    \
-    int i;\n    \
-    int imm_nrow = nrow;\n    \
-    double* imm_x = x;\n    \
-    double* imm_p = p;\n\n";
-    let tail_code = "\n    return 0;\n}\n";
-
-    let asm = symgs_generator.generate()?;
-
-    let variable_names = [
-        "nrow", "imm_nrow", "ucol", "lcol", "uval", "lval", "x", "imm_x", "tmp", "i", "p", "imm_p",
-        "d", "r",
-    ];
-    let asm_names = [
-        "NROW", "IMM_NROW", "UCOL", "LCOL", "UVAL", "LVAL", "X", "IMM_X", "TMP", "I", "P", "IMM_P",
-        "D", "R",
-    ];
-    let main_code = asm.print(1, &variable_names, &asm_names);
-
-    Ok(format!("{}{}{}", header_code, main_code, tail_code))
+    const double* r);\n\n";
+
+    let asm = run_passes(symgs_generator.generate_kernel()?, passes);
+
+    let function = gas::Function::new("symgs");
+    match format {
+        OutputFormat::Cxx => {}
+        OutputFormat::Gas => return Ok(function.print(&asm)?.into_bytes()),
+        OutputFormat::Nasm => return Ok(function.print_nasm(&asm)?.into_bytes()),
+        OutputFormat::Object => return Ok(function.object(&asm)?),
+        OutputFormat::Estimate => return get_estimate(&asm),
+    }
+    let main_code = function.print_toplevel(&asm)?;
+
+    Ok(format!("{}{}", header_code, main_code).into_bytes())
 }
@@ -0,0 +1,246 @@
+// The command line, with named parameters, configuration files and the
+// positional syntax side by side.
+
+extern crate core;
+
+use std::process::Command;
+
+fn run(args: &[&str]) -> (bool, String, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_kernel_generator"))
+        .args(args)
+        .output()
+        .unwrap();
+    (
+        output.status.success(),
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+fn code(args: &[&str]) -> String {
+    let (success, stdout, stderr) = run(args);
+    assert!(success, "{:?} failed: {}", args, stderr);
+    stdout
+}
+
+fn error(args: &[&str]) -> String {
+    let (success, _, stderr) = run(args);
+    assert!(!success, "{:?} is expected to fail", args);
+    stderr.lines().next().unwrap().to_string()
+}
+
+#[test]
+fn flags_match_the_positional_syntax() {
+    let positional = [
+        "spmv", "T2", "4096", "1", "T2", "4096", "-1", "2", "8", "0", "f", "t", "t",
+    ];
+    assert_eq!(code(&["spmv"]), code(&positional));
+
+    let positional = [
+        "spmv", "NTA", "512", "0", "None", "4096", "0", "1", "1", "2", "t", "t", "f",
+    ];
+    let named = [
+        "spmv",
+        "--col-pft",
+        "NTA",
+        "--col-pfd",
+        "512",
+        "--col-pld=0",
+        "--val-pft",
+        "none",
+        "--val-pld",
+        "0",
+        "--x-pld",
+        "1",
+        "--rowblock=1",
+        "--nops",
+        "2",
+        "--store-to-tmp",
+        "true",
+        "--move-base",
+        "f",
+    ];
+    assert_eq!(code(&named), code(&positional));
+}
+
+#[test]
+fn flags_override_the_config_file() {
+    let dir = std::env::temp_dir().join(format!("kernel_generator_cli_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let toml = dir.join("tuned.toml");
+    let json = dir.join("tuned.json");
+    std::fs::write(&toml, "rowblock = 1\nnops = 2\nmove_base = false\n").unwrap();
+    std::fs::write(&json, "{ \"rowblock\": 1, \"nops\": 2 }").unwrap();
+
+    let expected = code(&[
+        "--gas", "spmv", "T2", "4096", "1", "T2", "4096", "-1", "2", "1", "2", "f", "t", "t",
+    ]);
+    let toml = toml.to_str().unwrap();
+    let json = json.to_str().unwrap();
+    assert_eq!(
+        code(&["--gas", "spmv", "--config", toml, "--move-base", "t"]),
+        expected
+    );
+    assert_eq!(code(&["--gas", "spmv", "--config", json]), expected);
+
+    std::fs::write(toml, "rowblock = 1\nrowblock = 2\n").unwrap();
+    assert_eq!(
+        error(&["spmv", "--rowblock", "8", "--config", toml]),
+        format!("Error: {}: TOML parse error at line 2, column 1", toml)
+    );
+    let (_, _, stderr) = run(&["spmv", "--config", toml]);
+    assert!(!stderr.contains("Usage:"), "{}", stderr);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn errors_name_the_parameter_and_its_range() {
+    assert_eq!(
+        error(&["spmv", "--rowblock", "3"]),
+        "Error: `--rowblock` 3: expected 1, 2, 4 or 8"
+    );
+    assert_eq!(
+        error(&["spmv", "--col-pfd", "40000"]),
+        "Error: `--col-pfd` 40000: expected an integer from 1 to 32703"
+    );
+    assert_eq!(
+        error(&["spmv", "--x-pld=20"]),
+        "Error: `--x-pld` 20: expected an integer from 0 to 4"
+    );
+    assert_eq!(
+        error(&["symgs", "--nops-f1"]),
+        "Error: `--nops-f1` needs a value"
+    );
+    assert_eq!(
+        error(&["trsv", "--rowblocks=8"]),
+        "Error: unknown option `--rowblocks`"
+    );
+    assert_eq!(
+        error(&["trsv", "f", "0", "T3"]),
+        "Error: argument 3, <col_pft> \"T3\": expected one of T0, T1, T2, NTA, None"
+    );
+    assert_eq!(
+        error(&["spmv", "T2", "4096", "1"]),
+        "Error: not enough arguments, <val_pft> is missing"
+    );
+    assert_eq!(
+        error(&["gemv"]),
+        "Error: unknown kernel `gemv`, expected spmv, trsv or symgs"
+    );
+}
+
+// the rules `check` lists are reported before generating, by the flags that
+// make room for them
+#[test]
+fn broken_rules_name_the_flags() {
+    assert_eq!(
+        error(&["spmv", "--rowblock", "4", "--move-reg", "f"]),
+        "Error: `--rowblock` 4, `--move-reg` false: spmv: rowblock 4 is not a multiple of the 12 \
+         nanokernels move_reg f unrolls"
+    );
+
+    let (success, _, stderr) = run(&["spmv", "--col-pld", "3", "--val-pld", "6", "--x-pld", "4"]);
+    assert!(!success);
+    assert_eq!(
+        stderr,
+        "\
+Error: `--col-pld` 3, `--val-pld` 6, `--x-pld` 4, `--rowblock` 8, `--store-to-tmp` false: spmv: 75 \
+registers are more than the 32 registers plus 32 spill slots there are
+Error: `--col-pld` 3: spmv: col blocks reach register 18, past the 16 registers VEX encodes the \
+col indices of gathers in, which are never spilled
+"
+    );
+}
+
+#[test]
+fn check_lists_the_broken_rules_without_generating() {
+    assert_eq!(
+        code(&["check", "spmv"]),
+        "spmv: col 8 + res 8 + x 12 + val 0 = 28 of 32 registers\n"
+    );
+
+    let (success, stdout, _) = run(&["check", "spmv", "--rowblock", "4", "--val-pfd=32703"]);
+    assert!(!success);
+    assert_eq!(
+        stdout,
+        "\
+spmv: col 8 + res 4 + x 12 + val 0 = 24 of 32 registers
+spmv: val_prefetch_dist 32703 is over 32575, the farthest a prefetch reaches
+spmv: rowblock 4 is not supported, expected 1 or 8 to accumulate the results
+"
+    );
+
+    assert_eq!(
+        error(&["check", "symgs", "--spmv-rowblock"]),
+        "Error: `--spmv-rowblock` needs a value"
+    );
+}
+
+#[test]
+fn standalone_output_drops_the_inline_asm_comment_markers() {
+    let nasm = code(&["--nasm", "symgs"]);
+    assert!(nasm.contains("    ; --- precomputing start ---\n"));
+    assert!(nasm.lines().all(|line| !line.contains("//")));
+
+    let gas = code(&["--gas", "symgs"]);
+    assert!(gas.contains("    /* --- precomputing start --- */\n"));
+    assert!(gas.lines().all(|line| !line.contains("//")));
+}
+
+#[test]
+fn cxx_output_defines_the_function_in_assembly() {
+    let cxx = code(&["symgs"]);
+    assert!(cxx.starts_with("extern \"C\" int symgs(int nrow, "));
+    assert!(cxx.contains(");\n\nasm(R\"asm(\n    .pushsection .text\n"));
+    assert!(cxx.trim_end().ends_with("    .popsection\n)asm\");"));
+    // the registers are the ones of the .S file, not the compiler's
+    let gas = code(&["--gas", "symgs"]);
+    let body = |text: &str| {
+        let start = text.find("    .globl").unwrap();
+        let end = text.find("    .size").unwrap();
+        text[start..end].to_string()
+    };
+    assert_eq!(body(&cxx), body(&gas));
+    assert!(!cxx.contains("\"+r\""));
+}
+
+#[test]
+fn options_go_anywhere() {
+    let expected = code(&["--peephole", "--gas", "spmv", "--rowblock", "1"]);
+    assert_eq!(
+        code(&["--gas", "--peephole", "spmv", "--rowblock", "1"]),
+        expected
+    );
+    assert_eq!(
+        code(&["spmv", "--rowblock", "1", "--gas", "--peephole"]),
+        expected
+    );
+
+    assert_eq!(code(&["spmv", "--estimate"]), code(&["--estimate", "spmv"]));
+    assert_eq!(
+        code(&[
+            "trsv", "f", "-1", "T2", "4096", "1", "T2", "4096", "-1", "2", "8", "0", "f", "t", "t",
+            "--nasm"
+        ]),
+        code(&["--nasm", "trsv"])
+    );
+
+    assert_eq!(
+        error(&["--gas", "spmv", "--nasm"]),
+        "Error: `--gas` and `--nasm` cannot be given together"
+    );
+}
+
+#[test]
+fn help_prints_the_usage() {
+    for args in [
+        &["--help"][..],
+        &["-h"],
+        &["spmv", "--help"],
+        &["--gas", "trsv", "-h"],
+    ] {
+        let usage = code(args);
+        assert!(usage.starts_with("Usage:\n"), "{:?}: {}", args, usage);
+    }
+}
@@ -7,26 +7,47 @@ pub enum Action {
     TrsvBackward,
 }
 
+// the rowblock sizes `Generator::new` has code for
+pub(crate) fn supports_rowblock_size(rowblock_size: u8) -> bool {
+    matches!(rowblock_size, 1 | 8)
+}
+
+// the most registers `Generator` holds at once, every rowblock result loaded
+// first when they are in tmp
+pub(crate) fn n_registers(action: &Action, rowblock_size: u8, load_from_tmp: bool) -> u8 {
+    let n_loaded = match load_from_tmp {
+        true => rowblock_size,
+        false => 0,
+    };
+    let n_tmp = match (action, rowblock_size) {
+        (Action::TrsvForward, 1) => 2,
+        _ => 1,
+    };
+
+    n_loaded.max(n_tmp)
+}
+
 impl Generator {
     pub fn new(
-        dst_name: &'static str,
+        dst_name: &str,
 
-        general_reg_name: &'static str,
+        general_reg_name: &str,
 
         res_reg_se: (u8, u8),
         load_from_tmp: bool,
         tmp_offset: u16,
-        tmp_name: &'static str,
+        tmp_name: &str,
 
-        r_name: &'static str,
-        p_name: &'static str,
-        d_name: &'static str,
+        r_name: &str,
+        p_name: &str,
+        d_name: &str,
 
         action: Action,
         rowblock_size: u8,
-        avail_registers: [bool; 32],
+        avail_registers: [bool; N_REGISTERS],
+        avail_masks: [bool; N_MASKS],
         diag_status: DiagonalStatus,
-    ) -> Self {
+    ) -> Result<Self, GenerateError> {
         let tmp_offset = match load_from_tmp {
             true => Some(tmp_offset as i16),
             false => None,
@@ -41,49 +62,65 @@ impl Generator {
         let init_mask = match rowblock_size {
             1 => false,
             8 => true,
-            _ => panic!("not implemented"),
+            _ => {
+                return Err(GenerateError::Unsupported {
+                    parameter: "rowblock_size",
+                    value: rowblock_size.to_string(),
+                })
+            }
         };
 
-        Generator {
-            dst_name,
-            general_reg_name,
+        let mut mask_pool = MaskPool::new(avail_masks);
+        let mix_masks = match init_mask {
+            true => Some((mask_pool.get()?, mask_pool.get()?)),
+            false => None,
+        };
+        let avail_masks = *mask_pool.avail_list();
+
+        Ok(Generator {
+            dst_name: Name::from(dst_name),
+            general_reg_name: Name::from(general_reg_name),
             res_reg_se,
             load_from_tmp,
             tmp_offset,
-            tmp_name,
-            r_name,
-            p_name,
-            d_name,
+            tmp_name: Name::from(tmp_name),
+            r_name: Name::from(r_name),
+            p_name: Name::from(p_name),
+            d_name: Name::from(d_name),
             action,
             rowblock_size,
             avail_registers_except_res,
+            avail_masks,
             diag_status,
             reversed_res,
             init_mask,
-        }
+            mix_masks,
+        })
     }
 }
 
 pub struct Generator {
-    dst_name: &'static str,
+    dst_name: Name,
 
-    general_reg_name: &'static str,
+    general_reg_name: Name,
 
     res_reg_se: (u8, u8),
     load_from_tmp: bool,
     tmp_offset: Option<i16>,
-    tmp_name: &'static str,
+    tmp_name: Name,
 
-    r_name: &'static str,
-    p_name: &'static str,
-    d_name: &'static str,
+    r_name: Name,
+    p_name: Name,
+    d_name: Name,
 
     action: Action,
     rowblock_size: u8,
-    avail_registers_except_res: [bool; 32],
+    avail_registers_except_res: [bool; N_REGISTERS],
+    avail_masks: [bool; N_MASKS],
     diag_status: DiagonalStatus,
     reversed_res: bool,
     init_mask: bool,
+    mix_masks: Option<(Mask, Mask)>,
 }
 
 enum StateType {
@@ -126,53 +163,62 @@ enum StateType {
  */
 const RULEBOOK: &'static [Rule<Generator>] = &[
     Rule {
+        name: "InitializingMask",
         condition: Condition::Single {
             id: StateType::InitializingMask as u32,
         },
         callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let asm = Assembly::new().init_mix2mask(config.general_reg_name, 1, 2);
+            let (k0, k1) = config
+                .mix_masks
+                .ok_or(GenerateError::MissingParameter("mix_masks"))?;
+            let asm = Assembly::new().init_mix2mask(&config.general_reg_name, k0, k1);
             let states = Vec::new();
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "Loading",
         condition: Condition::Single {
             id: StateType::Loading as u32,
         },
         callback: |config: &Generator, rp: &mut RegisterPool, states: &Vec<State>| {
-            let idx = states[0].idx;
-            let reg = rp.get();
+            let idx = states[0].idx as u8;
+            let tmp_offset = config
+                .tmp_offset
+                .ok_or(GenerateError::MissingParameter("tmp_offset"))?;
+            let reg = rp.get()?;
 
             let base = {
                 let i = match config.reversed_res {
                     false => idx,
                     true => config.rowblock_size - idx - 1,
                 };
-                i as i16 * config.tmp_offset.unwrap()
+                i as i16 * tmp_offset
             };
-            let asm = Assembly::new().load_f64x8(reg, config.tmp_name, base);
+            let asm = Assembly::new().load_f64x8(reg, &config.tmp_name, base);
             let next_id = StateType::Lv0F64x8 as u32;
             let states = Vec::from([State {
                 id: next_id,
-                idx,
-                reg,
+                idx: idx.into(),
+                reg: Some(reg),
             }]);
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "Lv0F64x8 + Lv0F64x8 at +4",
         condition: Condition::SameId {
             id: StateType::Lv0F64x8 as u32,
             n_states: 2,
             idx_dist: 4,
         },
         callback: |_config: &Generator, rp: &mut RegisterPool, states: &Vec<State>| {
-            let idx = states[0].idx;
-            let reg0 = states[0].reg;
-            let reg1 = states[1].reg;
-            let reg2 = rp.get();
+            let idx = states[0].idx as u8;
+            let reg0 = states[0].register()?;
+            let reg1 = states[1].register()?;
+            let reg2 = rp.get()?;
             rp.free(reg0);
             rp.free(reg1);
 
@@ -180,14 +226,15 @@ const RULEBOOK: &'static [Rule<Generator>] = &[
             let next_id = StateType::Lv1F64x8 as u32;
             let states = Vec::from([State {
                 id: next_id,
-                idx,
-                reg: reg2,
+                idx: idx.into(),
+                reg: Some(reg2),
             }]);
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "Lv0F64x8 + Lv0F64x8 at +1",
         condition: Condition::SameId {
             id: StateType::Lv0F64x8 as u32,
             n_states: 2,
@@ -195,20 +242,25 @@ const RULEBOOK: &'static [Rule<Generator>] = &[
         },
         callback: |_config: &Generator, rp: &mut RegisterPool, states: &Vec<State>| {
             let id = StateType::Lv1F64x8 as u32;
-            let idx = states[0].idx;
-            let reg0 = states[0].reg;
-            let reg1 = states[1].reg;
-            let reg2 = rp.get();
+            let idx = states[0].idx as u8;
+            let reg0 = states[0].register()?;
+            let reg1 = states[1].register()?;
+            let reg2 = rp.get()?;
             rp.free(reg0);
             rp.free(reg1);
 
             let asm = Assembly::new().mix4add_f64x8(reg2, reg0, reg1);
-            let states = Vec::from([State { id, idx, reg: reg2 }]);
+            let states = Vec::from([State {
+                id,
+                idx: idx.into(),
+                reg: Some(reg2),
+            }]);
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "Lv0F64x8 + Lv0F64x8 at +2",
         condition: Condition::SameId {
             id: StateType::Lv0F64x8 as u32,
             n_states: 2,
@@ -216,26 +268,31 @@ const RULEBOOK: &'static [Rule<Generator>] = &[
         },
         callback: |_config: &Generator, rp: &mut RegisterPool, states: &Vec<State>| {
             let id = StateType::Lv1F64x8 as u32;
-            let idx = states[0].idx;
-            let reg0 = states[0].reg;
-            let reg1 = states[1].reg;
-            let reg2 = rp.get();
+            let idx = states[0].idx as u8;
+            let reg0 = states[0].register()?;
+            let reg1 = states[1].register()?;
+            let reg2 = rp.get()?;
             rp.free(reg0);
             rp.free(reg1);
 
             let asm = Assembly::new().mix4add_f64x8(reg2, reg0, reg1);
-            let states = Vec::from([State { id, idx, reg: reg2 }]);
+            let states = Vec::from([State {
+                id,
+                idx: idx.into(),
+                reg: Some(reg2),
+            }]);
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "Lv0F64x8",
         condition: Condition::Single {
             id: StateType::Lv0F64x8 as u32,
         },
         callback: |_config: &Generator, rp: &mut RegisterPool, states: &Vec<State>| {
-            let zmm_src = states[0].reg;
-            let ymm_dst = rp.get();
+            let zmm_src = states[0].register()?;
+            let ymm_dst = rp.get()?.ymm();
             rp.free(zmm_src);
 
             let asm = Assembly::new().fold4add_f64x8(ymm_dst, zmm_src);
@@ -243,99 +300,117 @@ const RULEBOOK: &'static [Rule<Generator>] = &[
             let states = Vec::from([State {
                 id: next_id,
                 idx: 0,
-                reg: ymm_dst,
+                reg: Some(ymm_dst.zmm()),
             }]);
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "Lv1F64x8 + Lv1F64x8 at +2",
         condition: Condition::SameId {
             id: StateType::Lv1F64x8 as u32,
             n_states: 2,
             idx_dist: 2,
         },
-        callback: |_config: &Generator, rp: &mut RegisterPool, states: &Vec<State>| {
-            let idx = states[0].idx;
-            let reg0 = states[0].reg;
-            let reg1 = states[1].reg;
-            let reg2 = rp.get();
+        callback: |config: &Generator, rp: &mut RegisterPool, states: &Vec<State>| {
+            let idx = states[0].idx as u8;
+            let reg0 = states[0].register()?;
+            let reg1 = states[1].register()?;
+            let reg2 = rp.get()?;
+            let (k0, k1) = config
+                .mix_masks
+                .ok_or(GenerateError::MissingParameter("mix_masks"))?;
             rp.free(reg0);
             rp.free(reg1);
 
-            let asm = Assembly::new().mix2add_f64x8(reg2, reg0, reg1, 1, 2);
+            let asm = Assembly::new().mix2add_f64x8(reg2, reg0, reg1, k0, k1);
             let next_id = StateType::Lv2F64x8 as u32;
             let states = Vec::from([State {
                 id: next_id,
-                idx,
-                reg: reg2,
+                idx: idx.into(),
+                reg: Some(reg2),
             }]);
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "Lv1F64x8 + Lv1F64x8 at +1",
         condition: Condition::SameId {
             id: StateType::Lv1F64x8 as u32,
             n_states: 2,
             idx_dist: 1,
         },
-        callback: |_config: &Generator, rp: &mut RegisterPool, states: &Vec<State>| {
+        callback: |config: &Generator, rp: &mut RegisterPool, states: &Vec<State>| {
             let id = StateType::Lv2F64x8 as u32;
-            let idx = states[0].idx;
-            let reg0 = states[0].reg;
-            let reg1 = states[1].reg;
-            let reg2 = rp.get();
+            let idx = states[0].idx as u8;
+            let reg0 = states[0].register()?;
+            let reg1 = states[1].register()?;
+            let reg2 = rp.get()?;
+            let (k0, k1) = config
+                .mix_masks
+                .ok_or(GenerateError::MissingParameter("mix_masks"))?;
             rp.free(reg0);
             rp.free(reg1);
 
-            let asm = Assembly::new().mix2add_f64x8(reg2, reg0, reg1, 1, 2);
-            let states = Vec::from([State { id, idx, reg: reg2 }]);
+            let asm = Assembly::new().mix2add_f64x8(reg2, reg0, reg1, k0, k1);
+            let states = Vec::from([State {
+                id,
+                idx: idx.into(),
+                reg: Some(reg2),
+            }]);
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "Lv1F64x8",
         condition: Condition::Single {
             id: StateType::Lv1F64x8 as u32,
         },
-        callback: |_config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
+        callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
             let _next_id = StateType::Lv2F64x4 as u32;
 
-            panic!("not implemented");
+            Err(GenerateError::Unsupported {
+                parameter: "rowblock_size",
+                value: config.rowblock_size.to_string(),
+            })
         },
     },
     Rule {
+        name: "Lv1F64x4",
         condition: Condition::Single {
             id: StateType::Lv1F64x4 as u32,
         },
         callback: |_config: &Generator, rp: &mut RegisterPool, states: &Vec<State>| {
-            let ymm_src = states[0].reg;
-            let xmm_dst = rp.get();
-            rp.free(ymm_src);
+            let ymm_src = states[0].register()?.ymm();
+            let xmm_dst = rp.get()?.xmm();
+            rp.free(ymm_src.zmm());
 
             let asm = Assembly::new().fold2add_f64x4(xmm_dst, ymm_src);
             let next_id = StateType::Lv2F64x2 as u32;
             let states = Vec::from([State {
                 id: next_id,
                 idx: 0,
-                reg: xmm_dst,
+                reg: Some(xmm_dst.zmm()),
             }]);
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "Lv2F64x8 + Lv2F64x8 at +1",
         condition: Condition::SameId {
             id: StateType::Lv2F64x8 as u32,
             n_states: 2,
             idx_dist: 1,
         },
         callback: |_config: &Generator, rp: &mut RegisterPool, states: &Vec<State>| {
-            let idx = states[0].idx;
-            let reg0 = states[0].reg;
-            let reg1 = states[1].reg;
-            let reg2 = rp.get();
+            let idx = states[0].idx as u8;
+            let reg0 = states[0].register()?;
+            let reg1 = states[1].register()?;
+            let reg2 = rp.get()?;
             rp.free(reg0);
             rp.free(reg1);
 
@@ -343,91 +418,106 @@ const RULEBOOK: &'static [Rule<Generator>] = &[
             let next_id = StateType::Lv3F64x8 as u32;
             let states = Vec::from([State {
                 id: next_id,
-                idx,
-                reg: reg2,
+                idx: idx.into(),
+                reg: Some(reg2),
             }]);
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "Lv2F64x8",
         condition: Condition::Single {
             id: StateType::Lv2F64x8 as u32,
         },
-        callback: |_config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
+        callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
             let _next_id = StateType::Lv3F64x4 as u32;
 
-            panic!("not implemented");
+            Err(GenerateError::Unsupported {
+                parameter: "rowblock_size",
+                value: config.rowblock_size.to_string(),
+            })
         },
     },
     Rule {
+        name: "Lv2F64x4",
         condition: Condition::Single {
             id: StateType::Lv2F64x4 as u32,
         },
-        callback: |_config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
+        callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
             let _next_id = StateType::Lv3F64x2 as u32;
 
-            panic!("not implemented");
+            Err(GenerateError::Unsupported {
+                parameter: "rowblock_size",
+                value: config.rowblock_size.to_string(),
+            })
         },
     },
     Rule {
+        name: "Lv2F64x2",
         condition: Condition::Single {
             id: StateType::Lv2F64x2 as u32,
         },
         callback: |_config: &Generator, _rp: &mut RegisterPool, states: &Vec<State>| {
-            let xmm = states[0].reg;
+            let xmm = states[0].register()?.xmm();
 
             let asm = Assembly::new().fold1add_f64x2(xmm, xmm);
             let next_id = StateType::Lv3F64x1 as u32;
             let states = Vec::from([State {
                 id: next_id,
                 idx: 0,
-                reg: xmm,
+                reg: Some(xmm.zmm()),
             }]);
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "Lv3F64x8",
         condition: Condition::Single {
             id: StateType::Lv3F64x8 as u32,
         },
         callback: |config: &Generator, rp: &mut RegisterPool, states: &Vec<State>| {
-            let zmm_res = states[0].reg;
+            let zmm_res = states[0].register()?;
 
             let asm = match config.action {
-                Action::Move => Assembly::new().store_f64x8(config.dst_name, 0x00, zmm_res),
+                Action::Move => Assembly::new().store_f64x8(&config.dst_name, 0x00, zmm_res),
                 Action::TrsvForward => {
-                    let zmm_tmp = rp.get();
-                    let zmm_d = rp.get();
+                    let zmm_tmp = rp.get()?;
+                    let zmm_d = rp.get()?;
                     rp.free(zmm_tmp);
                     rp.free(zmm_d);
 
                     let diag_reciprocal = match config.diag_status {
-                        DiagonalStatus::Default => panic!("not implemented"),
+                        DiagonalStatus::Default => {
+                            return Err(GenerateError::Unsupported {
+                                parameter: "diag_status",
+                                value: String::from("Default"),
+                            })
+                        }
                         DiagonalStatus::Excluded => false,
                         DiagonalStatus::ExcludedReciprocal => true,
                     };
 
                     let asm_load_d = match diag_reciprocal {
-                        true => Assembly::new().load_f64x8(zmm_d, config.d_name, 0x0),
+                        true => Assembly::new().load_f64x8(zmm_d, &config.d_name, 0x0),
                         false => Assembly::new(),
                     };
                     let asm_add = Assembly::new()
-                        .loadadd_f64x8(zmm_res, zmm_res, config.r_name, 0x0)
+                        .loadadd_f64x8(zmm_res, zmm_res, &config.r_name, 0x0)
                         .move_f64x8(zmm_tmp, zmm_res)
-                        .loadadd_f64x8(zmm_res, zmm_res, config.p_name, 0x0);
+                        .loadadd_f64x8(zmm_res, zmm_res, &config.p_name, 0x0);
                     let asm_diag = match diag_reciprocal {
                         true => {
-                            Assembly::new().loadmuladd_f64x8(zmm_res, zmm_d, config.dst_name, 0x0)
+                            Assembly::new().loadmuladd_f64x8(zmm_res, zmm_d, &config.dst_name, 0x0)
                         }
                         false => Assembly::new()
-                            .loaddiv_f64x8(zmm_res, zmm_res, config.d_name, 0x0)
-                            .loadadd_f64x8(zmm_res, zmm_res, config.dst_name, 0x0),
+                            .loaddiv_f64x8(zmm_res, zmm_res, &config.d_name, 0x0)
+                            .loadadd_f64x8(zmm_res, zmm_res, &config.dst_name, 0x0),
                     };
                     let asm_store = Assembly::new()
-                        .store_f64x8(config.dst_name, 0x0, zmm_res)
-                        .store_f64x8(config.p_name, 0x0, zmm_tmp);
+                        .store_f64x8(&config.dst_name, 0x0, zmm_res)
+                        .store_f64x8(&config.p_name, 0x0, zmm_tmp);
 
                     asm_load_d
                         .append(asm_add)
@@ -436,20 +526,27 @@ const RULEBOOK: &'static [Rule<Generator>] = &[
                 }
                 Action::TrsvBackward => {
                     let diag_reciprocal = match config.diag_status {
-                        DiagonalStatus::Default => panic!("not implemented"),
+                        DiagonalStatus::Default => {
+                            return Err(GenerateError::Unsupported {
+                                parameter: "diag_status",
+                                value: String::from("Default"),
+                            })
+                        }
                         DiagonalStatus::Excluded => false,
                         DiagonalStatus::ExcludedReciprocal => true,
                     };
 
                     let asm_add =
-                        Assembly::new().loadadd_f64x8(zmm_res, zmm_res, config.p_name, 0x0);
+                        Assembly::new().loadadd_f64x8(zmm_res, zmm_res, &config.p_name, 0x0);
                     let asm_diag = match diag_reciprocal {
-                        true => Assembly::new().loadmul_f64x8(zmm_res, zmm_res, config.d_name, 0x0),
+                        true => {
+                            Assembly::new().loadmul_f64x8(zmm_res, zmm_res, &config.d_name, 0x0)
+                        }
                         false => {
-                            Assembly::new().loaddiv_f64x8(zmm_res, zmm_res, config.d_name, 0x0)
+                            Assembly::new().loaddiv_f64x8(zmm_res, zmm_res, &config.d_name, 0x0)
                         }
                     };
-                    let asm_store = Assembly::new().store_f64x8(config.dst_name, 0x0, zmm_res);
+                    let asm_store = Assembly::new().store_f64x8(&config.dst_name, 0x0, zmm_res);
 
                     asm_add.append(asm_diag).append(asm_store)
                 }
@@ -461,68 +558,82 @@ const RULEBOOK: &'static [Rule<Generator>] = &[
             let states = Vec::from([State {
                 id: next_id,
                 idx: 0,
-                reg: 0,
+                reg: None,
             }]);
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "Lv3F64x4",
         condition: Condition::Single {
             id: StateType::Lv3F64x4 as u32,
         },
-        callback: |_config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            panic!("not implemented");
+        callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
+            Err(GenerateError::Unsupported {
+                parameter: "rowblock_size",
+                value: config.rowblock_size.to_string(),
+            })
         },
     },
     Rule {
+        name: "Lv3F64x2",
         condition: Condition::Single {
             id: StateType::Lv3F64x2 as u32,
         },
-        callback: |_config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            panic!("not implemented");
+        callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
+            Err(GenerateError::Unsupported {
+                parameter: "rowblock_size",
+                value: config.rowblock_size.to_string(),
+            })
         },
     },
     Rule {
+        name: "Lv3F64x1",
         condition: Condition::Single {
             id: StateType::Lv3F64x1 as u32,
         },
         callback: |config: &Generator, rp: &mut RegisterPool, states: &Vec<State>| {
-            let xmm_res = states[0].reg;
+            let xmm_res = states[0].register()?.xmm();
 
             let asm = match config.action {
-                Action::Move => Assembly::new().store_f64x1(config.dst_name, 0x0, xmm_res),
+                Action::Move => Assembly::new().store_f64x1(&config.dst_name, 0x0, xmm_res),
                 Action::TrsvForward => {
-                    let xmm_tmp = rp.get();
-                    let xmm_d = rp.get();
-                    rp.free(xmm_tmp);
-                    rp.free(xmm_d);
+                    let xmm_tmp = rp.get()?.xmm();
+                    let xmm_d = rp.get()?.xmm();
+                    rp.free(xmm_tmp.zmm());
+                    rp.free(xmm_d.zmm());
 
                     let diag_reciprocal = match config.diag_status {
-                        DiagonalStatus::Default => panic!("not implemented"),
+                        DiagonalStatus::Default => {
+                            return Err(GenerateError::Unsupported {
+                                parameter: "diag_status",
+                                value: String::from("Default"),
+                            })
+                        }
                         DiagonalStatus::Excluded => false,
                         DiagonalStatus::ExcludedReciprocal => true,
                     };
 
                     let asm_load_d = match diag_reciprocal {
-                        true => Assembly::new().load_f64x1(xmm_d, config.d_name, 0x0),
+                        true => Assembly::new().load_f64x1(xmm_d, &config.d_name, 0x0),
                         false => Assembly::new(),
                     };
                     let asm_add = Assembly::new()
-                        .loadadd_f64x1(xmm_res, xmm_res, config.r_name, 0x0)
+                        .loadadd_f64x1(xmm_res, xmm_res, &config.r_name, 0x0)
                         .move_f64x2(xmm_tmp, xmm_res)
-                        .loadadd_f64x1(xmm_res, xmm_res, config.p_name, 0x0);
+                        .loadadd_f64x1(xmm_res, xmm_res, &config.p_name, 0x0);
                     let asm_diag = match diag_reciprocal {
                         true => {
-                            Assembly::new().loadmuladd_f64x1(xmm_res, xmm_d, config.dst_name, 0x0)
+                            Assembly::new().loadmuladd_f64x1(xmm_res, xmm_d, &config.dst_name, 0x0)
                         }
                         false => Assembly::new()
-                            .loaddiv_f64x1(xmm_res, xmm_res, config.d_name, 0x0)
-                            .loadadd_f64x1(xmm_res, xmm_res, config.dst_name, 0x0),
+                            .loaddiv_f64x1(xmm_res, xmm_res, &config.d_name, 0x0)
+                            .loadadd_f64x1(xmm_res, xmm_res, &config.dst_name, 0x0),
                     };
                     let asm_store = Assembly::new()
-                        .store_f64x1(config.dst_name, 0x0, xmm_res)
-                        .store_f64x1(config.p_name, 0x0, xmm_tmp);
+                        .store_f64x1(&config.dst_name, 0x0, xmm_res)
+                        .store_f64x1(&config.p_name, 0x0, xmm_tmp);
 
                     asm_load_d
                         .append(asm_add)
@@ -531,43 +642,51 @@ const RULEBOOK: &'static [Rule<Generator>] = &[
                 }
                 Action::TrsvBackward => {
                     let diag_reciprocal = match config.diag_status {
-                        DiagonalStatus::Default => panic!("not implemented"),
+                        DiagonalStatus::Default => {
+                            return Err(GenerateError::Unsupported {
+                                parameter: "diag_status",
+                                value: String::from("Default"),
+                            })
+                        }
                         DiagonalStatus::Excluded => false,
                         DiagonalStatus::ExcludedReciprocal => true,
                     };
 
                     let asm_add =
-                        Assembly::new().loadadd_f64x1(xmm_res, xmm_res, config.p_name, 0x0);
+                        Assembly::new().loadadd_f64x1(xmm_res, xmm_res, &config.p_name, 0x0);
                     let asm_diag = match diag_reciprocal {
-                        true => Assembly::new().loadmul_f64x1(xmm_res, xmm_res, config.d_name, 0x0),
+                        true => {
+                            Assembly::new().loadmul_f64x1(xmm_res, xmm_res, &config.d_name, 0x0)
+                        }
                         false => {
-                            Assembly::new().loaddiv_f64x1(xmm_res, xmm_res, config.d_name, 0x0)
+                            Assembly::new().loaddiv_f64x1(xmm_res, xmm_res, &config.d_name, 0x0)
                         }
                     };
-                    let asm_store = Assembly::new().store_f64x1(config.dst_name, 0x0, xmm_res);
+                    let asm_store = Assembly::new().store_f64x1(&config.dst_name, 0x0, xmm_res);
 
                     asm_add.append(asm_diag).append(asm_store)
                 }
             };
-            rp.free(xmm_res);
+            rp.free(xmm_res.zmm());
 
             let next_id = StateType::Finalizing as u32;
             let states = Vec::from([State {
                 id: next_id,
                 idx: 0,
-                reg: 0,
+                reg: None,
             }]);
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "Finalizing",
         condition: Condition::Single {
             id: StateType::Finalizing as u32,
         },
         callback: |config: &Generator, rp: &mut RegisterPool, _states: &Vec<State>| {
             for i in config.res_reg_se.0..config.res_reg_se.1 {
-                rp.alloc(i);
+                rp.alloc(Zmm::new(i))?;
             }
 
             let asm = Assembly::new();
@@ -583,10 +702,14 @@ impl Generate for Generator {
         RULEBOOK
     }
 
-    fn avail_registers(&self) -> [bool; 32] {
+    fn avail_registers(&self) -> [bool; N_REGISTERS] {
         self.avail_registers_except_res.clone()
     }
 
+    fn avail_masks(&self) -> [bool; N_MASKS] {
+        self.avail_masks
+    }
+
     fn initial_states(&self) -> Vec<State> {
         let mut states = Vec::new();
 
@@ -594,15 +717,15 @@ impl Generate for Generator {
             states.push(State {
                 id: StateType::InitializingMask as u32,
                 idx: 0,
-                reg: 0,
+                reg: None,
             });
         }
 
         for i in 0..self.rowblock_size {
             let (id, reg) = match (self.load_from_tmp, i < self.rowblock_size - 1) {
-                (false, _) => (StateType::Lv0F64x8, self.res_reg_se.0 + i),
-                (true, true) => (StateType::Loading, 0),
-                (true, false) => (StateType::Lv0F64x8, self.res_reg_se.1 - 1),
+                (false, _) => (StateType::Lv0F64x8, Some(Zmm::new(self.res_reg_se.0 + i))),
+                (true, true) => (StateType::Loading, None),
+                (true, false) => (StateType::Lv0F64x8, Some(Zmm::new(self.res_reg_se.1 - 1))),
             };
             let idx = match self.reversed_res {
                 false => i,
@@ -610,7 +733,7 @@ impl Generate for Generator {
             };
             states.push(State {
                 id: id as u32,
-                idx,
+                idx: idx.into(),
                 reg,
             });
         }
@@ -0,0 +1,429 @@
+// Configurations checked against the rules of the generators, without
+// generating code.
+//
+// Each stage of a kernel lays its vector registers out in blocks for col,
+// the rowblock results, x and val. A `Report` has the register budget of
+// every stage and each rule the configuration breaks, so a configuration is
+// fixed in one go rather than one generator error at a time. The stages
+// check themselves with the functions their rules use, which keeps the two
+// in step.
+//
+// Spilling reaches every register but the col indices of gathers, which VEX
+// encodes in the first 16 only. The col blocks and the x blocks preloaded at
+// once share those, so a `ColIndexOverflow` or a `GatherIndexOverflow` is a
+// limit of the generators that no other parameter spills around.
+
+use crate::{accumulate, microkernel};
+use core::N_REGISTERS;
+
+use std::fmt;
+
+const N_PHYSICAL_REGISTERS: usize = 32;
+
+// the vector registers of a stage
+pub struct Budget {
+    pub stage: &'static str,
+    pub col: u8,
+    pub res: u8,
+    pub x: u8,
+    pub val: u8,
+    // physical registers left free to reload spilled ones
+    pub scratch: u8,
+}
+
+impl Budget {
+    pub fn total(&self) -> usize {
+        [self.col, self.res, self.x, self.val, self.scratch]
+            .iter()
+            .map(|&n| n as usize)
+            .sum()
+    }
+}
+
+impl fmt::Display for Budget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: col {} + res {} + x {} + val {}",
+            self.stage, self.col, self.res, self.x, self.val
+        )?;
+        if self.scratch > 0 {
+            write!(f, " + scratch {}", self.scratch)?;
+        }
+        match self.total() > N_PHYSICAL_REGISTERS {
+            true => write!(
+                f,
+                " = {} registers, {} spilled",
+                self.total(),
+                self.total() - N_PHYSICAL_REGISTERS
+            ),
+            false => write!(
+                f,
+                " = {} of {} registers",
+                self.total(),
+                N_PHYSICAL_REGISTERS
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Violation {
+    // more registers than there are, spilled ones included
+    RegisterOverflow {
+        stage: &'static str,
+        need: usize,
+    },
+    // more registers than the physical ones in a stage that does not spill
+    NoSpill {
+        stage: &'static str,
+        need: usize,
+    },
+    // `move_reg f` unrolls nanokernels until every block is back in place
+    IllegalUnrollFactor {
+        stage: &'static str,
+        rowblock_size: u8,
+        n_kernels_unrolled: u8,
+    },
+    // the rowblock results and temporaries of accumulating, in a stage that
+    // does not spill
+    AccumulateOverflow {
+        stage: &'static str,
+        need: u8,
+        free: usize,
+    },
+    // gathers take their col indices in the ymm registers VEX encodes
+    ColIndexOverflow {
+        stage: &'static str,
+        col_reg_e: u8,
+    },
+    // the col indices of the x blocks a stage preloads, a VEX register each
+    GatherIndexOverflow {
+        stage: &'static str,
+        x_blocks: u8,
+        registers: usize,
+    },
+    Unsupported {
+        stage: &'static str,
+        parameter: &'static str,
+        value: String,
+        expected: &'static str,
+    },
+    // a prefetch distance of 0, which prefetches the block being loaded, or
+    // one past the reach of the 16-bit displacement it is encoded with,
+    // counting the offset of the last block of a row
+    PrefetchOutOfRange {
+        stage: &'static str,
+        parameter: &'static str,
+        dist: u16,
+        max: u16,
+    },
+}
+
+// the rowblock parameter of a stage, as the command line and files name it
+fn rowblock_parameter(stage: &'static str) -> &'static str {
+    match stage {
+        "spmv" | "trsv" => "rowblock",
+        "precomputing" => "spmv_rowblock",
+        _ => "trsv_rowblock",
+    }
+}
+
+impl Violation {
+    // the parameters that make room for the rule if they are changed, as
+    // the command line and files name them
+    pub fn parameters(&self) -> Vec<&'static str> {
+        match self {
+            Self::RegisterOverflow { stage, .. } | Self::NoSpill { stage, .. } => Vec::from([
+                "col_pld",
+                "val_pld",
+                "x_pld",
+                rowblock_parameter(stage),
+                "store_to_tmp",
+            ]),
+            Self::IllegalUnrollFactor { stage, .. } => {
+                Vec::from([rowblock_parameter(stage), "move_reg"])
+            }
+            Self::AccumulateOverflow { stage, .. } => {
+                Vec::from([rowblock_parameter(stage), "store_to_tmp"])
+            }
+            Self::ColIndexOverflow { .. } => Vec::from(["col_pld"]),
+            Self::GatherIndexOverflow { .. } => Vec::from(["x_pld"]),
+            Self::Unsupported {
+                stage, parameter, ..
+            } => match *parameter {
+                "rowblock" => Vec::from([rowblock_parameter(stage)]),
+                parameter => Vec::from([parameter]),
+            },
+            Self::PrefetchOutOfRange { parameter, .. } => match *parameter {
+                "col_prefetch_dist" => Vec::from(["col_pfd"]),
+                _ => Vec::from(["val_pfd"]),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RegisterOverflow { stage, need } => write!(
+                f,
+                "{}: {} registers are more than the {} registers plus {} spill slots there are",
+                stage,
+                need,
+                N_PHYSICAL_REGISTERS,
+                N_REGISTERS - N_PHYSICAL_REGISTERS
+            ),
+            Self::NoSpill { stage, need } => write!(
+                f,
+                "{}: {} registers do not fit the {} physical ones, and only spmv and trsv \
+                 with store_to_tmp f spill the rest",
+                stage, need, N_PHYSICAL_REGISTERS
+            ),
+            Self::IllegalUnrollFactor {
+                stage,
+                rowblock_size,
+                n_kernels_unrolled,
+            } => write!(
+                f,
+                "{}: rowblock {} is not a multiple of the {} nanokernels move_reg f unrolls",
+                stage, rowblock_size, n_kernels_unrolled
+            ),
+            Self::AccumulateOverflow { stage, need, free } => write!(
+                f,
+                "{}: accumulating a rowblock takes {} registers at once, but only {} are free \
+                 without spilling",
+                stage, need, free
+            ),
+            Self::ColIndexOverflow { stage, col_reg_e } => write!(
+                f,
+                "{}: col blocks reach register {}, past the {} registers VEX encodes the col \
+                 indices of gathers in, which are never spilled",
+                stage,
+                col_reg_e - 1,
+                microkernel::N_VEX_REGISTERS
+            ),
+            Self::GatherIndexOverflow {
+                stage,
+                x_blocks,
+                registers,
+            } => write!(
+                f,
+                "{}: preloading x gathers {} blocks at once, but only {} of the {} registers VEX \
+                 encodes are free for their col indices, which are never spilled",
+                stage,
+                x_blocks,
+                registers,
+                microkernel::N_VEX_REGISTERS
+            ),
+            Self::Unsupported {
+                stage,
+                parameter,
+                value,
+                expected,
+            } => write!(
+                f,
+                "{}: {} {} is not supported, expected {}",
+                stage, parameter, value, expected
+            ),
+            Self::PrefetchOutOfRange {
+                stage,
+                parameter,
+                dist: 0,
+                max,
+            } => write!(
+                f,
+                "{}: {} 0 prefetches the block being loaded, expected 1 to {}",
+                stage, parameter, max
+            ),
+            Self::PrefetchOutOfRange {
+                stage,
+                parameter,
+                dist,
+                max,
+            } => write!(
+                f,
+                "{}: {} {} is over {}, the farthest a prefetch reaches",
+                stage, parameter, dist, max
+            ),
+        }
+    }
+}
+
+pub struct Report {
+    pub budgets: Vec<Budget>,
+    pub violations: Vec<Violation>,
+}
+
+impl Report {
+    pub(crate) fn new() -> Self {
+        Report {
+            budgets: Vec::new(),
+            violations: Vec::new(),
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    // the registers of a stage, which fit if they are spilled or need not be
+    pub(crate) fn registers(&mut self, budget: Budget, can_spill: bool) -> () {
+        let (stage, need) = (budget.stage, budget.total());
+        if need > N_REGISTERS {
+            self.violations
+                .push(Violation::RegisterOverflow { stage, need });
+        } else if need > N_PHYSICAL_REGISTERS && !can_spill {
+            self.violations.push(Violation::NoSpill { stage, need });
+        }
+        self.budgets.push(budget);
+    }
+
+    pub(crate) fn unroll(
+        &mut self,
+        stage: &'static str,
+        rowblock_size: u8,
+        n_kernels_unrolled: u8,
+    ) -> () {
+        if !rowblock_size.is_multiple_of(n_kernels_unrolled) {
+            self.violations.push(Violation::IllegalUnrollFactor {
+                stage,
+                rowblock_size,
+                n_kernels_unrolled,
+            });
+        }
+    }
+
+    // rows are counted in rowblocks with a shift when their number is only
+    // known at runtime, and one by one for a static iteration count; the
+    // rowblock results are accumulated at the end
+    pub(crate) fn rowblock(
+        &mut self,
+        stage: &'static str,
+        rowblock_size: u8,
+        static_iter: Option<u8>,
+    ) -> () {
+        match static_iter {
+            None if !rowblock_size.is_power_of_two() => {
+                self.violations.push(Violation::Unsupported {
+                    stage,
+                    parameter: "rowblock",
+                    value: rowblock_size.to_string(),
+                    expected: "a power of two to divide the rows by",
+                })
+            }
+            Some(0) => self.violations.push(Violation::Unsupported {
+                stage,
+                parameter: "static_iter",
+                value: "0".to_string(),
+                expected: "1 or more, or -1 for none",
+            }),
+            Some(_) if rowblock_size != 1 => self.violations.push(Violation::Unsupported {
+                stage,
+                parameter: "rowblock",
+                value: rowblock_size.to_string(),
+                expected: "1 with static_iter, which counts the rows one by one",
+            }),
+            _ => (),
+        }
+        if !accumulate::supports_rowblock_size(rowblock_size) {
+            self.violations.push(Violation::Unsupported {
+                stage,
+                parameter: "rowblock",
+                value: rowblock_size.to_string(),
+                expected: "1 or 8 to accumulate the results",
+            });
+        }
+    }
+
+    // a solve that skips its preload takes the registers over from the one
+    // before it, which the pre and post solves of a static iteration lay out
+    // for a single result register
+    pub(crate) fn preload_handover(
+        &mut self,
+        stage: &'static str,
+        rowblock_size: u8,
+        hands_over: bool,
+    ) -> () {
+        if !hands_over {
+            self.violations.push(Violation::Unsupported {
+                stage,
+                parameter: "rowblock",
+                value: rowblock_size.to_string(),
+                expected: "1 or store_to_tmp t with static_iter, for the preloaded registers \
+                           to line up with those of the pre and post solves",
+            });
+        }
+    }
+
+    // the unused registers and the last blocks of col, x and val are all
+    // physical ones only if the stage cannot spill, and more than those are
+    // a `NoSpill` already
+    pub(crate) fn accumulate(
+        &mut self,
+        stage: &'static str,
+        need: u8,
+        n_used: usize,
+        n_reused: usize,
+        can_spill: bool,
+    ) -> () {
+        if can_spill || n_used > N_PHYSICAL_REGISTERS {
+            return;
+        }
+        let free = N_PHYSICAL_REGISTERS - n_used + n_reused;
+        if need as usize > free {
+            self.violations
+                .push(Violation::AccumulateOverflow { stage, need, free });
+        }
+    }
+
+    pub(crate) fn gather_indices(
+        &mut self,
+        stage: &'static str,
+        col_reg_e: u8,
+        x_reg_s: u8,
+        x_blocks: u8,
+    ) -> () {
+        if col_reg_e > microkernel::N_VEX_REGISTERS {
+            self.violations
+                .push(Violation::ColIndexOverflow { stage, col_reg_e });
+        }
+        let registers = microkernel::n_gather_index_registers(x_reg_s, x_blocks);
+        if x_blocks as usize > registers {
+            self.violations.push(Violation::GatherIndexOverflow {
+                stage,
+                x_blocks,
+                registers,
+            });
+        }
+    }
+
+    pub(crate) fn prefetch(
+        &mut self,
+        stage: &'static str,
+        out_of_range: Vec<(&'static str, u16, u16)>,
+    ) -> () {
+        for (parameter, dist, max) in out_of_range {
+            self.violations.push(Violation::PrefetchOutOfRange {
+                stage,
+                parameter,
+                dist,
+                max,
+            });
+        }
+    }
+}
+
+// the budgets and then the violations, a line each
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for budget in self.budgets.iter() {
+            writeln!(f, "{}", budget)?;
+        }
+        for violation in self.violations.iter() {
+            writeln!(f, "{}", violation)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+// Values the kernel configurations have in common, as the command line and
+// the configuration files spell them, and the range each parameter takes.
+//
+// The builders take the values `Config::set` does and panic on the others,
+// so both check a parameter in one place. The ranges are those no other
+// parameter can make room for; `check` reports the rest.
+
+use crate::microkernel::N_VEX_REGISTERS;
+use crate::Direction;
+use core::config::{ConfigError, Value};
+use core::{PrefetchType, N_REGISTERS};
+
+// the configuration a builder makes, which is only wrong if it is given a
+// value out of range
+pub(crate) fn valid<C>(config: Result<C, ConfigError>) -> C {
+    config.unwrap_or_else(|err| panic!("{}", err))
+}
+
+const PREFETCH_TYPES: [&str; 5] = ["T0", "T1", "T2", "NTA", "None"];
+
+pub(crate) fn prefetch_type_value(prefetch_type: Option<PrefetchType>) -> Value {
+    let i = match prefetch_type {
+        Some(PrefetchType::T0) => 0,
+        Some(PrefetchType::T1) => 1,
+        Some(PrefetchType::T2) => 2,
+        Some(PrefetchType::NTA) => 3,
+        None => 4,
+    };
+    Value::Str(PREFETCH_TYPES[i].to_string())
+}
+
+pub(crate) fn prefetch_type(key: &str, value: &Value) -> Result<Option<PrefetchType>, ConfigError> {
+    match value.to_choice(key, &PREFETCH_TYPES)? {
+        0 => Ok(Some(PrefetchType::T0)),
+        1 => Ok(Some(PrefetchType::T1)),
+        2 => Ok(Some(PrefetchType::T2)),
+        3 => Ok(Some(PrefetchType::NTA)),
+        _ => Ok(None),
+    }
+}
+
+// the farthest the 16-bit displacement of a prefetch reaches from the third
+// block of a row, the farthest any kernel prefetches col from
+const MAX_PREFETCH_DIST: u16 = 32703;
+
+pub(crate) fn prefetch_dist(key: &str, value: &Value) -> Result<u16, ConfigError> {
+    Ok(value.to_int(key, 1, MAX_PREFETCH_DIST as i64)? as u16)
+}
+
+// gathers take the col indices of the blocks of col they preload, and of the
+// blocks of x they preload, in the registers VEX encodes; val may take any
+// register there is, spilled ones included
+pub(crate) fn col_preload_dist(
+    key: &str,
+    value: &Value,
+    blocks_per_row: u8,
+) -> Result<u8, ConfigError> {
+    let max = N_VEX_REGISTERS / blocks_per_row - 1;
+    Ok(value.to_int(key, 0, max as i64)? as u8)
+}
+
+pub(crate) fn x_preload_dist(
+    key: &str,
+    value: &Value,
+    blocks_per_row: u8,
+) -> Result<u8, ConfigError> {
+    let max = N_VEX_REGISTERS / blocks_per_row;
+    Ok(value.to_int(key, 0, max as i64)? as u8)
+}
+
+// -1 fuses the loads of val into the fmas
+pub(crate) fn val_preload_dist(
+    key: &str,
+    value: &Value,
+    blocks_per_row: u8,
+) -> Result<Option<u8>, ConfigError> {
+    let max = N_REGISTERS / blocks_per_row as usize - 1;
+    match value.to_int(key, -1, max as i64)? {
+        -1 => Ok(None),
+        value => Ok(Some(value as u8)),
+    }
+}
+
+// powers of two up to a block of 8 rows, which the rows are counted in with a
+// shift
+const ROWBLOCK_SIZES: [i64; 4] = [1, 2, 4, 8];
+
+pub(crate) fn rowblock_size(key: &str, value: &Value) -> Result<u8, ConfigError> {
+    match value {
+        Value::Int(size) if ROWBLOCK_SIZES.contains(size) => Ok(*size as u8),
+        _ => Err(value.invalid(key, "1, 2, 4 or 8".to_string())),
+    }
+}
+
+pub(crate) fn u8_value(value: u8) -> Value {
+    Value::Int(value as i64)
+}
+
+pub(crate) fn to_u8(key: &str, value: &Value) -> Result<u8, ConfigError> {
+    Ok(value.to_int(key, 0, u8::MAX as i64)? as u8)
+}
+
+// -1 stands for none, as in `val_pld` and `static_iter`
+pub(crate) fn option_u8_value(value: Option<u8>) -> Value {
+    Value::Int(value.map_or(-1, |value| value as i64))
+}
+
+pub(crate) fn to_option_u8(key: &str, value: &Value) -> Result<Option<u8>, ConfigError> {
+    match value.to_int(key, -1, u8::MAX as i64)? {
+        -1 => Ok(None),
+        value => Ok(Some(value as u8)),
+    }
+}
+
+const DIRECTIONS: [&str; 2] = ["forward", "backward"];
+
+pub(crate) fn direction_value(direction: Direction) -> Value {
+    let i = match direction {
+        Direction::Forward => 0,
+        Direction::Backward => 1,
+    };
+    Value::Str(DIRECTIONS[i].to_string())
+}
+
+pub(crate) fn direction(key: &str, value: &Value) -> Result<Direction, ConfigError> {
+    match value.to_choice(key, &DIRECTIONS)? {
+        0 => Ok(Direction::Forward),
+        _ => Ok(Direction::Backward),
+    }
+}
@@ -1,9 +1,22 @@
 extern crate core;
 
 mod accumulate;
+pub mod check;
+mod config;
 mod microkernel;
 pub mod spmv;
 pub mod sptrsv;
 pub mod symgs;
 
 pub use microkernel::Direction;
+
+// physical registers a layout that spills leaves free, enough to reload every
+// operand of an fma
+const N_SCRATCH_REGISTERS: u8 = 3;
+
+// general purpose registers the generators set up themselves: the row
+// counter, and copies of arguments that a kernel moves but needs again
+const COUNTER: &str = "I";
+const IMMUTABLE_X: &str = "IMM_X";
+const IMMUTABLE_NROW: &str = "IMM_NROW";
+const IMMUTABLE_P: &str = "IMM_P";
@@ -6,8 +6,12 @@ mod preload;
 pub use nanokernel::Generator as KernelGenerator;
 pub use nanokernel_end::Generator as PostkernelGenerator;
 pub use nanokernel_start::Generator as PrekernelGenerator;
+pub(crate) use preload::n_gather_index_registers;
 pub use preload::Generator as PreloadGenerator;
 
+// the ymm registers VEX encodes, which gathers take their col indices in
+pub(crate) const N_VEX_REGISTERS: u8 = 16;
+
 #[derive(Clone, Copy)]
 pub enum Direction {
     Forward,
@@ -6,15 +6,15 @@ impl Generator {
         direction: Direction,
         move_base: Option<u8>,
         col_stride: u16,
-        col_name: &'static str,
+        col_name: &str,
         val_stride: u16,
-        val_name: &'static str,
+        val_name: &str,
         move_base_tmp: Option<u8>,
-        tmp_name: &'static str,
+        tmp_name: &str,
         restore_tmp: Option<u8>,
         decrease_cnt: bool,
-        cnt_name: &'static str,
-        loop_name: &'static str,
+        cnt_name: &str,
+        loop_name: &str,
     ) -> Self {
         let move_base_cv = {
             let sign = match direction {
@@ -43,13 +43,13 @@ impl Generator {
 
         Generator {
             move_base_cv,
-            col_name,
-            val_name,
+            col_name: Name::from(col_name),
+            val_name: Name::from(val_name),
             move_base_tmp,
-            tmp_name,
+            tmp_name: Name::from(tmp_name),
             decrease_cnt,
-            cnt_name,
-            loop_name,
+            cnt_name: Name::from(cnt_name),
+            loop_name: Name::from(loop_name),
             restore_tmp,
         }
     }
@@ -57,15 +57,15 @@ impl Generator {
 
 pub struct Generator {
     move_base_cv: Option<(i16, i16)>,
-    col_name: &'static str,
-    val_name: &'static str,
+    col_name: Name,
+    val_name: Name,
 
     move_base_tmp: Option<i16>,
-    tmp_name: &'static str,
+    tmp_name: Name,
 
     decrease_cnt: bool,
-    cnt_name: &'static str,
-    loop_name: &'static str,
+    cnt_name: Name,
+    loop_name: Name,
 
     restore_tmp: Option<i16>,
 }
@@ -78,119 +78,75 @@ enum StateType {
     RestoringTmp,
 }
 
-const RULEBOOK: &'static [Rule<Generator>] = &[
-    Rule {
-        condition: Condition::Single {
-            id: StateType::MovingBaseCV as u32,
-        },
-        callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let (col_offset, val_offset) = config.move_base_cv.unwrap();
-
-            let asm = Assembly::new()
-                .add_immediate(config.col_name, col_offset)
-                .add_immediate(config.val_name, val_offset);
-            let states = Vec::new();
-
-            Ok((asm, states))
-        },
-    },
-    Rule {
-        condition: Condition::Single {
-            id: StateType::MovingBaseTmp as u32,
-        },
-        callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let tmp_offset = config.move_base_tmp.unwrap();
-
-            let asm = Assembly::new().add_immediate(config.tmp_name, tmp_offset);
-            let states = Vec::new();
-
-            Ok((asm, states))
-        },
-    },
-    Rule {
-        condition: Condition::Single {
-            id: StateType::DecreasingCnt as u32,
-        },
-        callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let asm = Assembly::new().sub_immediate(config.cnt_name, 0x1);
-            let next_id = StateType::Jumping as u32;
-            let states = Vec::from([State {
-                id: next_id,
-                idx: 0,
-                reg: 0,
-            }]);
-
-            Ok((asm, states))
-        },
-    },
-    Rule {
-        condition: Condition::Single {
-            id: StateType::Jumping as u32,
-        },
-        callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let asm = Assembly::new().jump_nz(config.loop_name);
-            let states = Vec::new();
-
-            Ok((asm, states))
-        },
-    },
-    Rule {
-        condition: Condition::Single {
-            id: StateType::RestoringTmp as u32,
-        },
-        callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let tmp_offset = config.restore_tmp.unwrap();
-
-            let asm = Assembly::new().sub_immediate(config.tmp_name, -tmp_offset);
-            let states = Vec::new();
-
-            Ok((asm, states))
-        },
-    },
-];
+impl From<StateType> for u32 {
+    fn from(id: StateType) -> u32 {
+        id as u32
+    }
+}
+
+const RULEBOOK: &'static [Rule<Generator>] = rulebook! {
+    StateType;
+
+    MovingBaseCV[_] => [] |config, _rp| {
+        let (col_offset, val_offset) = config
+            .move_base_cv
+            .ok_or(GenerateError::MissingParameter("move_base_cv"))?;
 
+        Assembly::new()
+            .add_immediate(&config.col_name, col_offset)
+            .add_immediate(&config.val_name, val_offset)
+    }
+
+    MovingBaseTmp[_] => [] |config, _rp| {
+        let tmp_offset = config
+            .move_base_tmp
+            .ok_or(GenerateError::MissingParameter("move_base_tmp"))?;
+
+        Assembly::new().add_immediate(&config.tmp_name, tmp_offset)
+    }
+
+    DecreasingCnt[_] => [Jumping[0]] |config, _rp| {
+        Assembly::new().sub_immediate(&config.cnt_name, 0x1)
+    }
+
+    Jumping[_] => [] |config, _rp| {
+        Assembly::new().jump_nz(&config.loop_name)
+    }
+
+    RestoringTmp[_] => [] |config, _rp| {
+        let tmp_offset = config
+            .restore_tmp
+            .ok_or(GenerateError::MissingParameter("restore_tmp"))?;
+
+        Assembly::new().sub_immediate(&config.tmp_name, -tmp_offset)
+    }
+};
 impl Generate for Generator {
     fn rulebook(&self) -> &'static [Rule<Self>] {
         RULEBOOK
     }
 
-    fn avail_registers(&self) -> [bool; 32] {
-        [false; 32]
+    fn avail_registers(&self) -> [bool; N_REGISTERS] {
+        [false; N_REGISTERS]
     }
 
     fn initial_states(&self) -> Vec<State> {
         let mut states = Vec::new();
 
         if self.move_base_cv.is_some() {
-            states.push(State {
-                id: StateType::MovingBaseCV as u32,
-                idx: 0,
-                reg: 0,
-            });
+            states.push(State::new(StateType::MovingBaseCV, 0));
         }
 
         if self.move_base_tmp.is_some() {
-            states.push(State {
-                id: StateType::MovingBaseTmp as u32,
-                idx: 0,
-                reg: 0,
-            });
+            states.push(State::new(StateType::MovingBaseTmp, 0));
         }
 
         if self.decrease_cnt {
-            states.push(State {
-                id: StateType::DecreasingCnt as u32,
-                idx: 0,
-                reg: 0,
-            });
+            states.push(State::new(StateType::DecreasingCnt, 0));
         }
 
         if self.restore_tmp.is_some() {
-            states.push(State {
-                id: StateType::RestoringTmp as u32,
-                idx: 0,
-                reg: 0,
-            });
+            states.push(State::new(StateType::RestoringTmp, 0));
         }
 
         states
@@ -4,17 +4,16 @@ use core::*;
 impl Generator {
     pub fn new(
         iteration_type: IterationType,
-        nrow_name: &'static str,
-        cnt_name: &'static str,
-        loop_name: &'static str,
+        nrow_name: &str,
+        cnt_name: &str,
+        loop_name: &str,
     ) -> Self {
         let (nrow_divisor, initial_cnt) = match iteration_type {
             IterationType::StaticIter { iter } => {
+                // the loop end always counts this one down, even for a
+                // single iteration
                 let nrow_divisor = None;
-                let initial_cnt = match iter {
-                    1 => None,
-                    iter => Some(iter),
-                };
+                let initial_cnt = Some(iter);
                 (nrow_divisor, initial_cnt)
             }
             IterationType::DynamicIter {
@@ -36,9 +35,9 @@ impl Generator {
         Generator {
             nrow_divisor,
             initial_cnt,
-            nrow_name,
-            cnt_name,
-            loop_name,
+            nrow_name: Name::from(nrow_name),
+            cnt_name: Name::from(cnt_name),
+            loop_name: Name::from(loop_name),
         }
     }
 }
@@ -46,9 +45,9 @@ impl Generator {
 pub struct Generator {
     nrow_divisor: Option<u8>,
     initial_cnt: Option<u8>,
-    nrow_name: &'static str,
-    cnt_name: &'static str,
-    loop_name: &'static str,
+    nrow_name: Name,
+    cnt_name: Name,
+    loop_name: Name,
 }
 
 enum StateType {
@@ -57,86 +56,67 @@ enum StateType {
     Labeling,
 }
 
-const RULEBOOK: &'static [Rule<Generator>] = &[
-    Rule {
-        condition: Condition::Single {
-            id: StateType::DividingNrow as u32,
-        },
-        callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let asm = match config.nrow_divisor.unwrap() {
-                x if x.is_power_of_two() => {
-                    let dist = x.ilog2() as u8;
-                    Assembly::new().shift_right(config.nrow_name, dist)
-                }
-                _ => panic!("not implemented"),
-            };
-            let states = Vec::new();
-
-            Ok((asm, states))
-        },
-    },
-    Rule {
-        condition: Condition::Single {
-            id: StateType::InitializingCnt as u32,
-        },
-        callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let iter = config.initial_cnt.unwrap() as i16;
-
-            let asm = Assembly::new().set_immediate(config.cnt_name, iter);
-            let states = Vec::new();
-
-            Ok((asm, states))
-        },
-    },
-    Rule {
-        condition: Condition::Single {
-            id: StateType::Labeling as u32,
-        },
-        callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let asm = Assembly::new().label(config.loop_name);
-            let states = Vec::new();
-
-            Ok((asm, states))
-        },
-    },
-];
+impl From<StateType> for u32 {
+    fn from(id: StateType) -> u32 {
+        id as u32
+    }
+}
 
+const RULEBOOK: &'static [Rule<Generator>] = rulebook! {
+    StateType;
+
+    DividingNrow[_] => [] |config, _rp| {
+        let nrow_divisor = config
+            .nrow_divisor
+            .ok_or(GenerateError::MissingParameter("nrow_divisor"))?;
+        match nrow_divisor {
+            x if x.is_power_of_two() => {
+                let dist = x.ilog2() as u8;
+                Assembly::new().shift_right(&config.nrow_name, dist)
+            }
+            x => {
+                return Err(GenerateError::Unsupported {
+                    parameter: "nrow_divisor",
+                    value: x.to_string(),
+                })
+            }
+        }
+    }
+
+    InitializingCnt[_] => [] |config, _rp| {
+        let initial_cnt = config
+            .initial_cnt
+            .ok_or(GenerateError::MissingParameter("initial_cnt"))?;
+        let iter = initial_cnt as i16;
+
+        Assembly::new().set_immediate(&config.cnt_name, iter)
+    }
+
+    Labeling[_] => [] |config, _rp| {
+        Assembly::new().label(&config.loop_name)
+    }
+};
 impl Generate for Generator {
     fn rulebook(&self) -> &'static [Rule<Self>] {
         RULEBOOK
     }
 
-    fn avail_registers(&self) -> [bool; 32] {
-        [false; 32]
+    fn avail_registers(&self) -> [bool; N_REGISTERS] {
+        [false; N_REGISTERS]
     }
 
     fn initial_states(&self) -> Vec<State> {
         let mut states = Vec::new();
 
         if self.nrow_divisor.is_some() {
-            let state = State {
-                id: StateType::DividingNrow as u32,
-                idx: 0,
-                reg: 0,
-            };
-            states.push(state);
+            states.push(State::new(StateType::DividingNrow, 0));
         }
 
         if self.initial_cnt.is_some() {
-            let counter_state = State {
-                id: StateType::InitializingCnt as u32,
-                idx: 0,
-                reg: 0,
-            };
-            states.push(counter_state);
+            states.push(State::new(StateType::InitializingCnt, 0));
         }
 
-        let label_state = State {
-            id: StateType::Labeling as u32,
-            idx: 0,
-            reg: 0,
-        };
-        states.push(label_state);
+        states.push(State::new(StateType::Labeling, 0));
 
         states
     }
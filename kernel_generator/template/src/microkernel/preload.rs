@@ -1,6 +1,16 @@
-use crate::microkernel::Direction;
+use crate::microkernel::{Direction, N_VEX_REGISTERS};
 use core::*;
 
+// gathers beyond these reuse the masks in turn
+const N_GATHER_MASKS: u8 = 4;
+
+// registers VEX encodes that are left for the col indices of every preloaded
+// block of x at once
+pub(crate) fn n_gather_index_registers(x_reg_s: u8, x_blocks: u8) -> usize {
+    let x_regs = x_reg_s..x_reg_s.saturating_add(x_blocks);
+    (0..N_VEX_REGISTERS).filter(|i| !x_regs.contains(i)).count()
+}
+
 impl Generator {
     pub fn new(
         col_premove: i16,
@@ -8,44 +18,64 @@ impl Generator {
         col_offset: u16,
         col_preload_dist: u8,
         col_reg_s: u8,
-        col_name: &'static str,
+        col_name: &str,
 
         val_premove: i16,
         val_stride: u16,
         val_offset: u16,
         val_preload_dist: u8,
         val_reg_s: Option<u8>,
-        val_name: &'static str,
+        val_name: &str,
 
         x_preload_dist: u8,
         x_reg_s: u8,
-        x_name: &'static str,
+        x_name: &str,
 
         direction: Direction,
         blocks_per_row: u8,
-    ) -> Self {
-        Generator {
+        avail_masks: [bool; N_MASKS],
+    ) -> Result<Self, GenerateError> {
+        let x_blocks = blocks_per_row.saturating_mul(x_preload_dist);
+        if x_blocks as usize > n_gather_index_registers(x_reg_s, x_blocks) {
+            return Err(GenerateError::Unsupported {
+                parameter: "x_preload_dist",
+                value: x_preload_dist.to_string(),
+            });
+        }
+
+        let mut mask_pool = MaskPool::new(avail_masks);
+        let masks = {
+            let n_masks = (blocks_per_row * x_preload_dist).min(N_GATHER_MASKS);
+            (0..n_masks)
+                .map(|_| mask_pool.get())
+                .collect::<Result<Vec<Mask>, GenerateError>>()?
+        };
+        let avail_masks = *mask_pool.avail_list();
+
+        Ok(Generator {
             col_premove,
             col_stride,
             col_offset,
             col_preload_dist,
             col_reg_s,
-            col_name,
+            col_name: Name::from(col_name),
 
             val_premove,
             val_stride,
             val_offset,
             val_preload_dist,
             val_reg_s,
-            val_name,
+            val_name: Name::from(val_name),
 
             x_preload_dist,
             x_reg_s,
-            x_name,
+            x_name: Name::from(x_name),
 
             direction,
             blocks_per_row,
-        }
+            masks,
+            avail_masks,
+        })
     }
 }
 
@@ -55,21 +85,23 @@ pub struct Generator {
     col_offset: u16,
     col_preload_dist: u8,
     col_reg_s: u8,
-    col_name: &'static str,
+    col_name: Name,
 
     val_premove: i16,
     val_stride: u16,
     val_offset: u16,
     val_preload_dist: u8,
     val_reg_s: Option<u8>,
-    val_name: &'static str,
+    val_name: Name,
 
     x_preload_dist: u8,
     x_reg_s: u8,
-    x_name: &'static str,
+    x_name: Name,
 
     direction: Direction,
     blocks_per_row: u8,
+    masks: Vec<Mask>,
+    avail_masks: [bool; N_MASKS],
 }
 
 impl Generator {
@@ -114,7 +146,11 @@ impl Generator {
     }
 
     fn do_premasking(&self) -> bool {
-        self.x_blocks_to_preload() <= 4
+        self.x_blocks_to_preload() <= N_GATHER_MASKS
+    }
+
+    fn mask(&self, idx: u8) -> Mask {
+        self.masks[idx as usize % self.masks.len()]
     }
 
     fn col_move_base(&self) -> i16 {
@@ -146,147 +182,90 @@ enum StateType {
     PostmovingBase,
 }
 
-const RULEBOOK: &'static [Rule<Generator>] = &[
-    Rule {
-        condition: Condition::Single {
-            id: StateType::PremovingBase as u32,
-        },
-        callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let asm = {
-                let asm_movcol = match config.col_move_base() {
-                    base if base < 0 => Assembly::new().sub_immediate(config.col_name, -base),
-                    _ => Assembly::new(),
-                };
-                let asm_movval = match config.val_move_base() {
-                    base if base < 0 => Assembly::new().sub_immediate(config.val_name, -base),
-                    _ => Assembly::new(),
-                };
-                asm_movcol.append(asm_movval)
-            };
-            let states = Vec::new();
-
-            Ok((asm, states))
-        },
-    },
-    Rule {
-        condition: Condition::Single {
-            id: StateType::Premasking as u32,
-        },
-        callback: |_config: &Generator, _rp: &mut RegisterPool, states: &Vec<State>| {
-            let idx = states[0].idx;
-            let k = idx + 1;
-
-            let asm = Assembly::new().mask_on(k);
-            let states = Vec::new();
-
-            Ok((asm, states))
-        },
-    },
-    Rule {
-        condition: Condition::Single {
-            id: StateType::LoadingColForX as u32,
-        },
-        callback: |config: &Generator, rp: &mut RegisterPool, states: &Vec<State>| {
-            let idx = states[0].idx;
-            let reg = rp.get();
-
-            let base = config.col_base(idx);
-
-            let asm = Assembly::new().load_i32x8(reg, config.col_name, base);
-            let next_id = StateType::PreloadingX as u32;
-            let states = Vec::from([State {
-                id: next_id,
-                idx,
-                reg,
-            }]);
-
-            Ok((asm, states))
-        },
-    },
-    Rule {
-        condition: Condition::Single {
-            id: StateType::PreloadingX as u32,
-        },
-        callback: |config: &Generator, rp: &mut RegisterPool, states: &Vec<State>| {
-            let idx = states[0].idx;
-            let reg_col = states[0].reg;
-            let reg_xv = config.x_reg_s + idx;
-            let k = idx % 4 + 1;
-
-            rp.free(reg_col);
-
-            let asm = match config.do_premasking() {
-                true => Assembly::new(),
-                false => Assembly::new().mask_on(k),
-            }
-            .gather_f64x8(reg_xv, config.x_name, reg_col, k);
-            let states = Vec::new();
-
-            Ok((asm, states))
-        },
-    },
-    Rule {
-        condition: Condition::Single {
-            id: StateType::PreloadingCol as u32,
-        },
-        callback: |config: &Generator, _rp: &mut RegisterPool, states: &Vec<State>| {
-            let idx = states[0].idx;
-            let reg = config.col_reg_s + idx;
-
-            let base = config.col_base(idx + config.x_blocks_to_preload());
-
-            let asm = Assembly::new().load_i32x8(reg, config.col_name, base);
-            let states = Vec::new();
-
-            Ok((asm, states))
-        },
-    },
-    Rule {
-        condition: Condition::Single {
-            id: StateType::PreloadingVal as u32,
-        },
-        callback: |config: &Generator, _rp: &mut RegisterPool, states: &Vec<State>| {
-            let idx = states[0].idx;
-            let reg = config.val_reg_s.unwrap() + idx;
-
-            let base = config.val_base(idx);
-
-            let asm = Assembly::new().load_f64x8(reg, config.val_name, base);
-            let states = Vec::new();
-
-            Ok((asm, states))
-        },
-    },
-    Rule {
-        condition: Condition::Single {
-            id: StateType::PostmovingBase as u32,
-        },
-        callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let asm = {
-                let asm_movcol = match config.col_move_base() {
-                    base if base > 0 => Assembly::new().add_immediate(config.col_name, base),
-                    _ => Assembly::new(),
-                };
-                let asm_movval = match config.val_move_base() {
-                    base if base > 0 => Assembly::new().add_immediate(config.val_name, base),
-                    _ => Assembly::new(),
-                };
-                asm_movcol.append(asm_movval)
-            };
-            let states = Vec::new();
-
-            Ok((asm, states))
-        },
-    },
-];
+impl From<StateType> for u32 {
+    fn from(id: StateType) -> u32 {
+        id as u32
+    }
+}
+
+const RULEBOOK: &'static [Rule<Generator>] = rulebook! {
+    StateType;
+
+    PremovingBase[_] => [] |config, _rp| {
+        let asm_movcol = match config.col_move_base() {
+            base if base < 0 => Assembly::new().sub_immediate(&config.col_name, -base),
+            _ => Assembly::new(),
+        };
+        let asm_movval = match config.val_move_base() {
+            base if base < 0 => Assembly::new().sub_immediate(&config.val_name, -base),
+            _ => Assembly::new(),
+        };
+
+        asm_movcol.append(asm_movval)
+    }
+
+    Premasking[idx: u8] => [] |config, _rp| {
+        let k = config.mask(idx);
+
+        Assembly::new().mask_on(k)
+    }
+
+    LoadingColForX[idx: u8] => [PreloadingX[idx](new reg)] |config, rp| {
+        let base = config.col_base(idx);
 
+        Assembly::new().load_i32x8(reg.ymm(), &config.col_name, base)
+    }
+
+    PreloadingX[idx: u8](reg_col) => [] |config, rp| {
+        let reg_xv = Zmm::new(config.x_reg_s + idx);
+        let k = config.mask(idx);
+
+        rp.free(reg_col);
+
+        let asm = match config.do_premasking() {
+            true => Assembly::new(),
+            false => Assembly::new().mask_on(k),
+        };
+        asm.gather_f64x8(reg_xv, &config.x_name, reg_col.ymm(), k)
+    }
+
+    PreloadingCol[idx: u8] => [] |config, _rp| {
+        let reg = Ymm::new(config.col_reg_s + idx);
+        let base = config.col_base(idx + config.x_blocks_to_preload());
+
+        Assembly::new().load_i32x8(reg, &config.col_name, base)
+    }
+
+    PreloadingVal[idx: u8] => [] |config, _rp| {
+        let val_reg_s = config
+            .val_reg_s
+            .ok_or(GenerateError::MissingParameter("val_reg_s"))?;
+        let reg = Zmm::new(val_reg_s + idx);
+        let base = config.val_base(idx);
+
+        Assembly::new().load_f64x8(reg, &config.val_name, base)
+    }
+
+    PostmovingBase[_] => [] |config, _rp| {
+        let asm_movcol = match config.col_move_base() {
+            base if base > 0 => Assembly::new().add_immediate(&config.col_name, base),
+            _ => Assembly::new(),
+        };
+        let asm_movval = match config.val_move_base() {
+            base if base > 0 => Assembly::new().add_immediate(&config.val_name, base),
+            _ => Assembly::new(),
+        };
+
+        asm_movcol.append(asm_movval)
+    }
+};
 impl Generate for Generator {
     fn rulebook(&self) -> &'static [Rule<Self>] {
         RULEBOOK
     }
 
-    fn avail_registers(&self) -> [bool; 32] {
-        let mut avail_registers = [true; 32];
+    fn avail_registers(&self) -> [bool; N_REGISTERS] {
+        let mut avail_registers = [true; N_REGISTERS];
 
         let iter = self.x_reg_s..(self.x_reg_s + self.x_blocks_to_preload());
         for i in iter {
@@ -296,56 +275,32 @@ impl Generate for Generator {
         avail_registers
     }
 
-    fn initial_states(&self) -> Vec<State> {
-        let mut states = Vec::new();
+    fn avail_masks(&self) -> [bool; N_MASKS] {
+        self.avail_masks
+    }
 
-        states.push(State {
-            id: StateType::PremovingBase as u32,
-            idx: 0,
-            reg: 0,
-        });
+    fn initial_states(&self) -> Vec<State> {
+        let mut states = Vec::from([State::new(StateType::PremovingBase, 0)]);
 
         if self.do_premasking() {
-            let mask_to_set = self.x_blocks_to_preload();
-            for i in 0..mask_to_set {
-                states.push(State {
-                    id: StateType::Premasking as u32,
-                    idx: i,
-                    reg: 0,
-                });
+            for i in 0..self.x_blocks_to_preload() {
+                states.push(State::new(StateType::Premasking, i.into()));
             }
         }
 
-        let col_blocks_to_load = self.x_blocks_to_preload();
-        for i in 0..col_blocks_to_load {
-            states.push(State {
-                id: StateType::LoadingColForX as u32,
-                idx: i,
-                reg: 0,
-            });
+        for i in 0..self.x_blocks_to_preload() {
+            states.push(State::new(StateType::LoadingColForX, i.into()));
         }
 
         for i in 0..self.col_blocks_to_preload() {
-            states.push(State {
-                id: StateType::PreloadingCol as u32,
-                idx: i,
-                reg: 0,
-            });
+            states.push(State::new(StateType::PreloadingCol, i.into()));
         }
 
         for i in 0..self.val_blocks_to_preload() {
-            states.push(State {
-                id: StateType::PreloadingVal as u32,
-                idx: i,
-                reg: 0,
-            });
+            states.push(State::new(StateType::PreloadingVal, i.into()));
         }
 
-        states.push(State {
-            id: StateType::PostmovingBase as u32,
-            idx: 0,
-            reg: 0,
-        });
+        states.push(State::new(StateType::PostmovingBase, 0));
 
         states
     }
@@ -0,0 +1,201 @@
+// Tuning parameters of spmv, with the defaults the README marks.
+//
+// `generator` makes the kernel of `kernel-generator spmv`, which computes
+// y = Ax on the operands of `spmv(nrow, col, val, x, tmp, y)`.
+//
+// The builders panic on the values out of the range `set` takes.
+
+use super::{Action, Generator};
+use crate::check::Report;
+use crate::config::*;
+use crate::Direction;
+use core::config::{self, Config as _, ConfigError, Fields, Value};
+use core::sparse_matrix::*;
+use core::PrefetchType;
+use serde::{Deserialize, Serialize};
+
+// the layout of the matrix the kernel takes, which is part of its interface
+// rather than a parameter: rows of 32 slots, the diagonal among them
+const MATRIX_FORMAT: SparseMatrixFormat = SparseMatrixFormat::ELL(ELLInfo {
+    diag: DiagonalStatus::Default,
+    lu: LUStatus::Default,
+    ordering: GridPointOrdering::Default,
+});
+
+// the blocks of 8 slots the kernel loads of a row
+const BLOCKS_PER_ROW: u8 = 4;
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(into = "Fields", try_from = "Fields")]
+pub struct Config {
+    pub(crate) col_prefetch_type: Option<PrefetchType>,
+    pub(crate) col_prefetch_dist: u16,
+    pub(crate) col_preload_dist: u8,
+    pub(crate) val_prefetch_type: Option<PrefetchType>,
+    pub(crate) val_prefetch_dist: u16,
+    pub(crate) val_preload_dist: Option<u8>,
+    pub(crate) x_preload_dist: u8,
+    pub(crate) rowblock_size: u8,
+    pub(crate) nops: u8,
+    pub(crate) store_to_tmp: bool,
+    pub(crate) move_reg: bool,
+    pub(crate) move_base: bool,
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Config {
+            col_prefetch_type: Some(PrefetchType::T2),
+            col_prefetch_dist: 4096,
+            col_preload_dist: 1,
+            val_prefetch_type: Some(PrefetchType::T2),
+            val_prefetch_dist: 4096,
+            val_preload_dist: None,
+            x_preload_dist: 2,
+            rowblock_size: 8,
+            nops: 0,
+            store_to_tmp: false,
+            move_reg: true,
+            move_base: true,
+        }
+    }
+
+    // `None` turns the prefetches of col off
+    pub fn col_prefetch_type(mut self, col_prefetch_type: Option<PrefetchType>) -> Self {
+        self.col_prefetch_type = col_prefetch_type;
+        self
+    }
+
+    pub fn col_prefetch_dist(self, col_prefetch_dist: u16) -> Self {
+        valid(self.set("col_pfd", &Value::Int(col_prefetch_dist as i64)))
+    }
+
+    pub fn col_preload_dist(self, col_preload_dist: u8) -> Self {
+        valid(self.set("col_pld", &u8_value(col_preload_dist)))
+    }
+
+    // `None` turns the prefetches of val off
+    pub fn val_prefetch_type(mut self, val_prefetch_type: Option<PrefetchType>) -> Self {
+        self.val_prefetch_type = val_prefetch_type;
+        self
+    }
+
+    pub fn val_prefetch_dist(self, val_prefetch_dist: u16) -> Self {
+        valid(self.set("val_pfd", &Value::Int(val_prefetch_dist as i64)))
+    }
+
+    // `None` fuses the loads of val into the fmas
+    pub fn val_preload_dist(self, val_preload_dist: Option<u8>) -> Self {
+        valid(self.set("val_pld", &option_u8_value(val_preload_dist)))
+    }
+
+    pub fn x_preload_dist(self, x_preload_dist: u8) -> Self {
+        valid(self.set("x_pld", &u8_value(x_preload_dist)))
+    }
+
+    pub fn rowblock_size(self, rowblock_size: u8) -> Self {
+        valid(self.set("rowblock", &u8_value(rowblock_size)))
+    }
+
+    pub fn nops(mut self, nops: u8) -> Self {
+        self.nops = nops;
+        self
+    }
+
+    pub fn store_to_tmp(mut self, store_to_tmp: bool) -> Self {
+        self.store_to_tmp = store_to_tmp;
+        self
+    }
+
+    pub fn move_reg(mut self, move_reg: bool) -> Self {
+        self.move_reg = move_reg;
+        self
+    }
+
+    pub fn move_base(mut self, move_base: bool) -> Self {
+        self.move_base = move_base;
+        self
+    }
+
+    pub fn generator(&self) -> Generator {
+        Generator::new(
+            MATRIX_FORMAT,
+            Action::AssignPosAx,
+            Direction::Forward,
+            self.col_prefetch_type
+                .map(|prefetch_type| (prefetch_type, self.col_prefetch_dist)),
+            self.col_preload_dist,
+            self.val_prefetch_type
+                .map(|prefetch_type| (prefetch_type, self.val_prefetch_dist)),
+            self.val_preload_dist,
+            self.x_preload_dist,
+            self.rowblock_size,
+            self.nops,
+            self.store_to_tmp,
+            self.move_reg,
+            self.move_base,
+        )
+    }
+
+    // the register budget and every rule the kernel breaks, without
+    // generating it
+    pub fn check(&self) -> Report {
+        self.generator().check()
+    }
+}
+
+impl config::Config for Config {
+    const TABLE: &'static str = "spmv";
+
+    fn fields(&self) -> Vec<(&'static str, Value)> {
+        Vec::from([
+            ("col_pft", prefetch_type_value(self.col_prefetch_type)),
+            ("col_pfd", Value::Int(self.col_prefetch_dist as i64)),
+            ("col_pld", u8_value(self.col_preload_dist)),
+            ("val_pft", prefetch_type_value(self.val_prefetch_type)),
+            ("val_pfd", Value::Int(self.val_prefetch_dist as i64)),
+            ("val_pld", option_u8_value(self.val_preload_dist)),
+            ("x_pld", u8_value(self.x_preload_dist)),
+            ("rowblock", u8_value(self.rowblock_size)),
+            ("nops", u8_value(self.nops)),
+            ("store_to_tmp", Value::Bool(self.store_to_tmp)),
+            ("move_reg", Value::Bool(self.move_reg)),
+            ("move_base", Value::Bool(self.move_base)),
+        ])
+    }
+
+    fn set(mut self, key: &str, value: &Value) -> Result<Self, ConfigError> {
+        match key {
+            "col_pft" => self.col_prefetch_type = prefetch_type(key, value)?,
+            "col_pfd" => self.col_prefetch_dist = prefetch_dist(key, value)?,
+            "col_pld" => self.col_preload_dist = col_preload_dist(key, value, BLOCKS_PER_ROW)?,
+            "val_pft" => self.val_prefetch_type = prefetch_type(key, value)?,
+            "val_pfd" => self.val_prefetch_dist = prefetch_dist(key, value)?,
+            "val_pld" => self.val_preload_dist = val_preload_dist(key, value, BLOCKS_PER_ROW)?,
+            "x_pld" => self.x_preload_dist = x_preload_dist(key, value, BLOCKS_PER_ROW)?,
+            "rowblock" => self.rowblock_size = rowblock_size(key, value)?,
+            "nops" => self.nops = to_u8(key, value)?,
+            "store_to_tmp" => self.store_to_tmp = value.to_bool(key)?,
+            "move_reg" => self.move_reg = value.to_bool(key)?,
+            "move_base" => self.move_base = value.to_bool(key)?,
+            _ => return Err(ConfigError::UnknownKey(key.to_string())),
+        }
+
+        Ok(self)
+    }
+}
+
+impl From<Config> for Fields {
+    fn from(config: Config) -> Self {
+        Fields::of(&config)
+    }
+}
+
+// the values on top of the defaults, as a file is read
+impl TryFrom<Fields> for Config {
+    type Error = ConfigError;
+
+    fn try_from(fields: Fields) -> Result<Self, ConfigError> {
+        fields.apply(Config::new())
+    }
+}
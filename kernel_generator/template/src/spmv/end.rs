@@ -3,39 +3,39 @@ use core::*;
 impl Generator {
     pub fn new(
         initial_cnt: Option<u8>,
-        cnt_name: &'static str,
+        cnt_name: &str,
 
         y_offset: i16,
-        y_name: &'static str,
+        y_name: &str,
 
-        nrow_name: &'static str,
+        nrow_name: &str,
 
-        loop_name: &'static str,
+        loop_name: &str,
     ) -> Self {
         Generator {
             initial_cnt,
-            cnt_name,
+            cnt_name: Name::from(cnt_name),
 
             y_offset,
-            y_name,
+            y_name: Name::from(y_name),
 
-            nrow_name,
+            nrow_name: Name::from(nrow_name),
 
-            loop_name,
+            loop_name: Name::from(loop_name),
         }
     }
 }
 
 pub struct Generator {
     initial_cnt: Option<u8>,
-    cnt_name: &'static str,
+    cnt_name: Name,
 
     y_offset: i16,
-    y_name: &'static str,
+    y_name: Name,
 
-    nrow_name: &'static str,
+    nrow_name: Name,
 
-    loop_name: &'static str,
+    loop_name: Name,
 }
 
 enum StateType {
@@ -47,45 +47,51 @@ enum StateType {
 
 const RULEBOOK: &'static [Rule<Generator>] = &[
     Rule {
+        name: "MovingY",
         condition: Condition::Single {
             id: StateType::MovingY as u32,
         },
         callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let asm = Assembly::new().add_immediate(config.y_name, config.y_offset);
+            let asm = Assembly::new().add_immediate(&config.y_name, config.y_offset);
             let states = Vec::new();
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "InitializingCnt",
         condition: Condition::Single {
             id: StateType::InitializingCnt as u32,
         },
         callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let asm =
-                Assembly::new().set_immediate(config.cnt_name, config.initial_cnt.unwrap() as i16);
+            let initial_cnt = config
+                .initial_cnt
+                .ok_or(GenerateError::MissingParameter("initial_cnt"))?;
+            let asm = Assembly::new().set_immediate(&config.cnt_name, initial_cnt as i16);
             let states = Vec::new();
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "DecreasingNrow",
         condition: Condition::Single {
             id: StateType::DecreasingNrow as u32,
         },
         callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let asm = Assembly::new().sub_immediate(config.nrow_name, 0x1);
+            let asm = Assembly::new().sub_immediate(&config.nrow_name, 0x1);
             let states = Vec::new();
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "Jumping",
         condition: Condition::Single {
             id: StateType::Jumping as u32,
         },
         callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let asm = Assembly::new().jump_nz(config.loop_name);
+            let asm = Assembly::new().jump_nz(&config.loop_name);
             let states = Vec::new();
 
             Ok((asm, states))
@@ -98,8 +104,8 @@ impl Generate for Generator {
         RULEBOOK
     }
 
-    fn avail_registers(&self) -> [bool; 32] {
-        [false; 32]
+    fn avail_registers(&self) -> [bool; N_REGISTERS] {
+        [false; N_REGISTERS]
     }
 
     fn initial_states(&self) -> Vec<State> {
@@ -108,26 +114,26 @@ impl Generate for Generator {
         states.push(State {
             id: StateType::MovingY as u32,
             idx: 0,
-            reg: 0,
+            reg: None,
         });
         if self.initial_cnt.is_some() {
             states.push(State {
                 id: StateType::InitializingCnt as u32,
                 idx: 0,
-                reg: 0,
+                reg: None,
             });
         }
 
         states.push(State {
             id: StateType::DecreasingNrow as u32,
             idx: 0,
-            reg: 0,
+            reg: None,
         });
 
         states.push(State {
             id: StateType::Jumping as u32,
             idx: 0,
-            reg: 0,
+            reg: None,
         });
 
         states
@@ -1,10 +1,14 @@
-use crate::{accumulate, microkernel};
+use crate::check::{Budget, Report};
+use crate::{accumulate, microkernel, COUNTER, N_SCRATCH_REGISTERS};
 use core::sparse_matrix::*;
 use core::*;
 use microkernel::{Direction, IterationType};
 
+mod config;
 mod end;
 
+pub use config::Config;
+
 #[derive(Clone, Copy)]
 pub enum Action {
     AssignPosAx,
@@ -19,25 +23,13 @@ impl Generator {
         action: Action,
         direction: Direction,
 
-        nrow_name: &'static str,
-
         col_prefetch_info: Option<(PrefetchType, u16)>,
         col_preload_dist: u8,
-        col_name: &'static str,
 
         val_prefetch_info: Option<(PrefetchType, u16)>,
         val_preload_dist: Option<u8>,
-        val_name: &'static str,
 
         x_preload_dist: u8,
-        x_name: &'static str,
-
-        tmp_name: &'static str,
-
-        cnt_name: &'static str,
-        loop_name: &'static str,
-
-        y_name: &'static str,
 
         rowblock_size: u8,
         nops: u8,
@@ -50,25 +42,25 @@ impl Generator {
             action,
             direction,
 
-            nrow_name,
+            nrow_name: Name::from("NROW"),
 
             col_prefetch_info,
             col_preload_dist,
-            col_name,
+            col_name: Name::from("COL"),
 
             val_prefetch_info,
             val_preload_dist,
-            val_name,
+            val_name: Name::from("VAL"),
 
             x_preload_dist,
-            x_name,
+            x_name: Name::from("X"),
 
-            tmp_name,
+            tmp_name: Name::from("TMP"),
 
-            cnt_name,
-            loop_name,
+            cnt_name: Name::from(COUNTER),
+            loop_name: Name::from("loop_spmv"),
 
-            y_name,
+            y_name: Name::from("Y"),
 
             rowblock_size,
             nops,
@@ -77,6 +69,41 @@ impl Generator {
             move_base,
         }
     }
+
+    // the operands and the loop of spmv inside a larger kernel, which are
+    // those of `spmv(nrow, col, val, x, tmp, y)` on its own
+    pub(crate) fn names(
+        mut self,
+        nrow_name: &str,
+        col_name: &str,
+        val_name: &str,
+        x_name: &str,
+        tmp_name: &str,
+        loop_name: &str,
+        y_name: &str,
+    ) -> Self {
+        self.nrow_name = Name::from(nrow_name);
+        self.col_name = Name::from(col_name);
+        self.val_name = Name::from(val_name);
+        self.x_name = Name::from(x_name);
+        self.tmp_name = Name::from(tmp_name);
+        self.loop_name = Name::from(loop_name);
+        self.y_name = Name::from(y_name);
+        self
+    }
+
+    // the general purpose registers of the kernel: the arguments in C
+    // parameter order and the row counter
+    fn operands(&self) -> Assembly {
+        Assembly::new()
+            .argument_int(&self.nrow_name)
+            .argument_pointer(&self.col_name)
+            .argument_pointer(&self.val_name)
+            .argument_pointer(&self.x_name)
+            .argument_pointer(&self.tmp_name)
+            .argument_pointer(&self.y_name)
+            .local_int(&self.cnt_name)
+    }
 }
 
 pub struct Generator {
@@ -84,25 +111,25 @@ pub struct Generator {
     action: Action,
     direction: Direction,
 
-    nrow_name: &'static str,
+    nrow_name: Name,
 
     col_prefetch_info: Option<(PrefetchType, u16)>,
     col_preload_dist: u8,
-    col_name: &'static str,
+    col_name: Name,
 
     val_prefetch_info: Option<(PrefetchType, u16)>,
     val_preload_dist: Option<u8>,
-    val_name: &'static str,
+    val_name: Name,
 
     x_preload_dist: u8,
-    x_name: &'static str,
+    x_name: Name,
 
-    tmp_name: &'static str,
+    tmp_name: Name,
 
-    cnt_name: &'static str,
-    loop_name: &'static str,
+    cnt_name: Name,
+    loop_name: Name,
 
-    y_name: &'static str,
+    y_name: Name,
 
     rowblock_size: u8,
     nops: u8,
@@ -177,13 +204,16 @@ impl Generator {
     }
 
     fn col_need(&self) -> u8 {
-        self.blocks_per_row() * (self.col_preload_dist + 1)
+        self.blocks_per_row()
+            .saturating_mul(self.col_preload_dist.saturating_add(1))
     }
     fn val_need(&self) -> u8 {
-        self.blocks_per_row() * self.val_preload_dist.map_or(0, |d| d + 1)
+        self.blocks_per_row()
+            .saturating_mul(self.val_preload_dist.map_or(0, |d| d.saturating_add(1)))
     }
     fn x_need(&self) -> u8 {
-        self.blocks_per_row() * (self.x_preload_dist + 1)
+        self.blocks_per_row()
+            .saturating_mul(self.x_preload_dist.saturating_add(1))
     }
     fn res_need(&self) -> u8 {
         match self.store_to_tmp {
@@ -192,22 +222,60 @@ impl Generator {
         }
     }
 
+    // the parameters `n_need` adds up the registers of
+    fn register_parameters(&self) -> Vec<(&'static str, String)> {
+        let val_preload_dist = match self.val_preload_dist {
+            None => "-1".to_string(),
+            Some(dist) => dist.to_string(),
+        };
+        let store_to_tmp = match self.store_to_tmp {
+            true => "t",
+            false => "f",
+        };
+        Vec::from([
+            ("col_preload_dist", self.col_preload_dist.to_string()),
+            ("val_preload_dist", val_preload_dist),
+            ("x_preload_dist", self.x_preload_dist.to_string()),
+            ("rowblock", self.rowblock_size.to_string()),
+            ("store_to_tmp", store_to_tmp.to_string()),
+        ])
+    }
+
+    fn n_need(&self) -> usize {
+        let n_need = [
+            self.col_need(),
+            self.res_need(),
+            self.x_need(),
+            self.val_need(),
+        ];
+        n_need.iter().map(|&n| n as usize).sum()
+    }
+
+    // registers that do not fit are spilled, so the first few are left free
+    // to reload them
+    fn reg_s(&self) -> u8 {
+        match self.n_need() > 32 {
+            true => N_SCRATCH_REGISTERS,
+            false => 0,
+        }
+    }
+
     fn col_se(&self) -> (u8, u8) {
-        (0, self.col_need())
+        (self.reg_s(), self.reg_s().saturating_add(self.col_need()))
     }
 
     fn res_se(&self) -> (u8, u8) {
-        (self.col_se().1, self.col_se().1 + self.res_need())
+        (self.col_se().1, self.col_se().1.saturating_add(self.res_need()))
     }
 
     fn x_se(&self) -> (u8, u8) {
-        (self.res_se().1, self.res_se().1 + self.x_need())
+        (self.res_se().1, self.res_se().1.saturating_add(self.x_need()))
     }
 
     fn val_se(&self) -> Option<(u8, u8)> {
         match self.val_need() {
             0 => None,
-            x => Some((self.x_se().1, self.x_se().1 + x)),
+            x => Some((self.x_se().1, self.x_se().1.saturating_add(x))),
         }
     }
 
@@ -261,9 +329,9 @@ impl Generator {
         match self.move_reg {
             true => 1,
             false => {
-                let col_groups = self.col_preload_dist + 1;
-                let val_groups = self.val_preload_dist.map_or(0, |d| d + 1);
-                let x_groups = self.x_preload_dist + 1;
+                let col_groups = self.col_preload_dist.saturating_add(1);
+                let val_groups = self.val_preload_dist.map_or(0, |d| d.saturating_add(1));
+                let x_groups = self.x_preload_dist.saturating_add(1);
                 let res_groups = match self.store_to_tmp {
                     true => 1,
                     false => self.rowblock_size,
@@ -279,6 +347,84 @@ impl Generator {
     fn kernels_iter(&self) -> u8 {
         self.rowblock_size / self.n_kernels_unrolled()
     }
+
+    // prefetch distances of 0, or past the reach of the 16-bit displacements
+    // of the nanokernels, which add the offset of the block in the row; col
+    // is prefetched by the blocks with an even index and val by every block
+    fn prefetch_out_of_range(&self) -> Vec<(&'static str, u16, u16)> {
+        let col_idx = (self.blocks_per_row() - 1) & !1;
+        let val_idx = self.blocks_per_row() - 1;
+        let limits = [
+            (
+                "col_prefetch_dist",
+                self.col_prefetch_info,
+                i16::MAX as u16 - col_idx as u16 * self.col_offset(),
+            ),
+            (
+                "val_prefetch_dist",
+                self.val_prefetch_info,
+                i16::MAX as u16 - val_idx as u16 * self.val_offset(),
+            ),
+        ];
+
+        limits
+            .into_iter()
+            .filter_map(|(parameter, prefetch_info, max)| match prefetch_info {
+                Some((_, dist)) if dist == 0 || dist > max => Some((parameter, dist, max)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // the last block of col, x and val, which accumulating takes registers
+    // from as well
+    fn n_reused_by_accumulate(&self) -> usize {
+        let n_blocks = match self.val_se() {
+            Some(_) => 3,
+            None => 2,
+        };
+
+        self.blocks_per_row() as usize * n_blocks
+    }
+
+    // the rules of the rulebook below, as one stage of a kernel
+    pub(crate) fn check_stage(
+        &self,
+        stage: &'static str,
+        can_spill: bool,
+        report: &mut Report,
+    ) -> () {
+        let budget = Budget {
+            stage,
+            col: self.col_need(),
+            res: self.res_need(),
+            x: self.x_need(),
+            val: self.val_need(),
+            scratch: self.reg_s(),
+        };
+        let n_used = budget.total();
+        report.registers(budget, can_spill);
+        let action = accumulate::Action::Move;
+        report.accumulate(
+            stage,
+            accumulate::n_registers(&action, self.rowblock_size, self.store_to_tmp),
+            n_used,
+            self.n_reused_by_accumulate(),
+            can_spill,
+        );
+        let x_blocks = self.blocks_per_row().saturating_mul(self.x_preload_dist);
+        report.gather_indices(stage, self.col_se().1, self.x_se().0, x_blocks);
+        report.prefetch(stage, self.prefetch_out_of_range());
+        report.unroll(stage, self.rowblock_size, self.n_kernels_unrolled());
+        report.rowblock(stage, self.rowblock_size, None);
+    }
+
+    pub fn check(&self) -> Report {
+        let mut report = Report::new();
+        self.check_stage("spmv", self.spill_area().is_some(), &mut report);
+
+        report
+    }
 }
 
 enum StateType {
@@ -294,10 +440,31 @@ enum StateType {
 
 const RULEBOOK: &'static [Rule<Generator>] = &[
     Rule {
+        name: "Preloading",
         condition: Condition::Single {
             id: StateType::Preloading as u32,
         },
-        callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
+        callback: |config: &Generator, rp: &mut RegisterPool, _states: &Vec<State>| {
+            if config.reg_s() as usize + config.n_need() > N_REGISTERS {
+                return Err(GenerateError::RegisterOverflow {
+                    parameters: config.register_parameters(),
+                    need: config.reg_s() as usize + config.n_need(),
+                    avail: N_REGISTERS,
+                });
+            }
+            if config.col_se().1 > microkernel::N_VEX_REGISTERS {
+                return Err(GenerateError::Unsupported {
+                    parameter: "col_preload_dist",
+                    value: config.col_preload_dist.to_string(),
+                });
+            }
+            if let Some(&(parameter, dist, _)) = config.prefetch_out_of_range().first() {
+                return Err(GenerateError::Unsupported {
+                    parameter,
+                    value: dist.to_string(),
+                });
+            }
+
             let col_reg_s = config.col_se().0;
             let val_reg_s = config.val_se().map(|reg_se| reg_se.0);
             let x_reg_s = config.x_se().0;
@@ -308,32 +475,36 @@ const RULEBOOK: &'static [Rule<Generator>] = &[
                 config.col_offset(),
                 config.col_preload_dist,
                 col_reg_s,
-                config.col_name,
+                &config.col_name,
                 0,
                 config.val_stride(),
                 config.val_offset(),
                 config.val_preload_dist.unwrap_or(0),
                 val_reg_s,
-                config.val_name,
+                &config.val_name,
                 config.x_preload_dist,
                 x_reg_s,
-                config.x_name,
+                &config.x_name,
                 config.direction,
                 config.blocks_per_row(),
-            );
+                *rp.masks().avail_list(),
+            )?;
 
-            let asm = preload_generator.generate()?.empty_line();
+            let asm = config
+                .operands()
+                .append(preload_generator.generate()?.empty_line());
             let next_id = StateType::InsertingGap as u32;
             let states = Vec::from([State {
                 id: next_id,
                 idx: 0,
-                reg: 0,
+                reg: None,
             }]);
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "InsertingGap",
         condition: Condition::Single {
             id: StateType::InsertingGap as u32,
         },
@@ -346,17 +517,26 @@ const RULEBOOK: &'static [Rule<Generator>] = &[
             let states = Vec::from([State {
                 id: next_id,
                 idx: 0,
-                reg: 0,
+                reg: None,
             }]);
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "Prekerneling",
         condition: Condition::Single {
             id: StateType::Prekerneling as u32,
         },
         callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
+            if !config.rowblock_size.is_multiple_of(config.n_kernels_unrolled()) {
+                return Err(GenerateError::IllegalUnrollFactor {
+                    parameter: "rowblock",
+                    value: config.rowblock_size.to_string(),
+                    n_kernels_unrolled: config.n_kernels_unrolled(),
+                });
+            }
+
             let iteration_type = IterationType::DynamicIter {
                 rowblock_size: config.rowblock_size,
                 inner_iter: config.kernels_iter(),
@@ -364,9 +544,9 @@ const RULEBOOK: &'static [Rule<Generator>] = &[
 
             let prekernel_generator = microkernel::PrekernelGenerator::new(
                 iteration_type,
-                config.nrow_name,
-                config.cnt_name,
-                config.loop_name,
+                &config.nrow_name,
+                &config.cnt_name,
+                &config.loop_name,
             );
 
             let asm = prekernel_generator.generate()?.empty_line();
@@ -374,8 +554,8 @@ const RULEBOOK: &'static [Rule<Generator>] = &[
             let states = (0..config.n_kernels_unrolled())
                 .map(|idx| State {
                     id: next_id,
-                    idx,
-                    reg: 0,
+                    idx: idx.into(),
+                    reg: None,
                 })
                 .collect();
 
@@ -383,11 +563,12 @@ const RULEBOOK: &'static [Rule<Generator>] = &[
         },
     },
     Rule {
+        name: "Kerneling",
         condition: Condition::Single {
             id: StateType::Kerneling as u32,
         },
-        callback: |config: &Generator, _rp: &mut RegisterPool, states: &Vec<State>| {
-            let kernel_idx = states[0].idx;
+        callback: |config: &Generator, rp: &mut RegisterPool, states: &Vec<State>| {
+            let kernel_idx = states[0].idx as u8;
 
             let kernel_generator = microkernel::KernelGenerator::new(
                 config.negate(),
@@ -395,38 +576,40 @@ const RULEBOOK: &'static [Rule<Generator>] = &[
                 config.col_offset(),
                 config.col_prefetch_info,
                 config.col_ls(kernel_idx),
-                config.col_name,
+                &config.col_name,
                 config.val_stride(),
                 config.val_offset(),
                 config.val_prefetch_info,
                 config.val_ls(kernel_idx),
-                config.val_name,
+                &config.val_name,
                 config.x_ls(kernel_idx),
-                config.x_name,
+                &config.x_name,
                 config.res_se(),
                 config.tmp_offset(),
-                config.tmp_name,
+                &config.tmp_name,
                 kernel_idx,
                 config.n_kernels_unrolled(),
                 config.direction,
                 config.blocks_per_row(),
+                *rp.masks().avail_list(),
                 config.store_to_tmp,
                 config.move_reg,
                 config.move_base,
-            );
+            )?;
 
             let asm = kernel_generator.generate()?.empty_line();
             let next_id = StateType::Kerneled as u32;
             let states = Vec::from([State {
                 id: next_id,
-                idx: kernel_idx,
-                reg: 0,
+                idx: kernel_idx.into(),
+                reg: None,
             }]);
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "Kerneled + Kerneled at +1",
         condition: Condition::SameId {
             id: StateType::Kerneled as u32,
             n_states: 2,
@@ -437,12 +620,13 @@ const RULEBOOK: &'static [Rule<Generator>] = &[
             let idx = states[1].idx;
 
             let asm = Assembly::new();
-            let states = Vec::from([State { id, idx, reg: 0 }]);
+            let states = Vec::from([State { id, idx, reg: None }]);
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "Kerneled",
         condition: Condition::Single {
             id: StateType::Kerneled as u32,
         },
@@ -452,13 +636,14 @@ const RULEBOOK: &'static [Rule<Generator>] = &[
             let states = Vec::from([State {
                 id: next_id,
                 idx: 0,
-                reg: 0,
+                reg: None,
             }]);
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "Postkerneling",
         condition: Condition::Single {
             id: StateType::Postkerneling as u32,
         },
@@ -481,15 +666,15 @@ const RULEBOOK: &'static [Rule<Generator>] = &[
                 config.direction,
                 move_base,
                 config.col_stride(),
-                config.col_name,
+                &config.col_name,
                 config.val_stride(),
-                config.val_name,
+                &config.val_name,
                 move_base_tmp,
-                config.tmp_name,
+                &config.tmp_name,
                 restore_tmp,
                 decrease_cnt,
-                config.cnt_name,
-                config.loop_name,
+                &config.cnt_name,
+                &config.loop_name,
             );
 
             let asm = postkernel_generator.generate()?.empty_line();
@@ -497,19 +682,20 @@ const RULEBOOK: &'static [Rule<Generator>] = &[
             let states = Vec::from([State {
                 id: next_id,
                 idx: 0,
-                reg: 0,
+                reg: None,
             }]);
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "Accumulating",
         condition: Condition::Single {
             id: StateType::Accumulating as u32,
         },
         callback: |config: &Generator, rp: &mut RegisterPool, _states: &Vec<State>| {
-            let dst_name = config.y_name;
-            let general_reg_name = config.cnt_name;
+            let dst_name = &config.y_name;
+            let general_reg_name = &config.cnt_name;
             let load_from_tmp = config.store_to_tmp;
             let tmp_offset = size_of::<f64>() as u16 * 8;
             let action = accumulate::Action::Move;
@@ -545,28 +731,30 @@ const RULEBOOK: &'static [Rule<Generator>] = &[
                 config.res_se(),
                 load_from_tmp,
                 tmp_offset,
-                config.tmp_name,
+                &config.tmp_name,
                 "",
                 "",
                 "",
                 action,
                 config.rowblock_size,
                 avail_registers,
+                *rp.masks().avail_list(),
                 config.diag_status(),
-            );
+            )?;
 
             let asm = accumulate_generator.generate()?.empty_line();
             let next_id = StateType::Ending as u32;
             let states = Vec::from([State {
                 id: next_id,
                 idx: 0,
-                reg: 0,
+                reg: None,
             }]);
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "Ending",
         condition: Condition::Single {
             id: StateType::Ending as u32,
         },
@@ -585,11 +773,11 @@ const RULEBOOK: &'static [Rule<Generator>] = &[
 
             let end_generator = end::Generator::new(
                 initial_cnt,
-                config.cnt_name,
+                &config.cnt_name,
                 y_offset,
-                config.y_name,
-                config.nrow_name,
-                config.loop_name,
+                &config.y_name,
+                &config.nrow_name,
+                &config.loop_name,
             );
 
             let asm = end_generator.generate()?;
@@ -605,8 +793,8 @@ impl Generate for Generator {
         RULEBOOK
     }
 
-    fn avail_registers(&self) -> [bool; 32] {
-        let mut arr = [true; 32];
+    fn avail_registers(&self) -> [bool; N_REGISTERS] {
+        let mut arr = [true; N_REGISTERS];
 
         let occupied_iter = {
             let col_iter = self.col_se().0..self.col_se().1;
@@ -620,18 +808,26 @@ impl Generate for Generator {
             col_iter.chain(x_iter).chain(val_iter).chain(res_iter)
         };
 
-        occupied_iter.for_each(|i| {
+        occupied_iter.filter(|&i| (i as usize) < N_REGISTERS).for_each(|i| {
             arr[i as usize] = false;
         });
 
         arr
     }
 
+    // spilled registers share `tmp` only when rowblock results do not
+    fn spill_area(&self) -> Option<&str> {
+        match self.store_to_tmp {
+            true => None,
+            false => Some(&self.tmp_name),
+        }
+    }
+
     fn initial_states(&self) -> Vec<State> {
         let initial_state = State {
             id: StateType::Preloading as u32,
             idx: 0,
-            reg: 0,
+            reg: None,
         };
         let states = Vec::from([initial_state]);
 
@@ -7,14 +7,14 @@ impl Generator {
         rowblock_size: u8,
         dynamic_inner_iter: Option<u8>,
 
-        x_name: &'static str,
-        p_name: &'static str,
-        d_name: &'static str,
-        r_name: &'static str,
-
-        nrow_name: &'static str,
-        cnt_name: &'static str,
-        loop_name: &'static str,
+        x_name: &str,
+        p_name: &str,
+        d_name: &str,
+        r_name: &str,
+
+        nrow_name: &str,
+        cnt_name: &str,
+        loop_name: &str,
     ) -> Self {
         let xpd_offset = match direction {
             Direction::Forward => Some(size_of::<f64>() as i16 * rowblock_size as i16),
@@ -35,15 +35,15 @@ impl Generator {
         Generator {
             xpd_offset,
             r_offset,
-            x_name,
-            p_name,
-            d_name,
-            r_name,
+            x_name: Name::from(x_name),
+            p_name: Name::from(p_name),
+            d_name: Name::from(d_name),
+            r_name: Name::from(r_name),
             init_cnt,
             decrease_nrow,
-            nrow_name,
-            cnt_name,
-            loop_name,
+            nrow_name: Name::from(nrow_name),
+            cnt_name: Name::from(cnt_name),
+            loop_name: Name::from(loop_name),
         }
     }
 }
@@ -52,16 +52,16 @@ pub struct Generator {
     xpd_offset: Option<i16>,
     r_offset: Option<i16>,
 
-    x_name: &'static str,
-    p_name: &'static str,
-    d_name: &'static str,
-    r_name: &'static str,
+    x_name: Name,
+    p_name: Name,
+    d_name: Name,
+    r_name: Name,
 
     init_cnt: Option<u8>,
     decrease_nrow: bool,
-    nrow_name: &'static str,
-    cnt_name: &'static str,
-    loop_name: &'static str,
+    nrow_name: Name,
+    cnt_name: Name,
+    loop_name: Name,
 }
 
 enum StateType {
@@ -75,15 +75,18 @@ enum StateType {
 
 const RULEBOOK: &'static [Rule<Generator>] = &[
     Rule {
+        name: "MovingXPD",
         condition: Condition::Single {
             id: StateType::MovingXPD as u32,
         },
         callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let offset = config.xpd_offset.unwrap();
+            let offset = config
+                .xpd_offset
+                .ok_or(GenerateError::MissingParameter("xpd_offset"))?;
             let asm = Assembly::new()
-                .add_immediate(config.x_name, offset)
-                .add_immediate(config.p_name, offset)
-                .add_immediate(config.d_name, offset);
+                .add_immediate(&config.x_name, offset)
+                .add_immediate(&config.p_name, offset)
+                .add_immediate(&config.d_name, offset);
 
             let states = Vec::new();
 
@@ -91,12 +94,15 @@ const RULEBOOK: &'static [Rule<Generator>] = &[
         },
     },
     Rule {
+        name: "MovingR",
         condition: Condition::Single {
             id: StateType::MovingR as u32,
         },
         callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let offset = config.r_offset.unwrap();
-            let asm = Assembly::new().add_immediate(config.r_name, offset);
+            let offset = config
+                .r_offset
+                .ok_or(GenerateError::MissingParameter("r_offset"))?;
+            let asm = Assembly::new().add_immediate(&config.r_name, offset);
 
             let states = Vec::new();
 
@@ -104,45 +110,51 @@ const RULEBOOK: &'static [Rule<Generator>] = &[
         },
     },
     Rule {
+        name: "InitializingCnt",
         condition: Condition::Single {
             id: StateType::InitializingCnt as u32,
         },
         callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let asm =
-                Assembly::new().set_immediate(config.cnt_name, config.init_cnt.unwrap() as i16);
+            let init_cnt = config
+                .init_cnt
+                .ok_or(GenerateError::MissingParameter("init_cnt"))?;
+            let asm = Assembly::new().set_immediate(&config.cnt_name, init_cnt as i16);
             let states = Vec::new();
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "DecreasingNrow",
         condition: Condition::Single {
             id: StateType::DecreasingNrow as u32,
         },
         callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let asm = Assembly::new().sub_immediate(config.nrow_name, 0x1);
+            let asm = Assembly::new().sub_immediate(&config.nrow_name, 0x1);
             let states = Vec::new();
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "DecreasingCnt",
         condition: Condition::Single {
             id: StateType::DecreasingCnt as u32,
         },
         callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let asm = Assembly::new().sub_immediate(config.cnt_name, 0x1);
+            let asm = Assembly::new().sub_immediate(&config.cnt_name, 0x1);
             let states = Vec::new();
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "Jumping",
         condition: Condition::Single {
             id: StateType::Jumping as u32,
         },
         callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let asm = Assembly::new().jump_nz(config.loop_name);
+            let asm = Assembly::new().jump_nz(&config.loop_name);
             let states = Vec::new();
 
             Ok((asm, states))
@@ -155,8 +167,8 @@ impl Generate for Generator {
         RULEBOOK
     }
 
-    fn avail_registers(&self) -> [bool; 32] {
-        [false; 32]
+    fn avail_registers(&self) -> [bool; N_REGISTERS] {
+        [false; N_REGISTERS]
     }
 
     fn initial_states(&self) -> Vec<State> {
@@ -166,7 +178,7 @@ impl Generate for Generator {
             states.push(State {
                 id: StateType::MovingXPD as u32,
                 idx: 0,
-                reg: 0,
+                reg: None,
             });
         }
 
@@ -174,7 +186,7 @@ impl Generate for Generator {
             states.push(State {
                 id: StateType::MovingR as u32,
                 idx: 0,
-                reg: 0,
+                reg: None,
             });
         }
 
@@ -182,7 +194,7 @@ impl Generate for Generator {
             states.push(State {
                 id: StateType::InitializingCnt as u32,
                 idx: 0,
-                reg: 0,
+                reg: None,
             });
         }
 
@@ -194,14 +206,14 @@ impl Generate for Generator {
             states.push(State {
                 id: id as u32,
                 idx: 0,
-                reg: 0,
+                reg: None,
             });
         }
 
         states.push(State {
             id: StateType::Jumping as u32,
             idx: 0,
-            reg: 0,
+            reg: None,
         });
 
         states
@@ -1,4 +1,5 @@
-use crate::{sptrsv, Direction};
+use crate::check::Report;
+use crate::{sptrsv, Direction, IMMUTABLE_NROW};
 use core::sparse_matrix::*;
 use core::*;
 
@@ -7,31 +8,28 @@ impl Generator {
         matrix_format: SparseMatrixFormat,
         static_iter: Option<u8>,
 
-        nrow_name: &'static str,
-        immutable_nrow_name: &'static str,
+        nrow_name: &str,
 
         col_prefetch_info: Option<(PrefetchType, u16)>,
         col_preload_dist: u8,
-        ucol_name: &'static str,
+        ucol_name: &str,
 
         val_prefetch_info: Option<(PrefetchType, u16)>,
         val_preload_dist: Option<u8>,
-        uval_name: &'static str,
+        uval_name: &str,
 
         x_preload_dist: u8,
-        x_name: &'static str,
-        immutable_x_name: &'static str,
+        x_name: &str,
 
-        tmp_name: &'static str,
+        tmp_name: &str,
 
-        cnt_name: &'static str,
-        prebackwarding_loop_name: &'static str,
-        backwarding_loop_name: &'static str,
-        postbackwarding_loop_name: &'static str,
+        prebackwarding_loop_name: &str,
+        backwarding_loop_name: &str,
+        postbackwarding_loop_name: &str,
 
-        p_name: &'static str,
-        d_name: &'static str,
-        r_name: &'static str,
+        p_name: &str,
+        d_name: &str,
+        r_name: &str,
 
         rowblock_size: u8,
         nops_before_prebackwarding: u8,
@@ -46,25 +44,22 @@ impl Generator {
         Generator {
             matrix_format,
             static_iter,
-            nrow_name,
-            immutable_nrow_name,
+            nrow_name: Name::from(nrow_name),
             col_prefetch_info,
             col_preload_dist,
-            ucol_name,
+            ucol_name: Name::from(ucol_name),
             val_prefetch_info,
             val_preload_dist,
-            uval_name,
+            uval_name: Name::from(uval_name),
             x_preload_dist,
-            x_name,
-            immutable_x_name,
-            tmp_name,
-            cnt_name,
-            prebackwarding_loop_name,
-            backwarding_loop_name,
-            postbackwarding_loop_name,
-            p_name,
-            d_name,
-            r_name,
+            x_name: Name::from(x_name),
+            tmp_name: Name::from(tmp_name),
+            prebackwarding_loop_name: Name::from(prebackwarding_loop_name),
+            backwarding_loop_name: Name::from(backwarding_loop_name),
+            postbackwarding_loop_name: Name::from(postbackwarding_loop_name),
+            p_name: Name::from(p_name),
+            d_name: Name::from(d_name),
+            r_name: Name::from(r_name),
             rowblock_size,
             nops_before_prebackwarding,
             nops_before_backwarding,
@@ -80,31 +75,28 @@ pub struct Generator {
     matrix_format: SparseMatrixFormat,
     static_iter: u8,
 
-    nrow_name: &'static str,
-    immutable_nrow_name: &'static str,
+    nrow_name: Name,
 
     col_prefetch_info: Option<(PrefetchType, u16)>,
     col_preload_dist: u8,
-    ucol_name: &'static str,
+    ucol_name: Name,
 
     val_prefetch_info: Option<(PrefetchType, u16)>,
     val_preload_dist: Option<u8>,
-    uval_name: &'static str,
+    uval_name: Name,
 
     x_preload_dist: u8,
-    x_name: &'static str,
-    immutable_x_name: &'static str,
+    x_name: Name,
 
-    tmp_name: &'static str,
+    tmp_name: Name,
 
-    cnt_name: &'static str,
-    prebackwarding_loop_name: &'static str,
-    backwarding_loop_name: &'static str,
-    postbackwarding_loop_name: &'static str,
+    prebackwarding_loop_name: Name,
+    backwarding_loop_name: Name,
+    postbackwarding_loop_name: Name,
 
-    p_name: &'static str,
-    d_name: &'static str,
-    r_name: &'static str,
+    p_name: Name,
+    d_name: Name,
+    r_name: Name,
 
     rowblock_size: u8,
     nops_before_prebackwarding: u8,
@@ -142,11 +134,13 @@ impl Generator {
     }
 
     fn col_premove(&self) -> i16 {
-        self.col_stride() as i16 * self.col_preload_dist as i16 * -1
+        let rows = self.col_preload_dist.saturating_add(self.x_preload_dist);
+        self.col_stride() as i16 * rows as i16 * -1
     }
 
     fn val_premove(&self) -> i16 {
-        self.val_stride() as i16 * self.val_preload_dist.unwrap_or(0) as i16 * -1
+        let rows = self.val_preload_dist.unwrap_or(0);
+        (self.val_stride() as i16).saturating_mul(rows as i16) * -1
     }
 
     fn prekernel_col_premove(&self) -> i16 {
@@ -178,6 +172,129 @@ impl Generator {
     }
 }
 
+impl Generator {
+    // the first static_iter rowblocks, counted rather than divided
+    fn pre_sptrsv(&self) -> sptrsv::Generator {
+        sptrsv::Generator::new(
+            self.matrix_format,
+            Direction::Backward,
+            Some(self.static_iter),
+            self.prekernel_col_premove(),
+            None,
+            self.col_preload_dist,
+            self.prekernel_val_premove(),
+            None,
+            self.val_preload_dist,
+            self.x_preload_dist,
+            1,
+            self.nops_before_prebackwarding,
+            false,
+            self.move_reg,
+            self.move_base,
+            false,
+        )
+        .names(
+            &self.nrow_name,
+            &self.ucol_name,
+            &self.uval_name,
+            &self.x_name,
+            &self.tmp_name,
+            &self.prebackwarding_loop_name,
+            &self.p_name,
+            &self.d_name,
+            &self.r_name,
+        )
+    }
+
+    fn sptrsv(&self) -> sptrsv::Generator {
+        let skip_preload = self.static_iter > 0;
+
+        sptrsv::Generator::new(
+            self.matrix_format,
+            Direction::Backward,
+            None,
+            self.kernel_col_premove(),
+            self.col_prefetch_info,
+            self.col_preload_dist,
+            self.kernel_val_premove(),
+            self.val_prefetch_info,
+            self.val_preload_dist,
+            self.x_preload_dist,
+            self.rowblock_size,
+            self.nops_before_backwarding,
+            self.store_to_tmp,
+            self.move_reg,
+            self.move_base,
+            skip_preload,
+        )
+        .names(
+            &self.nrow_name,
+            &self.ucol_name,
+            &self.uval_name,
+            &self.x_name,
+            &self.tmp_name,
+            &self.backwarding_loop_name,
+            &self.p_name,
+            &self.d_name,
+            &self.r_name,
+        )
+    }
+
+    // the last static_iter rowblocks
+    fn post_sptrsv(&self) -> sptrsv::Generator {
+        sptrsv::Generator::new(
+            self.matrix_format,
+            Direction::Backward,
+            Some(self.static_iter),
+            0,
+            None,
+            self.col_preload_dist,
+            0,
+            None,
+            self.val_preload_dist,
+            self.x_preload_dist,
+            1,
+            self.nops_before_postbackwarding,
+            false,
+            self.move_reg,
+            self.move_base,
+            true,
+        )
+        .names(
+            &self.nrow_name,
+            &self.ucol_name,
+            &self.uval_name,
+            &self.x_name,
+            &self.tmp_name,
+            &self.postbackwarding_loop_name,
+            &self.p_name,
+            &self.d_name,
+            &self.r_name,
+        )
+    }
+
+    // the main solve takes over the registers preloaded by the pre solve,
+    // and the post solve those of the main solve, which needs them laid out
+    // alike although the pre and post solves keep a single result register
+    fn hands_over_preload(&self) -> bool {
+        self.static_iter == 0
+            || self.pre_sptrsv().preloaded_registers() == self.sptrsv().preloaded_registers()
+    }
+
+    pub(crate) fn check(&self, report: &mut Report) -> () {
+        if self.static_iter > 0 {
+            self.pre_sptrsv()
+                .check_stage("prebackwarding", false, report);
+        }
+        self.sptrsv().check_stage("backwarding", false, report);
+        report.preload_handover("backwarding", self.rowblock_size, self.hands_over_preload());
+        if self.static_iter > 0 {
+            self.post_sptrsv()
+                .check_stage("postbackwarding", false, report);
+        }
+    }
+}
+
 enum StateType {
     GeneratingPreSptrsv,
     InitializingNrow,
@@ -187,57 +304,28 @@ enum StateType {
 
 const RULEBOOK: &'static [Rule<Generator>] = &[
     Rule {
+        name: "GeneratingPreSptrsv",
         condition: Condition::Single {
             id: StateType::GeneratingPreSptrsv as u32,
         },
         callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let sptrsv_generator = sptrsv::Generator::new(
-                config.matrix_format,
-                Direction::Backward,
-                Some(config.static_iter),
-                config.nrow_name,
-                config.prekernel_col_premove(),
-                None,
-                config.col_preload_dist,
-                config.ucol_name,
-                config.prekernel_val_premove(),
-                None,
-                config.val_preload_dist,
-                config.uval_name,
-                config.x_preload_dist,
-                config.x_name,
-                config.immutable_x_name,
-                config.tmp_name,
-                config.cnt_name,
-                config.prebackwarding_loop_name,
-                config.p_name,
-                config.d_name,
-                config.r_name,
-                1,
-                config.nops_before_prebackwarding,
-                false,
-                config.move_reg,
-                config.move_base,
-                false,
-            );
-
-            let asm = sptrsv_generator.generate()?.empty_line();
+            let asm = config.pre_sptrsv().generate()?.empty_line();
             let states = Vec::new();
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "InitializingNrow",
         condition: Condition::Single {
             id: StateType::InitializingNrow as u32,
         },
         callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
             let asm = {
-                let asm_move =
-                    Assembly::new().move_reg(config.nrow_name, config.immutable_nrow_name);
+                let asm_move = Assembly::new().move_reg(&config.nrow_name, IMMUTABLE_NROW);
                 let asm_sub = match config.static_iter {
                     0 => Assembly::new(),
-                    x => Assembly::new().sub_immediate(config.nrow_name, x as i16 * 2),
+                    x => Assembly::new().sub_immediate(&config.nrow_name, x as i16 * 2),
                 };
 
                 asm_move.append(asm_sub)
@@ -249,84 +337,31 @@ const RULEBOOK: &'static [Rule<Generator>] = &[
         },
     },
     Rule {
+        name: "GeneratingSptrsv",
         condition: Condition::Single {
             id: StateType::GeneratingSptrsv as u32,
         },
         callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let skip_preload = config.static_iter > 1;
-
-            let sptrsv_generator = sptrsv::Generator::new(
-                config.matrix_format,
-                Direction::Backward,
-                None,
-                config.nrow_name,
-                config.kernel_col_premove(),
-                config.col_prefetch_info,
-                config.col_preload_dist,
-                config.ucol_name,
-                config.kernel_val_premove(),
-                config.val_prefetch_info,
-                config.val_preload_dist,
-                config.uval_name,
-                config.x_preload_dist,
-                config.x_name,
-                config.immutable_x_name,
-                config.tmp_name,
-                config.cnt_name,
-                config.backwarding_loop_name,
-                config.p_name,
-                config.d_name,
-                config.r_name,
-                config.rowblock_size,
-                config.nops_before_backwarding,
-                config.store_to_tmp,
-                config.move_reg,
-                config.move_base,
-                skip_preload,
-            );
-
-            let asm = sptrsv_generator.generate()?.empty_line();
+            if !config.hands_over_preload() {
+                return Err(GenerateError::Unsupported {
+                    parameter: "rowblock",
+                    value: config.rowblock_size.to_string(),
+                });
+            }
+
+            let asm = config.sptrsv().generate()?.empty_line();
             let states = Vec::new();
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "GeneratingPostSptrsv",
         condition: Condition::Single {
             id: StateType::GeneratingPostSptrsv as u32,
         },
         callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let sptrsv_generator = sptrsv::Generator::new(
-                config.matrix_format,
-                Direction::Backward,
-                Some(config.static_iter),
-                config.nrow_name,
-                0,
-                None,
-                config.col_preload_dist,
-                config.ucol_name,
-                0,
-                None,
-                config.val_preload_dist,
-                config.uval_name,
-                config.x_preload_dist,
-                config.x_name,
-                config.immutable_x_name,
-                config.tmp_name,
-                config.cnt_name,
-                config.postbackwarding_loop_name,
-                config.p_name,
-                config.d_name,
-                config.r_name,
-                1,
-                config.nops_before_postbackwarding,
-                false,
-                config.move_reg,
-                config.move_base,
-                true,
-            );
-
-            let asm = sptrsv_generator.generate()?;
+            let asm = config.post_sptrsv().generate()?;
             let states = Vec::new();
 
             Ok((asm, states))
@@ -339,8 +374,8 @@ impl Generate for Generator {
         RULEBOOK
     }
 
-    fn avail_registers(&self) -> [bool; 32] {
-        [false; 32]
+    fn avail_registers(&self) -> [bool; N_REGISTERS] {
+        [false; N_REGISTERS]
     }
 
     fn initial_states(&self) -> Vec<State> {
@@ -350,27 +385,27 @@ impl Generate for Generator {
             states.push(State {
                 id: StateType::GeneratingPreSptrsv as u32,
                 idx: 0,
-                reg: 0,
+                reg: None,
             });
         }
 
         states.push(State {
             id: StateType::InitializingNrow as u32,
             idx: 0,
-            reg: 0,
+            reg: None,
         });
 
         states.push(State {
             id: StateType::GeneratingSptrsv as u32,
             idx: 0,
-            reg: 0,
+            reg: None,
         });
 
         if self.static_iter > 0 {
             states.push(State {
                 id: StateType::GeneratingPostSptrsv as u32,
                 idx: 0,
-                reg: 0,
+                reg: None,
             });
         }
 
@@ -0,0 +1,287 @@
+// Tuning parameters of symgs, with the defaults the README marks.
+//
+// `generator` makes the kernel of `kernel-generator symgs`, on the operands
+// of `symgs(nrow, ucol, lcol, uval, lval, x, tmp, p, d, r)`.
+//
+// The builders panic on the values out of the range `set` takes.
+
+use super::Generator;
+use crate::check::Report;
+use crate::config::*;
+use core::config::{self, Config as _, ConfigError, Fields, Value};
+use core::sparse_matrix::*;
+use core::PrefetchType;
+use serde::{Deserialize, Serialize};
+
+// the layout of the matrix the kernel takes, which is part of its interface
+// rather than a parameter: rows of 32 slots, with the diagonal in `d` instead
+const MATRIX_FORMAT: SparseMatrixFormat = SparseMatrixFormat::ELL(ELLInfo {
+    diag: DiagonalStatus::Excluded,
+    lu: LUStatus::Default,
+    ordering: GridPointOrdering::Default,
+});
+
+// the blocks of 8 slots the kernel loads of a row, those of one triangle
+const BLOCKS_PER_ROW: u8 = 2;
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(into = "Fields", try_from = "Fields")]
+pub struct Config {
+    pub(crate) static_iter: Option<u8>,
+    pub(crate) col_prefetch_type: Option<PrefetchType>,
+    pub(crate) col_prefetch_dist: u16,
+    pub(crate) col_preload_dist: u8,
+    pub(crate) val_prefetch_type: Option<PrefetchType>,
+    pub(crate) val_prefetch_dist: u16,
+    pub(crate) val_preload_dist: Option<u8>,
+    pub(crate) x_preload_dist: u8,
+    pub(crate) spmv_rowblock_size: u8,
+    pub(crate) sptrsv_rowblock_size: u8,
+    pub(crate) nops_c: u8,
+    pub(crate) nops_f0: u8,
+    pub(crate) nops_f1: u8,
+    pub(crate) nops_f2: u8,
+    pub(crate) nops_b0: u8,
+    pub(crate) nops_b1: u8,
+    pub(crate) nops_b2: u8,
+    pub(crate) store_to_tmp: bool,
+    pub(crate) move_reg: bool,
+    pub(crate) move_base: bool,
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Config {
+            static_iter: None,
+            col_prefetch_type: Some(PrefetchType::T2),
+            col_prefetch_dist: 4096,
+            col_preload_dist: 1,
+            val_prefetch_type: Some(PrefetchType::T2),
+            val_prefetch_dist: 4096,
+            val_preload_dist: None,
+            x_preload_dist: 2,
+            spmv_rowblock_size: 8,
+            sptrsv_rowblock_size: 8,
+            nops_c: 0,
+            nops_f0: 0,
+            nops_f1: 0,
+            nops_f2: 0,
+            nops_b0: 0,
+            nops_b1: 0,
+            nops_b2: 0,
+            store_to_tmp: false,
+            move_reg: true,
+            move_base: true,
+        }
+    }
+
+    // the iterations of the constant-time pre and post trsv, none by default
+    pub fn static_iter(mut self, static_iter: Option<u8>) -> Self {
+        self.static_iter = static_iter;
+        self
+    }
+
+    // `None` turns the prefetches of col off
+    pub fn col_prefetch_type(mut self, col_prefetch_type: Option<PrefetchType>) -> Self {
+        self.col_prefetch_type = col_prefetch_type;
+        self
+    }
+
+    pub fn col_prefetch_dist(self, col_prefetch_dist: u16) -> Self {
+        valid(self.set("col_pfd", &Value::Int(col_prefetch_dist as i64)))
+    }
+
+    pub fn col_preload_dist(self, col_preload_dist: u8) -> Self {
+        valid(self.set("col_pld", &u8_value(col_preload_dist)))
+    }
+
+    // `None` turns the prefetches of val off
+    pub fn val_prefetch_type(mut self, val_prefetch_type: Option<PrefetchType>) -> Self {
+        self.val_prefetch_type = val_prefetch_type;
+        self
+    }
+
+    pub fn val_prefetch_dist(self, val_prefetch_dist: u16) -> Self {
+        valid(self.set("val_pfd", &Value::Int(val_prefetch_dist as i64)))
+    }
+
+    // `None` fuses the loads of val into the fmas
+    pub fn val_preload_dist(self, val_preload_dist: Option<u8>) -> Self {
+        valid(self.set("val_pld", &option_u8_value(val_preload_dist)))
+    }
+
+    pub fn x_preload_dist(self, x_preload_dist: u8) -> Self {
+        valid(self.set("x_pld", &u8_value(x_preload_dist)))
+    }
+
+    // of the precomputing spmv
+    pub fn spmv_rowblock_size(self, spmv_rowblock_size: u8) -> Self {
+        valid(self.set("spmv_rowblock", &u8_value(spmv_rowblock_size)))
+    }
+
+    // of the forward and backward trsv
+    pub fn sptrsv_rowblock_size(self, sptrsv_rowblock_size: u8) -> Self {
+        valid(self.set("trsv_rowblock", &u8_value(sptrsv_rowblock_size)))
+    }
+
+    // of the precomputing spmv
+    pub fn nops_c(mut self, nops_c: u8) -> Self {
+        self.nops_c = nops_c;
+        self
+    }
+
+    // of the preforwarding trsv
+    pub fn nops_f0(mut self, nops_f0: u8) -> Self {
+        self.nops_f0 = nops_f0;
+        self
+    }
+
+    // of the forwarding trsv
+    pub fn nops_f1(mut self, nops_f1: u8) -> Self {
+        self.nops_f1 = nops_f1;
+        self
+    }
+
+    // of the postforwarding trsv
+    pub fn nops_f2(mut self, nops_f2: u8) -> Self {
+        self.nops_f2 = nops_f2;
+        self
+    }
+
+    // of the prebackwarding trsv
+    pub fn nops_b0(mut self, nops_b0: u8) -> Self {
+        self.nops_b0 = nops_b0;
+        self
+    }
+
+    // of the backwarding trsv
+    pub fn nops_b1(mut self, nops_b1: u8) -> Self {
+        self.nops_b1 = nops_b1;
+        self
+    }
+
+    // of the postbackwarding trsv
+    pub fn nops_b2(mut self, nops_b2: u8) -> Self {
+        self.nops_b2 = nops_b2;
+        self
+    }
+
+    pub fn store_to_tmp(mut self, store_to_tmp: bool) -> Self {
+        self.store_to_tmp = store_to_tmp;
+        self
+    }
+
+    pub fn move_reg(mut self, move_reg: bool) -> Self {
+        self.move_reg = move_reg;
+        self
+    }
+
+    pub fn move_base(mut self, move_base: bool) -> Self {
+        self.move_base = move_base;
+        self
+    }
+
+    pub fn generator(&self) -> Generator {
+        Generator::new(
+            MATRIX_FORMAT,
+            self.static_iter,
+            self.col_prefetch_type
+                .map(|prefetch_type| (prefetch_type, self.col_prefetch_dist)),
+            self.col_preload_dist,
+            self.val_prefetch_type
+                .map(|prefetch_type| (prefetch_type, self.val_prefetch_dist)),
+            self.val_preload_dist,
+            self.x_preload_dist,
+            self.spmv_rowblock_size,
+            self.sptrsv_rowblock_size,
+            self.nops_c,
+            self.nops_f0,
+            self.nops_f1,
+            self.nops_f2,
+            self.nops_b0,
+            self.nops_b1,
+            self.nops_b2,
+            self.store_to_tmp,
+            self.move_reg,
+            self.move_base,
+        )
+    }
+
+    // the register budget and every rule the kernel breaks, without
+    // generating it
+    pub fn check(&self) -> Report {
+        self.generator().check()
+    }
+}
+
+impl config::Config for Config {
+    const TABLE: &'static str = "symgs";
+
+    fn fields(&self) -> Vec<(&'static str, Value)> {
+        Vec::from([
+            ("static_iter", option_u8_value(self.static_iter)),
+            ("col_pft", prefetch_type_value(self.col_prefetch_type)),
+            ("col_pfd", Value::Int(self.col_prefetch_dist as i64)),
+            ("col_pld", u8_value(self.col_preload_dist)),
+            ("val_pft", prefetch_type_value(self.val_prefetch_type)),
+            ("val_pfd", Value::Int(self.val_prefetch_dist as i64)),
+            ("val_pld", option_u8_value(self.val_preload_dist)),
+            ("x_pld", u8_value(self.x_preload_dist)),
+            ("spmv_rowblock", u8_value(self.spmv_rowblock_size)),
+            ("trsv_rowblock", u8_value(self.sptrsv_rowblock_size)),
+            ("nops_c", u8_value(self.nops_c)),
+            ("nops_f0", u8_value(self.nops_f0)),
+            ("nops_f1", u8_value(self.nops_f1)),
+            ("nops_f2", u8_value(self.nops_f2)),
+            ("nops_b0", u8_value(self.nops_b0)),
+            ("nops_b1", u8_value(self.nops_b1)),
+            ("nops_b2", u8_value(self.nops_b2)),
+            ("store_to_tmp", Value::Bool(self.store_to_tmp)),
+            ("move_reg", Value::Bool(self.move_reg)),
+            ("move_base", Value::Bool(self.move_base)),
+        ])
+    }
+
+    fn set(mut self, key: &str, value: &Value) -> Result<Self, ConfigError> {
+        match key {
+            "static_iter" => self.static_iter = to_option_u8(key, value)?,
+            "col_pft" => self.col_prefetch_type = prefetch_type(key, value)?,
+            "col_pfd" => self.col_prefetch_dist = prefetch_dist(key, value)?,
+            "col_pld" => self.col_preload_dist = col_preload_dist(key, value, BLOCKS_PER_ROW)?,
+            "val_pft" => self.val_prefetch_type = prefetch_type(key, value)?,
+            "val_pfd" => self.val_prefetch_dist = prefetch_dist(key, value)?,
+            "val_pld" => self.val_preload_dist = val_preload_dist(key, value, BLOCKS_PER_ROW)?,
+            "x_pld" => self.x_preload_dist = x_preload_dist(key, value, BLOCKS_PER_ROW)?,
+            "spmv_rowblock" => self.spmv_rowblock_size = rowblock_size(key, value)?,
+            "trsv_rowblock" => self.sptrsv_rowblock_size = rowblock_size(key, value)?,
+            "nops_c" => self.nops_c = to_u8(key, value)?,
+            "nops_f0" => self.nops_f0 = to_u8(key, value)?,
+            "nops_f1" => self.nops_f1 = to_u8(key, value)?,
+            "nops_f2" => self.nops_f2 = to_u8(key, value)?,
+            "nops_b0" => self.nops_b0 = to_u8(key, value)?,
+            "nops_b1" => self.nops_b1 = to_u8(key, value)?,
+            "nops_b2" => self.nops_b2 = to_u8(key, value)?,
+            "store_to_tmp" => self.store_to_tmp = value.to_bool(key)?,
+            "move_reg" => self.move_reg = value.to_bool(key)?,
+            "move_base" => self.move_base = value.to_bool(key)?,
+            _ => return Err(ConfigError::UnknownKey(key.to_string())),
+        }
+
+        Ok(self)
+    }
+}
+
+impl From<Config> for Fields {
+    fn from(config: Config) -> Self {
+        Fields::of(&config)
+    }
+}
+
+// the values on top of the defaults, as a file is read
+impl TryFrom<Fields> for Config {
+    type Error = ConfigError;
+
+    fn try_from(fields: Fields) -> Result<Self, ConfigError> {
+        fields.apply(Config::new())
+    }
+}
@@ -1,4 +1,5 @@
-use crate::{sptrsv, Direction};
+use crate::check::Report;
+use crate::{sptrsv, Direction, IMMUTABLE_NROW};
 use core::sparse_matrix::*;
 use core::*;
 
@@ -7,31 +8,28 @@ impl Generator {
         matrix_format: SparseMatrixFormat,
         static_iter: Option<u8>,
 
-        nrow_name: &'static str,
-        immutable_nrow_name: &'static str,
+        nrow_name: &str,
 
         col_prefetch_info: Option<(PrefetchType, u16)>,
         col_preload_dist: u8,
-        lcol_name: &'static str,
+        lcol_name: &str,
 
         val_prefetch_info: Option<(PrefetchType, u16)>,
         val_preload_dist: Option<u8>,
-        lval_name: &'static str,
+        lval_name: &str,
 
         x_preload_dist: u8,
-        x_name: &'static str,
-        immutable_x_name: &'static str,
+        x_name: &str,
 
-        tmp_name: &'static str,
+        tmp_name: &str,
 
-        cnt_name: &'static str,
-        preforwarding_loop_name: &'static str,
-        forwarding_loop_name: &'static str,
-        postforwarding_loop_name: &'static str,
+        preforwarding_loop_name: &str,
+        forwarding_loop_name: &str,
+        postforwarding_loop_name: &str,
 
-        p_name: &'static str,
-        d_name: &'static str,
-        r_name: &'static str,
+        p_name: &str,
+        d_name: &str,
+        r_name: &str,
 
         rowblock_size: u8,
         nops_before_preforwarding: u8,
@@ -46,25 +44,22 @@ impl Generator {
         Generator {
             matrix_format,
             static_iter,
-            nrow_name,
-            immutable_nrow_name,
+            nrow_name: Name::from(nrow_name),
             col_prefetch_info,
             col_preload_dist,
-            lcol_name,
+            lcol_name: Name::from(lcol_name),
             val_prefetch_info,
             val_preload_dist,
-            lval_name,
+            lval_name: Name::from(lval_name),
             x_preload_dist,
-            x_name,
-            immutable_x_name,
-            tmp_name,
-            cnt_name,
-            preforwarding_loop_name,
-            forwarding_loop_name,
-            postforwarding_loop_name,
-            p_name,
-            d_name,
-            r_name,
+            x_name: Name::from(x_name),
+            tmp_name: Name::from(tmp_name),
+            preforwarding_loop_name: Name::from(preforwarding_loop_name),
+            forwarding_loop_name: Name::from(forwarding_loop_name),
+            postforwarding_loop_name: Name::from(postforwarding_loop_name),
+            p_name: Name::from(p_name),
+            d_name: Name::from(d_name),
+            r_name: Name::from(r_name),
             rowblock_size,
             nops_before_preforwarding,
             nops_before_forwarding,
@@ -80,31 +75,28 @@ pub struct Generator {
     matrix_format: SparseMatrixFormat,
     static_iter: u8,
 
-    nrow_name: &'static str,
-    immutable_nrow_name: &'static str,
+    nrow_name: Name,
 
     col_prefetch_info: Option<(PrefetchType, u16)>,
     col_preload_dist: u8,
-    lcol_name: &'static str,
+    lcol_name: Name,
 
     val_prefetch_info: Option<(PrefetchType, u16)>,
     val_preload_dist: Option<u8>,
-    lval_name: &'static str,
+    lval_name: Name,
 
     x_preload_dist: u8,
-    x_name: &'static str,
-    immutable_x_name: &'static str,
+    x_name: Name,
 
-    tmp_name: &'static str,
+    tmp_name: Name,
 
-    cnt_name: &'static str,
-    preforwarding_loop_name: &'static str,
-    forwarding_loop_name: &'static str,
-    postforwarding_loop_name: &'static str,
+    preforwarding_loop_name: Name,
+    forwarding_loop_name: Name,
+    postforwarding_loop_name: Name,
 
-    p_name: &'static str,
-    d_name: &'static str,
-    r_name: &'static str,
+    p_name: Name,
+    d_name: Name,
+    r_name: Name,
 
     rowblock_size: u8,
     nops_before_preforwarding: u8,
@@ -115,6 +107,129 @@ pub struct Generator {
     move_base: bool,
 }
 
+impl Generator {
+    // the first static_iter rowblocks, counted rather than divided
+    fn pre_sptrsv(&self) -> sptrsv::Generator {
+        sptrsv::Generator::new(
+            self.matrix_format,
+            Direction::Forward,
+            Some(self.static_iter),
+            0,
+            None,
+            self.col_preload_dist,
+            0,
+            None,
+            self.val_preload_dist,
+            self.x_preload_dist,
+            1,
+            self.nops_before_preforwarding,
+            false,
+            self.move_reg,
+            self.move_base,
+            false,
+        )
+        .names(
+            &self.nrow_name,
+            &self.lcol_name,
+            &self.lval_name,
+            &self.x_name,
+            &self.tmp_name,
+            &self.preforwarding_loop_name,
+            &self.p_name,
+            &self.d_name,
+            &self.r_name,
+        )
+    }
+
+    fn sptrsv(&self) -> sptrsv::Generator {
+        let skip_preload = self.static_iter > 0;
+
+        sptrsv::Generator::new(
+            self.matrix_format,
+            Direction::Forward,
+            None,
+            0,
+            self.col_prefetch_info,
+            self.col_preload_dist,
+            0,
+            self.val_prefetch_info,
+            self.val_preload_dist,
+            self.x_preload_dist,
+            self.rowblock_size,
+            self.nops_before_forwarding,
+            self.store_to_tmp,
+            self.move_reg,
+            self.move_base,
+            skip_preload,
+        )
+        .names(
+            &self.nrow_name,
+            &self.lcol_name,
+            &self.lval_name,
+            &self.x_name,
+            &self.tmp_name,
+            &self.forwarding_loop_name,
+            &self.p_name,
+            &self.d_name,
+            &self.r_name,
+        )
+    }
+
+    // the last static_iter rowblocks
+    fn post_sptrsv(&self) -> sptrsv::Generator {
+        sptrsv::Generator::new(
+            self.matrix_format,
+            Direction::Forward,
+            Some(self.static_iter),
+            0,
+            None,
+            self.col_preload_dist,
+            0,
+            None,
+            self.val_preload_dist,
+            self.x_preload_dist,
+            1,
+            self.nops_before_postforwarding,
+            false,
+            self.move_reg,
+            self.move_base,
+            true,
+        )
+        .names(
+            &self.nrow_name,
+            &self.lcol_name,
+            &self.lval_name,
+            &self.x_name,
+            &self.tmp_name,
+            &self.postforwarding_loop_name,
+            &self.p_name,
+            &self.d_name,
+            &self.r_name,
+        )
+    }
+
+    // the main solve takes over the registers preloaded by the pre solve,
+    // and the post solve those of the main solve, which needs them laid out
+    // alike although the pre and post solves keep a single result register
+    fn hands_over_preload(&self) -> bool {
+        self.static_iter == 0
+            || self.pre_sptrsv().preloaded_registers() == self.sptrsv().preloaded_registers()
+    }
+
+    pub(crate) fn check(&self, report: &mut Report) -> () {
+        if self.static_iter > 0 {
+            self.pre_sptrsv()
+                .check_stage("preforwarding", false, report);
+        }
+        self.sptrsv().check_stage("forwarding", false, report);
+        report.preload_handover("forwarding", self.rowblock_size, self.hands_over_preload());
+        if self.static_iter > 0 {
+            self.post_sptrsv()
+                .check_stage("postforwarding", false, report);
+        }
+    }
+}
+
 enum StateType {
     GeneratingPreSptrsv,
     InitializingNrow,
@@ -124,57 +239,28 @@ enum StateType {
 
 const RULEBOOK: &'static [Rule<Generator>] = &[
     Rule {
+        name: "GeneratingPreSptrsv",
         condition: Condition::Single {
             id: StateType::GeneratingPreSptrsv as u32,
         },
         callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let sptrsv_generator = sptrsv::Generator::new(
-                config.matrix_format,
-                Direction::Forward,
-                Some(config.static_iter),
-                config.nrow_name,
-                0,
-                None,
-                config.col_preload_dist,
-                config.lcol_name,
-                0,
-                None,
-                config.val_preload_dist,
-                config.lval_name,
-                config.x_preload_dist,
-                config.x_name,
-                config.immutable_x_name,
-                config.tmp_name,
-                config.cnt_name,
-                config.preforwarding_loop_name,
-                config.p_name,
-                config.d_name,
-                config.r_name,
-                1,
-                config.nops_before_preforwarding,
-                false,
-                config.move_reg,
-                config.move_base,
-                false,
-            );
-
-            let asm = sptrsv_generator.generate()?.empty_line();
+            let asm = config.pre_sptrsv().generate()?.empty_line();
             let states = Vec::new();
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "InitializingNrow",
         condition: Condition::Single {
             id: StateType::InitializingNrow as u32,
         },
         callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
             let asm = {
-                let asm_move =
-                    Assembly::new().move_reg(config.nrow_name, config.immutable_nrow_name);
+                let asm_move = Assembly::new().move_reg(&config.nrow_name, IMMUTABLE_NROW);
                 let asm_sub = match config.static_iter {
                     0 => Assembly::new(),
-                    x => Assembly::new().sub_immediate(config.nrow_name, x as i16 * 2),
+                    x => Assembly::new().sub_immediate(&config.nrow_name, x as i16 * 2),
                 };
 
                 asm_move.append(asm_sub)
@@ -186,83 +272,31 @@ const RULEBOOK: &'static [Rule<Generator>] = &[
         },
     },
     Rule {
+        name: "GeneratingSptrsv",
         condition: Condition::Single {
             id: StateType::GeneratingSptrsv as u32,
         },
         callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let skip_preload = config.static_iter > 1;
-            let sptrsv_generator = sptrsv::Generator::new(
-                config.matrix_format,
-                Direction::Forward,
-                None,
-                config.nrow_name,
-                0,
-                config.col_prefetch_info,
-                config.col_preload_dist,
-                config.lcol_name,
-                0,
-                config.val_prefetch_info,
-                config.val_preload_dist,
-                config.lval_name,
-                config.x_preload_dist,
-                config.x_name,
-                config.immutable_x_name,
-                config.tmp_name,
-                config.cnt_name,
-                config.forwarding_loop_name,
-                config.p_name,
-                config.d_name,
-                config.r_name,
-                config.rowblock_size,
-                config.nops_before_forwarding,
-                config.store_to_tmp,
-                config.move_reg,
-                config.move_base,
-                skip_preload,
-            );
-
-            let asm = sptrsv_generator.generate()?.empty_line();
+            if !config.hands_over_preload() {
+                return Err(GenerateError::Unsupported {
+                    parameter: "rowblock",
+                    value: config.rowblock_size.to_string(),
+                });
+            }
+
+            let asm = config.sptrsv().generate()?.empty_line();
             let states = Vec::new();
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "GeneratingPostSptrsv",
         condition: Condition::Single {
             id: StateType::GeneratingPostSptrsv as u32,
         },
         callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let sptrsv_generator = sptrsv::Generator::new(
-                config.matrix_format,
-                Direction::Forward,
-                Some(config.static_iter),
-                config.nrow_name,
-                0,
-                None,
-                config.col_preload_dist,
-                config.lcol_name,
-                0,
-                None,
-                config.val_preload_dist,
-                config.lval_name,
-                config.x_preload_dist,
-                config.x_name,
-                config.immutable_x_name,
-                config.tmp_name,
-                config.cnt_name,
-                config.postforwarding_loop_name,
-                config.p_name,
-                config.d_name,
-                config.r_name,
-                1,
-                config.nops_before_postforwarding,
-                false,
-                config.move_reg,
-                config.move_base,
-                true,
-            );
-
-            let asm = sptrsv_generator.generate()?;
+            let asm = config.post_sptrsv().generate()?;
             let states = Vec::new();
 
             Ok((asm, states))
@@ -275,8 +309,8 @@ impl Generate for Generator {
         RULEBOOK
     }
 
-    fn avail_registers(&self) -> [bool; 32] {
-        [false; 32]
+    fn avail_registers(&self) -> [bool; N_REGISTERS] {
+        [false; N_REGISTERS]
     }
 
     fn initial_states(&self) -> Vec<State> {
@@ -286,27 +320,27 @@ impl Generate for Generator {
             states.push(State {
                 id: StateType::GeneratingPreSptrsv as u32,
                 idx: 0,
-                reg: 0,
+                reg: None,
             });
         }
 
         states.push(State {
             id: StateType::InitializingNrow as u32,
             idx: 0,
-            reg: 0,
+            reg: None,
         });
 
         states.push(State {
             id: StateType::GeneratingSptrsv as u32,
             idx: 0,
-            reg: 0,
+            reg: None,
         });
 
         if self.static_iter > 0 {
             states.push(State {
                 id: StateType::GeneratingPostSptrsv as u32,
                 idx: 0,
-                reg: 0,
+                reg: None,
             });
         }
 
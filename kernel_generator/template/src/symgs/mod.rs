@@ -1,49 +1,29 @@
 // symgs implementation method : Xiaojian Yang, Shengguo Li, Fan Yuan, Dezun Dong, Chun Huang, and Zheng Wang. 2023. Optimizing Multi-grid Computation and Parallelization on Multi-cores. In Proceedings of the 37th ACM International Conference on Supercomputing (ICS '23). Association for Computing Machinery, New York, NY, USA, 227–239. https://doi.org/10.1145/3577193.3593726
 
+use crate::check::Report;
+use crate::{COUNTER, IMMUTABLE_NROW, IMMUTABLE_P, IMMUTABLE_X};
 use core::sparse_matrix::*;
 use core::*;
 
 mod backwarding;
+mod config;
 mod forwarding;
 mod precomputing;
 
+pub use config::Config;
+
 impl Generator {
     pub fn new(
         matrix_format: SparseMatrixFormat,
         sptrsv_static_iter: Option<u8>,
 
-        nrow_name: &'static str,
-        immutable_nrow_name: &'static str,
-
         col_prefetch_info: Option<(PrefetchType, u16)>,
         col_preload_dist: u8,
-        ucol_name: &'static str,
-        lcol_name: &'static str,
 
         val_prefetch_info: Option<(PrefetchType, u16)>,
         val_preload_dist: Option<u8>,
-        uval_name: &'static str,
-        lval_name: &'static str,
 
         x_preload_dist: u8,
-        x_name: &'static str,
-        immutable_x_name: &'static str,
-
-        tmp_name: &'static str,
-
-        cnt_name: &'static str,
-        precomputing_loop_name: &'static str,
-        preforwarding_loop_name: &'static str,
-        forwarding_loop_name: &'static str,
-        postforwarding_loop_name: &'static str,
-        prebackwarding_loop_name: &'static str,
-        backwarding_loop_name: &'static str,
-        postbackwarding_loop_name: &'static str,
-
-        p_name: &'static str,
-        immutable_p_name: &'static str,
-        d_name: &'static str,
-        r_name: &'static str,
 
         spmv_rowblock_size: u8,
         sptrsv_rowblock_size: u8,
@@ -61,32 +41,28 @@ impl Generator {
         let generator = Generator {
             matrix_format,
             sptrsv_static_iter,
-            nrow_name,
-            immutable_nrow_name,
+            nrow_name: Name::from("NROW"),
             col_prefetch_info,
             col_preload_dist,
-            ucol_name,
-            lcol_name,
+            ucol_name: Name::from("UCOL"),
+            lcol_name: Name::from("LCOL"),
             val_prefetch_info,
             val_preload_dist,
-            uval_name,
-            lval_name,
+            uval_name: Name::from("UVAL"),
+            lval_name: Name::from("LVAL"),
             x_preload_dist,
-            x_name,
-            immutable_x_name,
-            tmp_name,
-            cnt_name,
-            precomputing_loop_name,
-            preforwarding_loop_name,
-            forwarding_loop_name,
-            postforwarding_loop_name,
-            prebackwarding_loop_name,
-            backwarding_loop_name,
-            postbackwarding_loop_name,
-            p_name,
-            immutable_p_name,
-            d_name,
-            r_name,
+            x_name: Name::from("X"),
+            tmp_name: Name::from("TMP"),
+            precomputing_loop_name: Name::from("loop_c"),
+            preforwarding_loop_name: Name::from("loop_f0"),
+            forwarding_loop_name: Name::from("loop_f1"),
+            postforwarding_loop_name: Name::from("loop_f2"),
+            prebackwarding_loop_name: Name::from("loop_b0"),
+            backwarding_loop_name: Name::from("loop_b1"),
+            postbackwarding_loop_name: Name::from("loop_b2"),
+            p_name: Name::from("P"),
+            d_name: Name::from("D"),
+            r_name: Name::from("R"),
             spmv_rowblock_size,
             sptrsv_rowblock_size,
             nops_before_precomputing,
@@ -109,38 +85,34 @@ pub struct Generator {
     matrix_format: SparseMatrixFormat,
     sptrsv_static_iter: Option<u8>,
 
-    nrow_name: &'static str,
-    immutable_nrow_name: &'static str,
+    nrow_name: Name,
 
     col_prefetch_info: Option<(PrefetchType, u16)>,
     col_preload_dist: u8,
-    ucol_name: &'static str,
-    lcol_name: &'static str,
+    ucol_name: Name,
+    lcol_name: Name,
 
     val_prefetch_info: Option<(PrefetchType, u16)>,
     val_preload_dist: Option<u8>,
-    uval_name: &'static str,
-    lval_name: &'static str,
+    uval_name: Name,
+    lval_name: Name,
 
     x_preload_dist: u8,
-    x_name: &'static str,
-    immutable_x_name: &'static str,
+    x_name: Name,
 
-    tmp_name: &'static str,
+    tmp_name: Name,
 
-    cnt_name: &'static str,
-    precomputing_loop_name: &'static str,
-    preforwarding_loop_name: &'static str,
-    forwarding_loop_name: &'static str,
-    postforwarding_loop_name: &'static str,
-    prebackwarding_loop_name: &'static str,
-    backwarding_loop_name: &'static str,
-    postbackwarding_loop_name: &'static str,
+    precomputing_loop_name: Name,
+    preforwarding_loop_name: Name,
+    forwarding_loop_name: Name,
+    postforwarding_loop_name: Name,
+    prebackwarding_loop_name: Name,
+    backwarding_loop_name: Name,
+    postbackwarding_loop_name: Name,
 
-    p_name: &'static str,
-    immutable_p_name: &'static str,
-    d_name: &'static str,
-    r_name: &'static str,
+    p_name: Name,
+    d_name: Name,
+    r_name: Name,
 
     spmv_rowblock_size: u8,
     sptrsv_rowblock_size: u8,
@@ -156,178 +128,160 @@ pub struct Generator {
     move_base: bool,
 }
 
+impl Generator {
+    fn precomputing(&self) -> precomputing::Generator {
+        precomputing::Generator::new(
+            self.matrix_format,
+            &self.nrow_name,
+            self.col_prefetch_info,
+            self.col_preload_dist,
+            &self.ucol_name,
+            self.val_prefetch_info,
+            self.val_preload_dist,
+            &self.uval_name,
+            self.x_preload_dist,
+            &self.x_name,
+            &self.tmp_name,
+            &self.precomputing_loop_name,
+            &self.p_name,
+            self.spmv_rowblock_size,
+            self.nops_before_precomputing,
+            self.store_to_tmp,
+            self.move_reg,
+            self.move_base,
+        )
+    }
+
+    fn forwarding(&self) -> forwarding::Generator {
+        forwarding::Generator::new(
+            self.matrix_format,
+            self.sptrsv_static_iter,
+            &self.nrow_name,
+            self.col_prefetch_info,
+            self.col_preload_dist,
+            &self.lcol_name,
+            self.val_prefetch_info,
+            self.val_preload_dist,
+            &self.lval_name,
+            self.x_preload_dist,
+            &self.x_name,
+            &self.tmp_name,
+            &self.preforwarding_loop_name,
+            &self.forwarding_loop_name,
+            &self.postforwarding_loop_name,
+            &self.p_name,
+            &self.d_name,
+            &self.r_name,
+            self.sptrsv_rowblock_size,
+            self.nops_before_preforwarding,
+            self.nops_before_forwarding,
+            self.nops_before_postforwarding,
+            self.store_to_tmp,
+            self.move_reg,
+            self.move_base,
+        )
+    }
+
+    fn backwarding(&self) -> backwarding::Generator {
+        backwarding::Generator::new(
+            self.matrix_format,
+            self.sptrsv_static_iter,
+            &self.nrow_name,
+            self.col_prefetch_info,
+            self.col_preload_dist,
+            &self.ucol_name,
+            self.val_prefetch_info,
+            self.val_preload_dist,
+            &self.uval_name,
+            self.x_preload_dist,
+            &self.x_name,
+            &self.tmp_name,
+            &self.prebackwarding_loop_name,
+            &self.backwarding_loop_name,
+            &self.postbackwarding_loop_name,
+            &self.p_name,
+            &self.d_name,
+            &self.r_name,
+            self.sptrsv_rowblock_size,
+            self.nops_before_prebackwarding,
+            self.nops_before_backwarding,
+            self.nops_before_postbackwarding,
+            self.store_to_tmp,
+            self.move_reg,
+            self.move_base,
+        )
+    }
+
+    // the general purpose registers of the kernel: the arguments in C
+    // parameter order, the row counter, and the copies of nrow, x and p the
+    // sweeps restore them from
+    fn operands(&self) -> Assembly {
+        Assembly::new()
+            .argument_int(&self.nrow_name)
+            .argument_pointer(&self.ucol_name)
+            .argument_pointer(&self.lcol_name)
+            .argument_pointer(&self.uval_name)
+            .argument_pointer(&self.lval_name)
+            .argument_pointer(&self.x_name)
+            .argument_pointer(&self.tmp_name)
+            .argument_pointer(&self.p_name)
+            .argument_pointer(&self.d_name)
+            .argument_pointer(&self.r_name)
+            .local_int(COUNTER)
+            .local_copy(IMMUTABLE_NROW, &self.nrow_name)
+            .local_copy(IMMUTABLE_X, &self.x_name)
+            .local_copy(IMMUTABLE_P, &self.p_name)
+    }
+
+    // p = -Ux, then x = trsv(D+L, r+p) & p = Dx-p, then x = trsv(D+U, p)
+    fn pipeline(&self) -> Pipeline {
+        Pipeline::new()
+            .then("precomputing", self.precomputing())
+            .then("forwarding", self.forwarding())
+            .then("backwarding", self.backwarding())
+    }
+
+    pub fn check(&self) -> Report {
+        let mut report = Report::new();
+        self.precomputing().check(&mut report);
+        self.forwarding().check(&mut report);
+        self.backwarding().check(&mut report);
+
+        report
+    }
+}
+
 enum StateType {
-    Precomputing, // p = -Ux
-    Forwarding,   // x = trsv(D+L, r+p) & p = Dx-p
-    Backwarding,  // x = trsv(D+U, p)
+    Running,
 }
-// +ucol(over), +uval(over), +p
-
-// reset p
-
-// +lcol(over), +lval(over), +x, +p, +r, +d
-
-// fix ucol, uval, x, p, d
-
-// -ucol(over), -uval(over), -x, -p, -d
-
-const RULEBOOK: &'static [Rule<Generator>] = &[
-    Rule {
-        condition: Condition::Single {
-            id: StateType::Precomputing as u32,
-        },
-        callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let precomputing_generator = precomputing::Generator::new(
-                config.matrix_format,
-                config.nrow_name,
-                config.col_prefetch_info,
-                config.col_preload_dist,
-                config.ucol_name,
-                config.val_prefetch_info,
-                config.val_preload_dist,
-                config.uval_name,
-                config.x_preload_dist,
-                config.x_name,
-                config.tmp_name,
-                config.cnt_name,
-                config.precomputing_loop_name,
-                config.p_name,
-                config.immutable_p_name,
-                config.spmv_rowblock_size,
-                config.nops_before_precomputing,
-                config.store_to_tmp,
-                config.move_reg,
-                config.move_base,
-            );
-
-            let asm = Assembly::new()
-                .comment("--- precomputing start --- //")
-                .append(precomputing_generator.generate()?)
-                .comment("---  precomputing end  --- //")
-                .empty_line();
-            let next_id = StateType::Forwarding as u32;
-            let states = Vec::from([State {
-                id: next_id,
-                idx: 0,
-                reg: 0,
-            }]);
-
-            Ok((asm, states))
-        },
-    },
-    Rule {
-        condition: Condition::Single {
-            id: StateType::Forwarding as u32,
-        },
-        callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let forwarding_generator = forwarding::Generator::new(
-                config.matrix_format,
-                config.sptrsv_static_iter,
-                config.nrow_name,
-                config.immutable_nrow_name,
-                config.col_prefetch_info,
-                config.col_preload_dist,
-                config.lcol_name,
-                config.val_prefetch_info,
-                config.val_preload_dist,
-                config.lval_name,
-                config.x_preload_dist,
-                config.x_name,
-                config.immutable_x_name,
-                config.tmp_name,
-                config.cnt_name,
-                config.preforwarding_loop_name,
-                config.forwarding_loop_name,
-                config.postforwarding_loop_name,
-                config.p_name,
-                config.d_name,
-                config.r_name,
-                config.sptrsv_rowblock_size,
-                config.nops_before_preforwarding,
-                config.nops_before_forwarding,
-                config.nops_before_postforwarding,
-                config.store_to_tmp,
-                config.move_reg,
-                config.move_base,
-            );
-
-            let asm = Assembly::new()
-                .comment("--- forwarding start --- //")
-                .append(forwarding_generator.generate()?)
-                .comment("---  forwarding end  --- //")
-                .empty_line();
-            let next_id = StateType::Backwarding as u32;
-            let states = Vec::from([State {
-                id: next_id,
-                idx: 0,
-                reg: 0,
-            }]);
-
-            Ok((asm, states))
-        },
+
+const RULEBOOK: &'static [Rule<Generator>] = &[Rule {
+    name: "Running",
+    condition: Condition::Single {
+        id: StateType::Running as u32,
     },
-    Rule {
-        condition: Condition::Single {
-            id: StateType::Backwarding as u32,
-        },
-        callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let backwarding_generator = backwarding::Generator::new(
-                config.matrix_format,
-                config.sptrsv_static_iter,
-                config.nrow_name,
-                config.immutable_nrow_name,
-                config.col_prefetch_info,
-                config.col_preload_dist,
-                config.ucol_name,
-                config.val_prefetch_info,
-                config.val_preload_dist,
-                config.uval_name,
-                config.x_preload_dist,
-                config.x_name,
-                config.immutable_x_name,
-                config.tmp_name,
-                config.cnt_name,
-                config.prebackwarding_loop_name,
-                config.backwarding_loop_name,
-                config.postbackwarding_loop_name,
-                config.p_name,
-                config.d_name,
-                config.r_name,
-                config.sptrsv_rowblock_size,
-                config.nops_before_prebackwarding,
-                config.nops_before_backwarding,
-                config.nops_before_postbackwarding,
-                config.store_to_tmp,
-                config.move_reg,
-                config.move_base,
-            );
-
-            let asm = Assembly::new()
-                .comment("--- backwarding start --- //")
-                .append(backwarding_generator.generate()?)
-                .comment("---  backwarding end  --- //")
-                .empty_line();
-            let states = Vec::new();
-
-            Ok((asm, states))
-        },
+    callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
+        let asm = config.operands().append(config.pipeline().generate()?);
+        let states = Vec::new();
+
+        Ok((asm, states))
     },
-];
+}];
 
 impl Generate for Generator {
     fn rulebook(&self) -> &'static [Rule<Self>] {
         RULEBOOK
     }
 
-    fn avail_registers(&self) -> [bool; 32] {
-        [false; 32]
+    fn avail_registers(&self) -> [bool; N_REGISTERS] {
+        [false; N_REGISTERS]
     }
 
     fn initial_states(&self) -> Vec<State> {
         let initial_state = State {
-            id: StateType::Precomputing as u32,
+            id: StateType::Running as u32,
             idx: 0,
-            reg: 0,
+            reg: None,
         };
         let states = Vec::from([initial_state]);
 
@@ -1,31 +1,30 @@
+use crate::check::Report;
 use crate::spmv;
-use crate::Direction;
+use crate::{Direction, IMMUTABLE_P};
 use core::*;
 
 impl Generator {
     pub fn new(
         matrix_format: sparse_matrix::SparseMatrixFormat,
 
-        nrow_name: &'static str,
+        nrow_name: &str,
 
         col_prefetch_info: Option<(PrefetchType, u16)>,
         col_preload_dist: u8,
-        ucol_name: &'static str,
+        ucol_name: &str,
 
         val_prefetch_info: Option<(PrefetchType, u16)>,
         val_preload_dist: Option<u8>,
-        uval_name: &'static str,
+        uval_name: &str,
 
         x_preload_dist: u8,
-        x_name: &'static str,
+        x_name: &str,
 
-        tmp_name: &'static str,
+        tmp_name: &str,
 
-        cnt_name: &'static str,
-        loop_name: &'static str,
+        loop_name: &str,
 
-        p_name: &'static str,
-        immutable_p_name: &'static str,
+        p_name: &str,
 
         rowblock_size: u8,
         nops_before_precomputing: u8,
@@ -35,20 +34,18 @@ impl Generator {
     ) -> Self {
         Generator {
             matrix_format,
-            nrow_name,
+            nrow_name: Name::from(nrow_name),
             col_prefetch_info,
             col_preload_dist,
-            ucol_name,
+            ucol_name: Name::from(ucol_name),
             val_prefetch_info,
             val_preload_dist,
-            uval_name,
+            uval_name: Name::from(uval_name),
             x_preload_dist,
-            x_name,
-            tmp_name,
-            cnt_name,
-            loop_name,
-            p_name,
-            immutable_p_name,
+            x_name: Name::from(x_name),
+            tmp_name: Name::from(tmp_name),
+            loop_name: Name::from(loop_name),
+            p_name: Name::from(p_name),
             rowblock_size,
             nops_before_precomputing,
             store_to_tmp,
@@ -61,26 +58,24 @@ impl Generator {
 pub struct Generator {
     matrix_format: sparse_matrix::SparseMatrixFormat,
 
-    nrow_name: &'static str,
+    nrow_name: Name,
 
     col_prefetch_info: Option<(PrefetchType, u16)>,
     col_preload_dist: u8,
-    ucol_name: &'static str,
+    ucol_name: Name,
 
     val_prefetch_info: Option<(PrefetchType, u16)>,
     val_preload_dist: Option<u8>,
-    uval_name: &'static str,
+    uval_name: Name,
 
     x_preload_dist: u8,
-    x_name: &'static str,
+    x_name: Name,
 
-    tmp_name: &'static str,
+    tmp_name: Name,
 
-    cnt_name: &'static str,
-    loop_name: &'static str,
+    loop_name: Name,
 
-    p_name: &'static str,
-    immutable_p_name: &'static str,
+    p_name: Name,
 
     rowblock_size: u8,
     nops_before_precomputing: u8,
@@ -89,6 +84,43 @@ pub struct Generator {
     move_base: bool,
 }
 
+impl Generator {
+    // p = -Ux
+    fn spmv(&self) -> spmv::Generator {
+        let action = spmv::Action::AssignNegUx;
+        let direction = Direction::Forward;
+
+        spmv::Generator::new(
+            self.matrix_format,
+            action,
+            direction,
+            self.col_prefetch_info,
+            self.col_preload_dist,
+            self.val_prefetch_info,
+            self.val_preload_dist,
+            self.x_preload_dist,
+            self.rowblock_size,
+            self.nops_before_precomputing,
+            self.store_to_tmp,
+            self.move_reg,
+            self.move_base,
+        )
+        .names(
+            &self.nrow_name,
+            &self.ucol_name,
+            &self.uval_name,
+            &self.x_name,
+            &self.tmp_name,
+            &self.loop_name,
+            &self.p_name,
+        )
+    }
+
+    pub(crate) fn check(&self, report: &mut Report) -> () {
+        self.spmv().check_stage("precomputing", false, report);
+    }
+}
+
 enum StateType {
     GeneratingSpmv,
     RestoringP,
@@ -96,54 +128,29 @@ enum StateType {
 
 const RULEBOOK: &'static [Rule<Generator>] = &[
     Rule {
+        name: "GeneratingSpmv",
         condition: Condition::Single {
             id: StateType::GeneratingSpmv as u32,
         },
         callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let action = spmv::Action::AssignNegUx;
-            let direction = Direction::Forward;
-
-            let spmv_generator = spmv::Generator::new(
-                config.matrix_format,
-                action,
-                direction,
-                config.nrow_name,
-                config.col_prefetch_info,
-                config.col_preload_dist,
-                config.ucol_name,
-                config.val_prefetch_info,
-                config.val_preload_dist,
-                config.uval_name,
-                config.x_preload_dist,
-                config.x_name,
-                config.tmp_name,
-                config.cnt_name,
-                config.loop_name,
-                config.p_name,
-                config.rowblock_size,
-                config.nops_before_precomputing,
-                config.store_to_tmp,
-                config.move_reg,
-                config.move_base,
-            );
-
-            let asm = spmv_generator.generate()?;
+            let asm = config.spmv().generate()?;
             let next_id = StateType::RestoringP as u32;
             let states = Vec::from([State {
                 id: next_id,
                 idx: 0,
-                reg: 0,
+                reg: None,
             }]);
 
             Ok((asm, states))
         },
     },
     Rule {
+        name: "RestoringP",
         condition: Condition::Single {
             id: StateType::RestoringP as u32,
         },
         callback: |config: &Generator, _rp: &mut RegisterPool, _states: &Vec<State>| {
-            let asm = Assembly::new().move_reg(config.p_name, config.immutable_p_name);
+            let asm = Assembly::new().move_reg(&config.p_name, IMMUTABLE_P);
             let states = Vec::new();
 
             Ok((asm, states))
@@ -156,15 +163,15 @@ impl Generate for Generator {
         RULEBOOK
     }
 
-    fn avail_registers(&self) -> [bool; 32] {
-        [false; 32]
+    fn avail_registers(&self) -> [bool; N_REGISTERS] {
+        [false; N_REGISTERS]
     }
 
     fn initial_states(&self) -> Vec<State> {
         let states = Vec::from([State {
             id: StateType::GeneratingSpmv as u32,
             idx: 0,
-            reg: 0,
+            reg: None,
         }]);
 
         states
@@ -0,0 +1,228 @@
+// Kernel configurations checked without generating them, against what
+// generating them does.
+
+extern crate core;
+
+use core::config::Config;
+use core::sparse_matrix::*;
+use core::{Generate, PrefetchType};
+use template::check::Violation;
+use template::*;
+
+fn lines(report: &check::Report) -> Vec<String> {
+    report
+        .to_string()
+        .lines()
+        .map(|line| line.to_string())
+        .collect()
+}
+
+#[test]
+fn budgets_add_up_the_register_blocks() {
+    let report = spmv::Config::new().check();
+    assert!(report.is_ok());
+    assert_eq!(
+        lines(&report),
+        ["spmv: col 8 + res 8 + x 12 + val 0 = 28 of 32 registers"]
+    );
+
+    let report = spmv::Config::new()
+        .col_preload_dist(2)
+        .val_preload_dist(Some(1))
+        .check();
+    assert!(report.is_ok());
+    assert_eq!(
+        lines(&report),
+        ["spmv: col 12 + res 8 + x 12 + val 8 + scratch 3 = 43 registers, 11 spilled"]
+    );
+}
+
+#[test]
+fn every_broken_rule_is_listed() {
+    let report = spmv::Config::new()
+        .col_preload_dist(3)
+        .val_preload_dist(Some(6))
+        .x_preload_dist(4)
+        .rowblock_size(2)
+        .check();
+    assert_eq!(
+        lines(&report)[1..],
+        [
+            "spmv: 69 registers are more than the 32 registers plus 32 spill slots there are",
+            "spmv: col blocks reach register 18, past the 16 registers VEX encodes the col \
+             indices of gathers in, which are never spilled",
+            "spmv: rowblock 2 is not supported, expected 1 or 8 to accumulate the results",
+        ]
+    );
+
+    let report = spmv::Config::new()
+        .x_preload_dist(3)
+        .rowblock_size(1)
+        .store_to_tmp(true)
+        .check();
+    assert_eq!(
+        lines(&report)[1..],
+        [
+            "spmv: preloading x gathers 12 blocks at once, but only 9 of the 16 registers VEX \
+          encodes are free for their col indices, which are never spilled"
+        ]
+    );
+
+    let report = spmv::Config::new()
+        .val_prefetch_dist(32703)
+        .rowblock_size(2)
+        .move_reg(false)
+        .check();
+    assert_eq!(
+        lines(&report)[1..],
+        [
+            "spmv: val_prefetch_dist 32703 is over 32575, the farthest a prefetch reaches",
+            "spmv: rowblock 2 is not a multiple of the 6 nanokernels move_reg f unrolls",
+            "spmv: rowblock 2 is not supported, expected 1 or 8 to accumulate the results",
+        ]
+    );
+
+    // the generators take the values the configurations keep out of range
+    let info = ELLInfo::new(
+        DiagonalStatus::Default,
+        LUStatus::Default,
+        GridPointOrdering::Default,
+    );
+    let generator = spmv::Generator::new(
+        SparseMatrixFormat::ELL(info),
+        spmv::Action::AssignPosAx,
+        Direction::Forward,
+        Some((PrefetchType::T2, 40000)),
+        1,
+        Some((PrefetchType::T2, 0)),
+        None,
+        2,
+        3,
+        0,
+        false,
+        true,
+        true,
+    );
+    assert_eq!(
+        lines(&generator.check())[1..],
+        [
+            "spmv: col_prefetch_dist 40000 is over 32703, the farthest a prefetch reaches",
+            "spmv: val_prefetch_dist 0 prefetches the block being loaded, expected 1 to 32575",
+            "spmv: rowblock 3 is not supported, expected a power of two to divide the rows by",
+            "spmv: rowblock 3 is not supported, expected 1 or 8 to accumulate the results",
+        ]
+    );
+}
+
+#[test]
+fn accumulating_from_tmp_needs_a_register_per_row() {
+    let config = sptrsv::Config::new()
+        .col_preload_dist(3)
+        .val_preload_dist(Some(5))
+        .x_preload_dist(4)
+        .store_to_tmp(true);
+    assert_eq!(
+        lines(&config.check()),
+        [
+            "trsv: col 8 + res 1 + x 10 + val 12 = 31 of 32 registers",
+            "trsv: accumulating a rowblock takes 8 registers at once, but only 7 are free \
+             without spilling",
+        ]
+    );
+    assert!(config.x_preload_dist(3).check().is_ok());
+}
+
+#[test]
+fn symgs_checks_each_stage() {
+    let report = symgs::Config::new()
+        .static_iter(Some(2))
+        .store_to_tmp(true)
+        .check();
+    let stages: Vec<_> = report.budgets.iter().map(|budget| budget.stage).collect();
+    assert_eq!(
+        stages,
+        [
+            "precomputing",
+            "preforwarding",
+            "forwarding",
+            "postforwarding",
+            "prebackwarding",
+            "backwarding",
+            "postbackwarding",
+        ]
+    );
+    assert!(report.is_ok());
+
+    // the stages of symgs have no spill area
+    let report = symgs::Config::new()
+        .col_preload_dist(3)
+        .val_preload_dist(Some(5))
+        .x_preload_dist(4)
+        .check();
+    assert_eq!(report.violations.len(), 3);
+    assert!(report
+        .violations
+        .iter()
+        .all(|violation| matches!(violation, Violation::NoSpill { need: 41, .. })));
+}
+
+#[test]
+fn static_iterations_count_single_rows() {
+    let config = sptrsv::Config::new().static_iter(Some(0));
+    assert_eq!(
+        lines(&config.check())[1..],
+        ["trsv: static_iter 0 is not supported, expected 1 or more, or -1 for none",]
+    );
+    assert!(config.generator().generate().is_err());
+    assert_eq!(
+        lines(&config.static_iter(Some(2)).check())[1..],
+        ["trsv: rowblock 8 is not supported, expected 1 with static_iter, which counts the rows \
+          one by one"]
+    );
+    assert!(config.static_iter(Some(2)).rowblock_size(1).check().is_ok());
+
+    // the pre and post solves of symgs keep a single result register, so
+    // the main solve can only take their preloaded registers over with one
+    let config = symgs::Config::new().static_iter(Some(2));
+    assert_eq!(
+        lines(&config.check())[7..],
+        [
+            "forwarding: rowblock 8 is not supported, expected 1 or store_to_tmp t with \
+             static_iter, for the preloaded registers to line up with those of the pre and \
+             post solves",
+            "backwarding: rowblock 8 is not supported, expected 1 or store_to_tmp t with \
+             static_iter, for the preloaded registers to line up with those of the pre and \
+             post solves",
+        ]
+    );
+    assert!(config.generator().generate().is_err());
+    assert!(config.store_to_tmp(true).generator().generate().is_ok());
+}
+
+// spilling comes after `generate`, so the configurations that cannot spill
+// are left to `NoSpill` above
+#[test]
+fn check_agrees_with_generate() {
+    for col_pld in [0, 1, 3] {
+        for val_pld in [None, Some(0), Some(2), Some(5)] {
+            for x_pld in [0, 2, 4] {
+                for rowblock_size in [1, 2, 4, 8] {
+                    for move_reg in [true, false] {
+                        let config = spmv::Config::new()
+                            .col_preload_dist(col_pld)
+                            .val_preload_dist(val_pld)
+                            .x_preload_dist(x_pld)
+                            .rowblock_size(rowblock_size)
+                            .move_reg(move_reg);
+                        assert_eq!(
+                            config.check().is_ok(),
+                            config.generator().generate().is_ok(),
+                            "{}",
+                            config.to_toml()
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,257 @@
+// Kernel configurations built, written to JSON and TOML, and read back.
+
+extern crate core;
+
+use core::config::{Config, ConfigError, Value};
+use core::{Generate, PrefetchType};
+use template::*;
+
+fn kernel<T: Generate>(generator: T) -> String {
+    generator.generate().unwrap().print(0, &[], &[])
+}
+
+#[test]
+fn defaults_are_the_readme_values() {
+    assert_eq!(
+        spmv::Config::new().to_toml(),
+        "\
+col_pft = \"T2\"
+col_pfd = 4096
+col_pld = 1
+val_pft = \"T2\"
+val_pfd = 4096
+val_pld = -1
+x_pld = 2
+rowblock = 8
+nops = 0
+store_to_tmp = false
+move_reg = true
+move_base = true
+"
+    );
+}
+
+#[test]
+fn spmv_round_trip() {
+    let config = spmv::Config::new()
+        .col_prefetch_type(None)
+        .col_prefetch_dist(512)
+        .val_prefetch_type(Some(PrefetchType::NTA))
+        .val_preload_dist(Some(0))
+        .rowblock_size(1)
+        .nops(2)
+        .move_base(false);
+
+    for reloaded in [
+        spmv::Config::new().read_json(&config.to_json()).unwrap(),
+        spmv::Config::new().read_toml(&config.to_toml()).unwrap(),
+    ] {
+        assert_eq!(reloaded.to_json(), config.to_json());
+        assert_eq!(kernel(reloaded.generator()), kernel(config.generator()));
+    }
+}
+
+#[test]
+fn sptrsv_round_trip() {
+    let config = sptrsv::Config::new()
+        .direction(Direction::Backward)
+        .static_iter(Some(2))
+        .x_preload_dist(1)
+        .store_to_tmp(true);
+
+    let reloaded = sptrsv::Config::new().read_json(&config.to_json()).unwrap();
+    assert_eq!(reloaded.to_toml(), config.to_toml());
+    assert!(config
+        .to_toml()
+        .starts_with("direction = \"backward\"\nstatic_iter = 2\n"));
+}
+
+#[test]
+fn symgs_round_trip() {
+    let config = symgs::Config::new()
+        .spmv_rowblock_size(1)
+        .sptrsv_rowblock_size(1)
+        .nops_f1(3)
+        .nops_b2(1);
+
+    let reloaded = symgs::Config::new().read_toml(&config.to_toml()).unwrap();
+    assert_eq!(reloaded.to_json(), config.to_json());
+    assert_eq!(kernel(reloaded.generator()), kernel(config.generator()));
+}
+
+#[test]
+fn files_may_leave_values_out() {
+    let text = "\
+# tuned for level 2
+rowblock = 4   # fewer rows
+'move_reg' = false
+";
+    let config = spmv::Config::new().read_toml(text).unwrap();
+    let expected = spmv::Config::new().rowblock_size(4).move_reg(false);
+    assert_eq!(config.to_toml(), expected.to_toml());
+
+    let config = spmv::Config::new()
+        .read_json("{ \"x_pld\": 0, \"val_pft\": \"None\" }")
+        .unwrap();
+    assert_eq!(
+        config.fields()[3..7],
+        [
+            ("val_pft", Value::Str("None".to_string())),
+            ("val_pfd", Value::Int(4096)),
+            ("val_pld", Value::Int(-1)),
+            ("x_pld", Value::Int(0)),
+        ]
+    );
+}
+
+// the values at the top level go to every kernel, and a table to its own
+#[test]
+fn files_may_have_a_table_per_kernel() {
+    let text = "\
+col_pft = \"NTA\"
+
+[spmv]
+rowblock = 4
+
+[trsv]
+direction = \"backward\"
+rowblock = 1
+";
+    let config = spmv::Config::new().read_toml(text).unwrap();
+    let expected = spmv::Config::new()
+        .col_prefetch_type(Some(PrefetchType::NTA))
+        .rowblock_size(4);
+    assert_eq!(config.to_toml(), expected.to_toml());
+
+    let config = sptrsv::Config::new().read_toml(text).unwrap();
+    let expected = sptrsv::Config::new()
+        .col_prefetch_type(Some(PrefetchType::NTA))
+        .direction(Direction::Backward)
+        .rowblock_size(1);
+    assert_eq!(config.to_toml(), expected.to_toml());
+
+    assert_eq!(
+        symgs::Config::new()
+            .read_toml(text)
+            .err()
+            .unwrap()
+            .to_string(),
+        "no `symgs` table"
+    );
+
+    let config = symgs::Config::new()
+        .read_json("{ \"symgs\": { \"nops_f1\": 3 }, \"spmv\": { \"nops\": 1 } }")
+        .unwrap();
+    assert_eq!(config.to_json(), symgs::Config::new().nops_f1(3).to_json());
+}
+
+// the builders derive serde, so a configuration may be part of another
+#[test]
+fn configs_derive_serde() {
+    let config = sptrsv::Config::new().static_iter(Some(2)).x_preload_dist(1);
+    let text = serde_json::to_string(&config).unwrap();
+    let reloaded: sptrsv::Config = serde_json::from_str(&text).unwrap();
+    assert_eq!(reloaded.to_toml(), config.to_toml());
+
+    let err = serde_json::from_str::<spmv::Config>("{\"rowblock\": 3}").err();
+    assert_eq!(
+        err.unwrap().to_string(),
+        "invalid value 3 for `rowblock`, expected 1, 2, 4 or 8"
+    );
+}
+
+#[test]
+fn errors_name_the_parameter() {
+    let err = |text: &str| match spmv::Config::new().read_toml(text) {
+        Ok(_) => panic!("`{}` is expected to fail", text),
+        Err(err) => err.to_string(),
+    };
+
+    assert_eq!(err("rowblocks = 8"), "unknown parameter `rowblocks`");
+    assert_eq!(
+        err("val_pld = -2"),
+        "invalid value -2 for `val_pld`, expected an integer from -1 to 15"
+    );
+    assert_eq!(
+        err("col_pft = \"T3\""),
+        "invalid value \"T3\" for `col_pft`, expected one of T0, T1, T2, NTA, None"
+    );
+    assert_eq!(
+        err("move_reg = \"t\""),
+        "invalid value \"t\" for `move_reg`, expected true or false"
+    );
+    assert!(err("nops = 1\nnops = 2").contains("duplicate key `nops`"));
+    assert!(err("\nx_pld = 1.5").starts_with("TOML parse error at line 2"));
+    assert!(err("\nx_pld = 1.5").ends_with(
+        "invalid type: floating point `1.5`, expected a boolean, an integer, a string or a table"
+    ));
+    assert!(err("[spmv.x]\nnops = 1").ends_with("tables are not nested"));
+
+    let err = |text: &str| match spmv::Config::new().read_json(text) {
+        Ok(_) => panic!("`{}` is expected to fail", text),
+        Err(err) => err.to_string(),
+    };
+    assert_eq!(
+        err("{\n\"nops\": 1,\n}"),
+        "trailing comma at line 3 column 1"
+    );
+    assert_eq!(
+        err("{\"nops\": 1, \"nops\": 2}"),
+        "`nops` is given more than once at line 1 column 18"
+    );
+    assert_eq!(
+        err("{\"nops\": [1]}"),
+        "invalid type: sequence, expected a boolean, an integer, a string or a table at line 1 \
+         column 10"
+    );
+    assert_eq!(
+        err("{\"nops\": 1} {}"),
+        "trailing characters at line 1 column 13"
+    );
+}
+
+// the ranges are those the registers and encodings leave, whatever the other
+// parameters are
+#[test]
+fn values_are_checked_against_their_range() {
+    let err = |config: Result<spmv::Config, ConfigError>| config.err().unwrap().to_string();
+
+    let config = spmv::Config::new();
+    assert_eq!(
+        err(config.set("rowblock", &Value::Int(3))),
+        "invalid value 3 for `rowblock`, expected 1, 2, 4 or 8"
+    );
+    assert_eq!(
+        err(config.set("col_pfd", &Value::Int(0))),
+        "invalid value 0 for `col_pfd`, expected an integer from 1 to 32703"
+    );
+    assert_eq!(
+        err(config.set("val_pfd", &Value::Int(40000))),
+        "invalid value 40000 for `val_pfd`, expected an integer from 1 to 32703"
+    );
+    assert_eq!(
+        err(config.set("col_pld", &Value::Int(4))),
+        "invalid value 4 for `col_pld`, expected an integer from 0 to 3"
+    );
+    assert_eq!(
+        err(config.set("x_pld", &Value::Int(5))),
+        "invalid value 5 for `x_pld`, expected an integer from 0 to 4"
+    );
+
+    // a row of trsv and symgs is half as many blocks
+    let config = sptrsv::Config::new();
+    assert!(config.set("col_pld", &Value::Int(7)).is_ok());
+    assert!(config.set("col_pld", &Value::Int(8)).is_err());
+    assert!(config.set("val_pld", &Value::Int(31)).is_ok());
+    assert!(config.set("val_pld", &Value::Int(32)).is_err());
+    assert!(symgs::Config::new().set("x_pld", &Value::Int(9)).is_err());
+    assert!(symgs::Config::new()
+        .set("trsv_rowblock", &Value::Int(16))
+        .is_err());
+}
+
+#[test]
+#[should_panic(expected = "invalid value 3 for `rowblock`, expected 1, 2, 4 or 8")]
+fn builders_panic_out_of_range() {
+    spmv::Config::new().rowblock_size(3);
+}
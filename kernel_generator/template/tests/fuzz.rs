@@ -0,0 +1,950 @@
+// Differential fuzzing of the generators. Every sample draws a configuration
+// and a random HPCG-style matrix, runs the generated kernel in the emulator
+// and compares the result with `core::reference`.
+//
+// Samples are drawn from the parameters the generators support, and one
+// they still turn down, such as an unroll factor that does not divide the
+// rowblock, is drawn again. FUZZ_CASES (default 48) sets the number of
+// kernels run per kernel type and FUZZ_SEED (default 0x5eed) the seed. A
+// failing sample prints the kernel-generator arguments of its configuration
+// together with its seed.
+
+extern crate core;
+
+use core::reference::{self, ELLMatrix, Part, LU_SPLIT, ROW_SLOTS};
+use core::sparse_matrix::*;
+use core::{Assembly, Emulator, Generate, GenerateError, Memory, PrefetchType, SPILL_AREA_BYTES};
+use template::*;
+
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+
+const TOLERANCE: f64 = 1e-10;
+
+// the rowblock sizes the results can be accumulated for
+const ROWBLOCK_SIZES: [u8; 2] = [1, 8];
+
+// draws per kernel run before giving up on a sampler that mostly draws
+// configurations the generators turn down
+const MAX_DRAWS_PER_CASE: u64 = 20;
+
+// rows of zeroed padding around every array, so preloading past either end
+// of the matrix reads harmless data instead of faulting
+const PADDING_ROWS: usize = 64;
+
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed.max(1))
+    }
+
+    // xorshift64*
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn pick<T: Copy>(&mut self, arr: &[T]) -> T {
+        arr[self.below(arr.len() as u64) as usize]
+    }
+
+    fn flip(&mut self) -> bool {
+        self.below(2) == 0
+    }
+
+    fn uniform(&mut self, lo: f64, hi: f64) -> f64 {
+        lo + (hi - lo) * (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+fn env_u64(name: &str, default: u64) -> u64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|s| match s.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16).ok(),
+            None => s.parse().ok(),
+        })
+        .unwrap_or(default)
+}
+
+// parameters shared by every kernel, in kernel-generator argument order
+#[derive(Clone, Copy)]
+struct Common {
+    col_prefetch_info: Option<(PrefetchType, u16)>,
+    col_preload_dist: u8,
+    val_prefetch_info: Option<(PrefetchType, u16)>,
+    val_preload_dist: Option<u8>,
+    x_preload_dist: u8,
+    store_to_tmp: bool,
+    move_reg: bool,
+    move_base: bool,
+    peephole: bool,
+    schedule: bool,
+}
+
+impl Common {
+    // no prefetching or preloading, for the fixed configurations below
+    fn plain() -> Self {
+        Common {
+            col_prefetch_info: None,
+            col_preload_dist: 0,
+            val_prefetch_info: None,
+            val_preload_dist: None,
+            x_preload_dist: 0,
+            store_to_tmp: false,
+            move_reg: true,
+            move_base: true,
+            peephole: false,
+            schedule: false,
+        }
+    }
+
+    fn sample(rng: &mut Rng) -> Self {
+        let prefetch_info = |rng: &mut Rng| {
+            let prefetch_type = rng.pick(&[
+                Some(PrefetchType::T0),
+                Some(PrefetchType::T1),
+                Some(PrefetchType::T2),
+                Some(PrefetchType::NTA),
+                None,
+            ]);
+            prefetch_type.map(|t| (t, rng.pick(&[64, 512, 4096, 4608])))
+        };
+
+        Common {
+            col_prefetch_info: prefetch_info(rng),
+            col_preload_dist: rng.below(3) as u8,
+            val_prefetch_info: prefetch_info(rng),
+            val_preload_dist: rng.pick(&[None, Some(0), Some(1), Some(2)]),
+            x_preload_dist: rng.below(3) as u8,
+            store_to_tmp: rng.flip(),
+            move_reg: rng.flip(),
+            move_base: rng.flip(),
+            peephole: rng.flip(),
+            schedule: rng.flip(),
+        }
+    }
+
+    fn prefetch_args(info: Option<(PrefetchType, u16)>) -> String {
+        match info {
+            None => "None 0".to_string(),
+            Some((prefetch_type, dist)) => {
+                let name = match prefetch_type {
+                    PrefetchType::T0 => "T0",
+                    PrefetchType::T1 => "T1",
+                    PrefetchType::T2 => "T2",
+                    PrefetchType::NTA => "NTA",
+                };
+                format!("{} {}", name, dist)
+            }
+        }
+    }
+
+    fn data_args(&self) -> String {
+        format!(
+            "{} {} {} {} {}",
+            Self::prefetch_args(self.col_prefetch_info),
+            self.col_preload_dist,
+            Self::prefetch_args(self.val_prefetch_info),
+            self.val_preload_dist.map_or(-1, |d| d as i16),
+            self.x_preload_dist,
+        )
+    }
+
+    // leading options, each followed by a space
+    fn option_args(&self) -> String {
+        let option = |b: bool, name: &str| match b {
+            true => format!("--{} ", name),
+            false => String::new(),
+        };
+        format!(
+            "{}{}",
+            option(self.peephole, "peephole"),
+            option(self.schedule, "schedule")
+        )
+    }
+
+    fn flag_args(&self) -> String {
+        let flag = |b: bool| match b {
+            true => "t",
+            false => "f",
+        };
+        format!(
+            "{} {} {}",
+            flag(self.store_to_tmp),
+            flag(self.move_reg),
+            flag(self.move_base)
+        )
+    }
+}
+
+enum Outcome {
+    Passed,
+    Rejected(String),
+    Failed(String),
+}
+
+trait Sample: Sized {
+    fn sample(rng: &mut Rng) -> Self;
+
+    // kernel-generator arguments of the configuration
+    fn args(&self) -> String;
+
+    fn run(&self, rng: &mut Rng) -> Outcome;
+}
+
+// a configuration the generator turns down as unsupported, out of registers
+// or not unrollable is rejected; any other error, a panic and a kernel that
+// fails liveness verification are failures
+fn generate(generator: &impl Generate, c: &Common) -> Result<Assembly, Outcome> {
+    match panic::catch_unwind(AssertUnwindSafe(|| generator.generate_kernel())) {
+        Ok(Ok((asm, _))) => {
+            let asm = match c.peephole {
+                true => asm.peephole().0,
+                false => asm,
+            };
+            match c.schedule {
+                true => Ok(asm.schedule()),
+                false => Ok(asm),
+            }
+        }
+        Ok(Err(err)) => match err.root() {
+            GenerateError::RegisterOverflow { .. } => {
+                Err(Outcome::Rejected("register overflow".to_string()))
+            }
+            GenerateError::IllegalUnrollFactor { .. } => {
+                Err(Outcome::Rejected("illegal unroll factor".to_string()))
+            }
+            GenerateError::Unsupported { .. } => Err(Outcome::Rejected(err.root().to_string())),
+            _ => Err(Outcome::Failed(err.to_string())),
+        },
+        Err(payload) => {
+            let msg = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Err(Outcome::Failed(format!("panicked: {}", msg)))
+        }
+    }
+}
+
+fn compare(name: &str, expected: &[f64], actual: &[f64]) -> Result<(), String> {
+    let worst = expected
+        .iter()
+        .zip(actual.iter())
+        .enumerate()
+        .map(|(i, (e, a))| (i, (e - a).abs() / e.abs().max(1.0)))
+        .fold((0, 0.0), |acc, x| match x.1 > acc.1 || x.1.is_nan() {
+            true => x,
+            false => acc,
+        });
+
+    match worst.1 <= TOLERANCE {
+        true => Ok(()),
+        false => Err(format!(
+            "{}[{}] = {}, expected {}",
+            name, worst.0, actual[worst.0], expected[worst.0]
+        )),
+    }
+}
+
+// The rows of a triangular solve in the order it walks them. A rowblock
+// writes x back only once all of its rows are done, and the x of a row is
+// gathered `x_preload_dist` rows ahead, so a row reads the x of an earlier
+// row as the solve left it only if that row's block was written back before
+// the gather. The pre and post solves of symgs take `static_iter` rows of
+// a block each around the main solve.
+struct Sweep {
+    direction: Direction,
+    nrow: usize,
+    static_iter: usize,
+    rowblock_size: usize,
+    x_preload_dist: usize,
+}
+
+impl Sweep {
+    fn pos(&self, row: usize) -> usize {
+        match self.direction {
+            Direction::Forward => row,
+            Direction::Backward => self.nrow - 1 - row,
+        }
+    }
+
+    fn block(&self, pos: usize) -> usize {
+        let (s, post) = (self.static_iter, self.nrow - self.static_iter);
+        match (pos < s, pos < post) {
+            (true, _) => pos,
+            (false, true) => s + (pos - s) / self.rowblock_size,
+            (false, false) => s + (post - s) / self.rowblock_size + pos - post,
+        }
+    }
+
+    // whether a coupling of `row` to `col` gives the row-by-row result: the
+    // solve reads x[col] only after updating it, or does not read it at all
+    fn legal(&self, row: usize, col: usize) -> bool {
+        let (pos, col_pos) = (self.pos(row), self.pos(col));
+        match col_pos < pos {
+            true => {
+                pos >= self.x_preload_dist
+                    && self.block(col_pos) < self.block(pos - self.x_preload_dist)
+            }
+            false => true,
+        }
+    }
+}
+
+// random 27-point stencil on an n^3 grid, diagonally dominant like HPCG's.
+// Couplings for which `legal` is false are zeroed (their slots are kept).
+// The matrix is laid out as kernel_tuner fills it, with the diagonal in the
+// slots as well as in `diag`, whatever `DiagonalStatus` the kernel reads it by.
+fn random_matrix(
+    rng: &mut Rng,
+    info: ELLInfo,
+    n: usize,
+    legal: impl Fn(usize, usize) -> bool,
+) -> ELLMatrix {
+    let info = ELLInfo::new(DiagonalStatus::Default, info.lu, info.ordering);
+    ELLMatrix::stencil(info, n, |row, col| match row == col {
+        true => rng.uniform(26.0, 30.0),
+        false if !legal(row, col) => 0.0,
+        false => rng.uniform(-1.5, -0.5),
+    })
+}
+
+fn random_vector(rng: &mut Rng, len: usize) -> Vec<f64> {
+    (0..len).map(|_| rng.uniform(-1.0, 1.0)).collect()
+}
+
+// matrix and vectors laid out in emulator memory with `PADDING_ROWS` of
+// zeros on each side
+struct Operands {
+    memory: Memory,
+    cols: u64,
+    vals: u64,
+    tmp: u64,
+}
+
+impl Operands {
+    fn new(a: &ELLMatrix) -> Self {
+        let pad = PADDING_ROWS * ROW_SLOTS;
+        let mut memory = Memory::new();
+
+        let cols = {
+            let mut arr = vec![0; pad];
+            arr.extend_from_slice(a.cols());
+            arr.extend(vec![0; pad]);
+            memory.alloc_i32(&arr) + (pad * size_of::<i32>()) as u64
+        };
+        let vals = {
+            let mut arr = vec![0.0; pad];
+            arr.extend_from_slice(a.vals());
+            arr.extend(vec![0.0; pad]);
+            memory.alloc_f64(&arr) + (pad * size_of::<f64>()) as u64
+        };
+        // a rowblock of results, or every spill slot
+        let tmp = memory.alloc(SPILL_AREA_BYTES);
+
+        Operands {
+            memory,
+            cols,
+            vals,
+            tmp,
+        }
+    }
+
+    fn vector(&mut self, data: &[f64], fill: f64) -> u64 {
+        let mut arr = vec![fill; PADDING_ROWS];
+        arr.extend_from_slice(data);
+        arr.extend(vec![fill; PADDING_ROWS]);
+        self.memory.alloc_f64(&arr) + (PADDING_ROWS * size_of::<f64>()) as u64
+    }
+}
+
+fn run(emulator: &mut Emulator, asm: &Assembly) -> Result<(), String> {
+    emulator
+        .run(asm)
+        .map_err(|err| format!("emulator: {}", err))
+}
+
+fn read(emulator: &Emulator, addr: u64, len: usize) -> Vec<f64> {
+    emulator.memory().read_f64s(addr, len).unwrap()
+}
+
+struct SpmvSample {
+    common: Common,
+    rowblock_size: u8,
+    n_nops: u8,
+    n: usize,
+}
+
+impl Sample for SpmvSample {
+    fn sample(rng: &mut Rng) -> Self {
+        SpmvSample {
+            common: Common::sample(rng),
+            rowblock_size: rng.pick(&ROWBLOCK_SIZES),
+            n_nops: rng.below(3) as u8,
+            n: rng.pick(&[4, 6, 8]),
+        }
+    }
+
+    fn args(&self) -> String {
+        format!(
+            "{}spmv {} {} {} {}",
+            self.common.option_args(),
+            self.common.data_args(),
+            self.rowblock_size,
+            self.n_nops,
+            self.common.flag_args()
+        )
+    }
+
+    fn run(&self, rng: &mut Rng) -> Outcome {
+        let info = ELLInfo::new(
+            DiagonalStatus::Default,
+            LUStatus::Default,
+            GridPointOrdering::Default,
+        );
+        let c = self.common;
+        let generator = spmv::Generator::new(
+            SparseMatrixFormat::ELL(info),
+            spmv::Action::AssignPosAx,
+            Direction::Forward,
+            c.col_prefetch_info,
+            c.col_preload_dist,
+            c.val_prefetch_info,
+            c.val_preload_dist,
+            c.x_preload_dist,
+            self.rowblock_size,
+            self.n_nops,
+            c.store_to_tmp,
+            c.move_reg,
+            c.move_base,
+        );
+        let asm = match generate(&generator, &c) {
+            Ok(asm) => asm,
+            Err(outcome) => return outcome,
+        };
+
+        let a = random_matrix(rng, info, self.n, |_, _| true);
+        let nrow = a.nrow();
+        let x0 = random_vector(rng, nrow);
+
+        let mut operands = Operands::new(&a);
+        let x = operands.vector(&x0, 0.0);
+        let y = operands.vector(&vec![0.0; nrow], 0.0);
+        let (cols, vals, tmp) = (operands.cols, operands.vals, operands.tmp);
+
+        let mut emulator = Emulator::new(operands.memory)
+            .int("NROW", nrow as i32)
+            .pointer("COL", cols)
+            .pointer("VAL", vals)
+            .pointer("X", x)
+            .pointer("TMP", tmp)
+            .pointer("Y", y);
+        if let Err(msg) = run(&mut emulator, &asm) {
+            return Outcome::Failed(msg);
+        }
+
+        let mut y_ref = vec![0.0; nrow];
+        reference::spmv(&a, Part::Full, &x0, &mut y_ref);
+
+        match compare("y", &y_ref, &read(&emulator, y, nrow)) {
+            Ok(()) => Outcome::Passed,
+            Err(msg) => Outcome::Failed(msg),
+        }
+    }
+}
+
+struct SptrsvSample {
+    common: Common,
+    direction: Direction,
+    static_iter: Option<u8>,
+    rowblock_size: u8,
+    n_nops: u8,
+    n: usize,
+}
+
+impl Sample for SptrsvSample {
+    fn sample(rng: &mut Rng) -> Self {
+        let common = Common::sample(rng);
+        let direction = rng.pick(&[Direction::Forward, Direction::Backward]);
+        // a static iteration count walks the rows one by one
+        let static_iter = rng.pick(&[None, None, Some(1), Some(2), Some(4)]);
+        let rowblock_size = match static_iter {
+            Some(_) => 1,
+            None => rng.pick(&ROWBLOCK_SIZES),
+        };
+
+        SptrsvSample {
+            common,
+            direction,
+            static_iter,
+            rowblock_size,
+            n_nops: rng.below(3) as u8,
+            n: rng.pick(&[4, 6, 8]),
+        }
+    }
+
+    fn args(&self) -> String {
+        format!(
+            "{}trsv {} {} {} {} {} {}",
+            self.common.option_args(),
+            match self.direction {
+                Direction::Forward => "f",
+                Direction::Backward => "b",
+            },
+            self.static_iter.map_or(-1, |i| i as i16),
+            self.common.data_args(),
+            self.rowblock_size,
+            self.n_nops,
+            self.common.flag_args()
+        )
+    }
+
+    fn run(&self, rng: &mut Rng) -> Outcome {
+        let info = ELLInfo::new(
+            DiagonalStatus::Excluded,
+            LUStatus::Default,
+            GridPointOrdering::Default,
+        );
+        let c = self.common;
+        let generator = sptrsv::Generator::new(
+            SparseMatrixFormat::ELL(info),
+            self.direction,
+            self.static_iter,
+            0,
+            c.col_prefetch_info,
+            c.col_preload_dist,
+            0,
+            c.val_prefetch_info,
+            c.val_preload_dist,
+            c.x_preload_dist,
+            self.rowblock_size,
+            self.n_nops,
+            c.store_to_tmp,
+            c.move_reg,
+            c.move_base,
+            false,
+        );
+        let asm = match generate(&generator, &c) {
+            Ok(asm) => asm,
+            Err(outcome) => return outcome,
+        };
+
+        let sweep = Sweep {
+            direction: self.direction,
+            nrow: self.n * self.n * self.n,
+            static_iter: 0,
+            rowblock_size: self.rowblock_size as usize,
+            x_preload_dist: c.x_preload_dist as usize,
+        };
+        let a = random_matrix(rng, info, self.n, |row, col| sweep.legal(row, col));
+        let nrow = a.nrow();
+        // a static iteration count solves that many rows only
+        let n_solved = self.static_iter.map_or(nrow, |i| i as usize);
+        let x0 = random_vector(rng, nrow);
+        let p0 = random_vector(rng, nrow);
+        let r0 = random_vector(rng, nrow);
+
+        let mut operands = Operands::new(&a);
+        let x = operands.vector(&x0, 0.0);
+        let p = operands.vector(&p0, 0.0);
+        let d = operands.vector(a.diag(), 1.0);
+        let r = operands.vector(&r0, 0.0);
+        let (cols, vals, tmp) = (operands.cols, operands.vals, operands.tmp);
+
+        // a backward solve walks the upper part from the last row up
+        let (col, val, row0) = match self.direction {
+            Direction::Forward => (cols, vals, 0),
+            Direction::Backward => (
+                cols + ((nrow * ROW_SLOTS + LU_SPLIT) * size_of::<i32>()) as u64,
+                vals + ((nrow * ROW_SLOTS + LU_SPLIT) * size_of::<f64>()) as u64,
+                nrow,
+            ),
+        };
+        let row0 = (row0 * size_of::<f64>()) as u64;
+
+        let mut emulator = Emulator::new(operands.memory)
+            .int("NROW", nrow as i32)
+            .pointer("COL", col)
+            .pointer("VAL", val)
+            .pointer("X", x + row0)
+            .pointer("IMM_X", x)
+            .pointer("TMP", tmp)
+            .pointer("P", p + row0)
+            .pointer("D", d + row0)
+            .pointer("R", r + row0);
+        if let Err(msg) = run(&mut emulator, &asm) {
+            return Outcome::Failed(msg);
+        }
+
+        let mut x_ref = x0.clone();
+        let mut p_ref = p0.clone();
+        match self.direction {
+            Direction::Forward => {
+                reference::sptrsv_forward_rows(&a, n_solved, &r0, &mut p_ref, &mut x_ref)
+            }
+            Direction::Backward => reference::sptrsv_backward_rows(&a, n_solved, &p0, &mut x_ref),
+        }
+
+        let result = compare("x", &x_ref, &read(&emulator, x, nrow))
+            .and_then(|_| compare("p", &p_ref, &read(&emulator, p, nrow)));
+        match result {
+            Ok(()) => Outcome::Passed,
+            Err(msg) => Outcome::Failed(msg),
+        }
+    }
+}
+
+struct SymgsSample {
+    common: Common,
+    static_iter: Option<u8>,
+    spmv_rowblock_size: u8,
+    sptrsv_rowblock_size: u8,
+    n_nops: [u8; 7],
+    n: usize,
+}
+
+impl Sample for SymgsSample {
+    fn sample(rng: &mut Rng) -> Self {
+        let common = Common::sample(rng);
+        let spmv_rowblock_size = rng.pick(&ROWBLOCK_SIZES);
+        let sptrsv_rowblock_size = rng.pick(&ROWBLOCK_SIZES);
+        let n_nops = [(); 7].map(|_| rng.below(2) as u8);
+        let n = rng.pick(&[4, 6, 8]);
+
+        // the pre/post solves take `static_iter` rows each, and the rows left
+        // in between must fill whole rowblocks
+        let static_iters: Vec<_> = [None, Some(1), Some(2), Some(4)]
+            .into_iter()
+            .filter(|i| {
+                (n * n * n - 2 * i.unwrap_or(0) as usize)
+                    .is_multiple_of(sptrsv_rowblock_size as usize)
+            })
+            .collect();
+        let static_iter = rng.pick(&static_iters);
+
+        SymgsSample {
+            common,
+            static_iter,
+            spmv_rowblock_size,
+            sptrsv_rowblock_size,
+            n_nops,
+            n,
+        }
+    }
+
+    fn args(&self) -> String {
+        format!(
+            "{}symgs {} {} {} {} {} {}",
+            self.common.option_args(),
+            self.static_iter.map_or(-1, |i| i as i16),
+            self.common.data_args(),
+            self.spmv_rowblock_size,
+            self.sptrsv_rowblock_size,
+            self.n_nops.map(|n| n.to_string()).join(" "),
+            self.common.flag_args()
+        )
+    }
+
+    fn run(&self, rng: &mut Rng) -> Outcome {
+        let info = ELLInfo::new(
+            DiagonalStatus::Excluded,
+            LUStatus::Default,
+            GridPointOrdering::Default,
+        );
+        let c = self.common;
+        let generator = symgs::Generator::new(
+            SparseMatrixFormat::ELL(info),
+            self.static_iter,
+            c.col_prefetch_info,
+            c.col_preload_dist,
+            c.val_prefetch_info,
+            c.val_preload_dist,
+            c.x_preload_dist,
+            self.spmv_rowblock_size,
+            self.sptrsv_rowblock_size,
+            self.n_nops[0],
+            self.n_nops[1],
+            self.n_nops[2],
+            self.n_nops[3],
+            self.n_nops[4],
+            self.n_nops[5],
+            self.n_nops[6],
+            c.store_to_tmp,
+            c.move_reg,
+            c.move_base,
+        );
+        let asm = match generate(&generator, &c) {
+            Ok(asm) => asm,
+            Err(outcome) => return outcome,
+        };
+
+        let sweep = |direction| Sweep {
+            direction,
+            nrow: self.n * self.n * self.n,
+            static_iter: self.static_iter.unwrap_or(0) as usize,
+            rowblock_size: self.sptrsv_rowblock_size as usize,
+            x_preload_dist: c.x_preload_dist as usize,
+        };
+        let (forward, backward) = (sweep(Direction::Forward), sweep(Direction::Backward));
+        let a = random_matrix(rng, info, self.n, |row, col| {
+            forward.legal(row, col) && backward.legal(row, col)
+        });
+        let nrow = a.nrow();
+        let x0 = random_vector(rng, nrow);
+        let r0 = random_vector(rng, nrow);
+
+        let mut operands = Operands::new(&a);
+        let x = operands.vector(&x0, 0.0);
+        let p = operands.vector(&vec![0.0; nrow], 0.0);
+        let d = operands.vector(a.diag(), 1.0);
+        let r = operands.vector(&r0, 0.0);
+        let (cols, vals, tmp) = (operands.cols, operands.vals, operands.tmp);
+
+        let mut emulator = Emulator::new(operands.memory)
+            .int("NROW", nrow as i32)
+            .pointer("UCOL", cols + (LU_SPLIT * size_of::<i32>()) as u64)
+            .pointer("LCOL", cols)
+            .pointer("UVAL", vals + (LU_SPLIT * size_of::<f64>()) as u64)
+            .pointer("LVAL", vals)
+            .pointer("X", x)
+            .pointer("TMP", tmp)
+            .pointer("P", p)
+            .pointer("D", d)
+            .pointer("R", r);
+        if let Err(msg) = run(&mut emulator, &asm) {
+            return Outcome::Failed(msg);
+        }
+
+        let mut x_ref = x0.clone();
+        let mut p_ref = vec![0.0; nrow];
+        reference::symgs(&a, &r0, &mut p_ref, &mut x_ref);
+
+        match compare("x", &x_ref, &read(&emulator, x, nrow)) {
+            Ok(()) => Outcome::Passed,
+            Err(msg) => Outcome::Failed(msg),
+        }
+    }
+}
+
+fn fuzz<S: Sample>(kernel: &str) {
+    let n_cases = env_u64("FUZZ_CASES", 48);
+    let seed = env_u64("FUZZ_SEED", 0x5eed);
+
+    let mut rng = Rng::new(seed);
+    let mut n_passed = 0;
+    let mut n_rejected = 0;
+    let mut rejections = BTreeMap::new();
+    let mut failures = Vec::new();
+
+    while n_passed + (failures.len() as u64) < n_cases {
+        assert!(
+            n_rejected < n_cases * MAX_DRAWS_PER_CASE,
+            "{}: {} samples were rejected before {} ran",
+            kernel,
+            n_rejected,
+            n_cases
+        );
+
+        let case_seed = rng.next();
+        let mut case_rng = Rng::new(case_seed);
+        let s = S::sample(&mut case_rng);
+
+        match s.run(&mut case_rng) {
+            Outcome::Passed => n_passed += 1,
+            Outcome::Rejected(reason) => {
+                n_rejected += 1;
+                *rejections.entry(reason).or_insert(0) += 1
+            }
+            Outcome::Failed(msg) => {
+                failures.push(format!("{:#x}: {}\n    {}", case_seed, s.args(), msg))
+            }
+        }
+    }
+
+    eprintln!("{}: {} passed, {} failed", kernel, n_passed, failures.len());
+    for (reason, n) in rejections.iter() {
+        eprintln!("    {} rejected: {}", n, reason);
+    }
+    assert!(
+        failures.is_empty(),
+        "{} failures:\n{}",
+        kernel,
+        failures.join("\n")
+    );
+}
+
+// a fixed configuration that was once miscompiled
+fn check(s: impl Sample) {
+    match s.run(&mut Rng::new(0x5eed)) {
+        Outcome::Passed => (),
+        Outcome::Rejected(msg) | Outcome::Failed(msg) => panic!("{}\n    {}", s.args(), msg),
+    }
+}
+
+#[test]
+fn fuzz_spmv() {
+    fuzz::<SpmvSample>("spmv");
+}
+
+#[test]
+fn fuzz_sptrsv() {
+    fuzz::<SptrsvSample>("sptrsv");
+}
+
+#[test]
+fn fuzz_symgs() {
+    fuzz::<SymgsSample>("symgs");
+}
+
+#[test]
+fn backward_nanokernels_address_the_rows_below_the_base() {
+    for (move_reg, move_base) in [(true, false), (false, true)] {
+        check(SptrsvSample {
+            common: Common {
+                move_reg,
+                move_base,
+                ..Common::plain()
+            },
+            direction: Direction::Backward,
+            static_iter: None,
+            rowblock_size: 8,
+            n_nops: 0,
+            n: 4,
+        });
+    }
+}
+
+#[test]
+fn a_single_static_iteration_initializes_its_counter() {
+    check(SymgsSample {
+        common: Common::plain(),
+        static_iter: Some(1),
+        spmv_rowblock_size: 1,
+        sptrsv_rowblock_size: 1,
+        n_nops: [0; 7],
+        n: 4,
+    });
+}
+
+#[test]
+fn unroll_factors_that_do_not_divide_the_rowblock_are_rejected() {
+    let common = Common {
+        x_preload_dist: 2,
+        move_reg: false,
+        ..Common::plain()
+    };
+    let outcomes = [
+        SpmvSample {
+            common,
+            rowblock_size: 8,
+            n_nops: 0,
+            n: 4,
+        }
+        .run(&mut Rng::new(0x5eed)),
+        SptrsvSample {
+            common,
+            direction: Direction::Forward,
+            static_iter: None,
+            rowblock_size: 8,
+            n_nops: 0,
+            n: 4,
+        }
+        .run(&mut Rng::new(0x5eed)),
+    ];
+    for outcome in outcomes {
+        assert!(matches!(outcome, Outcome::Rejected(msg) if msg == "illegal unroll factor"));
+    }
+}
+
+// preloading val this far needs more registers than there are, which the
+// sampled configurations do not, so the rest go to tmp and back
+#[test]
+fn spilled_registers_are_reloaded_from_tmp() {
+    let common = |val_preload_dist| Common {
+        col_preload_dist: 1,
+        val_preload_dist: Some(val_preload_dist),
+        x_preload_dist: 2,
+        ..Common::plain()
+    };
+    check(SpmvSample {
+        common: common(6),
+        rowblock_size: 8,
+        n_nops: 0,
+        n: 4,
+    });
+    check(SptrsvSample {
+        common: common(10),
+        direction: Direction::Forward,
+        static_iter: None,
+        rowblock_size: 8,
+        n_nops: 0,
+        n: 4,
+    });
+}
+
+#[test]
+fn symgs_backward_undoes_the_whole_column_preload() {
+    check(SymgsSample {
+        common: Common {
+            col_preload_dist: 1,
+            x_preload_dist: 2,
+            ..Common::plain()
+        },
+        static_iter: None,
+        spmv_rowblock_size: 8,
+        sptrsv_rowblock_size: 1,
+        n_nops: [0; 7],
+        n: 4,
+    });
+}
+
+#[test]
+fn symgs_main_solves_reuse_the_preload_of_a_single_static_iteration() {
+    check(SymgsSample {
+        common: Common {
+            col_preload_dist: 1,
+            x_preload_dist: 1,
+            ..Common::plain()
+        },
+        static_iter: Some(1),
+        spmv_rowblock_size: 8,
+        sptrsv_rowblock_size: 1,
+        n_nops: [0; 7],
+        n: 4,
+    });
+}
+
+// FUZZ_SEED 0x8f875b6125d90f36 once emitted a kernel failing verification
+#[test]
+fn symgs_main_solve_takes_over_a_matching_preload_only() {
+    let sample = |store_to_tmp| SymgsSample {
+        common: Common {
+            col_prefetch_info: Some((PrefetchType::NTA, 512)),
+            val_prefetch_info: Some((PrefetchType::T2, 512)),
+            val_preload_dist: Some(2),
+            store_to_tmp,
+            move_base: false,
+            ..Common::plain()
+        },
+        static_iter: Some(4),
+        spmv_rowblock_size: 1,
+        sptrsv_rowblock_size: 8,
+        n_nops: [0, 0, 1, 0, 0, 0, 1],
+        n: 4,
+    };
+    assert!(matches!(
+        sample(false).run(&mut Rng::new(0x5eed)),
+        Outcome::Rejected(msg) if msg.ends_with("rowblock = 8 is not implemented")
+    ));
+    check(sample(true));
+}
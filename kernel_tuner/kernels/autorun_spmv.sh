@@ -22,27 +22,14 @@ do
                                 do
                                     for store_to_tmp in f #t f
                                     do
-                                        if [ "$store_to_tmp" = "t" ]
-                                        then
-                                            res_need=1
-                                        elif [ "$store_to_tmp" = "f" ]
-                                        then
-                                            res_need=$rowblock_size
-                                        fi
-                                        col_need=$((4 * (col_preld + 1)))
-                                        val_need=$((4 * (val_preld + 1)))
-                                        x_need=$((4 * (x_preld + 1)))
-
-                                        if [ $((res_need + col_need + x_need + val_need)) -gt 32 ]
-                                        then
-                                            continue
-                                        fi
-
-
                                         for move_reg in t #t f
                                         do
                                             for move_base in t #t f
                                             do
+                                                # skip the configurations the generator rejects,
+                                                # e.g. for needing more registers than there are
+                                                ./kernel-generator check spmv $col_prefi $col_prefd $col_preld $val_prefi $val_prefd $val_preld $x_preld $rowblock_size $nops $store_to_tmp $move_reg $move_base > /dev/null || continue
+
                                                 echo "./kernel-generator spmv $col_prefi $col_prefd $col_preld $val_prefi $val_prefd $val_preld $x_preld $rowblock_size $nops $store_to_tmp $move_reg $move_base"
                                                 ./kernel-generator spmv $col_prefi $col_prefd $col_preld $val_prefi $val_prefd $val_preld $x_preld $rowblock_size $nops $store_to_tmp $move_reg $move_base > src/spmv.cpp
 
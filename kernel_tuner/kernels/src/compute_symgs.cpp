@@ -8,6 +8,10 @@
 
 #define B 32
 
+// a rowblock of results with store_to_tmp t, or else a 64-byte slot for each
+// of the up to 32 registers the kernel spills (SPILL_AREA_BYTES of the generator)
+#define TMP_BYTES 2048
+
 static double* tmp_storage;
 static void* lib_handle;
 
@@ -15,7 +19,7 @@ static int (*symgs_ptr)(int, const int*, const int*, const double*, const double
 
 int init_symgs() {
     if (tmp_storage == 0) {
-        if ((tmp_storage = (double*)numa_alloc_onnode(sizeof(double) * 64, 1)) == 0) {
+        if ((tmp_storage = (double*)numa_alloc_onnode(TMP_BYTES, 1)) == 0) {
             return 1;
         }
         if ((lib_handle = dlopen(LIBPATH, RTLD_NOW)) == 0) {